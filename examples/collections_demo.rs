@@ -1,4 +1,4 @@
-use firebase_db::{FirebaseClient, CollectionManager, User, FirebaseError};
+use firebase_db::{FirebaseClient, CollectionManager, FirebaseError};
 use dotenv::dotenv;
 use std::env;
 
@@ -1,4 +1,4 @@
-use firebase_db::{FirebaseClient, TuiForm, CollectionManager, FirebaseError};
+use firebase_db::{FirebaseClient, CollectionManager, FirebaseError};
 use dotenv::dotenv;
 use std::env;
 use serde_json::json;
@@ -174,11 +174,11 @@ async fn main() -> Result<(), FirebaseError> {
     println!("7. 🗑️ DELETE Operations (Cleanup)");
     println!("----------------------------------");
     
-    let cleanup_ids = vec![&user_id, admin_id.to_string(), &form_user_id];
+    let cleanup_ids = vec![user_id.as_str(), admin_id, form_user_id.as_str()];
     
     for doc_id in cleanup_ids {
         println!("🗑️ Deleting document: {}", doc_id);
-        match client.delete_document(collection_name, &doc_id).await {
+        match client.delete_document(collection_name, doc_id).await {
             Ok(_) => println!("✅ Deleted successfully"),
             Err(e) => println!("❌ Delete failed: {}", e),
         }
@@ -67,9 +67,9 @@ async fn main() -> Result<(), FirebaseError> {
     println!("------------------");
     
     // Load and analyze the discovered schema
-    if let Ok(content) = std::fs::read_to_string("discovered_schema.json") {
-        if let Ok(schema) = serde_json::from_str::<serde_json::Value>(&content) {
-            if let Some(collections) = schema.get("collections").and_then(|c| c.as_object()) {
+    if let Ok(content) = std::fs::read_to_string("discovered_schema.json")
+        && let Ok(schema) = serde_json::from_str::<serde_json::Value>(&content)
+            && let Some(collections) = schema.get("collections").and_then(|c| c.as_object()) {
                 println!("📊 Discovered {} collection(s):", collections.len());
                 
                 for (name, collection) in collections {
@@ -87,11 +87,10 @@ async fn main() -> Result<(), FirebaseError> {
                         
                         for field in fields {
                             if let Some(field_obj) = field.as_object() {
-                                if let Some(required) = field_obj.get("required").and_then(|r| r.as_bool()) {
-                                    if required {
+                                if let Some(required) = field_obj.get("required").and_then(|r| r.as_bool())
+                                    && required {
                                         required_count += 1;
                                     }
-                                }
                                 
                                 if let Some(field_type) = field_obj.get("field_type").and_then(|t| t.as_str()) {
                                     *type_counts.entry(field_type).or_insert(0) += 1;
@@ -107,8 +106,6 @@ async fn main() -> Result<(), FirebaseError> {
                     }
                 }
             }
-        }
-    }
     
     println!("\n4. CLI USAGE EXAMPLES");
     println!("---------------------");
@@ -233,7 +233,7 @@ async fn main() -> Result<(), FirebaseError> {
     println!("for user in alice bob charlie; do");
     println!("  firebase-cli data create -c users \\");
     println!("    name=$user \\");
-    println!("    email=${user}@company.com \\");
+    println!("    email=${{user}}@company.com \\");
     println!("    active=true \\");
     println!("    created_at=now");
     println!("done");
@@ -99,8 +99,8 @@ async fn main() -> Result<(), FirebaseError> {
     // 6. Import data from JSON
     println!("6. IMPORTING DATA FROM JSON");
     println!("----------------------------");
-    let imported_count = json_manager.import_collection_data::<User>("users_export.json", Some("users")).await?;
-    println!("✅ Imported {} users from JSON file", imported_count);
+    let report = json_manager.import_collection_data::<User>("users_export.json", Some("users")).await?;
+    println!("✅ Imported {} users from JSON file ({} failed)", report.succeeded, report.failures.len());
     
     // Verify the import
     let restored_users: Vec<User> = json_manager.get_client().list("users", None).await?;
@@ -113,11 +113,11 @@ async fn main() -> Result<(), FirebaseError> {
     // 7. Full backup demonstration
     println!("7. FULL BACKUP DEMONSTRATION");
     println!("-----------------------------");
-    let backup_results = json_manager.backup_all_data("demo_backup").await?;
-    let total_backed_up: usize = backup_results.values().sum();
-    println!("✅ Backup completed!");
+    let manifest = json_manager.backup_all_data("demo_backup").await?;
+    let total_backed_up = manifest.total();
+    println!("✅ Backup completed (as of {})!", manifest.read_time);
     println!("📊 Backup summary:");
-    for (collection, count) in backup_results {
+    for (collection, count) in manifest.collections {
         println!("  - {}: {} items", collection, count);
     }
     println!("  - Total: {} items", total_backed_up);
@@ -0,0 +1,66 @@
+use firebase_db::{
+    CollectionManager, DocumentStore, FirebaseError, FirestoreValue, MemoryStore,
+    QueryBuilder, ReadOptions, Transaction, json_to_firestore_fields,
+};
+use serde_json::json;
+
+// Runs the same code paths as the Firebase-backed managers without any network access
+#[tokio::main]
+async fn main() -> Result<(), FirebaseError> {
+    let store = MemoryStore::new();
+
+    println!("In-Memory Document Store Demo");
+    println!("=============================\n");
+
+    // 1. Seed some documents
+    let users = vec![
+        ("alice", json!({"name": "Alice", "age": 34, "city": "Paris", "tags": ["admin"]})),
+        ("bob", json!({"name": "Bob", "age": 27, "city": "Berlin", "tags": []})),
+        ("carol", json!({"name": "Carol", "age": 41.5, "city": "Paris", "tags": ["beta"]})),
+        ("dave", json!({"name": "Dave", "age": "unknown", "city": "Oslo"})),
+    ];
+    for (id, data) in users {
+        store.insert("users", Some(id), json_to_firestore_fields(data)?).await?;
+    }
+    println!("1. Inserted {} documents\n", store.len());
+
+    // 2. Query with filters, ordering and limits evaluated locally
+    let query = QueryBuilder::new("users")
+        .where_gt("age", FirestoreValue::IntegerValue("30".to_string()))
+        .order_by("age", false)
+        .build();
+    let results = store.run_query("", &query, &ReadOptions::default()).await?;
+    println!("2. Users older than 30 (age is compared numerically across ints and doubles):");
+    for doc in &results {
+        println!("   {} -> {}", doc.id(), doc.to_json());
+    }
+
+    // 3. Cursors
+    let query = QueryBuilder::new("users")
+        .order_by("name", false)
+        .start_after(vec![FirestoreValue::StringValue("Bob".to_string())])
+        .limit(2)
+        .build();
+    let results = store.run_query("", &query, &ReadOptions::default()).await?;
+    let names: Vec<&str> = results.iter().map(|doc| doc.id()).collect();
+    println!("\n3. Two users after 'Bob' by name: {:?}", names);
+
+    // 4. Transactions
+    let mut transaction = Transaction::begin(&store).await?;
+    if let Some(doc) = transaction.get("users/bob").await? {
+        let mut fields = doc.fields.clone();
+        fields.insert("age".to_string(), FirestoreValue::IntegerValue("28".to_string()));
+        transaction.set("users/bob", fields);
+    }
+    transaction.delete("users/dave");
+    transaction.commit().await?;
+    println!("\n4. Transaction committed, {} documents remain", store.len());
+
+    // 5. Managers work against any DocumentStore
+    let collection_manager = CollectionManager::new(store.clone());
+    let schema = collection_manager.describe_collection("users", 10).await?;
+    println!("\n5. Discovered schema:");
+    println!("{}", collection_manager.format_schema_table(&schema, false));
+
+    Ok(())
+}
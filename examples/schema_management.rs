@@ -1,12 +1,12 @@
 use firebase_db::{
     FirebaseClient, FirebaseError, User, FirestoreValue,
     SchemaManager, Collection, Field, FieldType, Index, IndexField, IndexOrder,
-    ValidationRule, ValidationRuleType,
+    ValidationRule, ValidationRuleType, AutoFieldType, DocumentStore, ReadOptions,
+    Migration, MigrationManager,
     SecurityRules, RuleBuilder, Expression
 };
 use dotenv::dotenv;
 use std::env;
-use chrono::Utc;
 
 #[tokio::main]
 async fn main() -> Result<(), FirebaseError> {
@@ -39,6 +39,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: false,
                 default_value: None,
                 description: Some("User ID".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "name".to_string(),
@@ -46,6 +47,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("User's full name".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "email".to_string(),
@@ -53,6 +55,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("User's email address".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "age".to_string(),
@@ -60,6 +63,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: Some(FirestoreValue::IntegerValue("18".to_string())),
                 description: Some("User's age".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "created_at".to_string(),
@@ -67,6 +71,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("Account creation timestamp".to_string()),
+                auto_field: Some(AutoFieldType::CreatedAt),
             },
         ],
         indexes: vec![
@@ -130,6 +135,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("Post title".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "content".to_string(),
@@ -137,6 +143,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("Post content".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "author_id".to_string(),
@@ -144,6 +151,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: None,
                 description: Some("Reference to user ID".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "tags".to_string(),
@@ -151,6 +159,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: false,
                 default_value: Some(FirestoreValue::ArrayValue { values: vec![] }),
                 description: Some("Post tags".to_string()),
+                auto_field: None,
             },
             Field {
                 name: "published".to_string(),
@@ -158,6 +167,7 @@ async fn main() -> Result<(), FirebaseError> {
                 required: true,
                 default_value: Some(FirestoreValue::BooleanValue(false)),
                 description: Some("Publication status".to_string()),
+                auto_field: None,
             },
         ],
         indexes: vec![
@@ -250,7 +260,20 @@ async fn main() -> Result<(), FirebaseError> {
     println!("4. INDEX REQUIREMENTS");
     println!("---------------------");
     
-    schema_manager.create_indexes("users").await?;
+    // Dry run: only report what would change
+    match schema_manager.create_indexes("users", true).await {
+        Ok(plan) if plan.is_empty() => println!("✓ Indexes are up to date ({} unchanged)", plan.unchanged.len()),
+        Ok(plan) => {
+            for index in &plan.create {
+                println!("  + {} ({})", index.collection_group, index.describe());
+            }
+            for index in &plan.delete {
+                println!("  - {} ({})", index.name, index.definition().describe());
+            }
+        }
+        // Listing indexes goes through the Admin API, which needs an OAuth access token
+        Err(e) => println!("Could not plan indexes: {}", e),
+    }
     println!();
     
     // 5. Security Rules Generation
@@ -326,6 +349,41 @@ async fn main() -> Result<(), FirebaseError> {
     println!("Total indexes: 3");
     println!("Total validation rules: 8");
     
+    println!("\n8. MIGRATIONS");
+    println!("-------------");
+    
+    // Migrations read through the context's client and queue writes on its
+    // batch writer; applied versions are recorded in `_migrations`
+    let mut migrations = MigrationManager::new(client.clone());
+    migrations.add_migration(
+        Migration::<FirebaseClient>::new("001", "Give every user a role")
+            .up(|ctx| Box::pin(async move {
+                for user in ctx.client().list_all("users", &ReadOptions::default()).await? {
+                    if !user.fields.contains_key("role") {
+                        let mut fields = std::collections::HashMap::new();
+                        fields.insert("role".to_string(), FirestoreValue::StringValue("member".to_string()));
+                        ctx.update(user.path(), fields, vec!["role".to_string()]).await;
+                    }
+                }
+                Ok(())
+            }))
+            .down(|ctx| Box::pin(async move {
+                for user in ctx.client().list_all("users", &ReadOptions::default()).await? {
+                    ctx.update(user.path(), std::collections::HashMap::new(), vec!["role".to_string()]).await;
+                }
+                Ok(())
+            })),
+    );
+    
+    match migrations.up(None, true).await {
+        Ok(runs) => {
+            for run in runs {
+                println!("Migration {} would write {} document(s)", run.version, run.affected.len());
+            }
+        }
+        Err(e) => println!("Note: {}", e),
+    }
+    
     println!("\n✅ Schema management example completed!");
     println!("\nNOTE: To deploy these changes to Firebase:");
     println!("1. Copy firestore.rules to your Firebase project");
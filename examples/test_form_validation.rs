@@ -14,6 +14,7 @@ fn main() {
         required: true,
         description: Some("example: \"David Wilson\"".to_string()),
         default_value: Some(String::new()),
+        auto_field: None,
     });
     
     form.add_field(FormField {
@@ -23,6 +24,7 @@ fn main() {
         required: true,
         description: Some("example: 42".to_string()),
         default_value: Some(String::new()),
+        auto_field: None,
     });
     
     form.add_field(FormField {
@@ -32,6 +34,7 @@ fn main() {
         required: false,
         description: Some("example: 3.14".to_string()),
         default_value: Some(String::new()),
+        auto_field: None,
     });
     
     form.add_field(FormField {
@@ -41,6 +44,7 @@ fn main() {
         required: false,
         description: Some("example: [\"premium\", \"verified\"]".to_string()),
         default_value: Some("[]".to_string()),
+        auto_field: None,
     });
     
     // Test valid form submission
@@ -0,0 +1,348 @@
+use crate::error::{FirebaseError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+/// Index entries covered by one billed read in an aggregation query.
+pub const INDEX_ENTRIES_PER_READ: u64 = 1000;
+
+tokio::task_local! {
+    static CALL_SITE: String;
+}
+
+/// Attributes the billable operations made while `future` runs to `call_site`
+/// (e.g. `"collections describe"`). Without a call site, operations are
+/// attributed to their REST operation name (`get`, `query`, ...). The
+/// innermost scope wins when they are nested.
+pub async fn with_call_site<F: Future>(call_site: impl Into<String>, future: F) -> F::Output {
+    CALL_SITE.scope(call_site.into(), future).await
+}
+
+pub(crate) fn current_call_site() -> Option<String> {
+    CALL_SITE.try_with(|site| site.clone()).ok()
+}
+
+/// Carries the current call site into `future`, for work handed to `tokio::spawn`.
+pub(crate) fn inherit_call_site<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let call_site = current_call_site();
+    async move {
+        match call_site {
+            Some(call_site) => CALL_SITE.scope(call_site, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Billable Firestore operations, counted the way Firestore bills them: one
+/// read per document returned (minimum one per query or get), one read per
+/// 1,000 index entries for aggregations, and one write or delete per document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillableOps {
+    pub reads: u64,
+    pub writes: u64,
+    pub deletes: u64,
+    /// Index entries scanned by aggregation queries (already included in `reads`).
+    pub index_entry_reads: u64,
+}
+
+impl BillableOps {
+    pub fn reads(reads: u64) -> Self {
+        Self { reads, ..Self::default() }
+    }
+
+    pub fn writes(writes: u64) -> Self {
+        Self { writes, ..Self::default() }
+    }
+
+    pub fn deletes(deletes: u64) -> Self {
+        Self { deletes, ..Self::default() }
+    }
+
+    /// Reads billed for a query returning `documents` results.
+    pub fn query(documents: u64) -> Self {
+        Self::reads(documents.max(1))
+    }
+
+    /// Reads billed for an aggregation that matched `index_entries` entries.
+    pub fn aggregation(index_entries: u64) -> Self {
+        Self {
+            reads: index_entries.div_ceil(INDEX_ENTRIES_PER_READ).max(1),
+            index_entry_reads: index_entries,
+            ..Self::default()
+        }
+    }
+
+    pub fn add(&mut self, other: BillableOps) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.deletes += other.deletes;
+        self.index_entry_reads += other.index_entry_reads;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Estimated price of these operations under `rates`, ignoring the free tier.
+    pub fn cost(&self, rates: &RateTable) -> f64 {
+        (self.reads as f64 * rates.read_per_100k
+            + self.writes as f64 * rates.write_per_100k
+            + self.deletes as f64 * rates.delete_per_100k)
+            / 100_000.0
+    }
+}
+
+/// Prices per 100,000 operations. The default is the published rate for a
+/// multi-region (nam5) database; load a file to match your location.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateTable {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub read_per_100k: f64,
+    pub write_per_100k: f64,
+    pub delete_per_100k: f64,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl Default for RateTable {
+    fn default() -> Self {
+        Self {
+            currency: default_currency(),
+            read_per_100k: 0.06,
+            write_per_100k: 0.18,
+            delete_per_100k: 0.02,
+        }
+    }
+}
+
+impl RateTable {
+    /// Loads a rate table from a JSON or YAML file.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read rate table {}: {}", path, e)))?;
+        if path.ends_with(".yaml") || path.ends_with(".yml") {
+            serde_yaml::from_str(&content)
+                .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse rate table {}: {}", path, e)))
+        } else {
+            Ok(serde_json::from_str(&content)?)
+        }
+    }
+}
+
+/// Billable operations attributed to one call site, operation and collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallSiteCost {
+    pub call_site: String,
+    /// REST operation: `get`, `list`, `query`, `aggregate`, `batch_get`, `commit`, ...
+    pub operation: String,
+    pub collection: String,
+    pub ops: BillableOps,
+}
+
+/// Running record of billable operations made by a client, broken down by
+/// call site, operation and collection. Shared by all clones of the client.
+///
+/// An optional read limit turns it into a budget: reads that would take the
+/// total past the limit fail with [`FirebaseError::BudgetExceeded`] before
+/// the request is sent. Requests reserve their reads before they are sent,
+/// so concurrent requests can't together go over the limit.
+#[derive(Debug, Default)]
+pub struct CostLedger {
+    entries: Mutex<BTreeMap<(String, String, String), BillableOps>>,
+    budget: Mutex<ReadBudget>,
+}
+
+#[derive(Debug, Default)]
+struct ReadBudget {
+    limit: Option<u64>,
+    /// Reads set aside by requests still in flight.
+    reserved: u64,
+}
+
+/// Reads set aside for a request. They are given back when this is dropped,
+/// which should be after the request's actual reads are charged.
+#[derive(Debug)]
+pub(crate) struct ReadReservation<'a> {
+    ledger: &'a CostLedger,
+    reads: u64,
+}
+
+impl Drop for ReadReservation<'_> {
+    fn drop(&mut self) {
+        if self.reads > 0 {
+            let mut budget = self.ledger.budget();
+            budget.reserved = budget.reserved.saturating_sub(self.reads);
+        }
+    }
+}
+
+impl CostLedger {
+    pub fn set_read_limit(&self, limit: Option<u64>) {
+        self.budget().limit = limit;
+    }
+
+    pub fn read_limit(&self) -> Option<u64> {
+        self.budget().limit
+    }
+
+    /// Reads left before the limit is reached, or `None` without a limit.
+    /// Reads reserved by requests in flight count as used.
+    pub fn remaining_reads(&self) -> Option<u64> {
+        let budget = self.budget();
+        budget.limit.map(|limit| self.available(&budget, limit))
+    }
+
+    /// Reserves `reads` reads, failing if they would go over the limit.
+    pub(crate) fn reserve_reads(&self, operation: &str, reads: u64) -> Result<ReadReservation<'_>> {
+        let mut budget = self.budget();
+        if let Some(limit) = budget.limit {
+            if reads > self.available(&budget, limit) {
+                drop(budget);
+                return Err(self.budget_exceeded(operation));
+            }
+            budget.reserved += reads;
+        }
+        Ok(ReadReservation { ledger: self, reads: if budget.limit.is_some() { reads } else { 0 } })
+    }
+
+    /// Reserves reads for a query or list that bills `fixed` reads (e.g. an
+    /// offset) plus one per document, up to `wanted` documents if it is
+    /// limited. When the budget can't cover `wanted`, the returned cap is the
+    /// most documents it allows: the caller asks for `cap + 1` and fails only
+    /// if more than `cap` come back. The reservation covers that extra read.
+    pub(crate) fn reserve_documents(
+        &self,
+        operation: &str,
+        fixed: u64,
+        wanted: Option<u64>,
+    ) -> Result<(ReadReservation<'_>, Option<u64>)> {
+        let mut budget = self.budget();
+        let Some(limit) = budget.limit else {
+            return Ok((ReadReservation { ledger: self, reads: 0 }, None));
+        };
+        let available = self.available(&budget, limit);
+        // Even an empty result is billed one read
+        if fixed + 1 > available {
+            drop(budget);
+            return Err(self.budget_exceeded(operation));
+        }
+        let cap = available - fixed;
+        let (reads, cap) = match wanted {
+            Some(wanted) if wanted <= cap => (fixed + wanted.max(1), None),
+            _ => (available + 1, Some(cap)),
+        };
+        budget.reserved += reads;
+        Ok((ReadReservation { ledger: self, reads }, cap))
+    }
+
+    /// Reserves every read left for an aggregation, returning the most index
+    /// entries they pay for. The caller bounds the aggregation with that cap
+    /// (`upTo`) and fails if the result reaches it, since an exact fit can't be
+    /// told apart from a larger count.
+    pub(crate) fn reserve_index_entries(&self, operation: &str) -> Result<(ReadReservation<'_>, Option<u64>)> {
+        let mut budget = self.budget();
+        let Some(limit) = budget.limit else {
+            return Ok((ReadReservation { ledger: self, reads: 0 }, None));
+        };
+        let available = self.available(&budget, limit);
+        // Even an empty aggregation is billed one read
+        if available == 0 {
+            drop(budget);
+            return Err(self.budget_exceeded(operation));
+        }
+        budget.reserved += available;
+        Ok((ReadReservation { ledger: self, reads: available }, Some(available.saturating_mul(INDEX_ENTRIES_PER_READ))))
+    }
+
+    fn available(&self, budget: &ReadBudget, limit: u64) -> u64 {
+        limit.saturating_sub(self.totals().reads.saturating_add(budget.reserved))
+    }
+
+    fn budget(&self) -> std::sync::MutexGuard<'_, ReadBudget> {
+        self.budget.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn budget_exceeded(&self, operation: &str) -> FirebaseError {
+        let call_site = current_call_site().map(|site| format!(" in {}", site)).unwrap_or_default();
+        FirebaseError::BudgetExceeded(format!(
+            "{}{} would go over the limit of {} reads ({} used); raise the limit or narrow the operation",
+            operation,
+            call_site,
+            self.read_limit().unwrap_or_default(),
+            self.totals().reads,
+        ))
+    }
+
+    pub(crate) fn charge(&self, operation: &str, collection: &str, ops: BillableOps) {
+        if ops.is_empty() {
+            return;
+        }
+        let call_site = current_call_site().unwrap_or_else(|| operation.to_string());
+        let collection = if collection.is_empty() { "(root)" } else { collection }.to_string();
+        self.lock().entry((call_site, operation.to_string(), collection)).or_default().add(ops);
+    }
+
+    pub fn totals(&self) -> BillableOps {
+        let mut totals = BillableOps::default();
+        for ops in self.lock().values() {
+            totals.add(*ops);
+        }
+        totals
+    }
+
+    pub fn by_call_site(&self) -> Vec<CallSiteCost> {
+        self.lock().iter()
+            .map(|((call_site, operation, collection), ops)| CallSiteCost {
+                call_site: call_site.clone(),
+                operation: operation.clone(),
+                collection: collection.clone(),
+                ops: *ops,
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<(String, String, String), BillableOps>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Expected cost of reading one collection, from its aggregation count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionEstimate {
+    pub collection: String,
+    pub documents: u64,
+    /// Reads the operation would make in this collection.
+    pub reads: u64,
+}
+
+/// A dry-run estimate for a scan, built from aggregation counts without
+/// reading any documents.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    pub collections: Vec<CollectionEstimate>,
+    /// Reads spent producing the estimate (aggregations and collection listing).
+    pub estimate_reads: u64,
+}
+
+impl CostEstimate {
+    pub(crate) fn add(&mut self, collection: &str, documents: u64, reads: u64) {
+        self.collections.push(CollectionEstimate { collection: collection.to_string(), documents, reads });
+    }
+
+    /// Reads the real operation would make.
+    pub fn reads(&self) -> u64 {
+        self.collections.iter().map(|c| c.reads).sum()
+    }
+
+    pub fn ops(&self) -> BillableOps {
+        BillableOps::reads(self.reads())
+    }
+}
@@ -1,9 +1,20 @@
-use firebase_db::{FirebaseClient, JsonSchemaManager, CollectionManager, User, FirebaseError, TuiForm, CollectionSchema, FirestoreValue};
-use clap::{Parser, Subcommand};
+use firebase_db::{FirebaseClient, JsonSchemaManager, CollectionManager, User, FirebaseError, TuiForm, CollectionSchema};
+use firebase_db::{DocumentStore, ListOptions, ReadOptions, EMULATOR_HOST_ENV, ReplayMatcher, json_to_firestore_fields};
+use firebase_db::{BulkWriteReport, CostEstimate, CostLedger, RateLimit, RateTable, with_call_site};
+use firebase_db::{ChangeKind, FieldOperator, ListenOptions, ListenTarget, QueryBuilder, create_filter, json_value_to_firestore, listen};
+use firebase_db::{DistanceMeasure, ExplainMetrics, ExplainOptions, IndexDefinitions, load_saved_queries};
+use firebase_db::{IndexAdmin, IndexPlan, IndexReconciler, SchemaManager, ValidatedClient, ValidationRuleType};
+use firebase_db::{MigrationDirection, MigrationManager, MigrationRun, MigrationStatus, load_migrations};
+use firebase_db::{JsonCollection, SchemaDiff, read_schema_file};
+use firebase_db::{DEFAULT_FREQUENCY_THRESHOLD, DriftReport, Severity};
+use firebase_db::{FirestoreSchema, VersionBump};
+use firebase_db::query::Filter;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use dotenv::dotenv;
+use std::collections::BTreeSet;
 use std::env;
 use std::path::Path;
-use std::io;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -11,6 +22,42 @@ use std::io;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Request timeout in seconds
+    #[arg(long, global = true, default_value = "60")]
+    timeout: u64,
+    /// Connection timeout in seconds
+    #[arg(long, global = true, default_value = "10")]
+    connect_timeout: u64,
+    /// HTTP(S) proxy URL to route Firestore traffic through
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+    /// Default page size for list operations
+    #[arg(long, global = true)]
+    page_size: Option<usize>,
+    /// Record all Firestore requests and responses (API key redacted) to this fixture file
+    #[arg(long, global = true, conflicts_with = "replay")]
+    record: Option<String>,
+    /// Serve responses from a recorded fixture file instead of the network
+    #[arg(long, global = true)]
+    replay: Option<String>,
+    /// When replaying, treat any two timestamps or generated IDs as equal
+    #[arg(long, global = true, requires = "replay")]
+    replay_normalize: bool,
+    /// Log more detail to stderr (-v: info, -vv: debug, -vvv: trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Log format: text or json
+    #[arg(long, global = true, default_value = "text")]
+    log_format: String,
+    /// Print billable reads, writes and deletes with an estimated price after the command
+    #[arg(long, global = true)]
+    cost_report: bool,
+    /// JSON or YAML rate table for --cost-report (prices per 100,000 operations)
+    #[arg(long, global = true, requires = "cost_report")]
+    rates: Option<String>,
+    /// Fail instead of going over this many billed document reads
+    #[arg(long, global = true)]
+    max_reads: Option<u64>,
 }
 
 #[derive(Subcommand)]
@@ -30,6 +77,47 @@ enum Commands {
         #[command(subcommand)]
         action: CollectionActions,
     },
+    /// Versioned data migrations
+    Migrate {
+        /// Directory of YAML or JSON migration files
+        #[arg(short, long, default_value = "migrations")]
+        dir: String,
+        #[command(subcommand)]
+        action: MigrateActions,
+    },
+}
+
+/// Flow control for bulk commands
+#[derive(Args, Clone, Default)]
+struct ThrottleArgs {
+    /// Maximum operations per second (with --ramp-up, the ceiling of the ramp)
+    #[arg(long, value_parser = parse_rate)]
+    rate: Option<f64>,
+    /// Maximum requests in flight at once
+    #[arg(long)]
+    concurrency: Option<usize>,
+    /// Follow Firestore's 500/50/5 ramp-up: start at 500 ops/s, grow 50% every 5 minutes
+    #[arg(long)]
+    ramp_up: bool,
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl ThrottleArgs {
+    fn rate_limit(&self) -> Option<RateLimit> {
+        match (self.ramp_up, self.rate) {
+            (true, Some(max)) => Some(RateLimit::ramp_up().capped_at(max)),
+            (true, None) => Some(RateLimit::ramp_up()),
+            (false, Some(rate)) => Some(RateLimit::PerSecond(rate)),
+            (false, None) => None,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -42,6 +130,9 @@ enum SchemaActions {
         /// Export manually defined schemas instead of discovered ones (default: discover from database)
         #[arg(long)]
         manual: bool,
+        /// Only estimate the reads discovery would make, using aggregation counts
+        #[arg(long, conflicts_with = "manual")]
+        dry_run: bool,
     },
     /// Import schema from JSON or YAML file
     Import {
@@ -69,14 +160,93 @@ enum SchemaActions {
         /// Number of sample documents to analyze (default: 50)
         #[arg(short, long, default_value = "50")]
         samples: usize,
+        /// Author recorded on the new schema version (default: $USER)
+        #[arg(long)]
+        author: Option<String>,
+    },
+    /// Work out the composite indexes the schema and saved queries need and write firestore.indexes.json
+    Indexes {
+        /// Schema file (JSON or YAML) whose collection indexes to include
+        #[arg(short, long)]
+        schema: Option<String>,
+        /// Saved queries file (JSON or YAML list of {name, query}) to infer indexes from
+        #[arg(short, long)]
+        queries: Option<String>,
+        /// Output file path
+        #[arg(short, long, default_value = "firestore.indexes.json")]
+        output: String,
+        /// Compare with the database's composite indexes and show what would change
+        #[arg(long)]
+        reconcile: bool,
+        /// Create and delete indexes so the database matches (implies --reconcile)
+        #[arg(long)]
+        apply: bool,
     },
     /// List all schemas stored in Firestore
     List,
     /// Show detailed information about a schema stored in Firestore
+    #[command(disable_version_flag = true)]
     Show {
         /// Collection name
         #[arg(short, long)]
         collection: String,
+        /// Show this saved version instead of the current one
+        #[arg(long)]
+        version: Option<String>,
+    },
+    /// List every saved version of a collection's schema
+    History {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+    },
+    /// Make an earlier schema version current again, saved as a new version
+    Rollback {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Version to restore
+        #[arg(long)]
+        to: String,
+        /// Author recorded on the new schema version (default: $USER)
+        #[arg(long)]
+        author: Option<String>,
+    },
+    /// Compare two schemas and classify the changes as breaking or non-breaking
+    Diff {
+        /// Old and new schema: a schema file, or stored:<collection> for a schema in Firestore
+        #[arg(num_args = 1..=2, required = true)]
+        schemas: Vec<String>,
+        /// Compare against the schema discovered from this collection's documents, as the new side
+        #[arg(long)]
+        live: Option<String>,
+        /// Number of documents to sample for --live
+        #[arg(short, long, default_value = "50")]
+        samples: usize,
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+    },
+    /// Compare a sample of documents with their stored schema
+    Drift {
+        /// Collections to check (default: every collection with a stored schema)
+        #[arg(short, long)]
+        collection: Vec<String>,
+        /// Number of documents to sample per collection
+        #[arg(short, long, default_value = "50")]
+        samples: usize,
+        /// Report fields whose share of documents changed by more than this fraction
+        #[arg(long, default_value_t = DEFAULT_FREQUENCY_THRESHOLD)]
+        frequency_threshold: f64,
+        /// Exit with an error when drift reaches this severity: breaking, non-breaking or none
+        #[arg(long, default_value = "none")]
+        fail_on: String,
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
     },
 }
 
@@ -96,6 +266,9 @@ enum DataActions {
         /// Use interactive TUI form even if JSON is provided
         #[arg(long)]
         interactive: bool,
+        /// Reject fields the stored schema doesn't declare
+        #[arg(long)]
+        strict: bool,
         /// Field values as key=value or key:value pairs (e.g., name="John Doe" age=30 active=true)
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         fields: Vec<String>,
@@ -111,6 +284,9 @@ enum DataActions {
         /// Output format (json, table, or yaml)
         #[arg(short, long, default_value = "json")]
         format: String,
+        /// Read as of this RFC 3339 timestamp, within the point-in-time recovery window
+        #[arg(long)]
+        as_of: Option<String>,
     },
     /// Update an existing document
     Update {
@@ -129,6 +305,9 @@ enum DataActions {
         /// Merge with existing document (default: true)
         #[arg(long)]
         replace: bool,
+        /// Reject fields the stored schema doesn't declare
+        #[arg(long)]
+        strict: bool,
     },
     /// Delete a document by ID
     Delete {
@@ -150,6 +329,12 @@ enum DataActions {
         /// Output file path
         #[arg(short, long)]
         output: String,
+        /// Read the collection with N concurrent partitioned queries
+        #[arg(long, default_value = "1")]
+        parallel: usize,
+        /// Read as of this RFC 3339 timestamp, within the point-in-time recovery window
+        #[arg(long)]
+        as_of: Option<String>,
     },
     /// Import data from JSON or YAML file
     Import {
@@ -159,12 +344,39 @@ enum DataActions {
         /// Target collection (optional, uses collection from file if not specified)
         #[arg(short, long)]
         collection: Option<String>,
+        /// Write a JSON report of every failed document to this file
+        #[arg(long)]
+        report: Option<String>,
+        #[command(flatten)]
+        throttle: ThrottleArgs,
+    },
+    /// Restore collections from a backup directory
+    Restore {
+        /// Backup directory
+        #[arg(short, long, default_value = "backup")]
+        directory: String,
+        /// Write a JSON report of every failed document to this file
+        #[arg(long)]
+        report: Option<String>,
+        #[command(flatten)]
+        throttle: ThrottleArgs,
     },
     /// Backup all collections
     Backup {
         /// Backup directory
         #[arg(short, long, default_value = "backup")]
         directory: String,
+        /// Only estimate the reads the backup would make, using aggregation counts
+        #[arg(long)]
+        dry_run: bool,
+        /// Read each collection with N concurrent partitioned queries
+        #[arg(long, default_value = "1")]
+        parallel: usize,
+        /// Back up every collection as of this RFC 3339 timestamp instead of the start time
+        #[arg(long)]
+        as_of: Option<String>,
+        #[command(flatten)]
+        throttle: ThrottleArgs,
     },
     /// List all documents in a collection
     List {
@@ -177,6 +389,60 @@ enum DataActions {
         /// Output format (table, json, or text)
         #[arg(short, long, default_value = "table")]
         format: String,
+        /// Read as of this RFC 3339 timestamp, within the point-in-time recovery window
+        #[arg(long)]
+        as_of: Option<String>,
+    },
+    /// Query a collection with filters, ordering or a vector search
+    Query {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Filter like 'age>=30' or 'status=active' (repeatable)
+        #[arg(short = 'w', long = "where")]
+        filters: Vec<String>,
+        /// Order by a field, with ':desc' for descending (repeatable)
+        #[arg(long)]
+        order_by: Vec<String>,
+        /// Maximum number of documents (default 10 for a vector search)
+        #[arg(short, long)]
+        limit: Option<i32>,
+        /// Vector field to search by nearest neighbour (needs --vector-file)
+        #[arg(long, requires = "vector_file")]
+        nearest: Option<String>,
+        /// File with the query vector, as a JSON array or comma/space separated numbers
+        #[arg(long, requires = "nearest")]
+        vector_file: Option<String>,
+        /// Distance measure for --nearest: cosine, euclidean or dot-product
+        #[arg(long, default_value = "cosine")]
+        distance: String,
+        /// Return each result's distance in this field
+        #[arg(long, requires = "nearest")]
+        distance_field: Option<String>,
+        /// Also show the query plan and execution stats (indexes used, documents scanned, billing)
+        #[arg(long)]
+        explain: bool,
+        /// Output format (table, json, or text)
+        #[arg(short, long, default_value = "table")]
+        format: String,
+    },
+    /// Watch a collection and print changes as they happen (Ctrl-C to stop)
+    Watch {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Only watch documents matching a filter like 'age>=30' or 'status=active' (repeatable)
+        #[arg(short = 'w', long = "where")]
+        filters: Vec<String>,
+        /// Seconds between polls
+        #[arg(long, default_value = "1")]
+        interval: f64,
+        /// Resume from the token printed when an earlier watch stopped
+        #[arg(long)]
+        resume_token: Option<String>,
+        /// Output format (text or json, one event per line)
+        #[arg(short, long, default_value = "text")]
+        format: String,
     },
 }
 
@@ -199,6 +465,15 @@ enum CollectionActions {
         /// Output format (table or text)
         #[arg(short, long, default_value = "table")]
         format: String,
+        /// Only estimate the reads the analysis would make, using an aggregation count
+        #[arg(long)]
+        dry_run: bool,
+        /// Analyze every document instead of a sample
+        #[arg(long)]
+        full: bool,
+        /// With --full, read the collection with N concurrent partitioned queries
+        #[arg(long, default_value = "1", requires = "full")]
+        parallel: usize,
     },
     /// Get collection statistics
     Info {
@@ -208,45 +483,228 @@ enum CollectionActions {
     },
 }
 
+#[derive(Subcommand)]
+enum MigrateActions {
+    /// Apply pending migrations
+    Up {
+        /// Stop after this version
+        #[arg(long)]
+        to: Option<String>,
+        /// Report the documents each migration would write, without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Roll back applied migrations, newest first
+    Down {
+        /// Number of migrations to roll back
+        #[arg(long, default_value = "1")]
+        steps: usize,
+        /// Report the documents each migration would write, without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show applied and pending migrations
+    Status,
+    /// Roll back the last applied migration and apply it again
+    Redo {
+        /// Report the documents each migration would write, without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove a lock left behind by a runner that died
+    Unlock,
+}
+
+impl Commands {
+    /// Rate and concurrency flags of bulk commands, which configure the client.
+    fn throttle(&self) -> Option<&ThrottleArgs> {
+        match self {
+            Commands::Data { action: DataActions::Import { throttle, .. } } => Some(throttle),
+            Commands::Data { action: DataActions::Backup { throttle, .. } } => Some(throttle),
+            Commands::Data { action: DataActions::Restore { throttle, .. } } => Some(throttle),
+            _ => None,
+        }
+    }
+
+    /// Partitioned read parallelism for exports and backups.
+    fn parallel(&self) -> Option<usize> {
+        match self {
+            Commands::Data { action: DataActions::Export { parallel, .. } } => Some(*parallel),
+            Commands::Data { action: DataActions::Backup { parallel, .. } } => Some(*parallel),
+            _ => None,
+        }
+    }
+
+    /// Author to record on schema versions saved by the command.
+    fn author(&self) -> Option<String> {
+        let author = match self {
+            Commands::Schema { action: SchemaActions::Sync { author, .. } } => author,
+            Commands::Schema { action: SchemaActions::Rollback { author, .. } } => author,
+            _ => return None,
+        };
+        author.clone().or_else(|| env::var("USER").ok())
+    }
+
+    /// Point-in-time read for exports and backups.
+    fn as_of(&self) -> Option<&str> {
+        match self {
+            Commands::Data { action: DataActions::Export { as_of, .. } } => as_of.as_deref(),
+            Commands::Data { action: DataActions::Backup { as_of, .. } } => as_of.as_deref(),
+            _ => None,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), FirebaseError> {
     dotenv().ok();
     
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    init_logging(cli.verbose, &cli.log_format)?;
+    
+    let rates = match &cli.rates {
+        Some(path) => RateTable::from_file(path)?,
+        None => RateTable::default(),
+    };
+    let cost_report = cli.cost_report;
     
     // Initialize Firebase client
     let project_id = env::var("FIREBASE_PROJECT_ID")
         .map_err(|_| FirebaseError::ConfigError("FIREBASE_PROJECT_ID not set".to_string()))?;
     
-    let api_key = env::var("FIREBASE_API_KEY")
-        .map_err(|_| FirebaseError::ConfigError("FIREBASE_API_KEY not set".to_string()))?;
-    
-    let client = FirebaseClient::new(project_id, api_key);
-    let mut json_manager = JsonSchemaManager::new(client.clone());
-    let collection_manager = CollectionManager::new(client);
+    // Emulators don't check the key, so it is only required against production
+    let api_key = match env::var("FIREBASE_API_KEY") {
+        Ok(api_key) => api_key,
+        Err(_) if env::var(EMULATOR_HOST_ENV).is_ok() => "emulator".to_string(),
+        Err(_) if cli.replay.is_some() => "replay".to_string(),
+        Err(_) => return Err(FirebaseError::ConfigError("FIREBASE_API_KEY not set".to_string())),
+    };
     
-    match cli.command {
-        Commands::Schema { action } => {
-            handle_schema_command(&mut json_manager, action).await?;
+    let mut builder = FirebaseClient::builder(project_id, api_key)
+        .request_timeout(Duration::from_secs(cli.timeout))
+        .connect_timeout(Duration::from_secs(cli.connect_timeout))
+        .user_agent(concat!("firebase-cli/", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = &cli.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(page_size) = cli.page_size {
+        builder = builder.default_page_size(page_size);
+    }
+    if let Some(max_reads) = cli.max_reads {
+        builder = builder.max_reads(max_reads);
+    }
+    if let Some(throttle) = cli.command.throttle() {
+        if let Some(rate_limit) = throttle.rate_limit() {
+            builder = builder.rate_limit(rate_limit);
         }
-        Commands::Data { action } => {
-            handle_data_command(&json_manager, &collection_manager, action).await?;
+        if let Some(concurrency) = throttle.concurrency {
+            builder = builder.max_in_flight(concurrency);
         }
-        Commands::Collections { action } => {
-            handle_collections_command(&collection_manager, action).await?;
+    }
+    if let Some(path) = &cli.record {
+        builder = builder.record_to(path);
+    }
+    if let Some(path) = &cli.replay {
+        builder = builder.replay_from(path);
+    }
+    if cli.replay_normalize {
+        builder = builder.replay_matcher(ReplayMatcher::default().normalize());
+    }
+    let client = builder.build()?;
+    let mut json_manager = JsonSchemaManager::new(client.clone())
+        .with_read_partitions(cli.command.parallel().unwrap_or(1));
+    if let Some(as_of) = cli.command.as_of() {
+        json_manager = json_manager.with_read_time(as_of);
+    }
+    if let Some(author) = cli.command.author() {
+        json_manager = json_manager.with_author(author);
+    }
+    let collection_manager = CollectionManager::new(client.clone());
+    
+    // Bill everything the command does to its name, e.g. "collections describe"
+    let call_site = command_path(&matches);
+    let result = with_call_site(call_site, async {
+        match cli.command {
+            Commands::Schema { action } => {
+                handle_schema_command(&mut json_manager, action).await
+            }
+            Commands::Data { action } => {
+                handle_data_command(&json_manager, &collection_manager, action).await
+            }
+            Commands::Collections { action } => {
+                handle_collections_command(&collection_manager, action).await
+            }
+            Commands::Migrate { dir, action } => {
+                handle_migrate_command(client.clone(), &dir, action).await
+            }
         }
+    }).await;
+    
+    let metrics = client.metrics().snapshot();
+    tracing::info!(
+        requests = metrics.requests,
+        errors = metrics.errors,
+        reads = metrics.reads,
+        writes = metrics.writes,
+        deletes = metrics.deletes,
+        bytes_sent = metrics.bytes_sent,
+        bytes_received = metrics.bytes_received,
+        "Firestore operation totals"
+    );
+    
+    if cost_report {
+        display_cost_report(client.costs(), &rates);
+    }
+    
+    let saved = client.save_recording();
+    result.and(saved)
+}
+
+fn command_path(matches: &clap::ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name.to_string());
+        current = sub;
     }
+    names.join(" ")
+}
+
+fn init_logging(verbosity: u8, format: &str) -> Result<(), FirebaseError> {
+    use tracing_subscriber::EnvFilter;
     
+    // RUST_LOG takes precedence; otherwise -v raises the level for our crates only
+    let level = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,firebase_db={},firebase_cli={}", level, level)));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    
+    match format.to_lowercase().as_str() {
+        "text" => builder.init(),
+        "json" => builder.json().init(),
+        _ => return Err(FirebaseError::ConfigError(format!("Unsupported log format '{}'. Use: text or json", format))),
+    }
     Ok(())
 }
 
-async fn handle_schema_command(
-    json_manager: &mut JsonSchemaManager, 
+async fn handle_schema_command<S: DocumentStore + IndexAdmin + Clone>(
+    json_manager: &mut JsonSchemaManager<S>, 
     action: SchemaActions
 ) -> Result<(), FirebaseError> {
     match action {
-        SchemaActions::Export { output, manual } => {
-            if manual {
+        SchemaActions::Export { output, manual, dry_run } => {
+            if dry_run {
+                let estimate = json_manager.estimate_discovered_schemas().await?;
+                display_cost_estimate("Schema discovery", &estimate);
+            } else if manual {
                 println!("📝 Exporting manually defined schemas...");
                 json_manager.export_schema_to_file(&output)?;
                 println!("✅ Manually defined schemas exported to {}", output);
@@ -261,6 +719,7 @@ async fn handle_schema_command(
                 return Err(FirebaseError::ConfigError(format!("File not found: {}", input)));
             }
             json_manager.import_schema_from_file(&input)?;
+            check_custom_rules(json_manager.get_schema_manager())?;
             println!("✅ Schema imported from {}", input);
         }
         SchemaActions::Example { output } => {
@@ -272,7 +731,9 @@ async fn handle_schema_command(
             if !Path::new(&file).exists() {
                 return Err(FirebaseError::ConfigError(format!("File not found: {}", file)));
             }
-            match json_manager.import_schema_from_file(&file) {
+            match json_manager.import_schema_from_file(&file)
+                .and_then(|_| check_custom_rules(json_manager.get_schema_manager()))
+            {
                 Ok(_) => println!("✅ Schema file {} is valid", file),
                 Err(e) => {
                     println!("❌ Schema file {} is invalid: {}", file, e);
@@ -280,12 +741,16 @@ async fn handle_schema_command(
                 }
             }
         }
-        SchemaActions::Sync { collection, samples } => {
+        SchemaActions::Sync { collection, samples, .. } => {
             println!("🔍 Discovering schema for collection '{}'...", collection);
             match json_manager.discover_and_save_schema(&collection, samples).await {
                 Ok(schema) => {
                     println!("✅ Schema discovered and saved to Firestore");
                     println!("📊 Collection: {}", schema.collection_name);
+                    match (&schema.previous_version, schema.bump) {
+                        (Some(previous), Some(bump)) => println!("📝 Version: {} ({} bump from {})", schema.version, bump, previous),
+                        _ => println!("📝 Version: {}", schema.version),
+                    }
                     println!("📝 Fields found: {}", schema.fields.len());
                     println!("📄 Documents analyzed: {}", schema.total_documents);
                     println!("📅 Last updated: {}", schema.last_updated);
@@ -296,6 +761,51 @@ async fn handle_schema_command(
                 }
             }
         }
+        SchemaActions::Indexes { schema, queries, output, reconcile, apply } => {
+            if schema.is_none() && queries.is_none() {
+                return Err(FirebaseError::ConfigError("Pass --schema, --queries or both".to_string()));
+            }
+            if let Some(schema) = &schema {
+                json_manager.import_schema_from_file(schema)?;
+            }
+            let saved = match &queries {
+                Some(queries) => load_saved_queries(queries)?,
+                None => Vec::new(),
+            };
+            for saved_query in &saved {
+                saved_query.query.validate()
+                    .map_err(|e| FirebaseError::ValidationError(format!("Saved query '{}': {}", saved_query.name, e)))?;
+            }
+            
+            let definitions = json_manager.get_schema_manager()
+                .index_definitions(saved.iter().map(|saved_query| &saved_query.query));
+            definitions.save(&output)?;
+            display_index_definitions(&definitions);
+            println!("✅ Wrote {} composite indexes and {} field overrides to {}",
+                definitions.indexes.len(), definitions.field_overrides.len(), output);
+            if !(reconcile || apply) {
+                println!("🚀 Deploy with: firebase deploy --only firestore:indexes");
+                return Ok(());
+            }
+
+            // Only collection groups this run knows about are reconciled
+            let mut groups: BTreeSet<String> = definitions.indexes.iter()
+                .map(|index| index.collection_group.clone())
+                .collect();
+            groups.extend(json_manager.get_schemas().into_iter().map(|collection| collection.name));
+            let reconciler = IndexReconciler::new(json_manager.get_client());
+            let plan = reconciler.plan(&definitions, &groups).await?;
+            display_index_plan(&plan);
+            if plan.is_empty() {
+                println!("✅ Indexes are up to date");
+            } else if apply {
+                println!("⏳ Applying {} changes and waiting for index builds...", plan.create.len() + plan.delete.len());
+                reconciler.apply(&plan).await?;
+                println!("✅ Created {} and deleted {} indexes", plan.create.len(), plan.delete.len());
+            } else {
+                println!("💡 Dry run; pass --apply to make these changes");
+            }
+        }
         SchemaActions::List => {
             println!("📋 Listing all schemas stored in Firestore...");
             match json_manager.list_firestore_schemas().await {
@@ -321,14 +831,27 @@ async fn handle_schema_command(
                 }
             }
         }
-        SchemaActions::Show { collection } => {
+        SchemaActions::Show { collection, version } => {
             println!("📖 Loading schema for collection '{}'...", collection);
-            match json_manager.load_schema_from_firestore(&collection).await {
+            let loaded = match &version {
+                Some(version) => json_manager.load_schema_version(&collection, version).await,
+                None => json_manager.load_schema_from_firestore(&collection).await,
+            };
+            match loaded {
                 Ok(Some(schema)) => {
                     println!("✅ Schema found for collection '{}'", collection);
                     println!("📊 Collection: {}", schema.collection_name);
                     println!("📝 Version: {}", schema.version);
+                    if let Some(previous) = &schema.previous_version {
+                        println!("⏮️  Previous version: {}", previous);
+                    }
+                    if let Some(restored) = &schema.restored_from {
+                        println!("↩️  Restored from: {}", restored);
+                    }
                     println!("📅 Last updated: {}", schema.last_updated);
+                    if let Some(author) = &schema.author {
+                        println!("👤 Author: {}", author);
+                    }
                     println!("📄 Total documents: {}", schema.total_documents);
                     println!("🔧 Discovery source: {}", schema.discovery_source);
                     if let Some(desc) = &schema.description {
@@ -385,9 +908,15 @@ async fn handle_schema_command(
                         }
                     }
                 },
-                Ok(None) => {
-                    println!("❌ No schema found for collection '{}'", collection);
-                    println!("💡 Use 'schema sync -c {}' to discover and save the schema", collection);
+                Ok(None) => match &version {
+                    Some(version) => {
+                        println!("❌ No version {} of the schema for collection '{}'", version, collection);
+                        println!("💡 Use 'schema history -c {}' to list the saved versions", collection);
+                    }
+                    None => {
+                        println!("❌ No schema found for collection '{}'", collection);
+                        println!("💡 Use 'schema sync -c {}' to discover and save the schema", collection);
+                    }
                 },
                 Err(e) => {
                     println!("❌ Failed to load schema: {}", e);
@@ -395,19 +924,123 @@ async fn handle_schema_command(
                 }
             }
         }
+        SchemaActions::History { collection, format } => {
+            let history = json_manager.schema_history(&collection).await?;
+            match format.to_lowercase().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&history)?),
+                "text" => {
+                    let current = json_manager.load_schema_from_firestore(&collection).await?;
+                    display_schema_history(&collection, &history, current.as_ref().map(|schema| schema.version.as_str()));
+                }
+                _ => return Err(FirebaseError::ConfigError(format!("Unsupported format '{}'. Use: text or json", format))),
+            }
+        }
+        SchemaActions::Rollback { collection, to, .. } => {
+            println!("⏪ Rolling back schema for collection '{}' to version {}...", collection, to);
+            let schema = json_manager.rollback_schema(&collection, &to).await?;
+            println!("✅ Saved version {} with the content of {}", schema.version, to);
+            if let (Some(previous), Some(bump)) = (&schema.previous_version, schema.bump) {
+                println!("📝 {} bump from {}", bump, previous);
+            }
+        }
+        SchemaActions::Diff { schemas, live, samples, format } => {
+            let mut sides = Vec::new();
+            for spec in &schemas {
+                sides.push(load_diff_side(json_manager, spec).await?);
+            }
+            if let Some(collection) = &live {
+                let schema = json_manager.discover_schema(collection, samples).await?;
+                sides.push((format!("live:{}", collection), vec![schema.to_json_collection()], true));
+            }
+            let [(old_label, mut old, old_single), (new_label, mut new, new_single)]: [_; 2] = sides.try_into()
+                .map_err(|_| FirebaseError::ConfigError("Give two schemas, or one schema and --live <collection>".to_string()))?;
+
+            // Against a single stored or live collection, only that collection of a schema file matters
+            if old_single && !new_single {
+                new.retain(|collection| old.iter().any(|other| other.name == collection.name));
+            } else if new_single && !old_single {
+                old.retain(|collection| new.iter().any(|other| other.name == collection.name));
+            }
+            let diff = SchemaDiff::between(&old, &new);
+
+            match format.to_lowercase().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&diff)?),
+                "text" => display_schema_diff(&old_label, &new_label, &diff),
+                _ => return Err(FirebaseError::ConfigError(format!("Unsupported format '{}'. Use: text or json", format))),
+            }
+        }
+        SchemaActions::Drift { collection, samples, frequency_threshold, fail_on, format } => {
+            let fail_on = match fail_on.to_lowercase().as_str() {
+                "breaking" => Some(Severity::Breaking),
+                "non-breaking" | "any" => Some(Severity::NonBreaking),
+                "none" => None,
+                _ => return Err(FirebaseError::ConfigError(format!("Unsupported --fail-on '{}'. Use: breaking, non-breaking or none", fail_on))),
+            };
+            let json = match format.to_lowercase().as_str() {
+                "json" => true,
+                "text" => false,
+                _ => return Err(FirebaseError::ConfigError(format!("Unsupported format '{}'. Use: text or json", format))),
+            };
+            let collections = if collection.is_empty() {
+                json_manager.list_firestore_schemas().await?.into_iter().map(|schema| schema.collection_name).collect()
+            } else {
+                collection
+            };
+
+            let mut reports = Vec::new();
+            for collection in &collections {
+                let report = json_manager.check_drift(collection, samples, frequency_threshold).await?;
+                if !json {
+                    display_drift_report(&report);
+                }
+                reports.push(report);
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&reports)?);
+            } else if reports.is_empty() {
+                println!("No stored schemas to check; use 'schema sync' to save one");
+            }
+
+            if let Some(threshold) = fail_on {
+                let failed: Vec<&str> = reports.iter()
+                    .filter(|report| report.exceeds(threshold))
+                    .map(|report| report.collection.as_str())
+                    .collect();
+                if !failed.is_empty() {
+                    return Err(FirebaseError::ValidationError(format!(
+                        "{} schema drift in: {}", threshold, failed.join(", ")
+                    )));
+                }
+            }
+        }
     }
     Ok(())
 }
 
-async fn handle_data_command(
-    json_manager: &JsonSchemaManager,
-    collection_manager: &CollectionManager,
+/// One side of `schema diff`: its label, its collections and whether it is a
+/// single collection from Firestore rather than a schema file.
+async fn load_diff_side<S: DocumentStore + Clone>(
+    json_manager: &JsonSchemaManager<S>,
+    spec: &str,
+) -> Result<(String, Vec<JsonCollection>, bool), FirebaseError> {
+    match spec.strip_prefix("stored:") {
+        Some(collection) => match json_manager.load_schema_from_firestore(collection).await? {
+            Some(schema) => Ok((spec.to_string(), vec![schema.to_json_collection()], true)),
+            None => Err(FirebaseError::NotFound(format!("No stored schema for collection '{}'", collection))),
+        },
+        None => Ok((spec.to_string(), read_schema_file(spec)?, false)),
+    }
+}
+
+async fn handle_data_command<S: DocumentStore + Clone + 'static>(
+    json_manager: &JsonSchemaManager<S>,
+    collection_manager: &CollectionManager<S>,
     action: DataActions
 ) -> Result<(), FirebaseError> {
     let client = json_manager.get_client();
     
     match action {
-        DataActions::Create { collection, id, json, interactive, fields } => {
+        DataActions::Create { collection, id, json, interactive, strict, fields } => {
             // Check if user is asking for help
             if fields.len() == 1 && fields[0].to_lowercase() == "help" {
                 // Show collection-specific help
@@ -435,7 +1068,12 @@ async fn handle_data_command(
                     }
                 };
                 
-                let mut form = TuiForm::from_schema(&collection, &schema);
+                let rules = json_manager.stored_validation_rules(&collection).await.unwrap_or_else(|e| {
+                    tracing::warn!(collection, error = %e, "Could not load stored validation rules; the form won't check them");
+                    Vec::new()
+                });
+                let mut form = TuiForm::from_schema(&collection, &schema)
+                    .with_custom_rules(rules, json_manager.get_schema_manager().validators().clone());
                 println!("🖥️ Opening interactive form for document creation...");
                 
                 match form.run()? {
@@ -450,34 +1088,26 @@ async fn handle_data_command(
                 add_timestamps_to_document(data)
             };
             
-            // Validate against stored schema if available
-            println!("🔍 Validating against stored schema...");
-            match json_manager.validate_against_schema(&collection, &data).await {
-                Ok(errors) => {
-                    if !errors.is_empty() {
-                        println!("⚠️ Validation warnings:");
-                        for error in &errors {
-                            println!("  • {}", error);
-                        }
-                        println!("📝 Proceeding with creation despite warnings...");
-                    } else {
-                        println!("✅ Data validates against schema");
-                    }
-                },
-                Err(_) => {
-                    // Schema validation failed (e.g., no schema found) - continue anyway
+            let fields = json_to_firestore_fields(data)?;
+            println!("🔄 Creating document in collection '{}'...", collection);
+            let document = match validated_client(json_manager, &collection, strict).await? {
+                Some(validated) => {
+                    println!("🔍 Validating against stored schema...");
+                    validated.insert(&collection, id.as_deref(), fields).await
+                        .inspect_err(display_violations)?
+                }
+                None => {
                     println!("💡 No schema found for validation, proceeding...");
+                    client.insert(&collection, id.as_deref(), fields).await?
                 }
-            }
-            
-            println!("🔄 Creating document in collection '{}'...", collection);
-            let doc_id = client.create_document(&collection, id, data).await?;
-            println!("✅ Document created with ID: {}", doc_id);
+            };
+            println!("✅ Document created with ID: {}", document.id());
         }
         
-        DataActions::Read { collection, id, format } => {
+        DataActions::Read { collection, id, format, as_of } => {
             println!("🔍 Reading document '{}' from collection '{}'...", id, collection);
-            let data = client.get_document(&collection, &id).await?;
+            let options = ReadOptions { read_time: as_of, ..ReadOptions::default() };
+            let data = get_document_json(client, &collection, &id, &options).await?;
             
             match format.to_lowercase().as_str() {
                 "json" => {
@@ -496,29 +1126,48 @@ async fn handle_data_command(
             }
         }
         
-        DataActions::Update { collection, id, json, interactive, replace } => {
-            let data = if interactive || json.is_none() {
-                // Get existing document for the form
-                let existing_data = client.get_document(&collection, &id).await?;
-                
-                let mut form = TuiForm::from_existing_data(&collection, &id, &existing_data);
-                println!("🖥️ Opening interactive form for document update...");
-                
-                match form.run()? {
-                    Some(data) => add_updated_timestamp(data),
-                    None => {
-                        println!("❌ Document update cancelled");
-                        return Ok(());
+        DataActions::Update { collection, id, json, interactive, replace, strict } => {
+            let data = match json {
+                Some(json) if !interactive => {
+                    let data = parse_json_or_yaml(&json)?;
+                    add_updated_timestamp(data)
+                }
+                _ => {
+                    // Get existing document for the form
+                    let existing_data = get_document_json(client, &collection, &id, &ReadOptions::default()).await?;
+                    
+                    let rules = json_manager.stored_validation_rules(&collection).await.unwrap_or_else(|e| {
+                        tracing::warn!(collection, error = %e, "Could not load stored validation rules; the form won't check them");
+                        Vec::new()
+                    });
+                    let mut form = TuiForm::from_existing_data(&collection, &id, &existing_data)
+                        .with_custom_rules(rules, json_manager.get_schema_manager().validators().clone());
+                    println!("🖥️ Opening interactive form for document update...");
+                    
+                    match form.run()? {
+                        Some(data) => add_updated_timestamp(data),
+                        None => {
+                            println!("❌ Document update cancelled");
+                            return Ok(());
+                        }
                     }
                 }
-            } else {
-                let data = parse_json_or_yaml(&json.unwrap())?;
-                add_updated_timestamp(data)
             };
             
             let merge_mode = !replace;
             println!("🔄 Updating document '{}' in collection '{}' (merge: {})...", id, collection, merge_mode);
-            client.update_document(&collection, &id, data, merge_mode).await?;
+            let fields = json_to_firestore_fields(data)?;
+            let mask: Option<Vec<String>> = merge_mode.then(|| fields.keys().cloned().collect());
+            let path = format!("{}/{}", collection, id);
+            match validated_client(json_manager, &collection, strict).await? {
+                Some(validated) => {
+                    validated.patch(&path, fields, mask.as_deref()).await
+                        .inspect_err(display_violations)?;
+                }
+                None => {
+                    client.patch(&path, fields, mask.as_deref()).await?;
+                }
+            }
             println!("✅ Document updated successfully");
         }
         
@@ -534,37 +1183,116 @@ async fn handle_data_command(
             }
             
             println!("🗑️ Deleting document '{}' from collection '{}'...", id, collection);
-            client.delete_document(&collection, &id).await?;
+            client.remove(&format!("{}/{}", collection, id)).await?;
             println!("✅ Document deleted successfully");
         }
         
-        DataActions::Export { collection, output } => {
+        DataActions::Export { collection, output, .. } => {
             println!("🔄 Exporting data from collection '{}'...", collection);
             let count = json_manager.export_collection_raw(&collection, &output).await?;
             println!("✅ Exported {} items from '{}' to {}", count, collection, output);
         }
-        DataActions::Import { input, collection } => {
+        DataActions::Import { input, collection, report, .. } => {
             if !Path::new(&input).exists() {
                 return Err(FirebaseError::ConfigError(format!("File not found: {}", input)));
             }
             println!("🔄 Importing data from {}...", input);
-            let count = json_manager.import_collection_data::<User>(&input, collection.as_deref()).await?;
-            println!("✅ Imported {} items", count);
+            let result = json_manager.import_collection_data::<User>(&input, collection.as_deref()).await?;
+            finish_bulk_write("Imported", &result, report.as_deref())?;
+        }
+        DataActions::Restore { directory, report, .. } => {
+            println!("🔄 Restoring backup from '{}'...", directory);
+            let result = json_manager.restore_backup(&directory).await?;
+            finish_bulk_write("Restored", &result, report.as_deref())?;
         }
-        DataActions::Backup { directory } => {
+        DataActions::Backup { directory, dry_run: true, .. } => {
+            let estimate = json_manager.estimate_backup().await?;
+            display_cost_estimate(&format!("Backup to '{}'", directory), &estimate);
+        }
+        DataActions::Backup { directory, dry_run: false, .. } => {
             println!("🔄 Creating backup in directory '{}'...", directory);
-            let results = json_manager.backup_all_data(&directory).await?;
-            let total: usize = results.values().sum();
-            println!("✅ Backup completed! Total items backed up: {}", total);
-            for (collection, count) in results {
+            let manifest = json_manager.backup_all_data(&directory).await?;
+            println!("✅ Backup completed! Total items backed up: {}", manifest.total());
+            println!("🕒 Read as of {}", manifest.read_time);
+            for (collection, count) in manifest.collections {
                 println!("  - {}: {} items", collection, count);
             }
         }
-        DataActions::List { collection, limit, format } => {
+        DataActions::Query { collection, filters, order_by, limit, nearest, vector_file, distance, distance_field, explain, format } => {
+            let mut query = collection_query(&collection, &filters)?;
+            for order in &order_by {
+                let (field, descending) = match order.rsplit_once(':') {
+                    Some((field, direction)) if direction.eq_ignore_ascii_case("desc") => (field, true),
+                    Some((field, direction)) if direction.eq_ignore_ascii_case("asc") => (field, false),
+                    _ => (order.as_str(), false),
+                };
+                query = query.order_by(field, descending);
+            }
+            if let (Some(field), Some(file)) = (&nearest, &vector_file) {
+                let measure = match distance.to_lowercase().as_str() {
+                    "cosine" => DistanceMeasure::Cosine,
+                    "euclidean" => DistanceMeasure::Euclidean,
+                    "dot-product" | "dot_product" => DistanceMeasure::DotProduct,
+                    other => return Err(FirebaseError::ValidationError(format!(
+                        "Unknown distance measure '{}'. Use cosine, euclidean or dot-product", other
+                    ))),
+                };
+                query = query.find_nearest(field, read_vector_file(file)?, limit.unwrap_or(10), measure);
+                if let Some(distance_field) = &distance_field {
+                    query = query.distance_result_field(distance_field);
+                }
+            } else if let Some(limit) = limit {
+                query = query.limit(limit);
+            }
+
+            let parent = collection.trim_matches('/').rsplit_once('/').map_or("", |(parent, _)| parent);
+            println!("🔍 Querying collection '{}'...", collection);
+            let (documents, metrics) = if explain {
+                let explained = client.explain_query(parent, &query.build(), &ReadOptions::default(), &ExplainOptions::analyze()).await?;
+                (explained.documents, Some(explained.metrics))
+            } else {
+                (client.run_query(parent, &query.build(), &ReadOptions::default()).await?, None)
+            };
+            let documents: Vec<(String, serde_json::Value)> = documents.iter()
+                .map(|document| (document.id().to_string(), document.to_json()))
+                .collect();
+            match format.to_lowercase().as_str() {
+                "json" => {
+                    let results: Vec<serde_json::Value> = documents.iter()
+                        .map(|(id, data)| serde_json::json!({ "id": id, "data": data }))
+                        .collect();
+                    match &metrics {
+                        Some(metrics) => println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                            "results": results,
+                            "explainMetrics": metrics,
+                        }))?),
+                        None => println!("{}", serde_json::to_string_pretty(&results)?),
+                    }
+                }
+                _ if documents.is_empty() => println!("  No documents found."),
+                "table" => display_documents_table(&collection, &documents),
+                "text" => {
+                    for (i, (doc_id, data)) in documents.iter().enumerate() {
+                        println!("{}. {} - {} fields", i + 1, doc_id, count_fields(data));
+                    }
+                }
+                _ => return Err(FirebaseError::ValidationError(format!("Unsupported format: {}", format))),
+            }
+            if let Some(metrics) = &metrics
+                && !format.eq_ignore_ascii_case("json")
+            {
+                display_explain_metrics(metrics);
+            }
+        }
+        DataActions::Watch { collection, filters, interval, resume_token, format } => {
+            watch_collection(client, &collection, &filters, interval, resume_token, &format).await?;
+        }
+        DataActions::List { collection, limit, format, as_of } => {
             println!("📋 Listing documents from collection '{}':", collection);
             
             // Use generic document listing instead of User-specific
-            let documents = list_collection_documents(&client, &collection, limit).await?;
+            let options = ReadOptions { read_time: as_of, ..ReadOptions::default() };
+            let documents = list_collection_documents(client, &collection, limit, &options).await?;
             
             if documents.is_empty() {
                 println!("  No documents found.");
@@ -604,8 +1332,8 @@ async fn handle_data_command(
     Ok(())
 }
 
-async fn handle_collections_command(
-    collection_manager: &CollectionManager,
+async fn handle_collections_command<S: DocumentStore + Clone + 'static>(
+    collection_manager: &CollectionManager<S>,
     action: CollectionActions
 ) -> Result<(), FirebaseError> {
     match action {
@@ -628,10 +1356,23 @@ async fn handle_collections_command(
             
             println!("\n📈 Summary: Found {} collection(s)", collections.len());
         }
-        CollectionActions::Describe { collection, sample, format } => {
+        CollectionActions::Describe { collection, sample, dry_run: true, full, .. } => {
+            let estimate = if full {
+                collection_manager.estimate_describe_full(&collection).await?
+            } else {
+                collection_manager.estimate_describe(&collection, sample).await?
+            };
+            display_cost_estimate(&format!("Describing '{}'", collection), &estimate);
+        }
+        CollectionActions::Describe { collection, sample, format, dry_run: false, full, parallel } => {
             println!("🔍 Analyzing collection '{}'...", collection);
             
-            match collection_manager.describe_collection(&collection, sample).await {
+            let schema = if full {
+                collection_manager.describe_collection_full(&collection, parallel).await
+            } else {
+                collection_manager.describe_collection(&collection, sample).await
+            };
+            match schema {
                 Ok(schema) => {
                     let use_table = format.to_lowercase() == "table";
                     let output = collection_manager.format_schema_table(&schema, use_table);
@@ -673,107 +1414,608 @@ async fn handle_collections_command(
     Ok(())
 }
 
+/// Prints changes to a collection until Ctrl-C, then the token to resume from.
+async fn watch_collection<S: DocumentStore + Clone + 'static>(
+    client: &S,
+    collection: &str,
+    filters: &[String],
+    interval: f64,
+    resume_token: Option<String>,
+    format: &str,
+) -> Result<(), FirebaseError> {
+    if !matches!(format, "text" | "json") {
+        return Err(FirebaseError::ValidationError(format!("Unsupported format: {}", format)));
+    }
+    if !interval.is_finite() || interval <= 0.0 {
+        return Err(FirebaseError::ConfigError("--interval must be greater than 0".to_string()));
+    }
+
+    let query = collection_query(collection, filters)?;
+    let parent = collection.trim_matches('/').rsplit_once('/').map_or("", |(parent, _)| parent);
+
+    let mut options = ListenOptions::default().poll_interval(Duration::from_secs_f64(interval));
+    if let Some(token) = resume_token {
+        options = options.resume_token(token);
+    }
+    let mut listener = listen(client.clone(), ListenTarget::query(parent, query.build()), options);
+    eprintln!("👀 Watching '{}' for changes (Ctrl-C to stop)...", collection);
+
+    let mut last_token = None;
+    loop {
+        let event = tokio::select! {
+            event = listener.next() => event,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let Some(event) = event else { break };
+        let event = event?;
+        if format == "json" {
+            println!("{}", serde_json::to_string(&event)?);
+        } else {
+            for change in &event.changes {
+                let (symbol, verb) = match change.kind {
+                    ChangeKind::Added => ("➕", "added"),
+                    ChangeKind::Modified => ("✏️", "modified"),
+                    ChangeKind::Removed => ("➖", "removed"),
+                };
+                match &change.document {
+                    Some(document) => println!("{} [{}] {} {}: {}", symbol, event.read_time, verb, change.id(), document.to_json()),
+                    None => println!("{} [{}] {} {}", symbol, event.read_time, verb, change.id()),
+                }
+            }
+        }
+        last_token = Some(event.resume_token);
+    }
+
+    if let Some(token) = last_token {
+        eprintln!("🔖 Resume with --resume-token {}", token);
+    }
+    Ok(())
+}
+
+/// A query on a (possibly nested) collection, with `--where` filters ANDed together.
+fn collection_query(collection: &str, filters: &[String]) -> Result<QueryBuilder, FirebaseError> {
+    let filters = filters.iter().map(|filter| parse_where(filter)).collect::<Result<Vec<_>, _>>()?;
+    let collection_id = collection.trim_matches('/').rsplit('/').next().unwrap_or(collection);
+    let mut query = QueryBuilder::new(collection_id);
+    if !filters.is_empty() {
+        query = query.and(filters);
+    }
+    Ok(query)
+}
+
+/// Reads a query vector from a JSON array, or from numbers separated by commas or whitespace.
+fn read_vector_file(path: &str) -> Result<Vec<f64>, FirebaseError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| FirebaseError::ConfigError(format!("Failed to read vector file {}: {}", path, e)))?;
+    let vector: Vec<f64> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(&content)?
+    } else {
+        content.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse::<f64>()
+                .map_err(|e| FirebaseError::ValidationError(format!("Invalid number '{}' in {}: {}", part, path, e))))
+            .collect::<Result<_, _>>()?
+    };
+    if vector.is_empty() {
+        return Err(FirebaseError::ValidationError(format!("Vector file {} is empty", path)));
+    }
+    Ok(vector)
+}
+
+/// Parses a filter like `age>=30`, `status=active` or `name!=bob`.
+fn parse_where(expression: &str) -> Result<Filter, FirebaseError> {
+    const OPERATORS: &[(&str, FieldOperator)] = &[
+        (">=", FieldOperator::GreaterThanOrEqual),
+        ("<=", FieldOperator::LessThanOrEqual),
+        ("!=", FieldOperator::NotEqual),
+        ("==", FieldOperator::Equal),
+        (">", FieldOperator::GreaterThan),
+        ("<", FieldOperator::LessThan),
+        ("=", FieldOperator::Equal),
+    ];
+    let invalid = || FirebaseError::ValidationError(
+        format!("Invalid filter '{}'. Use field<op>value with one of =, !=, <, <=, >, >=", expression)
+    );
+
+    let position = expression.find(['<', '>', '!', '=']).ok_or_else(invalid)?;
+    let (field, rest) = expression.split_at(position);
+    let (symbol, op) = OPERATORS.iter().find(|(symbol, _)| rest.starts_with(symbol)).ok_or_else(invalid)?;
+    let field = field.trim();
+    if field.is_empty() {
+        return Err(invalid());
+    }
+    let value = parse_field_value_with_inference(&rest[symbol.len()..])?;
+    Ok(create_filter(field, *op, json_value_to_firestore(value)))
+}
+
 // Helper functions for document display and listing
-async fn list_collection_documents(
-    client: &FirebaseClient, 
+async fn get_document_json<S: DocumentStore>(
+    client: &S,
+    collection: &str,
+    id: &str,
+    options: &ReadOptions,
+) -> Result<serde_json::Value, FirebaseError> {
+    client.fetch(&format!("{}/{}", collection, id), options).await?
+        .map(|document| document.to_json())
+        .ok_or_else(|| FirebaseError::NotFound(format!("Document '{}' not found in collection '{}'", id, collection)))
+}
+
+async fn list_collection_documents<S: DocumentStore>(
+    client: &S, 
     collection: &str, 
-    limit: Option<usize>
+    limit: Option<usize>,
+    options: &ReadOptions,
 ) -> Result<Vec<(String, serde_json::Value)>, FirebaseError> {
-    // This is a simplified version - ideally we'd implement a generic list method
-    // For now, we'll make a request to list documents directly
-    let url = format!("{}?key={}", 
-        format!("{}/{}", client.base_url, collection.trim_start_matches('/')), 
-        client.api_key);
+    let list = ListOptions { page_size: limit, page_token: None };
+    let page = client.list_page(collection, &list, options).await?;
     
-    let response = client.client
-        .get(&url)
-        .send()
-        .await?;
+    Ok(page.documents.iter()
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|doc| (doc.id().to_string(), doc.to_json()))
+        .collect())
+}
+
+/// Prints a bulk write summary, saves the report if asked, and fails the
+/// command if any document could not be written.
+fn finish_bulk_write(verb: &str, report: &BulkWriteReport, report_file: Option<&str>) -> Result<(), FirebaseError> {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(FirebaseError::DatabaseError(format!("List failed: {}", error_text)));
+    println!("✅ {} {} of {} documents ({} after retries)", verb, report.succeeded, report.total(), report.retried);
+    if let Some(path) = report_file {
+        std::fs::write(path, serde_json::to_string_pretty(report)?)?;
+        println!("📝 Report written to {}", path);
+    }
+    if report.is_success() {
+        return Ok(());
     }
     
-    #[derive(serde::Deserialize)]
-    struct ListResponse {
-        documents: Option<Vec<DocumentResponse>>,
+    const SHOWN: usize = 20;
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("#").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Document").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Operation").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Attempts").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Error").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for failure in report.failures.iter().take(SHOWN) {
+        table.add_row(vec![
+            Cell::new(failure.index),
+            Cell::new(&failure.path).fg(Color::Yellow),
+            Cell::new(&failure.operation),
+            Cell::new(failure.attempts),
+            Cell::new(&failure.error).fg(Color::Red),
+        ]);
+    }
+    println!("❌ {} documents failed:", report.failures.len());
+    println!("{}", table);
+    if report.failures.len() > SHOWN && report_file.is_none() {
+        println!("   ... and {} more; use --report to save them all", report.failures.len() - SHOWN);
+    }
+    Err(FirebaseError::DatabaseError(format!("{} of {} documents failed", report.failures.len(), report.total())))
+}
+
+async fn handle_migrate_command<S: DocumentStore + Clone + 'static>(
+    client: S,
+    dir: &str,
+    action: MigrateActions,
+) -> Result<(), FirebaseError> {
+    let mut manager = MigrationManager::new(client);
+    for file in load_migrations(dir)? {
+        manager.add_migration(file.into_migration());
+    }
+
+    let runs = match action {
+        MigrateActions::Status => {
+            let statuses = manager.status().await?;
+            if statuses.is_empty() {
+                println!("No migrations found in {}", dir);
+            } else {
+                display_migration_status(&statuses);
+            }
+            return Ok(());
+        }
+        MigrateActions::Unlock => {
+            if manager.force_unlock().await? {
+                println!("🔓 Removed the migration lock");
+            } else {
+                println!("No migration lock is held");
+            }
+            return Ok(());
+        }
+        MigrateActions::Up { to, dry_run } => manager.up(to.as_deref(), dry_run).await?,
+        MigrateActions::Down { steps, dry_run } => manager.down(steps, dry_run).await?,
+        MigrateActions::Redo { dry_run } => manager.redo(dry_run).await?,
+    };
+    if runs.is_empty() {
+        println!("✅ Nothing to migrate");
+    }
+    for run in &runs {
+        display_migration_run(run);
+    }
+    Ok(())
+}
+
+/// A client enforcing the collection's stored schema, or `None` if it has
+/// none. Fails if the schema can't be loaded rather than writing unvalidated.
+async fn validated_client<S: DocumentStore + Clone>(
+    json_manager: &JsonSchemaManager<S>,
+    collection: &str,
+    strict: bool,
+) -> Result<Option<ValidatedClient<S>>, FirebaseError> {
+    let schema = match json_manager.stored_collection_schema(collection).await {
+        Ok(Some(schema)) => schema,
+        Ok(None) => return Ok(None),
+        Err(e @ FirebaseError::BudgetExceeded(_)) => return Err(e),
+        Err(e) => {
+            return Err(FirebaseError::ConfigError(format!(
+                "Could not load the stored schema for {}, so nothing was written: {}", collection, e
+            )));
+        }
+    };
+    let mut schemas = json_manager.get_schema_manager().clone();
+    schemas.define_collection(schema);
+    Ok(Some(ValidatedClient::new(schemas).strict(strict)))
+}
+
+fn display_violations(error: &FirebaseError) {
+    if let FirebaseError::SchemaViolation(report) = error {
+        println!("❌ Document does not match the schema:");
+        for violation in &report.violations {
+            println!("  • {} [{}]", violation.message, violation.rule);
+        }
     }
+}
+
+/// Fails if a `custom` rule names a validator the CLI doesn't have.
+fn check_custom_rules<S: DocumentStore>(schema_manager: &SchemaManager<S>) -> Result<(), FirebaseError> {
+    let unresolved: Vec<String> = schema_manager.unresolved_custom_rules().into_iter()
+        .filter_map(|(collection, rule)| match &rule.rule {
+            ValidationRuleType::Custom(name) => Some(format!("'{}' on {}.{}", name, collection, rule.field)),
+            _ => None,
+        })
+        .collect();
+    if unresolved.is_empty() {
+        return Ok(());
+    }
+    let available: Vec<&str> = schema_manager.validators().names().collect();
+    Err(FirebaseError::ConfigError(format!(
+        "Unknown custom validators: {}. Available: {}", unresolved.join(", "), available.join(", ")
+    )))
+}
+
+fn display_index_definitions(definitions: &IndexDefinitions) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
-    #[derive(serde::Deserialize)]
-    struct DocumentResponse {
-        name: String,
-        fields: std::collections::HashMap<String, FirestoreValue>,
+    if definitions.indexes.is_empty() && definitions.field_overrides.is_empty() {
+        println!("  No composite indexes needed; automatic single-field indexes cover these queries.");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Collection group").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Scope").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Fields").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for index in &definitions.indexes {
+        table.add_row(vec![
+            Cell::new(&index.collection_group).fg(Color::Yellow),
+            Cell::new(format!("{:?}", index.query_scope)),
+            Cell::new(index.describe()),
+        ]);
+    }
+    for field_override in &definitions.field_overrides {
+        table.add_row(vec![
+            Cell::new(&field_override.collection_group).fg(Color::Yellow),
+            Cell::new("Field override"),
+            Cell::new(&field_override.field_path),
+        ]);
     }
+    println!("{}", table);
+}
+
+fn display_index_plan(plan: &IndexPlan) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
-    // Get the raw text first to debug JSON structure
-    let response_text = response.text().await?;
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Action").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Collection group").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Scope").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Fields").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for index in &plan.create {
+        table.add_row(vec![
+            Cell::new("create").fg(Color::Green),
+            Cell::new(&index.collection_group).fg(Color::Yellow),
+            Cell::new(format!("{:?}", index.query_scope)),
+            Cell::new(index.describe()),
+        ]);
+    }
+    for index in &plan.delete {
+        let definition = index.definition();
+        table.add_row(vec![
+            Cell::new("delete").fg(Color::Red),
+            Cell::new(&definition.collection_group).fg(Color::Yellow),
+            Cell::new(format!("{:?}", definition.query_scope)),
+            Cell::new(definition.describe()),
+        ]);
+    }
+    for index in &plan.unchanged {
+        let definition = index.definition();
+        table.add_row(vec![
+            Cell::new("keep"),
+            Cell::new(&definition.collection_group).fg(Color::Yellow),
+            Cell::new(format!("{:?}", definition.query_scope)),
+            Cell::new(definition.describe()),
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn display_schema_diff(old_label: &str, new_label: &str, diff: &SchemaDiff) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
-    // Try to parse the JSON and provide better error messages
-    let list_response: ListResponse = match serde_json::from_str(&response_text) {
-        Ok(response) => response,
-        Err(e) => {
-            eprintln!("JSON parsing error: {}", e);
-            eprintln!("Response JSON (first 1000 chars):");
-            eprintln!("{}", &response_text[..response_text.len().min(1000)]);
-            return Err(FirebaseError::DatabaseError(format!("JSON parsing failed: {}", e)));
+    println!("🔍 {} → {}", old_label, new_label);
+    if diff.is_empty() {
+        println!("✅ No differences");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Collection").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Change").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Severity").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for change in &diff.changes {
+        table.add_row(vec![
+            Cell::new(&change.collection).fg(Color::Yellow),
+            Cell::new(change.to_string()),
+            Cell::new(change.severity.to_string()).fg(if change.is_breaking() { Color::Red } else { Color::Green }),
+        ]);
+    }
+    println!("{}", table);
+    let breaking = diff.breaking().count();
+    if breaking > 0 {
+        println!("⚠️  {} change(s), {} breaking", diff.changes.len(), breaking);
+    } else {
+        println!("✅ {} change(s), none breaking", diff.changes.len());
+    }
+}
+
+fn display_schema_history(collection: &str, history: &[FirestoreSchema], current: Option<&str>) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
+    
+    if history.is_empty() {
+        println!("📭 No saved versions of the schema for collection '{}'", collection);
+        println!("💡 Use 'schema sync -c {}' to discover and save the schema", collection);
+        return;
+    }
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Version").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Bump").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Saved").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Author").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Source").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Fields").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for schema in history {
+        let version = if current == Some(schema.version.as_str()) {
+            format!("{} (current)", schema.version)
+        } else {
+            schema.version.clone()
+        };
+        let source = match &schema.restored_from {
+            Some(restored) => format!("{} of {}", schema.discovery_source, restored),
+            None => schema.discovery_source.clone(),
+        };
+        table.add_row(vec![
+            Cell::new(version).fg(Color::Yellow),
+            Cell::new(schema.bump.map(|bump| bump.to_string()).unwrap_or_else(|| "-".to_string()))
+                .fg(if schema.bump == Some(VersionBump::Major) { Color::Red } else { Color::Green }),
+            Cell::new(&schema.last_updated),
+            Cell::new(schema.author.as_deref().unwrap_or("-")),
+            Cell::new(source),
+            Cell::new(schema.fields.len()),
+        ]);
+    }
+    println!("🕘 Schema history for collection '{}':", collection);
+    println!("{}", table);
+}
+
+fn display_drift_report(report: &DriftReport) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
+    
+    println!("🔍 {} (schema v{}, {} documents sampled)", report.collection, report.schema_version, report.sampled);
+    if report.is_clean() {
+        println!("✅ No drift");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Field").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Drift").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Severity").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for finding in &report.findings {
+        table.add_row(vec![
+            Cell::new(&finding.field).fg(Color::Yellow),
+            Cell::new(finding.to_string()),
+            Cell::new(finding.severity.to_string()).fg(if finding.severity == Severity::Breaking { Color::Red } else { Color::Green }),
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn display_migration_status(statuses: &[MigrationStatus]) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
+    
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Version").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Description").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Status").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Applied at").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Documents").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for status in statuses {
+        let state = match (&status.applied_at, status.known) {
+            (Some(_), true) => Cell::new("applied").fg(Color::Green),
+            (Some(_), false) => Cell::new("applied (no file)").fg(Color::Red),
+            (None, _) => Cell::new("pending").fg(Color::Yellow),
+        };
+        table.add_row(vec![
+            Cell::new(&status.version).fg(Color::Yellow),
+            Cell::new(&status.description),
+            state,
+            Cell::new(status.applied_at.as_deref().unwrap_or("-")),
+            Cell::new(status.documents.map(|count| count.to_string()).unwrap_or_else(|| "-".to_string())),
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn display_migration_run(run: &MigrationRun) {
+    let verb = match (run.direction, run.dry_run) {
+        (MigrationDirection::Up, false) => "⬆️  Applied",
+        (MigrationDirection::Down, false) => "⬇️  Rolled back",
+        (MigrationDirection::Up, true) => "🔍 Would apply",
+        (MigrationDirection::Down, true) => "🔍 Would roll back",
+    };
+    println!("{} {} {} ({} document(s))", verb, run.version, run.description, run.affected.len());
+    if run.dry_run {
+        for document in &run.affected {
+            println!("  • {} {}", document.operation, document.path);
         }
+    }
+}
+
+fn display_explain_metrics(metrics: &ExplainMetrics) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement, CellAlignment};
+    
+    let mut plan = Table::new();
+    plan.set_content_arrangement(ContentArrangement::Dynamic);
+    plan.set_header(vec![
+        Cell::new("Scope").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Index").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for index in &metrics.plan_summary.indexes_used {
+        plan.add_row(vec![
+            Cell::new(&index.query_scope),
+            Cell::new(&index.properties).fg(Color::Yellow),
+        ]);
+    }
+    println!("\n📐 Query plan:");
+    println!("{}", plan);
+    
+    let Some(stats) = &metrics.execution_stats else {
+        return;
     };
-    let mut results = Vec::new();
+    let billing = &stats.debug_stats.billing_details;
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Statistic").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Value").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    let rows = [
+        ("Results returned", stats.results_returned.to_string()),
+        ("Execution time", stats.execution_duration.clone()),
+        ("Documents scanned", stats.debug_stats.documents_scanned.to_string()),
+        ("Index entries scanned", stats.debug_stats.index_entries_scanned.to_string()),
+        ("Read operations", stats.read_operations.to_string()),
+        ("Billable documents", billing.documents_billable.to_string()),
+        ("Billable index entries", billing.index_entries_billable.to_string()),
+        ("Small ops", billing.small_ops.to_string()),
+        ("Minimum query cost", billing.min_query_cost.to_string()),
+    ];
+    for (name, value) in rows {
+        table.add_row(vec![Cell::new(name), Cell::new(value).set_alignment(CellAlignment::Right)]);
+    }
+    println!("📈 Execution stats:");
+    println!("{}", table);
+}
+
+fn display_cost_estimate(operation: &str, estimate: &CostEstimate) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement, CellAlignment};
     
-    if let Some(documents) = list_response.documents {
-        for (i, doc) in documents.iter().enumerate() {
-            if let Some(limit_val) = limit {
-                if i >= limit_val {
-                    break;
-                }
-            }
-            
-            let doc_id = doc.name.split('/').last().unwrap_or("unknown").to_string();
-            
-            // Convert Firestore fields to JSON
-            let mut json_fields = serde_json::Map::new();
-            for (key, value) in &doc.fields {
-                if let Ok(json_value) = firestore_value_to_json_value(value) {
-                    json_fields.insert(key.clone(), json_value);
-                }
-            }
-            
-            results.push((doc_id, serde_json::Value::Object(json_fields)));
-        }
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Collection").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Documents").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Reads").add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    for collection in &estimate.collections {
+        table.add_row(vec![
+            Cell::new(&collection.collection).fg(Color::Yellow),
+            Cell::new(collection.documents).set_alignment(CellAlignment::Right),
+            Cell::new(collection.reads).set_alignment(CellAlignment::Right),
+        ]);
     }
+    table.add_row(vec![
+        Cell::new("Total").add_attribute(Attribute::Bold),
+        Cell::new(""),
+        Cell::new(estimate.reads()).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+    ]);
     
-    Ok(results)
+    println!("🧮 Dry run: {} would make about {} reads", operation, estimate.reads());
+    println!("{}", table);
+    println!("   (this estimate cost {} reads)", estimate.estimate_reads);
 }
 
-fn firestore_value_to_json_value(value: &FirestoreValue) -> Result<serde_json::Value, FirebaseError> {
-    match value {
-        FirestoreValue::StringValue(s) => Ok(serde_json::Value::String(s.clone())),
-        FirestoreValue::IntegerValue(i) => {
-            Ok(serde_json::Value::Number(serde_json::Number::from(i.parse::<i64>().unwrap_or(0))))
-        }
-        FirestoreValue::DoubleValue(f) => {
-            Ok(serde_json::Value::Number(serde_json::Number::from_f64(*f).unwrap_or(serde_json::Number::from(0))))
-        }
-        FirestoreValue::BooleanValue(b) => Ok(serde_json::Value::Bool(*b)),
-        FirestoreValue::NullValue(_) => Ok(serde_json::Value::Null),
-        FirestoreValue::TimestampValue(ts) => Ok(serde_json::Value::String(ts.clone())),
-        FirestoreValue::ArrayValue { values } => {
-            let mut json_array = Vec::new();
-            for val in values {
-                json_array.push(firestore_value_to_json_value(val)?);
-            }
-            Ok(serde_json::Value::Array(json_array))
-        }
-        FirestoreValue::MapValue { fields } => {
-            let mut json_map = serde_json::Map::new();
-            for (key, val) in fields {
-                json_map.insert(key.clone(), firestore_value_to_json_value(val)?);
-            }
-            Ok(serde_json::Value::Object(json_map))
-        }
-        FirestoreValue::Unknown => Ok(serde_json::Value::String("(unknown value type)".to_string())),
+/// Written to stderr so the command's own output can still be piped.
+fn display_cost_report(costs: &CostLedger, rates: &RateTable) {
+    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement, CellAlignment};
+    
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Call site").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Operation").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Collection").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Reads").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Index entries").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Writes").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new("Deletes").add_attribute(Attribute::Bold).fg(Color::Cyan),
+        Cell::new(format!("Cost ({})", rates.currency)).add_attribute(Attribute::Bold).fg(Color::Cyan),
+    ]);
+    
+    for entry in costs.by_call_site() {
+        table.add_row(vec![
+            Cell::new(&entry.call_site),
+            Cell::new(&entry.operation).fg(Color::Grey),
+            Cell::new(&entry.collection).fg(Color::Yellow),
+            Cell::new(entry.ops.reads).set_alignment(CellAlignment::Right),
+            Cell::new(entry.ops.index_entry_reads).set_alignment(CellAlignment::Right),
+            Cell::new(entry.ops.writes).set_alignment(CellAlignment::Right),
+            Cell::new(entry.ops.deletes).set_alignment(CellAlignment::Right),
+            Cell::new(format!("{:.8}", entry.ops.cost(rates))).set_alignment(CellAlignment::Right),
+        ]);
     }
+    
+    let totals = costs.totals();
+    table.add_row(vec![
+        Cell::new("Total").add_attribute(Attribute::Bold),
+        Cell::new(""),
+        Cell::new(""),
+        Cell::new(totals.reads).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+        Cell::new(totals.index_entry_reads).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+        Cell::new(totals.writes).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+        Cell::new(totals.deletes).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+        Cell::new(format!("{:.8}", totals.cost(rates))).add_attribute(Attribute::Bold).set_alignment(CellAlignment::Right),
+    ]);
+    
+    eprintln!("\n💰 Billable operations (free tier not applied)");
+    eprintln!("{}", table);
 }
 
 fn display_document_table(doc_id: &str, data: &serde_json::Value) {
@@ -820,26 +2062,6 @@ fn display_document_table(doc_id: &str, data: &serde_json::Value) {
     println!("{}", table);
 }
 
-fn display_document_yaml(doc_id: &str, data: &serde_json::Value) {
-    println!("\n📄 Document: {}", doc_id);
-    println!("---");
-    
-    if let serde_json::Value::Object(map) = data {
-        for (key, value) in map {
-            let value_str = match value {
-                serde_json::Value::String(s) => format!("\"{}\"", s),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Array(_) => "[array]".to_string(),
-                serde_json::Value::Object(_) => "{object}".to_string(),
-                serde_json::Value::Null => "null".to_string(),
-            };
-            println!("{}: {}", key, value_str);
-        }
-    }
-    println!("---");
-}
-
 fn display_documents_table(collection_name: &str, documents: &[(String, serde_json::Value)]) {
     use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
@@ -1035,22 +2257,21 @@ fn parse_field_value_with_inference(value_str: &str) -> Result<serde_json::Value
         return Ok(serde_json::Value::Number(serde_json::Number::from(int_val)));
     }
     
-    if let Ok(float_val) = trimmed.parse::<f64>() {
-        if let Some(num) = serde_json::Number::from_f64(float_val) {
+    if let Ok(float_val) = trimmed.parse::<f64>()
+        && let Some(num) = serde_json::Number::from_f64(float_val) {
             return Ok(serde_json::Value::Number(num));
         }
-    }
     
     // Handle timestamp patterns (ISO 8601-like)
     if trimmed.len() >= 19 && 
        (trimmed.contains('T') || trimmed.contains(' ')) &&
        (trimmed.contains('-') || trimmed.contains(':')) {
         // Try parsing as timestamp
-        if let Ok(_) = chrono::DateTime::parse_from_rfc3339(trimmed) {
+        if chrono::DateTime::parse_from_rfc3339(trimmed).is_ok() {
             return Ok(serde_json::Value::String(trimmed.to_string()));
         }
         // Try other common timestamp formats
-        if let Ok(_) = chrono::NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S") {
+        if chrono::NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S").is_ok() {
             return Ok(serde_json::Value::String(trimmed.to_string()));
         }
     }
@@ -1065,10 +2286,10 @@ fn parse_field_value_with_inference(value_str: &str) -> Result<serde_json::Value
 }
 
 // Show collection-specific help for create command
-async fn show_collection_help(
+async fn show_collection_help<S: DocumentStore + Clone>(
     collection_name: &str,
-    json_manager: &JsonSchemaManager,
-    collection_manager: &CollectionManager,
+    json_manager: &JsonSchemaManager<S>,
+    collection_manager: &CollectionManager<S>,
 ) -> Result<(), FirebaseError> {
     use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
     
@@ -1103,11 +2324,9 @@ async fn show_collection_help(
             let required_str = if field.required { "Yes ✓" } else { "No" };
             let default_str = field.default_value
                 .as_ref()
-                .map(|v| format_json_value_compact(v))
+                .map(format_json_value_compact)
                 .unwrap_or_else(|| "-".to_string());
-            let description = field.description
-                .as_ref()
-                .map(|d| d.clone())
+            let description = field.description.clone()
                 .unwrap_or_else(|| "-".to_string());
             
             table.add_row(vec![
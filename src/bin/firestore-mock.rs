@@ -0,0 +1,70 @@
+use firebase_db::{FaultConfig, FirebaseError, MockServer};
+use clap::Parser;
+use std::time::Duration;
+
+/// Serves an in-memory subset of the Firestore v1 REST API for local and CI testing.
+///
+/// Point clients at it with FIRESTORE_EMULATOR_HOST=<host>:<port>.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+    /// Port to listen on (0 picks a free port)
+    #[arg(short, long, default_value = "8080")]
+    port: u16,
+    /// Project ID used in document resource names
+    #[arg(long, default_value = "demo-project")]
+    project: String,
+    /// Load data from this JSON file on start and save every change back to it
+    #[arg(long)]
+    data_file: Option<String>,
+    /// Delay added to every response, in milliseconds
+    #[arg(long, default_value = "0")]
+    latency_ms: u64,
+    /// Additional random delay of up to this many milliseconds
+    #[arg(long, default_value = "0")]
+    jitter_ms: u64,
+    /// Fraction of requests (0.0-1.0) to fail with --error-status
+    #[arg(long, default_value = "0.0")]
+    error_rate: f64,
+    /// Fail the first N requests with --error-status
+    #[arg(long, default_value = "0")]
+    fail_first: usize,
+    /// HTTP status returned for injected failures (e.g. 429, 500, 503)
+    #[arg(long, default_value = "503")]
+    error_status: u16,
+    /// Only inject latency and failures into requests whose path contains this string
+    #[arg(long)]
+    fault_path: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), FirebaseError> {
+    let args = Args::parse();
+
+    if !(0.0..=1.0).contains(&args.error_rate) {
+        return Err(FirebaseError::ConfigError("--error-rate must be between 0.0 and 1.0".to_string()));
+    }
+
+    let mut server = MockServer::new(&args.project).faults(FaultConfig {
+        latency: Duration::from_millis(args.latency_ms),
+        jitter: Duration::from_millis(args.jitter_ms),
+        error_rate: args.error_rate,
+        fail_first: args.fail_first,
+        error_status: args.error_status,
+        path_filter: args.fault_path,
+    });
+    if let Some(data_file) = &args.data_file {
+        server = server.persist_to(data_file)?;
+        println!("💾 Persisting {} documents to {}", server.store().len(), data_file);
+    }
+
+    let listener = tokio::net::TcpListener::bind((args.host.as_str(), args.port)).await?;
+    let addr = listener.local_addr()?;
+    println!("🔥 firestore-mock listening on http://{}", addr);
+    println!("   export FIRESTORE_EMULATOR_HOST={}", addr);
+
+    server.serve(listener).await
+}
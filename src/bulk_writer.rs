@@ -0,0 +1,308 @@
+use crate::billing::inherit_call_site;
+use crate::error::{FirebaseError, RpcCode};
+use crate::memory::generate_document_id;
+use crate::models::FirestoreValue;
+use crate::store::{DocumentStore, Write, document_path};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// Firestore's limit on writes in one `batchWrite` request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+const DEFAULT_BATCH_SIZE: usize = 20;
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// Error codes worth retrying; anything else (a missing document, a create
+// that already exists, a bad request) will fail the same way again
+const RETRYABLE_CODES: &[RpcCode] = &[
+    RpcCode::Aborted,
+    RpcCode::Unavailable,
+    RpcCode::ResourceExhausted,
+    RpcCode::DeadlineExceeded,
+    RpcCode::Internal,
+];
+
+/// A write that still failed after all attempts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulkWriteFailure {
+    /// Position of the write in the order it was queued.
+    pub index: usize,
+    pub path: String,
+    /// `create`, `set`, `update` or `delete`.
+    pub operation: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// Outcome of everything written through a [`BulkWriter`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkWriteReport {
+    pub succeeded: usize,
+    /// Writes that succeeded, but only after one or more retries.
+    pub retried: usize,
+    pub failures: Vec<BulkWriteFailure>,
+}
+
+impl BulkWriteReport {
+    pub fn total(&self) -> usize {
+        self.succeeded + self.failures.len()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct QueuedWrite {
+    index: usize,
+    write: Write,
+    attempts: u32,
+}
+
+#[derive(Debug)]
+struct WriteOutcome {
+    index: usize,
+    path: String,
+    operation: &'static str,
+    attempts: u32,
+    error: Option<String>,
+}
+
+/// Writes many documents quickly by sending batches of independent
+/// (non-atomic) writes concurrently, retrying each failed write on its own
+/// with exponential backoff.
+///
+/// Writes are queued with [`create`](Self::create), [`set`](Self::set),
+/// [`update`](Self::update) and [`delete`](Self::delete); full batches are sent
+/// as they fill up. [`flush`](Self::flush) waits for everything queued so far
+/// and [`close`](Self::close) returns the final report. Writes in different
+/// batches may be applied in any order.
+pub struct BulkWriter<S: DocumentStore + Clone + 'static> {
+    store: S,
+    batch_size: usize,
+    max_in_flight: usize,
+    max_retries: u32,
+    initial_backoff: Duration,
+    batch: Vec<QueuedWrite>,
+    in_flight: JoinSet<Vec<WriteOutcome>>,
+    next_index: usize,
+    report: BulkWriteReport,
+}
+
+impl<S: DocumentStore + Clone + 'static> BulkWriter<S> {
+    /// Sends up to `store.max_in_flight()` batches at once.
+    pub fn new(store: S) -> Self {
+        let max_in_flight = store.max_in_flight().max(1);
+        Self {
+            store,
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_in_flight,
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            batch: Vec::new(),
+            in_flight: JoinSet::new(),
+            next_index: 0,
+            report: BulkWriteReport::default(),
+        }
+    }
+
+    /// Writes per request, at most [`MAX_BATCH_SIZE`].
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Retries per write after the first attempt.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Delay before the first retry; it doubles with each further attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Queues a create, generating an ID when none is given. Returns the document path.
+    pub async fn create(
+        &mut self,
+        collection: &str,
+        doc_id: Option<&str>,
+        fields: HashMap<String, FirestoreValue>,
+    ) -> String {
+        let id = doc_id.map(str::to_string).unwrap_or_else(generate_document_id);
+        let path = document_path(collection, &id);
+        self.queue(Write::Create { path: path.clone(), fields }).await;
+        path
+    }
+
+    pub async fn set(&mut self, path: &str, fields: HashMap<String, FirestoreValue>) {
+        self.queue(Write::Set { path: path.to_string(), fields }).await;
+    }
+
+    pub async fn update(&mut self, path: &str, fields: HashMap<String, FirestoreValue>, mask: Vec<String>) {
+        self.queue(Write::Update { path: path.to_string(), fields, mask }).await;
+    }
+
+    pub async fn delete(&mut self, path: &str) {
+        self.queue(Write::Delete { path: path.to_string() }).await;
+    }
+
+    /// Queues any write, waiting only if all batch slots are busy.
+    pub async fn queue(&mut self, write: Write) {
+        // A batch may not touch the same document twice
+        if self.batch.iter().any(|queued| queued.write.path() == write.path()) {
+            self.send_batch().await;
+        }
+
+        self.batch.push(QueuedWrite { index: self.next_index, write, attempts: 0 });
+        self.next_index += 1;
+        if self.batch.len() >= self.batch_size {
+            self.send_batch().await;
+        }
+    }
+
+    /// Sends any partial batch and waits for every queued write to finish.
+    pub async fn flush(&mut self) {
+        self.send_batch().await;
+        while let Some(outcomes) = self.in_flight.join_next().await {
+            self.record(outcomes);
+        }
+    }
+
+    /// Flushes and returns the report for everything written.
+    pub async fn close(mut self) -> BulkWriteReport {
+        self.flush().await;
+        std::mem::take(&mut self.report)
+    }
+
+    /// The report so far; writes still in flight are not included.
+    pub fn report(&self) -> &BulkWriteReport {
+        &self.report
+    }
+
+    async fn send_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        while self.in_flight.len() >= self.max_in_flight {
+            if let Some(outcomes) = self.in_flight.join_next().await {
+                self.record(outcomes);
+            }
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        let store = self.store.clone();
+        let max_retries = self.max_retries;
+        let initial_backoff = self.initial_backoff;
+        self.in_flight.spawn(inherit_call_site(write_batch(store, batch, max_retries, initial_backoff)));
+    }
+
+    fn record(&mut self, outcomes: Result<Vec<WriteOutcome>, tokio::task::JoinError>) {
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                // Only happens if a batch task panicked; its writes are unaccounted for
+                tracing::error!(error = %e, "Bulk write batch failed");
+                return;
+            }
+        };
+        for outcome in outcomes {
+            match outcome.error {
+                None => {
+                    self.report.succeeded += 1;
+                    if outcome.attempts > 1 {
+                        self.report.retried += 1;
+                    }
+                }
+                Some(error) => {
+                    tracing::warn!(path = %outcome.path, attempts = outcome.attempts, error = %error, "Bulk write failed");
+                    self.report.failures.push(BulkWriteFailure {
+                        index: outcome.index,
+                        path: outcome.path,
+                        operation: outcome.operation.to_string(),
+                        attempts: outcome.attempts,
+                        error,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Sends one batch, then resends whichever writes failed with a retryable
+/// error until they succeed or run out of attempts.
+async fn write_batch<S: DocumentStore>(
+    store: S,
+    mut pending: Vec<QueuedWrite>,
+    max_retries: u32,
+    initial_backoff: Duration,
+) -> Vec<WriteOutcome> {
+    let mut outcomes = Vec::with_capacity(pending.len());
+    let mut backoff = initial_backoff;
+
+    while !pending.is_empty() {
+        for queued in &mut pending {
+            queued.attempts += 1;
+        }
+        let writes: Vec<Write> = pending.iter().map(|queued| queued.write.clone()).collect();
+        // Each result is Ok, or the error message and whether it is worth retrying
+        let results: Vec<Result<(), (String, bool)>> = match store.batch_write(writes).await {
+            Ok(results) => results.into_iter()
+                .map(|result| result.map(|_| ()).map_err(|e| (e.to_string(), is_retryable(&e))))
+                .collect(),
+            // The whole request failed, so every write in it gets the same error
+            Err(e) => {
+                let failure = (e.to_string(), is_retryable(&e));
+                pending.iter().map(|_| Err(failure.clone())).collect()
+            }
+        };
+
+        let mut retry = Vec::new();
+        for (queued, result) in pending.into_iter().zip(results) {
+            match result {
+                Err((_, true)) if queued.attempts <= max_retries => retry.push(queued),
+                result => outcomes.push(WriteOutcome {
+                    index: queued.index,
+                    path: queued.write.path().to_string(),
+                    operation: queued.write.operation(),
+                    attempts: queued.attempts,
+                    error: result.err().map(|(message, _)| message),
+                }),
+            }
+        }
+
+        if !retry.is_empty() {
+            tracing::debug!(writes = retry.len(), backoff_ms = backoff.as_millis() as u64, "Retrying bulk writes");
+            tokio::time::sleep(jittered(backoff)).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        pending = retry;
+    }
+
+    outcomes
+}
+
+pub(crate) fn is_retryable(error: &FirebaseError) -> bool {
+    match error {
+        FirebaseError::RequestError(e) => e.is_timeout() || e.is_connect(),
+        FirebaseError::ApiError(status) => RETRYABLE_CODES.contains(&status.code),
+        _ => false,
+    }
+}
+
+/// Spreads retries from concurrent batches over 50-100% of `backoff`.
+fn jittered(backoff: Duration) -> Duration {
+    backoff.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+}
@@ -1,5 +1,9 @@
+use crate::billing::{BillableOps, CostEstimate};
 use crate::error::{FirebaseError, Result};
 use crate::firebase::FirebaseClient;
+use crate::models::{FirestoreValue, json_value_to_firestore};
+use crate::partition::read_collection;
+use crate::store::{DocumentStore, ListOptions, ReadOptions, StoredDocument};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
@@ -42,92 +46,65 @@ pub struct CollectionSchema {
     pub sample_document: Option<serde_json::Value>,
 }
 
-pub struct CollectionManager {
-    client: FirebaseClient,
+pub struct CollectionManager<S: DocumentStore = FirebaseClient> {
+    client: S,
 }
 
-impl CollectionManager {
-    pub fn new(client: FirebaseClient) -> Self {
+impl<S: DocumentStore> CollectionManager<S> {
+    pub fn new(client: S) -> Self {
         Self { client }
     }
 
     pub async fn list_collections(&self) -> Result<Vec<CollectionInfo>> {
-        // Since Firebase REST API doesn't have a direct "list collections" endpoint,
-        // we'll discover collections by trying known collection names and checking metadata
         let mut collections = Vec::new();
-        
-        // Try common collection names and any we find in metadata
-        let potential_collections = vec![
-            "users", "posts", "comments", "products", "orders", "customers",
-            "articles", "messages", "notifications", "settings", "logs",
-            "events", "analytics", "feedback", "reviews", "categories",
-        ];
-
-        for collection_name in potential_collections {
+
+        // Prefer asking the store for its root collections; fall back to probing
+        // common names (and our metadata collection) if that isn't available
+        let collection_names = match self.client.list_collection_ids("").await {
+            Ok(ids) => ids,
+            Err(e @ FirebaseError::BudgetExceeded(_)) => return Err(e),
+            Err(_) => [
+                "users", "posts", "comments", "products", "orders", "customers",
+                "articles", "messages", "notifications", "settings", "logs",
+                "events", "analytics", "feedback", "reviews", "categories",
+                "_metadata_collections",
+            ].iter().map(|name| name.to_string()).collect(),
+        };
+
+        for collection_name in &collection_names {
             match self.get_collection_info(collection_name).await {
                 Ok(info) => {
                     if info.document_count > 0 {
                         collections.push(info);
                     }
                 }
+                Err(e @ FirebaseError::BudgetExceeded(_)) => return Err(e),
                 Err(_) => {
                     // Collection doesn't exist or is empty, skip
                 }
             }
         }
 
-        // Also check metadata collections
-        match self.get_collection_info("_metadata_collections").await {
-            Ok(info) => {
-                if info.document_count > 0 {
-                    collections.push(info);
-                }
-            }
-            Err(_) => {} // Ignore if metadata collection doesn't exist
-        }
-
         // Sort by document count (largest first)
-        collections.sort_by(|a, b| b.document_count.cmp(&a.document_count));
+        collections.sort_by_key(|c| std::cmp::Reverse(c.document_count));
 
         Ok(collections)
     }
 
     pub async fn get_collection_info(&self, collection_name: &str) -> Result<CollectionInfo> {
-        // Get collection document count by listing documents
-        let url = format!("{}/{}?key={}&pageSize=1", 
-            self.client.base_url,
-            collection_name.trim_start_matches('/'),
-            self.client.api_key
-        );
-        
-        let response = self.client.client
-            .get(&url)
-            .send()
+        let page = self.client
+            .list_page(collection_name, &ListOptions::page_size(1), &ReadOptions::default())
             .await?;
-        
-        if response.status() == 404 {
-            return Err(FirebaseError::NotFound(collection_name.to_string()));
-        }
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to get collection info: {}", error_text)));
-        }
-
-        let response_text = response.text().await?;
-        let list_response: serde_json::Value = serde_json::from_str(&response_text)?;
 
         // Get document count (this is an approximation)
-        let document_count = self.estimate_document_count(collection_name).await.unwrap_or(0);
+        let document_count = match self.estimate_document_count(collection_name).await {
+            Ok(count) => count,
+            Err(e @ FirebaseError::BudgetExceeded(_)) => return Err(e),
+            Err(_) => 0,
+        };
         
         // Get last modified time from first document
-        let last_modified = list_response
-            .get("documents")
-            .and_then(|docs| docs.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|doc| doc.get("updateTime"))
-            .and_then(|time| time.as_str())
-            .map(|s| s.to_string());
+        let last_modified = page.documents.first().and_then(|doc| doc.update_time.clone());
 
         Ok(CollectionInfo {
             name: collection_name.to_string(),
@@ -138,39 +115,12 @@ impl CollectionManager {
     }
 
     async fn estimate_document_count(&self, collection_name: &str) -> Result<usize> {
-        // Get a larger sample to estimate count
-        let url = format!("{}/{}?key={}&pageSize=100", 
-            self.client.base_url,
-            collection_name.trim_start_matches('/'),
-            self.client.api_key
-        );
-        
-        let response = self.client.client
-            .get(&url)
-            .send()
+        // Get a larger sample to estimate count. If we get a full page there
+        // are likely more, so this is a minimum count
+        let page = self.client
+            .list_page(collection_name, &ListOptions::page_size(100), &ReadOptions::default())
             .await?;
-        
-        if !response.status().is_success() {
-            return Ok(0);
-        }
-
-        let response_text = response.text().await?;
-        let list_response: serde_json::Value = serde_json::from_str(&response_text)?;
-
-        let count = list_response
-            .get("documents")
-            .and_then(|docs| docs.as_array())
-            .map(|arr| arr.len())
-            .unwrap_or(0);
-
-        // If we got 100 documents, there are likely more
-        if count == 100 {
-            // For a more accurate count, we'd need to paginate through all results
-            // For now, we'll estimate based on the fact we hit the limit
-            Ok(count) // This is a minimum count
-        } else {
-            Ok(count)
-        }
+        Ok(page.documents.len())
     }
 
     fn format_size_estimate(&self, doc_count: usize) -> String {
@@ -192,172 +142,69 @@ impl CollectionManager {
     }
 
     pub async fn describe_collection(&self, collection_name: &str, sample_size: usize) -> Result<CollectionSchema> {
-        // Get sample documents to analyze schema
-        let url = format!("{}/{}?key={}&pageSize={}", 
-            self.client.base_url,
-            collection_name.trim_start_matches('/'),
-            self.client.api_key,
-            sample_size.min(100) // Firebase limits page size
-        );
-        
-        let response = self.client.client
-            .get(&url)
-            .send()
-            .await?;
-        
-        if response.status() == 404 {
-            return Err(FirebaseError::NotFound(collection_name.to_string()));
-        }
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to describe collection: {}", error_text)));
-        }
-
-        let response_text = response.text().await?;
-        let list_response: serde_json::Value = serde_json::from_str(&response_text)?;
-
-        let empty_vec = vec![];
-        let documents = list_response
-            .get("documents")
-            .and_then(|docs| docs.as_array())
-            .unwrap_or(&empty_vec);
+        // Get sample documents to analyze schema (Firebase limits page size)
+        let list = ListOptions::page_size(sample_size.min(100));
+        let documents = self.client
+            .list_page(collection_name, &list, &ReadOptions::default())
+            .await?
+            .documents;
 
         if documents.is_empty() {
             return Err(FirebaseError::NotFound(format!("No documents found in collection {}", collection_name)));
         }
 
-        // Analyze field patterns
-        let mut field_stats: HashMap<String, FieldStats> = HashMap::new();
-        let mut sample_document = None;
-
-        for (i, doc) in documents.iter().enumerate() {
-            if i == 0 {
-                // Keep first document as sample
-                sample_document = Some(self.convert_firestore_doc_to_json(doc));
-            }
+        Ok(analyze_documents(collection_name, &documents))
+    }
 
-            if let Some(fields) = doc.get("fields").and_then(|f| f.as_object()) {
-                for (field_name, field_value) in fields {
-                    let stats = field_stats.entry(field_name.clone()).or_insert(FieldStats::new());
-                    stats.frequency += 1;
-                    
-                    let (field_type, sample_value) = self.analyze_firestore_field(field_value);
-                    stats.field_types.insert(field_type);
-                    
-                    if stats.sample_values.len() < 5 {
-                        stats.sample_values.insert(sample_value);
-                    }
-                }
-            }
+    /// Analyzes every document instead of a sample, reading the collection with
+    /// `partitions` concurrent partitioned queries.
+    pub async fn describe_collection_full(&self, collection_name: &str, partitions: usize) -> Result<CollectionSchema>
+    where
+        S: Clone + 'static,
+    {
+        let documents = read_collection(&self.client, collection_name, partitions, &ReadOptions::default()).await?;
+        if documents.is_empty() {
+            return Err(FirebaseError::NotFound(format!("No documents found in collection {}", collection_name)));
         }
 
-        // Convert field stats to field info
-        let total_docs = documents.len();
-        let fields: Vec<FieldInfo> = field_stats.into_iter().map(|(name, stats)| {
-            let field_type = if stats.field_types.len() == 1 {
-                stats.field_types.iter().next().unwrap().clone()
-            } else {
-                format!("Mixed({})", stats.field_types.iter().cloned().collect::<Vec<_>>().join(", "))
-            };
-
-            let is_required = stats.frequency == total_docs;
-            let sample_values: Vec<String> = stats.sample_values.into_iter().collect();
-
-            // Detect automatic fields based on name patterns and values
-            let auto_field = detect_auto_field(&name, &field_type, &sample_values);
-            
-            // Override field type for automatic timestamp fields
-            let field_type = if matches!(auto_field, Some(AutoFieldType::CreatedAt) | Some(AutoFieldType::UpdatedAt) | Some(AutoFieldType::CurrentTimestamp)) {
-                "timestamp".to_string()
-            } else {
-                field_type
-            };
-
-            FieldInfo {
-                name,
-                field_type,
-                is_required,
-                frequency: stats.frequency,
-                unique_values: sample_values.len(),
-                sample_values,
-                auto_field,
-            }
-        }).collect();
-
-        Ok(CollectionSchema {
-            collection_name: collection_name.to_string(),
-            total_documents: total_docs,
-            fields,
-            sample_document,
-        })
+        Ok(analyze_documents(collection_name, &documents))
     }
 
-    fn convert_firestore_doc_to_json(&self, doc: &serde_json::Value) -> serde_json::Value {
-        if let Some(fields) = doc.get("fields").and_then(|f| f.as_object()) {
-            let mut json_doc = serde_json::Map::new();
-            
-            for (key, value) in fields {
-                json_doc.insert(key.clone(), self.convert_firestore_value_to_json(value));
-            }
-            
-            serde_json::Value::Object(json_doc)
-        } else {
-            serde_json::Value::Null
-        }
+    /// Estimates the reads `describe_collection` would make, from an aggregation count.
+    pub async fn estimate_describe(&self, collection_name: &str, sample_size: usize) -> Result<CostEstimate> {
+        let documents = self.client.count_documents(collection_name).await?;
+        let mut estimate = CostEstimate {
+            estimate_reads: BillableOps::aggregation(documents).reads,
+            ..CostEstimate::default()
+        };
+        let sampled = documents.min(sample_size.min(100) as u64);
+        estimate.add(collection_name, documents, BillableOps::query(sampled).reads);
+        Ok(estimate)
     }
 
-    fn convert_firestore_value_to_json(&self, value: &serde_json::Value) -> serde_json::Value {
-        if let Some(string_val) = value.get("stringValue").and_then(|v| v.as_str()) {
-            serde_json::Value::String(string_val.to_string())
-        } else if let Some(int_val) = value.get("integerValue").and_then(|v| v.as_str()) {
-            if let Ok(num) = int_val.parse::<i64>() {
-                serde_json::Value::Number(serde_json::Number::from(num))
-            } else {
-                serde_json::Value::String(int_val.to_string())
-            }
-        } else if let Some(double_val) = value.get("doubleValue").and_then(|v| v.as_f64()) {
-            serde_json::Value::Number(serde_json::Number::from_f64(double_val).unwrap_or(serde_json::Number::from(0)))
-        } else if let Some(bool_val) = value.get("booleanValue").and_then(|v| v.as_bool()) {
-            serde_json::Value::Bool(bool_val)
-        } else if let Some(timestamp) = value.get("timestampValue").and_then(|v| v.as_str()) {
-            serde_json::Value::String(timestamp.to_string())
-        } else if let Some(array) = value.get("arrayValue").and_then(|v| v.get("values")).and_then(|v| v.as_array()) {
-            let json_array: Vec<serde_json::Value> = array.iter()
-                .map(|item| self.convert_firestore_value_to_json(item))
-                .collect();
-            serde_json::Value::Array(json_array)
-        } else if let Some(map) = value.get("mapValue").and_then(|v| v.get("fields")).and_then(|v| v.as_object()) {
-            let mut json_map = serde_json::Map::new();
-            for (k, v) in map {
-                json_map.insert(k.clone(), self.convert_firestore_value_to_json(v));
-            }
-            serde_json::Value::Object(json_map)
-        } else {
-            serde_json::Value::Null
-        }
+    /// Estimates the reads `describe_collection_full` would make.
+    pub async fn estimate_describe_full(&self, collection_name: &str) -> Result<CostEstimate> {
+        let documents = self.client.count_documents(collection_name).await?;
+        let mut estimate = CostEstimate {
+            estimate_reads: BillableOps::aggregation(documents).reads,
+            ..CostEstimate::default()
+        };
+        estimate.add(collection_name, documents, BillableOps::query(documents).reads);
+        Ok(estimate)
     }
 
-    fn analyze_firestore_field(&self, field_value: &serde_json::Value) -> (String, String) {
-        if let Some(string_val) = field_value.get("stringValue").and_then(|v| v.as_str()) {
-            ("string".to_string(), format!("\"{}\"", string_val))
-        } else if let Some(int_val) = field_value.get("integerValue").and_then(|v| v.as_str()) {
-            ("integer".to_string(), int_val.to_string())
-        } else if let Some(double_val) = field_value.get("doubleValue") {
-            ("double".to_string(), double_val.to_string())
-        } else if let Some(bool_val) = field_value.get("booleanValue") {
-            ("boolean".to_string(), bool_val.to_string())
-        } else if field_value.get("timestampValue").is_some() {
-            ("timestamp".to_string(), "2024-01-01T00:00:00Z".to_string())
-        } else if field_value.get("arrayValue").is_some() {
-            ("array".to_string(), "[...]".to_string())
-        } else if field_value.get("mapValue").is_some() {
-            ("map".to_string(), "{...}".to_string())
-        } else if field_value.get("nullValue").is_some() {
-            ("null".to_string(), "null".to_string())
-        } else {
-            ("unknown".to_string(), "?".to_string())
+    /// Estimates the reads `list_collections` would make: the collection listing,
+    /// then two sample pages (1 and 100 documents) per collection.
+    pub async fn estimate_list_collections(&self) -> Result<CostEstimate> {
+        let mut estimate = CostEstimate { estimate_reads: 1, ..CostEstimate::default() };
+        estimate.add("(root)", 0, 1);
+        for collection_name in self.client.list_collection_ids("").await? {
+            let documents = self.client.count_documents(&collection_name).await?;
+            estimate.estimate_reads += BillableOps::aggregation(documents).reads;
+            let reads = BillableOps::query(documents.min(1)).reads + BillableOps::query(documents.min(100)).reads;
+            estimate.add(&collection_name, documents, reads);
         }
+        Ok(estimate)
     }
 
     pub fn format_collections_table(&self, collections: &[CollectionInfo], use_table: bool) -> String {
@@ -495,7 +342,91 @@ impl FieldStats {
     }
 }
 
-fn detect_auto_field(field_name: &str, field_type: &str, sample_values: &[String]) -> Option<AutoFieldType> {
+/// Infers a field-level schema from a sample of documents.
+fn analyze_documents(collection_name: &str, documents: &[StoredDocument]) -> CollectionSchema {
+    let mut field_stats: HashMap<String, FieldStats> = HashMap::new();
+
+    for doc in documents {
+        for (field_name, field_value) in &doc.fields {
+            let stats = field_stats.entry(field_name.clone()).or_insert(FieldStats::new());
+            stats.frequency += 1;
+            
+            let (field_type, sample_value) = analyze_field_value(field_value);
+            stats.field_types.insert(field_type);
+            
+            if stats.sample_values.len() < 5 {
+                stats.sample_values.insert(sample_value);
+            }
+        }
+    }
+
+    // Convert field stats to field info, ordered by name so output is stable
+    let total_docs = documents.len();
+    let mut field_stats: Vec<(String, FieldStats)> = field_stats.into_iter().collect();
+    field_stats.sort_by(|a, b| a.0.cmp(&b.0));
+    let fields: Vec<FieldInfo> = field_stats.into_iter().map(|(name, stats)| {
+        let field_type = if stats.field_types.len() == 1 {
+            stats.field_types.iter().next().unwrap().clone()
+        } else {
+            let mut types: Vec<String> = stats.field_types.iter().cloned().collect();
+            types.sort();
+            format!("Mixed({})", types.join(", "))
+        };
+
+        let is_required = stats.frequency == total_docs;
+        let mut sample_values: Vec<String> = stats.sample_values.into_iter().collect();
+        sample_values.sort();
+
+        // Detect automatic fields based on name patterns and values
+        let auto_field = detect_auto_field(&name, &field_type, &sample_values);
+        
+        // Override field type for automatic timestamp fields
+        let field_type = if matches!(auto_field, Some(AutoFieldType::CreatedAt) | Some(AutoFieldType::UpdatedAt) | Some(AutoFieldType::CurrentTimestamp)) {
+            "timestamp".to_string()
+        } else {
+            field_type
+        };
+
+        FieldInfo {
+            name,
+            field_type,
+            is_required,
+            frequency: stats.frequency,
+            unique_values: sample_values.len(),
+            sample_values,
+            auto_field,
+        }
+    }).collect();
+
+    CollectionSchema {
+        collection_name: collection_name.to_string(),
+        total_documents: total_docs,
+        fields,
+        // Keep first document as sample
+        sample_document: documents.first().map(|doc| doc.to_json()),
+    }
+}
+
+fn analyze_field_value(value: &FirestoreValue) -> (String, String) {
+    let (field_type, sample) = match value {
+        FirestoreValue::StringValue(s) => ("string", format!("\"{}\"", s)),
+        FirestoreValue::IntegerValue(i) => ("integer", i.clone()),
+        FirestoreValue::DoubleValue(d) => ("double", d.to_string()),
+        FirestoreValue::BooleanValue(b) => ("boolean", b.to_string()),
+        FirestoreValue::TimestampValue(_) => ("timestamp", "2024-01-01T00:00:00Z".to_string()),
+        FirestoreValue::ArrayValue { .. } => ("array", "[...]".to_string()),
+        FirestoreValue::MapValue { .. } => ("map", "{...}".to_string()),
+        FirestoreValue::NullValue(_) => ("null", "null".to_string()),
+        FirestoreValue::ReferenceValue(r) => ("reference", r.clone()),
+        FirestoreValue::BytesValue(_) => ("bytes", "<bytes>".to_string()),
+        FirestoreValue::GeoPointValue { latitude, longitude } => ("geopoint", format!("({}, {})", latitude, longitude)),
+        FirestoreValue::VectorValue(components) => ("vector", format!("<{} dimensions>", components.len())),
+        FirestoreValue::Unknown => ("unknown", "?".to_string()),
+    };
+    (field_type.to_string(), sample)
+}
+
+pub(crate) fn detect_auto_field(field_name: &str, field_type: &str, sample_values: &[String]) -> Option<AutoFieldType> {
     let name_lower = field_name.to_lowercase();
     
     // Pattern-based detection for common automatic fields
@@ -584,6 +515,22 @@ impl AutoFieldType {
         }
     }
     
+    /// Like [`generate_value`](Self::generate_value), with timestamps as
+    /// `TimestampValue`s.
+    pub fn generate_firestore_value(&self) -> FirestoreValue {
+        match self {
+            AutoFieldType::CurrentTimestamp | AutoFieldType::CreatedAt | AutoFieldType::UpdatedAt => {
+                FirestoreValue::TimestampValue(chrono::Utc::now().to_rfc3339())
+            }
+            _ => json_value_to_firestore(self.generate_value()),
+        }
+    }
+    
+    /// Whether the value is regenerated on every update, not just on create.
+    pub fn on_update(&self) -> bool {
+        matches!(self, AutoFieldType::UpdatedAt)
+    }
+    
     pub fn description(&self) -> &'static str {
         match self {
             AutoFieldType::CurrentTimestamp => "Current timestamp (ISO 8601)",
@@ -1,3 +1,6 @@
+use crate::query::QueryError;
+use crate::validation::ValidationReport;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -23,8 +26,150 @@ pub enum FirebaseError {
     #[error("Database operation failed: {0}")]
     DatabaseError(String),
     
+    /// Firestore answered with an error status.
+    #[error("Database operation failed: {0}")]
+    ApiError(ApiStatus),
+    
     #[error("Validation failed: {0}")]
     ValidationError(String),
+    
+    #[error("Read budget exceeded: {0}")]
+    BudgetExceeded(String),
+    
+    #[error("Invalid query: {0}")]
+    InvalidQuery(#[from] QueryError),
+    
+    /// A document broke its schema; holds every violation. Returned by
+    /// `SchemaManager::validate` in place of `ValidationError`.
+    #[error("Validation failed: {0}")]
+    SchemaViolation(ValidationReport),
+}
+
+pub type Result<T> = std::result::Result<T, FirebaseError>;
+
+/// An error status from Firestore: the status of a failed REST call, or the
+/// `google.rpc.Status` of a single write or operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiStatus {
+    pub code: RpcCode,
+    /// The HTTP status, when the whole request failed.
+    pub http_status: Option<u16>,
+    pub message: String,
+}
+
+impl ApiStatus {
+    /// A status that didn't come from an HTTP response.
+    pub fn new(code: RpcCode, message: impl Into<String>) -> Self {
+        Self { code, http_status: None, message: message.into() }
+    }
+}
+
+impl fmt::Display for ApiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// The canonical `google.rpc.Code` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RpcCode {
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl RpcCode {
+    const ALL: [RpcCode; 16] = [
+        RpcCode::Cancelled, RpcCode::Unknown, RpcCode::InvalidArgument, RpcCode::DeadlineExceeded,
+        RpcCode::NotFound, RpcCode::AlreadyExists, RpcCode::PermissionDenied, RpcCode::ResourceExhausted,
+        RpcCode::FailedPrecondition, RpcCode::Aborted, RpcCode::OutOfRange, RpcCode::Unimplemented,
+        RpcCode::Internal, RpcCode::Unavailable, RpcCode::DataLoss, RpcCode::Unauthenticated,
+    ];
+
+    /// Unrecognised codes (and 0, which isn't an error) become `Unknown`.
+    pub fn from_code(code: i32) -> Self {
+        Self::ALL.into_iter().find(|rpc_code| *rpc_code as i32 == code).unwrap_or(RpcCode::Unknown)
+    }
+
+    /// Parses the name used in REST error bodies, e.g. `UNAVAILABLE`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rpc_code| rpc_code.name() == name)
+    }
+
+    /// The usual code for an HTTP error status, for responses whose body
+    /// doesn't name one.
+    pub fn from_http_status(status: u16) -> Self {
+        match status {
+            400 => RpcCode::InvalidArgument,
+            401 => RpcCode::Unauthenticated,
+            403 => RpcCode::PermissionDenied,
+            404 => RpcCode::NotFound,
+            409 => RpcCode::Aborted,
+            412 => RpcCode::FailedPrecondition,
+            429 => RpcCode::ResourceExhausted,
+            499 => RpcCode::Cancelled,
+            500 => RpcCode::Internal,
+            501 => RpcCode::Unimplemented,
+            503 => RpcCode::Unavailable,
+            504 => RpcCode::DeadlineExceeded,
+            _ => RpcCode::Unknown,
+        }
+    }
+
+    /// The HTTP status Google APIs answer with for this code.
+    pub fn http_status(self) -> u16 {
+        match self {
+            RpcCode::Cancelled => 499,
+            RpcCode::InvalidArgument | RpcCode::FailedPrecondition | RpcCode::OutOfRange => 400,
+            RpcCode::Unauthenticated => 401,
+            RpcCode::PermissionDenied => 403,
+            RpcCode::NotFound => 404,
+            RpcCode::AlreadyExists | RpcCode::Aborted => 409,
+            RpcCode::ResourceExhausted => 429,
+            RpcCode::Unimplemented => 501,
+            RpcCode::Unavailable => 503,
+            RpcCode::DeadlineExceeded => 504,
+            RpcCode::Unknown | RpcCode::Internal | RpcCode::DataLoss => 500,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RpcCode::Cancelled => "CANCELLED",
+            RpcCode::Unknown => "UNKNOWN",
+            RpcCode::InvalidArgument => "INVALID_ARGUMENT",
+            RpcCode::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            RpcCode::NotFound => "NOT_FOUND",
+            RpcCode::AlreadyExists => "ALREADY_EXISTS",
+            RpcCode::PermissionDenied => "PERMISSION_DENIED",
+            RpcCode::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            RpcCode::FailedPrecondition => "FAILED_PRECONDITION",
+            RpcCode::Aborted => "ABORTED",
+            RpcCode::OutOfRange => "OUT_OF_RANGE",
+            RpcCode::Unimplemented => "UNIMPLEMENTED",
+            RpcCode::Internal => "INTERNAL",
+            RpcCode::Unavailable => "UNAVAILABLE",
+            RpcCode::DataLoss => "DATA_LOSS",
+            RpcCode::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
 }
 
-pub type Result<T> = std::result::Result<T, FirebaseError>;
\ No newline at end of file
+impl fmt::Display for RpcCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
\ No newline at end of file
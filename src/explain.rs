@@ -0,0 +1,192 @@
+use crate::query::{effective_orders, is_inequality};
+use crate::models::FirestoreValue;
+use crate::query::{Direction, FieldOperator, Filter, StructuredQuery};
+use crate::store::StoredDocument;
+use serde::{Deserialize, Serialize};
+
+/// Requests Query Explain on a query or aggregation. By default only the
+/// plan is returned and the query is not run; with `analyze` the query runs
+/// (and is billed) and execution statistics come back with the results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainOptions {
+    #[serde(default)]
+    pub analyze: bool,
+}
+
+impl ExplainOptions {
+    /// Plan only: no documents are read.
+    pub fn plan() -> Self {
+        Self { analyze: false }
+    }
+
+    /// Runs the query and reports execution statistics.
+    pub fn analyze() -> Self {
+        Self { analyze: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainMetrics {
+    #[serde(default)]
+    pub plan_summary: PlanSummary,
+    /// Only present when the query was run with [`ExplainOptions::analyze`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_stats: Option<ExecutionStats>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanSummary {
+    #[serde(default)]
+    pub indexes_used: Vec<IndexUsed>,
+}
+
+/// One index the planner chose, e.g. scope `Collection` with properties
+/// `(age ASC, __name__ ASC)`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexUsed {
+    #[serde(default)]
+    pub query_scope: String,
+    #[serde(default)]
+    pub properties: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionStats {
+    #[serde(default, with = "int64")]
+    pub results_returned: u64,
+    /// A protobuf duration such as `0.012s`.
+    #[serde(default)]
+    pub execution_duration: String,
+    #[serde(default, with = "int64")]
+    pub read_operations: u64,
+    #[serde(default)]
+    pub debug_stats: DebugStats,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DebugStats {
+    #[serde(default, with = "int64")]
+    pub documents_scanned: u64,
+    #[serde(default, with = "int64")]
+    pub index_entries_scanned: u64,
+    #[serde(default)]
+    pub billing_details: BillingDetails,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BillingDetails {
+    #[serde(default, with = "int64")]
+    pub documents_billable: u64,
+    #[serde(default, with = "int64")]
+    pub index_entries_billable: u64,
+    #[serde(default, with = "int64")]
+    pub small_ops: u64,
+    #[serde(default, with = "int64")]
+    pub min_query_cost: u64,
+}
+
+/// Results of [`DocumentStore::explain_query`](crate::store::DocumentStore::explain_query).
+/// `documents` is empty unless the query was analyzed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExplainedQuery {
+    pub documents: Vec<StoredDocument>,
+    pub metrics: ExplainMetrics,
+}
+
+/// Results of [`DocumentStore::explain_count`](crate::store::DocumentStore::explain_count).
+/// `count` is `None` unless the aggregation was analyzed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExplainedCount {
+    pub count: Option<u64>,
+    pub metrics: ExplainMetrics,
+}
+
+/// The index a query would need: equality and array filters first, then
+/// the effective order (including implicit inequality and `__name__` orders).
+pub(crate) fn plan_summary(query: &StructuredQuery) -> PlanSummary {
+    let mut properties: Vec<String> = Vec::new();
+    if let Some(filter) = &query.r#where {
+        collect_equality_properties(filter, &mut properties);
+    }
+    match &query.find_nearest {
+        Some(nearest) => {
+            let dimension = match &nearest.query_vector {
+                FirestoreValue::VectorValue(vector) => vector.len(),
+                _ => 0,
+            };
+            properties.push(format!("{} VECTOR<{}>", nearest.vector_field.field_path, dimension));
+        }
+        None => {
+            for order in effective_orders(query) {
+                let direction = match order.direction {
+                    Some(Direction::Descending) => "DESC",
+                    _ => "ASC",
+                };
+                let property = format!("{} {}", order.field.field_path, direction);
+                if !properties.contains(&property) {
+                    properties.push(property);
+                }
+            }
+        }
+    }
+
+    let collection_group = query.from.iter().any(|selector| selector.all_descendants.unwrap_or(false));
+    PlanSummary {
+        indexes_used: vec![IndexUsed {
+            query_scope: if collection_group { "Collection group" } else { "Collection" }.to_string(),
+            properties: format!("({})", properties.join(", ")),
+        }],
+    }
+}
+
+fn collect_equality_properties(filter: &Filter, properties: &mut Vec<String>) {
+    match filter {
+        Filter::CompositeFilter(composite) => {
+            for filter in &composite.filters {
+                collect_equality_properties(filter, properties);
+            }
+        }
+        Filter::FieldFilter(field_filter) if !is_inequality(field_filter.op) => {
+            let kind = match field_filter.op {
+                FieldOperator::ArrayContains | FieldOperator::ArrayContainsAny => "CONTAINS",
+                _ => "ASC",
+            };
+            let property = format!("{} {}", field_filter.field.field_path, kind);
+            if !properties.contains(&property) {
+                properties.push(property);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Formats a duration the way protobuf JSON does, e.g. `0.012000s`.
+pub(crate) fn format_duration(duration: std::time::Duration) -> String {
+    format!("{:.6}s", duration.as_secs_f64())
+}
+
+/// int64 values are strings in Firestore's JSON, but accept plain numbers too.
+mod int64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Int64 {
+            Number(u64),
+            String(String),
+        }
+        match Int64::deserialize(deserializer)? {
+            Int64::Number(n) => Ok(n),
+            Int64::String(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
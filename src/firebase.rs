@@ -1,17 +1,321 @@
-use crate::error::{FirebaseError, Result};
-use crate::models::{FirestoreValue, ToFirestore, FromFirestore};
-use crate::query::{StructuredQuery, QueryBuilder};
-use reqwest::Client;
+use crate::error::{ApiStatus, FirebaseError, Result, RpcCode};
+use crate::models::{FirestoreValue, ToFirestore, FromFirestore, json_to_firestore_fields};
+use crate::query::{Cursor, StructuredQuery, QueryBuilder};
+use crate::billing::{BillableOps, CostLedger};
+use crate::metrics::ClientMetrics;
+use crate::recording::{Recorder, ReplayMatcher, redact_url};
+use crate::throttle::{RateLimit, Throttle};
+use crate::listen::{ListenOptions, ListenTarget, Listener};
+use crate::explain::{ExplainMetrics, ExplainOptions, ExplainedCount, ExplainedQuery};
+use crate::index_admin::{AdminIndex, IndexAdmin, Operation};
+use crate::indexes::CompositeIndex;
+use crate::store::{
+    CommitResponse, DocumentPage, DocumentStore, ListOptions, ReadOptions, StoredDocument, Write,
+    WriteResult, document_path, normalize_path,
+};
+use async_trait::async_trait;
+use reqwest::{Client, Method};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
 use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Environment variable naming a local emulator (`host:port`), as used by the
+/// Firebase tools and `firestore-mock`.
+pub const EMULATOR_HOST_ENV: &str = "FIRESTORE_EMULATOR_HOST";
+
+/// Environment variable holding an OAuth 2.0 access token for the Admin API.
+pub const ACCESS_TOKEN_ENV: &str = "GOOGLE_OAUTH_ACCESS_TOKEN";
+
+/// Custom methods (`documents:commit`, `users:runQuery`, ...) used by the client.
+/// Document IDs may contain ':', so only these are treated as methods.
+pub(crate) const REST_OPERATIONS: &[&str] = &[
+    "runQuery", "runAggregationQuery", "partitionQuery", "batchGet", "commit", "batchWrite", "beginTransaction",
+    "rollback", "listCollectionIds",
+];
 
 #[derive(Debug, Clone)]
 pub struct FirebaseClient {
-    pub client: Client,
+    http: Client,
     project_id: String,
-    pub api_key: String,
-    pub base_url: String,
+    api_key: String,
+    access_token: Option<String>,
+    base_url: String,
+    documents_root: String,
+    default_page_size: Option<usize>,
+    recorder: Option<Arc<Recorder>>,
+    metrics: Arc<ClientMetrics>,
+    costs: Arc<CostLedger>,
+    throttle: Option<Arc<Throttle>>,
+}
+
+/// Configures the HTTP transport and defaults used by a [`FirebaseClient`].
+/// Connect and request timeouts are enabled by default so a hung connection
+/// surfaces as an error instead of blocking forever.
+#[derive(Debug, Clone)]
+pub struct FirebaseClientBuilder {
+    project_id: String,
+    api_key: String,
+    access_token: Option<String>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    proxy: Option<String>,
+    user_agent: Option<String>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    http2_prior_knowledge: bool,
+    base_url: Option<String>,
+    emulator_host: Option<String>,
+    default_page_size: Option<usize>,
+    max_reads: Option<u64>,
+    rate_limit: Option<RateLimit>,
+    max_in_flight: Option<usize>,
+    recording: Option<Recording>,
+    replay_matcher: ReplayMatcher,
+}
+
+#[derive(Debug, Clone)]
+enum Recording {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl FirebaseClientBuilder {
+    pub fn new(project_id: impl Into<String>, api_key: impl Into<String>) -> Self {
+        Self {
+            project_id: project_id.into(),
+            api_key: api_key.into(),
+            access_token: std::env::var(ACCESS_TOKEN_ENV).ok().filter(|token| !token.is_empty()),
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+            proxy: None,
+            user_agent: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            http2_prior_knowledge: false,
+            base_url: None,
+            emulator_host: std::env::var(EMULATOR_HOST_ENV).ok().filter(|host| !host.is_empty()),
+            default_page_size: None,
+            max_reads: None,
+            rate_limit: None,
+            max_in_flight: None,
+            recording: None,
+            replay_matcher: ReplayMatcher::default(),
+        }
+    }
+
+    /// Maximum time to wait for the TCP/TLS connection to be established.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum time for a whole request, from sending to reading the body.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Disables both the connect and request timeouts.
+    pub fn no_timeouts(mut self) -> Self {
+        self.connect_timeout = None;
+        self.request_timeout = None;
+        self
+    }
+
+    /// Routes all traffic through the given HTTP(S) proxy URL.
+    pub fn proxy(mut self, proxy_url: impl Into<String>) -> Self {
+        self.proxy = Some(proxy_url.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Maximum number of idle keep-alive connections kept per host.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Speak HTTP/2 directly instead of negotiating it (Firestore supports it).
+    pub fn http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// OAuth 2.0 access token sent as a bearer token with every request
+    /// (defaults to `GOOGLE_OAUTH_ACCESS_TOKEN`). The Admin API used for index
+    /// management needs one, e.g. from `gcloud auth print-access-token`.
+    pub fn access_token(mut self, token: impl Into<String>) -> Self {
+        self.access_token = Some(token.into());
+        self
+    }
+
+    /// Overrides the documents endpoint, e.g. to point at an emulator.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Talks plain HTTP to an emulator at `host:port`. Defaults to the
+    /// `FIRESTORE_EMULATOR_HOST` environment variable; an explicit
+    /// [`base_url`](Self::base_url) takes precedence.
+    pub fn emulator_host(mut self, host: impl Into<String>) -> Self {
+        self.emulator_host = Some(host.into());
+        self
+    }
+
+    /// Ignores `FIRESTORE_EMULATOR_HOST` and always talks to production.
+    pub fn no_emulator(mut self) -> Self {
+        self.emulator_host = None;
+        self
+    }
+
+    /// Page size used by list operations when the caller does not pass one.
+    pub fn default_page_size(mut self, page_size: usize) -> Self {
+        self.default_page_size = Some(page_size);
+        self
+    }
+
+    /// Read budget: requests that would take the client's billed reads past
+    /// `max_reads` fail with `BudgetExceeded` instead of being sent. Adjust it
+    /// later through [`FirebaseClient::costs`].
+    pub fn max_reads(mut self, max_reads: u64) -> Self {
+        self.max_reads = Some(max_reads);
+        self
+    }
+
+    /// Limits operations per second, e.g. `RateLimit::ramp_up()` for bulk loads
+    /// into a new collection. Shared by all clones of the client.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Maximum number of requests in flight at once. Bulk operations such as
+    /// imports use this many concurrent writers.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Records every request and response (API key redacted) into a fixture
+    /// file at `path`, overwriting it. The fixture is written by
+    /// [`FirebaseClient::save_recording`], or when the last clone of the
+    /// client is dropped.
+    pub fn record_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.recording = Some(Recording::Record(path.into()));
+        self
+    }
+
+    /// Serves responses from a fixture written by [`record_to`](Self::record_to)
+    /// instead of the network. Requests that don't match a recorded one fail.
+    pub fn replay_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.recording = Some(Recording::Replay(path.into()));
+        self
+    }
+
+    /// How replayed requests are matched with recorded ones; exact by default.
+    pub fn replay_matcher(mut self, matcher: ReplayMatcher) -> Self {
+        self.replay_matcher = matcher;
+        self
+    }
+
+    pub fn build(self) -> Result<FirebaseClient> {
+        let mut http = Client::builder();
+
+        if let Some(timeout) = self.connect_timeout {
+            http = http.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.request_timeout {
+            http = http.timeout(timeout);
+        }
+        if let Some(proxy_url) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy_url)
+                .map_err(|e| FirebaseError::ConfigError(format!("Invalid proxy URL '{}': {}", proxy_url, e)))?;
+            http = http.proxy(proxy);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            http = http.user_agent(user_agent);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            http = http.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            http = http.pool_idle_timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            http = http.http2_prior_knowledge();
+        }
+
+        let http = http.build()
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to build HTTP client: {}", e)))?;
+
+        let base_url = match (self.base_url, &self.emulator_host) {
+            (Some(base_url), _) => base_url,
+            (None, Some(host)) => format!(
+                "http://{}/v1/projects/{}/databases/(default)/documents",
+                host.trim_end_matches('/'), self.project_id
+            ),
+            (None, None) => format!(
+                "https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents",
+                self.project_id
+            ),
+        };
+
+        let documents_root = format!("projects/{}/databases/(default)/documents", self.project_id);
+
+        let recorder = match self.recording {
+            Some(Recording::Record(path)) => Some(Arc::new(Recorder::record(path))),
+            Some(Recording::Replay(path)) => Some(Arc::new(Recorder::replay(path, self.replay_matcher)?)),
+            None => None,
+        };
+
+        let costs = CostLedger::default();
+        costs.set_read_limit(self.max_reads);
+        let throttle = if self.rate_limit.is_some() || self.max_in_flight.is_some() {
+            Some(Arc::new(Throttle::new(self.rate_limit, self.max_in_flight)?))
+        } else {
+            None
+        };
+        
+        Ok(FirebaseClient {
+            http,
+            project_id: self.project_id,
+            api_key: self.api_key,
+            access_token: self.access_token,
+            base_url,
+            documents_root,
+            default_page_size: self.default_page_size,
+            recorder,
+            metrics: Arc::new(ClientMetrics::default()),
+            costs: Arc::new(costs),
+            throttle,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListIndexesResponse {
+    #[serde(default)]
+    indexes: Vec<AdminIndex>,
+    next_page_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,205 +328,457 @@ struct UpdateDocumentRequest {
     fields: HashMap<String, FirestoreValue>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct ListDocumentsResponse {
-    documents: Option<Vec<Document>>,
-    #[serde(rename = "nextPageToken")]
+    #[serde(default)]
+    documents: Vec<StoredDocument>,
     next_page_token: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Document {
-    name: String,
-    fields: HashMap<String, FirestoreValue>,
-    #[serde(rename = "createTime")]
-    create_time: Option<String>,
-    #[serde(rename = "updateTime")]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunQueryResponseItem {
+    document: Option<StoredDocument>,
+    explain_metrics: Option<ExplainMetrics>,
+    #[allow(dead_code)]
+    read_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchGetResponseItem {
+    found: Option<StoredDocument>,
+    missing: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AggregationResponseItem {
+    result: Option<AggregationResult>,
+    explain_metrics: Option<ExplainMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AggregationResult {
+    #[serde(default)]
+    aggregate_fields: HashMap<String, FirestoreValue>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitResponseBody {
+    #[serde(default)]
+    write_results: Vec<WriteResultBody>,
+    commit_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteResultBody {
     update_time: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartitionQueryResponse {
+    #[serde(default)]
+    partitions: Vec<Cursor>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchWriteResponseBody {
+    #[serde(default)]
+    write_results: Vec<WriteResultBody>,
+    #[serde(default)]
+    status: Vec<RpcStatus>,
+}
+
+/// A `google.rpc.Status`; code 0 means OK.
+#[derive(Debug, Default, Deserialize)]
+struct RpcStatus {
+    #[serde(default)]
+    code: i32,
+    #[serde(default)]
+    message: String,
+}
+
+impl RpcStatus {
+    fn into_result(self) -> Result<()> {
+        match self.code {
+            0 => Ok(()),
+            5 => Err(FirebaseError::NotFound(self.message)),
+            code => Err(FirebaseError::ApiError(ApiStatus { code: RpcCode::from_code(code), http_status: None, message: self.message })),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListCollectionIdsResponse {
+    #[serde(default)]
+    collection_ids: Vec<String>,
+    next_page_token: Option<String>,
+}
+
+/// `{"error": {"code": 503, "message": "...", "status": "UNAVAILABLE"}}`
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: ErrorDetails,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetails {
+    #[serde(default)]
+    message: String,
+    status: Option<String>,
+}
+
+/// Status and body of a completed REST call.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl HttpResponse {
+    fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Errors carry the status named in the Google API error body, falling
+    /// back to the one the HTTP status implies.
+    fn into_result(self, context: &str) -> Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let body: Option<ErrorBody> = serde_json::from_str(&self.body).ok();
+        let (code, error_text) = match body {
+            Some(ErrorBody { error }) => (error.status.as_deref().and_then(RpcCode::from_name), error.message),
+            None if self.body.is_empty() => (None, "Unknown error".to_string()),
+            None => (None, self.body),
+        };
+        Err(FirebaseError::ApiError(ApiStatus {
+            code: code.unwrap_or_else(|| RpcCode::from_http_status(self.status)),
+            http_status: Some(self.status),
+            message: format!("{}: {}", context, error_text),
+        }))
+    }
+}
+
 impl FirebaseClient {
     pub fn new(project_id: String, api_key: String) -> Self {
-        let base_url = format!(
-            "https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents",
-            project_id
-        );
-        
-        Self {
-            client: Client::new(),
-            project_id,
-            api_key,
-            base_url,
+        Self::builder(project_id, api_key)
+            .build()
+            .expect("default HTTP client configuration is valid")
+    }
+
+    pub fn builder(project_id: impl Into<String>, api_key: impl Into<String>) -> FirebaseClientBuilder {
+        FirebaseClientBuilder::new(project_id, api_key)
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.project_id
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn http_client(&self) -> &Client {
+        &self.http
+    }
+
+    pub fn default_page_size(&self) -> Option<usize> {
+        self.default_page_size
+    }
+
+    /// Request and document counters, shared by all clones of this client.
+    pub fn metrics(&self) -> &ClientMetrics {
+        &self.metrics
+    }
+
+    /// Billable reads, writes and deletes by call site, shared by all clones of
+    /// this client.
+    pub fn costs(&self) -> &CostLedger {
+        &self.costs
+    }
+
+    /// Rate and concurrency limits applied to every request, if configured.
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_deref()
+    }
+
+    /// Writes the interactions recorded so far to the fixture file. Does
+    /// nothing unless recording.
+    pub fn save_recording(&self) -> Result<()> {
+        match &self.recorder {
+            Some(recorder) => recorder.save(),
+            None => Ok(()),
         }
     }
-    
-    pub async fn create<T: ToFirestore>(&self, collection: &str, item: &T) -> Result<String> {
-        let collection_path = collection.trim_start_matches('/');
-        let url = format!("{}/{}?key={}", 
-            self.base_url, 
-            collection_path,
-            self.api_key
+
+    /// Recorded interactions not yet served in replay mode, so tests can check
+    /// a session was replayed completely.
+    pub fn replay_remaining(&self) -> usize {
+        self.recorder.as_ref().map(|r| r.remaining()).unwrap_or(0)
+    }
+
+    /// Resource name of a document, e.g. `projects/p/databases/(default)/documents/users/abc`.
+    pub fn document_name(&self, path: &str) -> String {
+        format!("{}/{}", self.documents_root, normalize_path(path))
+    }
+
+    /// URL of an Admin API resource under the database, e.g. `collectionGroups/users/indexes`.
+    fn admin_endpoint(&self, path: &str) -> String {
+        let database = self.base_url.strip_suffix("/documents").unwrap_or(&self.base_url);
+        format!("{}/{}", database, path)
+    }
+
+    /// URL of a full resource name such as `projects/p/databases/(default)/operations/x`.
+    fn resource_endpoint(&self, name: &str) -> String {
+        match self.base_url.find("/projects/") {
+            Some(pos) => format!("{}/{}", &self.base_url[..pos], name),
+            None => self.admin_endpoint(name.rsplit_once("/databases/").and_then(|(_, rest)| rest.split_once('/')).map_or(name, |(_, path)| path)),
+        }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        let path = normalize_path(path);
+        if path.is_empty() || path.starts_with(':') {
+            format!("{}{}", self.base_url, path)
+        } else {
+            format!("{}/{}", self.base_url, path)
+        }
+    }
+
+    /// Sends one REST call. Every request made by the client goes through here,
+    /// inside a `firestore` span carrying the operation, collection, document
+    /// ID, status, latency and body sizes.
+    pub(crate) async fn execute(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: &[(&str, String)],
+        body: Option<&serde_json::Value>,
+    ) -> Result<HttpResponse> {
+        let (op, collection, doc_id) = self.describe_request(&method, endpoint, body);
+        let payload = body.map(serde_json::to_vec).transpose()?;
+        let request_bytes = payload.as_ref().map(|p| p.len()).unwrap_or(0);
+
+        let span = tracing::info_span!(
+            "firestore",
+            op,
+            collection = %collection,
+            doc_id = doc_id.as_deref(),
+            request_bytes,
+            status = tracing::field::Empty,
+            response_bytes = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
         );
-        
-        let request_body = CreateDocumentRequest {
-            fields: item.to_firestore(),
+
+        // A commit counts one operation per write towards the rate limit
+        let cost = body.and_then(|b| b.get("writes")).and_then(|w| w.as_array()).map_or(1, |w| w.len() as u32);
+
+        async {
+            let _permit = match &self.throttle {
+                Some(throttle) => Some(throttle.acquire(cost).await),
+                None => None,
+            };
+            let started = Instant::now();
+            let result = self.send(method, endpoint, query, body, payload).await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+            let span = tracing::Span::current();
+            span.record("latency_ms", latency_ms);
+
+            match &result {
+                Ok(response) => {
+                    span.record("status", response.status);
+                    span.record("response_bytes", response.body.len());
+                    self.metrics.record_request(request_bytes, response.body.len(), response.is_success());
+                    if response.is_success() {
+                        tracing::debug!(status = response.status, latency_ms, "Firestore request completed");
+                    } else {
+                        tracing::warn!(status = response.status, latency_ms, "Firestore request failed");
+                    }
+                }
+                Err(e) => {
+                    self.metrics.record_error();
+                    tracing::warn!(error = %e, latency_ms, "Firestore request error");
+                }
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: &[(&str, String)],
+        body: Option<&serde_json::Value>,
+        payload: Option<Vec<u8>>,
+    ) -> Result<HttpResponse> {
+        let mut url = reqwest::Url::parse(endpoint)
+            .map_err(|e| FirebaseError::ConfigError(format!("Invalid URL '{}': {}", endpoint, e)))?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("key", &self.api_key);
+            for (name, value) in query {
+                pairs.append_pair(name, value);
+            }
+        }
+
+        if let Some(recorder) = &self.recorder
+            && recorder.is_replay()
+        {
+            let (status, body) = recorder.lookup(method.as_str(), &redact_url(&url), body)?;
+            return Ok(HttpResponse { status, body });
+        }
+
+        let recorded_url = self.recorder.as_ref().map(|_| redact_url(&url));
+        let mut request = self.http.request(method.clone(), url);
+        if let Some(token) = &self.access_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(payload) = payload {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(payload);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let response_body = response.text().await?;
+
+        if let (Some(recorder), Some(url)) = (&self.recorder, recorded_url) {
+            recorder.capture(method.as_str(), &url, body, status, &response_body);
+        }
+        Ok(HttpResponse { status, body: response_body })
+    }
+
+    /// Works out the operation name, collection and document ID of a request
+    /// for its tracing span.
+    fn describe_request(&self, method: &Method, endpoint: &str, body: Option<&serde_json::Value>) -> (&'static str, String, Option<String>) {
+        let Some(relative) = endpoint.strip_prefix(self.base_url.as_str()) else {
+            // Admin API calls live outside the documents tree
+            return ("admin", String::new(), None);
         };
-        
-        let response = self.client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to create document: {}", error_text)));
+        let (path, operation) = match relative.rsplit_once(':') {
+            Some((path, op)) if REST_OPERATIONS.contains(&op) => (path.trim_matches('/'), Some(op)),
+            _ => (relative.trim_matches('/'), None),
+        };
+
+        let query_collection = || {
+            body.and_then(|b| {
+                b.pointer("/structuredQuery/from/0/collectionId")
+                    .or_else(|| b.pointer("/structuredAggregationQuery/structuredQuery/from/0/collectionId"))
+            })
+            .and_then(|c| c.as_str())
+            .unwrap_or(path)
+            .to_string()
+        };
+
+        let op = match operation {
+            Some("runQuery") => return ("query", query_collection(), None),
+            Some("runAggregationQuery") => return ("aggregate", query_collection(), None),
+            Some("batchGet") => "batch_get",
+            Some("commit") => "commit",
+            Some("beginTransaction") => "begin_transaction",
+            Some("rollback") => "rollback",
+            Some("listCollectionIds") => "list_collection_ids",
+            Some(_) => "other",
+            None => {
+                let is_document = !path.is_empty() && path.split('/').count() % 2 == 0;
+                match (method.as_str(), is_document) {
+                    ("GET", true) => "get",
+                    ("GET", false) => "list",
+                    ("POST", _) => "create",
+                    ("PATCH", _) => "update",
+                    ("DELETE", _) => "delete",
+                    _ => "other",
+                }
+            }
+        };
+
+        if operation.is_none() && !path.is_empty() && path.split('/').count() % 2 == 0 {
+            let (collection, doc_id) = path.rsplit_once('/').unwrap_or(("", path));
+            (op, collection.to_string(), Some(doc_id.to_string()))
+        } else {
+            (op, path.to_string(), None)
+        }
+    }
+
+    fn write_to_json(&self, write: &Write) -> serde_json::Value {
+        match write {
+            Write::Create { path, fields } => json!({
+                "update": { "name": self.document_name(path), "fields": fields },
+                "currentDocument": { "exists": false },
+            }),
+            Write::Set { path, fields } => json!({
+                "update": { "name": self.document_name(path), "fields": fields },
+            }),
+            Write::Update { path, fields, mask } => {
+                let mut mask = mask.clone();
+                mask.sort();
+                json!({
+                    "update": { "name": self.document_name(path), "fields": fields },
+                    "updateMask": { "fieldPaths": mask },
+                    "currentDocument": { "exists": true },
+                })
+            }
+            Write::Delete { path } => json!({ "delete": self.document_name(path) }),
         }
-        
-        let response_text = response.text().await?;
-        let raw_doc: serde_json::Value = serde_json::from_str(&response_text)?;
-        
-        let doc_id = raw_doc.get("name")
-            .and_then(|n| n.as_str())
-            .and_then(|name| name.split('/').last())
-            .ok_or_else(|| FirebaseError::DatabaseError("Invalid document name".to_string()))?
-            .to_string();
-        
-        Ok(doc_id)
+    }
+    
+    pub async fn create<T: ToFirestore>(&self, collection: &str, item: &T) -> Result<String> {
+        let document = self.insert(collection, None, item.to_firestore()).await?;
+        Ok(document.id().to_string())
     }
     
     pub async fn get<T: FromFirestore>(&self, collection: &str, doc_id: &str) -> Result<T> {
-        let collection_path = collection.trim_start_matches('/');
-        let url = format!("{}/{}/{}?key={}", 
-            self.base_url,
-            collection_path,
-            doc_id,
-            self.api_key
-        );
-        
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
-        
-        if response.status() == 404 {
-            return Err(FirebaseError::NotFound(doc_id.to_string()));
-        }
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to get document: {}", error_text)));
-        }
+        let document = self.fetch(&document_path(collection, doc_id), &ReadOptions::default()).await?
+            .ok_or_else(|| FirebaseError::NotFound(doc_id.to_string()))?;
         
-        let response_text = response.text().await?;
-        let raw_doc: serde_json::Value = serde_json::from_str(&response_text)?;
-        
-        if let Some(fields) = raw_doc.get("fields") {
-            let json_data = convert_raw_firestore_fields_to_json(fields)?;
-            let firestore_fields = json_to_firestore_fields(json_data)?;
-            T::from_firestore(&firestore_fields)
-        } else {
-            Err(FirebaseError::DatabaseError("Document has no fields".to_string()))
+        if document.fields.is_empty() {
+            return Err(FirebaseError::DatabaseError("Document has no fields".to_string()));
         }
+        T::from_firestore(&document.fields)
     }
     
     pub async fn update<T: ToFirestore>(&self, collection: &str, doc_id: &str, item: &T) -> Result<()> {
-        let collection_path = collection.trim_start_matches('/');
         let fields = item.to_firestore();
         let field_paths: Vec<String> = fields.keys().cloned().collect();
-        let update_mask = field_paths.join("&updateMask.fieldPaths=");
-        
-        let url = format!("{}/{}/{}?key={}&updateMask.fieldPaths={}", 
-            self.base_url,
-            collection_path,
-            doc_id,
-            self.api_key,
-            update_mask
-        );
         
         let mut update_fields = fields.clone();
         update_fields.insert("updated_at".to_string(), FirestoreValue::TimestampValue(Utc::now().to_rfc3339()));
         
-        let request_body = UpdateDocumentRequest { fields: update_fields };
-        
-        let response = self.client
-            .patch(&url)
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to update document: {}", error_text)));
-        }
-        
+        self.patch(&document_path(collection, doc_id), update_fields, Some(&field_paths)).await?;
         Ok(())
     }
     
     pub async fn delete(&self, collection: &str, doc_id: &str) -> Result<()> {
-        let collection_path = collection.trim_start_matches('/');
-        let url = format!("{}/{}/{}?key={}", 
-            self.base_url,
-            collection_path,
-            doc_id,
-            self.api_key
-        );
-        
-        let response = self.client
-            .delete(&url)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to delete document: {}", error_text)));
-        }
-        
-        Ok(())
+        self.remove(&document_path(collection, doc_id)).await
     }
     
     pub async fn list<T: FromFirestore>(&self, collection: &str, limit: Option<usize>) -> Result<Vec<T>> {
-        let collection_path = collection.trim_start_matches('/');
-        let mut url = format!("{}/{}?key={}", 
-            self.base_url,
-            collection_path,
-            self.api_key
-        );
-        
-        if let Some(limit) = limit {
-            url.push_str(&format!("&pageSize={}", limit));
-        }
-        
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to list documents: {}", error_text)));
-        }
-        
-        let response_text = response.text().await?;
-        let raw_list_response: serde_json::Value = serde_json::from_str(&response_text)?;
+        let list = ListOptions { page_size: limit, page_token: None };
+        let page = self.list_page(collection, &list, &ReadOptions::default()).await?;
         
         let mut results = Vec::new();
-        if let Some(documents_array) = raw_list_response.get("documents").and_then(|d| d.as_array()) {
-            for doc in documents_array {
-                if let Some(fields) = doc.get("fields") {
-                    match convert_raw_firestore_fields_to_json(fields) {
-                        Ok(json_data) => {
-                            // Try to convert from the raw JSON data
-                            if let Ok(firestore_fields) = json_to_firestore_fields(json_data) {
-                                match T::from_firestore(&firestore_fields) {
-                                    Ok(item) => results.push(item),
-                                    Err(e) => eprintln!("Failed to parse document: {:?}", e),
-                                }
-                            }
-                        },
-                        Err(e) => eprintln!("Failed to convert fields: {:?}", e),
-                    }
-                }
+        for document in page.documents {
+            match T::from_firestore(&document.fields) {
+                Ok(item) => results.push(item),
+                Err(e) => tracing::warn!(document = %document.name, error = %e, "Failed to parse document"),
             }
         }
         
@@ -230,42 +786,13 @@ impl FirebaseClient {
     }
     
     pub async fn query<T: FromFirestore>(&self, query: StructuredQuery) -> Result<Vec<T>> {
-        let url = format!("{}:runQuery?key={}", 
-            self.base_url,
-            self.api_key
-        );
-        
-        let request_body = RunQueryRequest {
-            structured_query: query,
-        };
-        
-        let response = self.client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to run query: {}", error_text)));
-        }
-        
-        let response_text = response.text().await?;
-        let lines: Vec<&str> = response_text.lines().collect();
+        let documents = self.run_query("", &query, &ReadOptions::default()).await?;
         
         let mut results = Vec::new();
-        for line in lines {
-            if line.trim().is_empty() {
-                continue;
-            }
-            
-            if let Ok(query_response) = serde_json::from_str::<QueryDocumentResponse>(line) {
-                if let Some(document) = query_response.document {
-                    match T::from_firestore(&document.fields) {
-                        Ok(item) => results.push(item),
-                        Err(e) => eprintln!("Failed to parse document: {:?}", e),
-                    }
-                }
+        for document in documents {
+            match T::from_firestore(&document.fields) {
+                Ok(item) => results.push(item),
+                Err(e) => tracing::warn!(document = %document.name, error = %e, "Failed to parse document"),
             }
         }
         
@@ -276,271 +803,582 @@ impl FirebaseClient {
         QueryBuilder::new(collection)
     }
     
+    /// Streams changes to a document or query results; see [`Listener`].
+    pub fn listen(&self, target: ListenTarget, options: ListenOptions) -> Listener {
+        crate::listen::listen(self.clone(), target, options)
+    }
+    
     // Generic CRUD methods for working with serde_json::Value
     pub async fn create_document(&self, collection: &str, doc_id: Option<String>, data: serde_json::Value) -> Result<String> {
         let fields = json_to_firestore_fields(data)?;
-        let request = CreateDocumentRequest { fields };
-        
-        let url = if let Some(id) = doc_id {
-            format!("{}?key={}&documentId={}", 
-                format!("{}/{}", self.base_url, collection.trim_start_matches('/')), 
-                self.api_key, id)
-        } else {
-            format!("{}?key={}", 
-                format!("{}/{}", self.base_url, collection.trim_start_matches('/')), 
-                self.api_key)
-        };
+        let document = self.insert(collection, doc_id.as_deref(), fields).await?;
+        Ok(document.id().to_string())
+    }
+    
+    pub async fn get_document(&self, collection: &str, doc_id: &str) -> Result<serde_json::Value> {
+        match self.fetch(&document_path(collection, doc_id), &ReadOptions::default()).await? {
+            Some(document) => Ok(document.to_json()),
+            None => Err(FirebaseError::NotFound(format!("Document '{}' not found in collection '{}'", doc_id, collection))),
+        }
+    }
+    
+    pub async fn update_document(&self, collection: &str, doc_id: &str, data: serde_json::Value, merge: bool) -> Result<()> {
+        let fields = json_to_firestore_fields(data)?;
+        let mask: Option<Vec<String>> = merge.then(|| fields.keys().cloned().collect());
+        self.patch(&document_path(collection, doc_id), fields, mask.as_deref()).await?;
+        Ok(())
+    }
+    
+    pub async fn delete_document(&self, collection: &str, doc_id: &str) -> Result<()> {
+        self.remove(&document_path(collection, doc_id)).await.map_err(|e| match e {
+            FirebaseError::NotFound(_) => FirebaseError::NotFound(format!("Document '{}' not found in collection '{}'", doc_id, collection)),
+            other => other,
+        })
+    }
+}
+
+#[async_trait]
+impl DocumentStore for FirebaseClient {
+    async fn fetch(&self, path: &str, options: &ReadOptions) -> Result<Option<StoredDocument>> {
+        let mut query = Vec::new();
+        add_read_params(&mut query, options)?;
         
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await?;
+        let _reserved = self.costs.reserve_reads("get", 1)?;
+        let response = self.execute(Method::GET, &self.endpoint(path), &query, None).await?;
+        if response.status == 404 {
+            self.costs.charge("get", parent_collection(path), BillableOps::reads(1));
+            return Ok(None);
+        }
         
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Create failed: {}", error_text)));
+        let response = response.into_result("Failed to get document")?;
+        self.metrics.add_reads(1);
+        self.costs.charge("get", parent_collection(path), BillableOps::reads(1));
+        Ok(Some(serde_json::from_str(&response.body)?))
+    }
+
+    async fn insert(
+        &self,
+        collection: &str,
+        doc_id: Option<&str>,
+        fields: HashMap<String, FirestoreValue>,
+    ) -> Result<StoredDocument> {
+        let mut query = Vec::new();
+        if let Some(id) = doc_id {
+            query.push(("documentId", id.to_string()));
         }
         
-        let response_text = response.text().await?;
-        let raw_document: serde_json::Value = serde_json::from_str(&response_text)?;
+        let body = serde_json::to_value(CreateDocumentRequest { fields })?;
+        let response = self.execute(Method::POST, &self.endpoint(collection), &query, Some(&body)).await?
+            .into_result("Failed to create document")?;
+        self.metrics.add_writes(1);
+        self.costs.charge("create", normalize_path(collection), BillableOps::writes(1));
+        Ok(serde_json::from_str(&response.body)?)
+    }
+
+    async fn patch(
+        &self,
+        path: &str,
+        fields: HashMap<String, FirestoreValue>,
+        mask: Option<&[String]>,
+    ) -> Result<StoredDocument> {
+        // Sorted so the same update always sends the same request
+        let mut query: Vec<(&str, String)> = mask.unwrap_or_default().iter()
+            .map(|field| ("updateMask.fieldPaths", field.clone()))
+            .collect();
+        query.sort();
         
-        let doc_id = raw_document.get("name")
-            .and_then(|n| n.as_str())
-            .and_then(|name| name.split('/').last())
-            .unwrap_or("unknown")
-            .to_string();
+        let body = serde_json::to_value(UpdateDocumentRequest { fields })?;
+        let response = self.execute(Method::PATCH, &self.endpoint(path), &query, Some(&body)).await?
+            .into_result("Failed to update document")?;
+        self.metrics.add_writes(1);
+        self.costs.charge("update", parent_collection(path), BillableOps::writes(1));
+        Ok(serde_json::from_str(&response.body)?)
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        let response = self.execute(Method::DELETE, &self.endpoint(path), &[], None).await?;
+        if response.status == 404 {
+            return Err(crate::store::not_found(path));
+        }
+        response.into_result("Failed to delete document")?;
+        self.metrics.add_deletes(1);
+        self.costs.charge("delete", parent_collection(path), BillableOps::deletes(1));
+        Ok(())
+    }
+
+    async fn list_page(&self, collection: &str, list: &ListOptions, options: &ReadOptions) -> Result<DocumentPage> {
+        // Under a read budget, ask for one document more than is left, to
+        // tell a page cut short by the budget from one that fit exactly
+        let requested = list.page_size.or(self.default_page_size);
+        let (_reserved, cap) = self.costs.reserve_documents("list", 0, requested.map(|size| size as u64))?;
+        let page_size = match cap {
+            Some(cap) => Some(cap.saturating_add(1).min(i32::MAX as u64) as usize),
+            None => requested,
+        };
+        let mut query = Vec::new();
+        if let Some(page_size) = page_size {
+            query.push(("pageSize", page_size.to_string()));
+        }
+        if let Some(token) = &list.page_token {
+            query.push(("pageToken", token.clone()));
+        }
+        add_read_params(&mut query, options)?;
+        
+        let response = self.execute(Method::GET, &self.endpoint(collection), &query, None).await?;
+        if response.status == 404 {
+            return Err(FirebaseError::NotFound(collection.to_string()));
+        }
         
-        Ok(doc_id)
+        let response = response.into_result("Failed to list documents")?;
+        let list_response: ListDocumentsResponse = serde_json::from_str(&response.body)?;
+        self.metrics.add_reads(list_response.documents.len());
+        self.costs.charge("list", normalize_path(collection), BillableOps::query(list_response.documents.len() as u64));
+        if let Some(cap) = cap
+            && list_response.documents.len() as u64 > cap
+        {
+            return Err(self.costs.budget_exceeded("list"));
+        }
+        let next_page_token = list_response.next_page_token.filter(|t| !t.is_empty());
+        Ok(DocumentPage {
+            documents: list_response.documents,
+            next_page_token,
+        })
     }
-    
-    pub async fn get_document(&self, collection: &str, doc_id: &str) -> Result<serde_json::Value> {
-        let url = format!("{}/{}?key={}", 
-            format!("{}/{}", self.base_url, collection.trim_start_matches('/')), 
-            doc_id, self.api_key);
+
+    async fn run_query(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<Vec<StoredDocument>> {
+        query.validate()?;
+        // Documents skipped by an offset are billed as if they were returned
+        let skipped = query.offset.unwrap_or(0).max(0) as u64;
+        
+        // Under a read budget, ask for one document more than is left, to
+        // tell results cut short by the budget from ones that fit exactly
+        let mut query = query.clone();
+        let limit = query.find_nearest.as_ref().map(|nearest| nearest.limit).or(query.limit);
+        let (_reserved, cap) = self.costs.reserve_documents("query", skipped, limit.map(|limit| limit.max(0) as u64))?;
+        if let Some(cap) = cap {
+            let probe = cap.saturating_add(1).min(i32::MAX as u64) as i32;
+            // A vector search is bounded by its own limit
+            match &mut query.find_nearest {
+                Some(nearest) => nearest.limit = probe,
+                None => query.limit = Some(probe),
+            }
+        }
+        
+        let mut body = json!({ "structuredQuery": query });
+        add_read_options(&mut body, options)?;
         
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let endpoint = format!("{}:runQuery", self.endpoint(parent));
+        let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+            .into_result("Failed to run query")?;
         
-        if response.status().as_u16() == 404 {
-            return Err(FirebaseError::NotFound(format!("Document '{}' not found in collection '{}'", doc_id, collection)));
+        let items: Vec<RunQueryResponseItem> = parse_response_stream(&response.body)?;
+        let documents: Vec<StoredDocument> = items.into_iter().filter_map(|item| item.document).collect();
+        self.metrics.add_reads(documents.len());
+        self.costs.charge("query", query_collection(&query), BillableOps::query(documents.len() as u64 + skipped));
+        if let Some(cap) = cap
+            && documents.len() as u64 > cap
+        {
+            return Err(self.costs.budget_exceeded("query"));
         }
+        Ok(documents)
+    }
+
+    async fn partition_query(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        partition_count: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<Cursor>> {
+        query.validate()?;
+        let endpoint = format!("{}:partitionQuery", self.endpoint(parent));
+        let mut partitions = Vec::new();
+        let mut page_token: Option<String> = None;
         
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Get failed: {}", error_text)));
+        loop {
+            let _reserved = self.costs.reserve_reads("partition_query", 1)?;
+            let mut body = json!({ "structuredQuery": query, "partitionCount": partition_count.to_string() });
+            if let Some(token) = &page_token {
+                body["pageToken"] = json!(token);
+            }
+            add_read_options(&mut body, options)?;
+            
+            let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+                .into_result("Failed to partition query")?;
+            let page: PartitionQueryResponse = serde_json::from_str(&response.body)?;
+            self.costs.charge("partition_query", query_collection(query), BillableOps::query(page.partitions.len() as u64));
+            partitions.extend(page.partitions);
+            
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
+            }
         }
         
-        let response_text = response.text().await?;
-        let raw_document: serde_json::Value = serde_json::from_str(&response_text)?;
+        Ok(partitions)
+    }
+
+    async fn count(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<u64> {
+        query.validate()?;
+        let (_reserved, cap) = self.costs.reserve_index_entries("aggregate")?;
+        let count_aggregation = match cap {
+            Some(cap) => json!({ "upTo": cap.min(i64::MAX as u64).to_string() }),
+            None => json!({}),
+        };
+        let mut body = json!({
+            "structuredAggregationQuery": {
+                "structuredQuery": query,
+                "aggregations": [{ "alias": "count", "count": count_aggregation }],
+            }
+        });
+        add_read_options(&mut body, options)?;
         
-        if let Some(fields) = raw_document.get("fields") {
-            convert_raw_firestore_fields_to_json(fields)
-        } else {
-            Ok(serde_json::Value::Object(serde_json::Map::new()))
+        let endpoint = format!("{}:runAggregationQuery", self.endpoint(parent));
+        let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+            .into_result("Failed to run aggregation query")?;
+        
+        let items: Vec<AggregationResponseItem> = parse_response_stream(&response.body)?;
+        let count = items.into_iter()
+            .filter_map(|item| item.result)
+            .find_map(|result| match result.aggregate_fields.get("count") {
+                Some(FirestoreValue::IntegerValue(n)) => n.parse::<u64>().ok(),
+                _ => None,
+            })
+            .unwrap_or(0);
+        self.costs.charge("aggregate", query_collection(query), BillableOps::aggregation(count));
+        if cap.is_some_and(|cap| count >= cap) {
+            return Err(self.costs.budget_exceeded("aggregate"));
         }
+        Ok(count)
     }
-    
-    pub async fn update_document(&self, collection: &str, doc_id: &str, data: serde_json::Value, merge: bool) -> Result<()> {
-        let fields = json_to_firestore_fields(data)?;
-        let request = UpdateDocumentRequest { fields };
+
+    async fn explain_query(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        options: &ReadOptions,
+        explain: &ExplainOptions,
+    ) -> Result<ExplainedQuery> {
+        query.validate()?;
+        // Planning alone reads nothing; analyzing is billed like the query itself
+        let skipped = query.offset.unwrap_or(0).max(0) as u64;
+        let _reserved = if explain.analyze {
+            Some(self.costs.reserve_reads("query", skipped + 1)?)
+        } else {
+            None
+        };
+        let mut body = json!({ "structuredQuery": query, "explainOptions": explain });
+        add_read_options(&mut body, options)?;
+        
+        let endpoint = format!("{}:runQuery", self.endpoint(parent));
+        let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+            .into_result("Failed to explain query")?;
         
-        let update_mask = if merge {
-            let field_names: Vec<String> = request.fields.keys().cloned().collect();
-            format!("&updateMask.fieldPaths={}", field_names.join("&updateMask.fieldPaths="))
+        let mut explained = ExplainedQuery::default();
+        for item in parse_response_stream::<RunQueryResponseItem>(&response.body)? {
+            explained.documents.extend(item.document);
+            if let Some(metrics) = item.explain_metrics {
+                explained.metrics = metrics;
+            }
+        }
+        if explain.analyze {
+            self.metrics.add_reads(explained.documents.len());
+            self.costs.charge("query", query_collection(query), BillableOps::query(explained.documents.len() as u64 + skipped));
+        }
+        Ok(explained)
+    }
+
+    async fn explain_count(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        options: &ReadOptions,
+        explain: &ExplainOptions,
+    ) -> Result<ExplainedCount> {
+        query.validate()?;
+        let _reserved = if explain.analyze {
+            Some(self.costs.reserve_reads("aggregate", 1)?)
         } else {
-            String::new()
+            None
         };
+        let mut body = json!({
+            "structuredAggregationQuery": {
+                "structuredQuery": query,
+                "aggregations": [{ "alias": "count", "count": {} }],
+            },
+            "explainOptions": explain,
+        });
+        add_read_options(&mut body, options)?;
+        
+        let endpoint = format!("{}:runAggregationQuery", self.endpoint(parent));
+        let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+            .into_result("Failed to explain aggregation query")?;
+        
+        let mut explained = ExplainedCount::default();
+        for item in parse_response_stream::<AggregationResponseItem>(&response.body)? {
+            if let Some(result) = item.result
+                && let Some(FirestoreValue::IntegerValue(n)) = result.aggregate_fields.get("count")
+            {
+                explained.count = n.parse::<u64>().ok();
+            }
+            if let Some(metrics) = item.explain_metrics {
+                explained.metrics = metrics;
+            }
+        }
+        if let Some(count) = explained.count {
+            self.costs.charge("aggregate", query_collection(query), BillableOps::aggregation(count));
+        }
+        Ok(explained)
+    }
+
+    async fn batch_get(&self, paths: &[String], options: &ReadOptions) -> Result<Vec<Option<StoredDocument>>> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
         
-        let url = format!("{}/{}?key={}{}", 
-            format!("{}/{}", self.base_url, collection.trim_start_matches('/')), 
-            doc_id, self.api_key, update_mask);
+        let _reserved = self.costs.reserve_reads("batch_get", paths.len() as u64)?;
+        let names: Vec<String> = paths.iter().map(|path| self.document_name(path)).collect();
+        let mut body = json!({ "documents": names });
+        add_read_options(&mut body, options)?;
         
-        let response = self.client
-            .patch(&url)
-            .json(&request)
-            .send()
-            .await?;
+        let response = self.execute(Method::POST, &self.endpoint(":batchGet"), &[], Some(&body)).await?
+            .into_result("Failed to batch get documents")?;
         
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Update failed: {}", error_text)));
+        // Results may arrive in any order, so index them by name
+        let items: Vec<BatchGetResponseItem> = parse_response_stream(&response.body)?;
+        let mut found: HashMap<String, StoredDocument> = HashMap::new();
+        for item in items {
+            if let Some(document) = item.found {
+                found.insert(document.name.clone(), document);
+            } else if let Some(name) = item.missing {
+                found.remove(&name);
+            }
         }
         
-        Ok(())
+        self.metrics.add_reads(found.len());
+        // Every requested document is billed, found or not
+        for path in paths {
+            self.costs.charge("batch_get", parent_collection(path), BillableOps::reads(1));
+        }
+        Ok(names.iter().map(|name| found.get(name).cloned()).collect())
     }
-    
-    pub async fn delete_document(&self, collection: &str, doc_id: &str) -> Result<()> {
-        let url = format!("{}/{}?key={}", 
-            format!("{}/{}", self.base_url, collection.trim_start_matches('/')), 
-            doc_id, self.api_key);
+
+    async fn commit(&self, writes: Vec<Write>, transaction: Option<&str>) -> Result<CommitResponse> {
+        let deletes = writes.iter().filter(|w| matches!(w, Write::Delete { .. })).count();
+        let updates = writes.len() - deletes;
+        let charges: Vec<(String, BillableOps)> = writes.iter()
+            .map(|write| {
+                let ops = match write {
+                    Write::Delete { .. } => BillableOps::deletes(1),
+                    _ => BillableOps::writes(1),
+                };
+                (parent_collection(write.path()).to_string(), ops)
+            })
+            .collect();
+        let writes: Vec<serde_json::Value> = writes.iter().map(|write| self.write_to_json(write)).collect();
+        let mut body = json!({ "writes": writes });
+        if let Some(transaction) = transaction {
+            body["transaction"] = json!(transaction);
+        }
         
-        let response = self.client
-            .delete(&url)
-            .send()
-            .await?;
+        let response = self.execute(Method::POST, &self.endpoint(":commit"), &[], Some(&body)).await?
+            .into_result("Failed to commit writes")?;
         
-        if response.status().as_u16() == 404 {
-            return Err(FirebaseError::NotFound(format!("Document '{}' not found in collection '{}'", doc_id, collection)));
+        let commit: CommitResponseBody = serde_json::from_str(&response.body)?;
+        self.metrics.add_writes(updates);
+        self.metrics.add_deletes(deletes);
+        for (collection, ops) in charges {
+            self.costs.charge("commit", &collection, ops);
+        }
+        Ok(CommitResponse {
+            write_results: commit.write_results.into_iter()
+                .map(|result| WriteResult { update_time: result.update_time })
+                .collect(),
+            commit_time: commit.commit_time,
+        })
+    }
+
+    async fn batch_write(&self, writes: Vec<Write>) -> Result<Vec<Result<WriteResult>>> {
+        if writes.is_empty() {
+            return Ok(Vec::new());
         }
         
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Delete failed: {}", error_text)));
+        let body = json!({ "writes": writes.iter().map(|write| self.write_to_json(write)).collect::<Vec<_>>() });
+        let response = self.execute(Method::POST, &self.endpoint(":batchWrite"), &[], Some(&body)).await?
+            .into_result("Failed to batch write documents")?;
+        
+        let batch: BatchWriteResponseBody = serde_json::from_str(&response.body)?;
+        let mut statuses = batch.status.into_iter();
+        let mut write_results = batch.write_results.into_iter();
+        let mut results = Vec::with_capacity(writes.len());
+        for write in &writes {
+            let status = statuses.next().unwrap_or_default();
+            let update_time = write_results.next().and_then(|result| result.update_time);
+            let result = status.into_result().map(|()| WriteResult { update_time });
+            if result.is_ok() {
+                let collection = parent_collection(write.path());
+                if matches!(write, Write::Delete { .. }) {
+                    self.metrics.add_deletes(1);
+                    self.costs.charge("batch_write", collection, BillableOps::deletes(1));
+                } else {
+                    self.metrics.add_writes(1);
+                    self.costs.charge("batch_write", collection, BillableOps::writes(1));
+                }
+            }
+            results.push(result);
         }
+        Ok(results)
+    }
+
+    async fn begin_transaction(&self, read_only: bool) -> Result<String> {
+        let body = if read_only {
+            json!({ "options": { "readOnly": {} } })
+        } else {
+            json!({ "options": { "readWrite": {} } })
+        };
         
+        let response = self.execute(Method::POST, &self.endpoint(":beginTransaction"), &[], Some(&body)).await?
+            .into_result("Failed to begin transaction")?;
+        
+        let value: serde_json::Value = serde_json::from_str(&response.body)?;
+        value.get("transaction")
+            .and_then(|t| t.as_str())
+            .map(|t| t.to_string())
+            .ok_or_else(|| FirebaseError::DatabaseError("Transaction ID missing from response".to_string()))
+    }
+
+    async fn rollback(&self, transaction: &str) -> Result<()> {
+        let body = json!({ "transaction": transaction });
+        self.execute(Method::POST, &self.endpoint(":rollback"), &[], Some(&body)).await?
+            .into_result("Failed to roll back transaction")?;
         Ok(())
     }
+
+    fn max_in_flight(&self) -> usize {
+        self.throttle.as_ref().and_then(|t| t.max_in_flight()).unwrap_or(1)
+    }
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>> {
+        let endpoint = format!("{}:listCollectionIds", self.endpoint(parent));
+        let mut collection_ids = Vec::new();
+        let mut page_token: Option<String> = None;
+        
+        loop {
+            let _reserved = self.costs.reserve_reads("list_collection_ids", 1)?;
+            let mut body = json!({ "pageSize": 300 });
+            if let Some(token) = &page_token {
+                body["pageToken"] = json!(token);
+            }
+            
+            let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+                .into_result("Failed to list collection IDs")?;
+            let page: ListCollectionIdsResponse = serde_json::from_str(&response.body)?;
+            // Billed as one read per request, however many IDs come back
+            self.costs.charge("list_collection_ids", normalize_path(parent), BillableOps::reads(1));
+            collection_ids.extend(page.collection_ids);
+            
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
+            }
+        }
+        
+        Ok(collection_ids)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RunQueryRequest {
-    structured_query: StructuredQuery,
+/// The collection holding the document at `path` (`users/alice` -> `users`).
+fn parent_collection(path: &str) -> &str {
+    let path = normalize_path(path);
+    path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or(path)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct QueryDocumentResponse {
-    document: Option<Document>,
-    #[serde(rename = "readTime")]
-    read_time: Option<String>,
+fn query_collection(query: &StructuredQuery) -> &str {
+    query.from.first().map(|from| from.collection_id.as_str()).unwrap_or_default()
 }
 
-// Helper functions for JSON <-> Firestore conversion
-fn json_to_firestore_fields(value: serde_json::Value) -> Result<HashMap<String, FirestoreValue>> {
-    let mut fields = HashMap::new();
-    
-    if let serde_json::Value::Object(map) = value {
-        for (key, val) in map {
-            fields.insert(key, json_value_to_firestore(val)?);
-        }
-    } else {
-        return Err(FirebaseError::ValidationError("Root value must be an object".to_string()));
+/// Adds the transaction or read time of `options` to a GET request's query string.
+fn add_read_params(query: &mut Vec<(&str, String)>, options: &ReadOptions) -> Result<()> {
+    options.read_time()?;
+    if let Some(transaction) = &options.transaction {
+        query.push(("transaction", transaction.clone()));
     }
-    
-    Ok(fields)
+    if let Some(read_time) = &options.read_time {
+        query.push(("readTime", read_time.clone()));
+    }
+    Ok(())
 }
 
-fn json_value_to_firestore(value: serde_json::Value) -> Result<FirestoreValue> {
-    match value {
-        serde_json::Value::String(s) => Ok(FirestoreValue::StringValue(s)),
-        serde_json::Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                Ok(FirestoreValue::IntegerValue(i.to_string()))
-            } else if let Some(f) = n.as_f64() {
-                Ok(FirestoreValue::DoubleValue(f))
-            } else {
-                Ok(FirestoreValue::StringValue(n.to_string()))
-            }
-        }
-        serde_json::Value::Bool(b) => Ok(FirestoreValue::BooleanValue(b)),
-        serde_json::Value::Null => Ok(FirestoreValue::NullValue(None)),
-        serde_json::Value::Array(arr) => {
-            let mut values = Vec::new();
-            for item in arr {
-                values.push(json_value_to_firestore(item)?);
-            }
-            Ok(FirestoreValue::ArrayValue { values })
-        }
-        serde_json::Value::Object(map) => {
-            let mut fields = HashMap::new();
-            for (key, val) in map {
-                fields.insert(key, json_value_to_firestore(val)?);
-            }
-            Ok(FirestoreValue::MapValue { fields })
-        }
+/// Adds the transaction or read time of `options` to a POST request body.
+fn add_read_options(body: &mut serde_json::Value, options: &ReadOptions) -> Result<()> {
+    options.read_time()?;
+    if let Some(transaction) = &options.transaction {
+        body["transaction"] = json!(transaction);
     }
+    if let Some(read_time) = &options.read_time {
+        body["readTime"] = json!(read_time);
+    }
+    Ok(())
 }
 
-fn firestore_fields_to_json(fields: HashMap<String, FirestoreValue>) -> Result<serde_json::Value> {
-    let mut map = serde_json::Map::new();
-    
-    for (key, value) in fields {
-        map.insert(key, firestore_value_to_json(value)?);
+/// Parses a streamed REST response (`runQuery`, `batchGet`, ...). These come
+/// back as a JSON array, or as newline-delimited objects when streamed.
+pub(crate) fn parse_response_stream<T: serde::de::DeserializeOwned>(body: &str) -> Result<Vec<T>> {
+    let trimmed = body.trim();
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+    if trimmed.starts_with('[') {
+        return Ok(serde_json::from_str(trimmed)?);
     }
     
-    Ok(serde_json::Value::Object(map))
+    let mut items = Vec::new();
+    for line in trimmed.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        items.push(serde_json::from_str(line)?);
+    }
+    Ok(items)
 }
 
-fn firestore_value_to_json(value: FirestoreValue) -> Result<serde_json::Value> {
-    match value {
-        FirestoreValue::StringValue(s) => Ok(serde_json::Value::String(s)),
-        FirestoreValue::IntegerValue(i) => {
-            Ok(serde_json::Value::Number(serde_json::Number::from(i.parse::<i64>().unwrap_or(0))))
-        }
-        FirestoreValue::DoubleValue(f) => {
-            Ok(serde_json::Value::Number(serde_json::Number::from_f64(f).unwrap_or(serde_json::Number::from(0))))
-        }
-        FirestoreValue::BooleanValue(b) => Ok(serde_json::Value::Bool(b)),
-        FirestoreValue::NullValue(_) => Ok(serde_json::Value::Null),
-        FirestoreValue::TimestampValue(ts) => Ok(serde_json::Value::String(ts)),
-        FirestoreValue::ArrayValue { values } => {
-            let mut json_arr = Vec::new();
-            for val in values {
-                json_arr.push(firestore_value_to_json(val)?);
-            }
-            Ok(serde_json::Value::Array(json_arr))
-        }
-        FirestoreValue::MapValue { fields } => {
-            let mut json_map = serde_json::Map::new();
-            for (key, val) in fields {
-                json_map.insert(key, firestore_value_to_json(val)?);
+#[async_trait]
+impl IndexAdmin for FirebaseClient {
+    async fn list_indexes(&self, collection_group: &str) -> Result<Vec<AdminIndex>> {
+        let endpoint = self.admin_endpoint(&format!("collectionGroups/{}/indexes", collection_group));
+        let mut indexes = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let query: Vec<(&str, String)> = page_token.iter().map(|token| ("pageToken", token.clone())).collect();
+            let response = self.execute(Method::GET, &endpoint, &query, None).await?
+                .into_result("Failed to list indexes")?;
+            let page: ListIndexesResponse = serde_json::from_str(&response.body)?;
+            indexes.extend(page.indexes);
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
             }
-            Ok(serde_json::Value::Object(json_map))
         }
-        FirestoreValue::Unknown => Ok(serde_json::Value::String("(unknown)".to_string())),
+        Ok(indexes)
     }
-}
 
-// Convert raw Firestore fields (from JSON response) to JSON
-fn convert_raw_firestore_fields_to_json(fields: &serde_json::Value) -> Result<serde_json::Value> {
-    let mut json_map = serde_json::Map::new();
-    
-    if let Some(fields_obj) = fields.as_object() {
-        for (key, value) in fields_obj {
-            json_map.insert(key.clone(), convert_raw_firestore_value_to_json(value)?);
-        }
+    async fn create_index(&self, index: &CompositeIndex) -> Result<Operation> {
+        let endpoint = self.admin_endpoint(&format!("collectionGroups/{}/indexes", index.collection_group));
+        let body = json!({ "queryScope": index.query_scope, "fields": index.fields });
+        let response = self.execute(Method::POST, &endpoint, &[], Some(&body)).await?
+            .into_result("Failed to create index")?;
+        Ok(serde_json::from_str(&response.body)?)
     }
-    
-    Ok(serde_json::Value::Object(json_map))
-}
 
-// Convert raw Firestore value to JSON (handles the actual Firestore format)
-fn convert_raw_firestore_value_to_json(value: &serde_json::Value) -> Result<serde_json::Value> {
-    if let Some(string_val) = value.get("stringValue").and_then(|v| v.as_str()) {
-        Ok(serde_json::Value::String(string_val.to_string()))
-    } else if let Some(int_val) = value.get("integerValue").and_then(|v| v.as_str()) {
-        if let Ok(num) = int_val.parse::<i64>() {
-            Ok(serde_json::Value::Number(serde_json::Number::from(num)))
-        } else {
-            Ok(serde_json::Value::String(int_val.to_string()))
-        }
-    } else if let Some(double_val) = value.get("doubleValue").and_then(|v| v.as_f64()) {
-        Ok(serde_json::Value::Number(serde_json::Number::from_f64(double_val).unwrap_or(serde_json::Number::from(0))))
-    } else if let Some(bool_val) = value.get("booleanValue").and_then(|v| v.as_bool()) {
-        Ok(serde_json::Value::Bool(bool_val))
-    } else if let Some(timestamp) = value.get("timestampValue").and_then(|v| v.as_str()) {
-        Ok(serde_json::Value::String(timestamp.to_string()))
-    } else if value.get("arrayValue").is_some() {
-        let json_array = if let Some(array_values) = value.get("arrayValue").and_then(|v| v.get("values")).and_then(|v| v.as_array()) {
-            let mut json_array = Vec::new();
-            for item in array_values {
-                json_array.push(convert_raw_firestore_value_to_json(item)?);
-            }
-            json_array
-        } else {
-            // Empty array case - Firestore might not include "values" field for empty arrays
-            Vec::new()
-        };
-        Ok(serde_json::Value::Array(json_array))
-    } else if let Some(map_fields) = value.get("mapValue").and_then(|v| v.get("fields")) {
-        convert_raw_firestore_fields_to_json(map_fields)
-    } else if value.get("nullValue").is_some() {
-        Ok(serde_json::Value::Null)
-    } else {
-        // Fallback for unknown types
-        Ok(serde_json::Value::Null)
-    }
-}
\ No newline at end of file
+    async fn delete_index(&self, name: &str) -> Result<()> {
+        self.execute(Method::DELETE, &self.resource_endpoint(name), &[], None).await?
+            .into_result("Failed to delete index")?;
+        Ok(())
+    }
+
+    async fn get_operation(&self, name: &str) -> Result<Operation> {
+        let response = self.execute(Method::GET, &self.resource_endpoint(name), &[], None).await?
+            .into_result("Failed to get operation")?;
+        Ok(serde_json::from_str(&response.body)?)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::error::{ApiStatus, FirebaseError, Result, RpcCode};
+use crate::indexes::{CompositeIndex, IndexDefinitions, IndexedField, QueryScope};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+const NAME_FIELD: &str = "__name__";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A composite index as the Firestore Admin API reports it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminIndex {
+    /// e.g. `projects/p/databases/(default)/collectionGroups/users/indexes/CICAgOi3kJAK`.
+    #[serde(default)]
+    pub name: String,
+    pub query_scope: QueryScope,
+    #[serde(default)]
+    pub fields: Vec<IndexedField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<IndexState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IndexState {
+    StateUnspecified,
+    Creating,
+    Ready,
+    NeedsRepair,
+}
+
+impl AdminIndex {
+    pub fn collection_group(&self) -> &str {
+        self.name.split_once("/collectionGroups/")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .unwrap_or("")
+    }
+
+    /// The index as a definition, without the trailing `__name__` field the
+    /// API adds to every index.
+    pub fn definition(&self) -> CompositeIndex {
+        let mut fields = self.fields.clone();
+        if fields.last().is_some_and(|field| field.field_path == NAME_FIELD) {
+            fields.pop();
+        }
+        CompositeIndex { collection_group: self.collection_group().to_string(), query_scope: self.query_scope, fields }
+    }
+}
+
+/// A long-running operation, e.g. an index build.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    /// e.g. `projects/p/databases/(default)/operations/S1RhcmJ1...`.
+    pub name: String,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<OperationError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OperationError {
+    #[serde(default)]
+    pub code: i32,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// The composite index endpoints of the Firestore Admin API.
+#[async_trait]
+pub trait IndexAdmin: Send + Sync {
+    /// Lists the composite indexes of a collection group, or of all groups for `-`.
+    async fn list_indexes(&self, collection_group: &str) -> Result<Vec<AdminIndex>>;
+
+    /// Starts building an index, returning the operation to wait on.
+    async fn create_index(&self, index: &CompositeIndex) -> Result<Operation>;
+
+    /// Deletes an index by its full resource name.
+    async fn delete_index(&self, name: &str) -> Result<()>;
+
+    async fn get_operation(&self, name: &str) -> Result<Operation>;
+}
+
+/// What reconciling would change in one or more collection groups.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexPlan {
+    pub create: Vec<CompositeIndex>,
+    pub delete: Vec<AdminIndex>,
+    pub unchanged: Vec<AdminIndex>,
+}
+
+impl IndexPlan {
+    /// True when the database already matches.
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// Brings a database's composite indexes in line with [`IndexDefinitions`]:
+/// [`plan`](Self::plan) diffs them, [`apply`](Self::apply) creates and
+/// deletes indexes and waits for the builds. Field overrides are left alone.
+pub struct IndexReconciler<'a, A: IndexAdmin> {
+    admin: &'a A,
+    poll_interval: Duration,
+    timeout: Duration,
+}
+
+impl<'a, A: IndexAdmin> IndexReconciler<'a, A> {
+    pub fn new(admin: &'a A) -> Self {
+        Self { admin, poll_interval: DEFAULT_POLL_INTERVAL, timeout: DEFAULT_TIMEOUT }
+    }
+
+    /// How often to check on index builds (default 2s).
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How long to wait for each index build (default 30 minutes).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Compares `desired` with the indexes in `collection_groups`. Existing
+    /// indexes there that are not desired are planned for deletion; other
+    /// collection groups are not touched.
+    pub async fn plan(&self, desired: &IndexDefinitions, collection_groups: &BTreeSet<String>) -> Result<IndexPlan> {
+        let mut plan = IndexPlan::default();
+        let mut existing = Vec::new();
+        for group in collection_groups {
+            existing.extend(self.admin.list_indexes(group).await?);
+        }
+
+        for index in existing {
+            if desired.indexes.contains(&index.definition()) {
+                plan.unchanged.push(index);
+            } else {
+                plan.delete.push(index);
+            }
+        }
+        for index in &desired.indexes {
+            if collection_groups.contains(&index.collection_group)
+                && !plan.unchanged.iter().any(|existing| &existing.definition() == index)
+                && !plan.create.contains(index)
+            {
+                plan.create.push(index.clone());
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Deletes, then creates, then waits for every build to finish.
+    pub async fn apply(&self, plan: &IndexPlan) -> Result<()> {
+        for index in &plan.delete {
+            tracing::info!(index = %index.name, "Deleting index");
+            self.admin.delete_index(&index.name).await?;
+        }
+        let mut operations = Vec::new();
+        for index in &plan.create {
+            tracing::info!(collection_group = %index.collection_group, fields = %index.describe(), "Creating index");
+            operations.push(self.admin.create_index(index).await?);
+        }
+        for operation in operations {
+            self.wait(operation).await?;
+        }
+        Ok(())
+    }
+
+    /// Polls `operation` until it is done, failing if it reports an error or
+    /// takes longer than the timeout.
+    pub async fn wait(&self, mut operation: Operation) -> Result<Operation> {
+        let started = Instant::now();
+        while !operation.done {
+            if started.elapsed() > self.timeout {
+                return Err(FirebaseError::DatabaseError(format!(
+                    "Timed out after {:?} waiting for operation {}", self.timeout, operation.name
+                )));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+            operation = self.admin.get_operation(&operation.name).await?;
+            tracing::debug!(operation = %operation.name, done = operation.done, "Polled operation");
+        }
+        match &operation.error {
+            Some(error) if error.code != 0 => Err(FirebaseError::ApiError(ApiStatus {
+                code: RpcCode::from_code(error.code),
+                http_status: None,
+                message: format!("Operation {} failed: {}", operation.name, error.message),
+            })),
+            _ => Ok(operation),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::error::{FirebaseError, Result};
+use crate::models::FirestoreValue;
+use crate::query::{CompositeOperator, Direction, FieldOperator, Filter, StructuredQuery, UnaryOperator};
+use crate::schema::{Collection, IndexOrder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+
+const NAME_FIELD: &str = "__name__";
+
+/// The contents of a `firestore.indexes.json` file, as deployed with
+/// `firebase deploy --only firestore:indexes`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexDefinitions {
+    #[serde(default)]
+    pub indexes: Vec<CompositeIndex>,
+    #[serde(default)]
+    pub field_overrides: Vec<FieldOverride>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeIndex {
+    pub collection_group: String,
+    pub query_scope: QueryScope,
+    pub fields: Vec<IndexedField>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueryScope {
+    Collection,
+    CollectionGroup,
+}
+
+/// One field of an index. Exactly one of `order`, `array_config` and
+/// `vector_config` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedField {
+    pub field_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Direction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub array_config: Option<ArrayConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_config: Option<VectorConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ArrayConfig {
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorConfig {
+    pub dimension: usize,
+    /// Firestore's only vector index type; always `{}`.
+    #[serde(default)]
+    pub flat: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Replaces the automatic single-field indexes of one field. Collection group
+/// queries need these, since single-field indexes are only automatic in
+/// collection scope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldOverride {
+    pub collection_group: String,
+    pub field_path: String,
+    #[serde(default)]
+    pub indexes: Vec<FieldIndex>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldIndex {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Direction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub array_config: Option<ArrayConfig>,
+    pub query_scope: QueryScope,
+}
+
+/// A named query kept in a saved-queries file (JSON or YAML), in the REST
+/// `StructuredQuery` format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub query: StructuredQuery,
+}
+
+impl IndexedField {
+    pub fn ascending(field_path: impl Into<String>) -> Self {
+        Self::ordered(field_path, Direction::Ascending)
+    }
+
+    pub fn descending(field_path: impl Into<String>) -> Self {
+        Self::ordered(field_path, Direction::Descending)
+    }
+
+    pub fn ordered(field_path: impl Into<String>, direction: Direction) -> Self {
+        Self { field_path: field_path.into(), order: Some(direction), array_config: None, vector_config: None }
+    }
+
+    pub fn contains(field_path: impl Into<String>) -> Self {
+        Self { field_path: field_path.into(), order: None, array_config: Some(ArrayConfig::Contains), vector_config: None }
+    }
+
+    pub fn vector(field_path: impl Into<String>, dimension: usize) -> Self {
+        Self {
+            field_path: field_path.into(),
+            order: None,
+            array_config: None,
+            vector_config: Some(VectorConfig { dimension, flat: serde_json::Map::new() }),
+        }
+    }
+
+    /// e.g. `age DESC`, `tags CONTAINS` or `embedding VECTOR<768>`.
+    pub fn describe(&self) -> String {
+        let kind = match (&self.order, &self.array_config, &self.vector_config) {
+            (Some(Direction::Descending), _, _) => "DESC".to_string(),
+            (Some(Direction::Ascending), _, _) => "ASC".to_string(),
+            (_, Some(ArrayConfig::Contains), _) => "CONTAINS".to_string(),
+            (_, _, Some(vector)) => format!("VECTOR<{}>", vector.dimension),
+            _ => "?".to_string(),
+        };
+        format!("{} {}", self.field_path, kind)
+    }
+}
+
+impl CompositeIndex {
+    /// e.g. `(status ASC, age DESC)`.
+    pub fn describe(&self) -> String {
+        format!("({})", self.fields.iter().map(IndexedField::describe).collect::<Vec<_>>().join(", "))
+    }
+}
+
+impl IndexDefinitions {
+    /// The indexes needed by all of `queries`.
+    pub fn from_queries<'a>(queries: impl IntoIterator<Item = &'a StructuredQuery>) -> Self {
+        let mut definitions = Self::default();
+        for query in queries {
+            definitions.add_query(query);
+        }
+        definitions
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read index file {}: {}", path, e)))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Writes the definitions as `firestore.indexes.json`.
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to write index file {}: {}", path, e)))
+    }
+
+    /// Sorts by collection group and fields, so output is stable between runs.
+    pub fn sort(&mut self) {
+        self.indexes.sort_by_key(|index| (index.collection_group.clone(), index.query_scope, index.describe()));
+        self.field_overrides.sort_by(|a, b| {
+            (&a.collection_group, &a.field_path).cmp(&(&b.collection_group, &b.field_path))
+        });
+    }
+
+    /// Adds the composite indexes and field overrides `query` needs. Each
+    /// branch of an OR is planned on its own, as Firestore does.
+    ///
+    /// Queries served by automatic single-field indexes need nothing: a
+    /// single order or inequality, or only equality filters (which Firestore
+    /// merges). Collection group queries get field overrides instead.
+    pub fn add_query(&mut self, query: &StructuredQuery) {
+        let Some(selector) = query.from.first() else {
+            return;
+        };
+        let scope = if selector.all_descendants.unwrap_or(false) { QueryScope::CollectionGroup } else { QueryScope::Collection };
+        let branches = match &query.r#where {
+            Some(filter) => disjuncts(filter),
+            None => vec![Vec::new()],
+        };
+
+        for branch in branches {
+            let (equalities, inequalities) = split_filters(&branch);
+            let (fields, composite) = match &query.find_nearest {
+                Some(nearest) => {
+                    let dimension = match &nearest.query_vector {
+                        FirestoreValue::VectorValue(vector) => vector.len(),
+                        _ => 0,
+                    };
+                    let mut fields = equalities;
+                    fields.push(IndexedField::vector(&nearest.vector_field.field_path, dimension));
+                    (fields, true)
+                }
+                None => {
+                    let orders = branch_orders(query, &inequalities);
+                    let ordered = !orders.is_empty();
+                    let mut fields: Vec<IndexedField> = equalities.into_iter()
+                        .filter(|field| !orders.iter().any(|order| order.field_path == field.field_path))
+                        .collect();
+                    fields.extend(orders);
+                    let composite = ordered && fields.len() > 1;
+                    (fields, composite)
+                }
+            };
+
+            if composite {
+                self.add_index(CompositeIndex { collection_group: selector.collection_id.clone(), query_scope: scope, fields });
+            } else if scope == QueryScope::CollectionGroup {
+                for field in fields {
+                    self.add_override(&selector.collection_id, &field);
+                }
+            }
+        }
+    }
+
+    /// Adds the composite indexes declared on a schema collection. Firestore
+    /// has no unique indexes, so `unique` is ignored, and single-field
+    /// indexes are skipped because Firestore creates them automatically.
+    pub fn add_schema(&mut self, collection: &Collection) {
+        for index in &collection.indexes {
+            if index.fields.len() < 2 {
+                continue;
+            }
+            let fields = index.fields.iter()
+                .map(|field| match field.order {
+                    IndexOrder::Ascending => IndexedField::ascending(&field.field_path),
+                    IndexOrder::Descending => IndexedField::descending(&field.field_path),
+                })
+                .collect();
+            self.add_index(CompositeIndex {
+                collection_group: collection.name.clone(),
+                query_scope: QueryScope::Collection,
+                fields,
+            });
+        }
+    }
+
+    pub fn add_index(&mut self, index: CompositeIndex) {
+        if !self.indexes.contains(&index) {
+            self.indexes.push(index);
+        }
+    }
+
+    pub fn merge(&mut self, other: IndexDefinitions) {
+        for index in other.indexes {
+            self.add_index(index);
+        }
+        for field_override in other.field_overrides {
+            let existing = self.field_overrides.iter_mut().find(|existing| {
+                existing.collection_group == field_override.collection_group && existing.field_path == field_override.field_path
+            });
+            match existing {
+                Some(existing) => {
+                    for index in field_override.indexes {
+                        if !existing.indexes.contains(&index) {
+                            existing.indexes.push(index);
+                        }
+                    }
+                }
+                None => self.field_overrides.push(field_override),
+            }
+        }
+    }
+
+    /// An override replaces all automatic indexes on the field, so it keeps
+    /// the collection-scope defaults and adds the collection group index.
+    fn add_override(&mut self, collection_group: &str, field: &IndexedField) {
+        let index = FieldIndex { order: field.order, array_config: field.array_config, query_scope: QueryScope::CollectionGroup };
+        let mut indexes = vec![
+            FieldIndex { order: Some(Direction::Ascending), array_config: None, query_scope: QueryScope::Collection },
+            FieldIndex { order: Some(Direction::Descending), array_config: None, query_scope: QueryScope::Collection },
+            FieldIndex { order: None, array_config: Some(ArrayConfig::Contains), query_scope: QueryScope::Collection },
+        ];
+        indexes.push(index);
+        self.merge(IndexDefinitions {
+            indexes: Vec::new(),
+            field_overrides: vec![FieldOverride {
+                collection_group: collection_group.to_string(),
+                field_path: field.field_path.clone(),
+                indexes,
+            }],
+        });
+    }
+}
+
+/// Reads a saved-queries file: a JSON or YAML list of [`SavedQuery`].
+pub fn load_saved_queries(path: &str) -> Result<Vec<SavedQuery>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| FirebaseError::ConfigError(format!("Failed to read queries file {}: {}", path, e)))?;
+    // Filters are externally tagged enums, which serde_yaml only reads as
+    // YAML tags, so YAML goes through a JSON value first
+    let value: serde_json::Value = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&content)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse queries file {}: {}", path, e)))?
+    } else {
+        serde_json::from_str(&content)?
+    };
+    serde_json::from_value(value)
+        .map_err(|e| FirebaseError::ConfigError(format!("Invalid saved query in {}: {}", path, e)))
+}
+
+/// The filter as an OR of AND-only branches, each a list of field and unary filters.
+pub(crate) fn disjuncts(filter: &Filter) -> Vec<Vec<&Filter>> {
+    match filter {
+        Filter::CompositeFilter(composite) => match composite.op {
+            CompositeOperator::Or => composite.filters.iter().flat_map(disjuncts).collect(),
+            CompositeOperator::And => composite.filters.iter().fold(vec![Vec::new()], |branches, filter| {
+                let children = disjuncts(filter);
+                branches.iter()
+                    .flat_map(|branch| children.iter().map(move |child| [branch.as_slice(), child].concat()))
+                    .collect()
+            }),
+        },
+        _ => vec![vec![filter]],
+    }
+}
+
+/// Equality-like filters as index fields (sorted, so equivalent queries
+/// share an index), and the fields with inequality filters.
+fn split_filters(branch: &[&Filter]) -> (Vec<IndexedField>, BTreeSet<String>) {
+    let mut equalities: Vec<IndexedField> = Vec::new();
+    let mut inequalities = BTreeSet::new();
+    for filter in branch {
+        let (field_path, field) = match filter {
+            Filter::FieldFilter(field_filter) => {
+                let path = &field_filter.field.field_path;
+                match field_filter.op {
+                    FieldOperator::Equal | FieldOperator::In => (path, IndexedField::ascending(path)),
+                    FieldOperator::ArrayContains | FieldOperator::ArrayContainsAny => (path, IndexedField::contains(path)),
+                    _ => {
+                        inequalities.insert(path.clone());
+                        continue;
+                    }
+                }
+            }
+            Filter::UnaryFilter(unary) => {
+                let path = &unary.field.field_path;
+                match unary.op {
+                    UnaryOperator::IsNull | UnaryOperator::IsNan => (path, IndexedField::ascending(path)),
+                    UnaryOperator::IsNotNull | UnaryOperator::IsNotNan => {
+                        inequalities.insert(path.clone());
+                        continue;
+                    }
+                }
+            }
+            Filter::CompositeFilter(_) => continue,
+        };
+        if field_path != NAME_FIELD && !equalities.contains(&field) {
+            equalities.push(field);
+        }
+    }
+    equalities.sort_by(|a, b| a.field_path.cmp(&b.field_path));
+    (equalities, inequalities)
+}
+
+/// Explicit orders, then implicit ones on the branch's inequality fields,
+/// without the trailing `__name__` that every index has.
+fn branch_orders(query: &StructuredQuery, inequalities: &BTreeSet<String>) -> Vec<IndexedField> {
+    let explicit = query.order_by.clone().unwrap_or_default();
+    let last_direction = explicit.last().and_then(|order| order.direction).unwrap_or(Direction::Ascending);
+    let mut orders: Vec<IndexedField> = explicit.iter()
+        .map(|order| IndexedField::ordered(&order.field.field_path, order.direction.unwrap_or(Direction::Ascending)))
+        .collect();
+    for field_path in inequalities {
+        if !orders.iter().any(|order| &order.field_path == field_path) {
+            orders.push(IndexedField::ordered(field_path, last_direction));
+        }
+    }
+    orders.retain(|order| order.field_path != NAME_FIELD);
+    orders
+}
@@ -1,11 +1,37 @@
+use crate::billing::{BillableOps, CostEstimate};
+use crate::bulk_writer::{BulkWriteFailure, BulkWriteReport, BulkWriter};
 use crate::error::{FirebaseError, Result};
+use crate::collections::detect_auto_field;
 use crate::firebase::FirebaseClient;
-use crate::models::{FromFirestore, ToFirestore, FirestoreValue};
+use crate::models::{FromFirestore, ToFirestore, FirestoreValue, json_to_firestore_fields};
+use crate::partition::read_collection;
+use crate::store::{DocumentStore, ReadOptions, document_path};
 use crate::schema::{SchemaManager, Collection, Field, FieldType, Index, IndexField, IndexOrder, ValidationRule, ValidationRuleType};
+use crate::schema_drift::{DriftReport, detect_drift};
+use crate::schema_history::{SCHEMAS_COLLECTION, VersionBump, parse_version, schema_path, version_path, versions_path};
+use crate::store::{Transaction, Write};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 
+fn read_data_export(input_file: &str) -> Result<DataExport> {
+    let file_content = fs::read_to_string(input_file)
+        .map_err(|e| FirebaseError::ConfigError(format!("Failed to read import file: {}", e)))?;
+
+    if input_file.ends_with(".yaml") || input_file.ends_with(".yml") {
+        serde_yaml::from_str(&file_content)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse YAML import file: {}", e)))
+    } else {
+        Ok(serde_json::from_str(&file_content)?)
+    }
+}
+
+// Collections copied by `backup_all_data`. These are fixed for now rather than discovered
+const BACKUP_COLLECTIONS: &[&str] = &["users"];
+
+/// Written next to the collection files by `backup_all_data`.
+const BACKUP_MANIFEST: &str = "manifest.json";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonSchema {
     pub version: String,
@@ -36,6 +62,9 @@ pub struct JsonField {
     pub description: Option<String>,
     #[serde(default)]
     pub sample_values: Vec<String>,
+    /// How many of the sampled documents had this field, for discovered schemas.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +95,28 @@ pub struct DataExport {
     pub exported_at: String,
     pub count: usize,
     pub data: Vec<serde_json::Value>,
+    /// IDs of the documents in `data`, in the same order (raw exports only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub document_ids: Vec<String>,
+    /// The point in time the documents were read at, if pinned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_time: Option<String>,
+}
+
+/// Describes a backup made by `backup_all_data`. Every collection in it was
+/// read at `read_time`, so the files are consistent with each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub created_at: String,
+    pub read_time: String,
+    /// Documents backed up per collection.
+    pub collections: BTreeMap<String, usize>,
+}
+
+impl BackupManifest {
+    pub fn total(&self) -> usize {
+        self.collections.values().sum()
+    }
 }
 
 // Schema document stored in the 'schemas' collection in Firestore
@@ -83,22 +134,77 @@ pub struct FirestoreSchema {
     #[serde(default)]
     pub validation_rules: Vec<JsonValidationRule>,
     pub description: Option<String>,
+    /// Who saved this version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// The version this one replaced, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+    /// How the version number was incremented from `previous_version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bump: Option<VersionBump>,
+    /// The version whose content this one restores, for rollbacks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<String>,
+}
+
+impl FirestoreSchema {
+    /// The collection as it would appear in a schema file.
+    pub fn to_json_collection(&self) -> JsonCollection {
+        JsonCollection {
+            name: self.collection_name.clone(),
+            description: self.description.clone(),
+            fields: self.fields.clone(),
+            indexes: self.indexes.clone(),
+            validation_rules: self.validation_rules.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct JsonSchemaManager {
-    schema_manager: SchemaManager,
-    client: FirebaseClient,
+pub struct JsonSchemaManager<S: DocumentStore + Clone = FirebaseClient> {
+    schema_manager: SchemaManager<S>,
+    client: S,
+    read_partitions: usize,
+    read_time: Option<String>,
+    author: Option<String>,
 }
 
-impl JsonSchemaManager {
-    pub fn new(client: FirebaseClient) -> Self {
+impl<S: DocumentStore + Clone> JsonSchemaManager<S> {
+    pub fn new(client: S) -> Self {
         Self {
             schema_manager: SchemaManager::new(client.clone()),
             client,
+            read_partitions: 1,
+            read_time: None,
+            author: None,
         }
     }
 
+    /// Read collections for exports and backups with this many concurrent
+    /// partitioned queries instead of page by page.
+    pub fn with_read_partitions(mut self, partitions: usize) -> Self {
+        self.read_partitions = partitions.max(1);
+        self
+    }
+
+    /// Read exports and backups as of this RFC 3339 timestamp, which must be
+    /// within the point-in-time recovery window.
+    pub fn with_read_time(mut self, read_time: impl Into<String>) -> Self {
+        self.read_time = Some(read_time.into());
+        self
+    }
+
+    /// Record this author on schema versions saved without one.
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    fn read_options(&self) -> ReadOptions {
+        ReadOptions { read_time: self.read_time.clone(), ..ReadOptions::default() }
+    }
+
     // Schema Import/Export
     pub fn export_schema_to_file(&self, file_path: &str) -> Result<()> {
         let json_schema = self.convert_to_json_schema();
@@ -168,6 +274,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Auto-generated user ID".to_string()),
                             sample_values: vec!["user_123".to_string(), "user_456".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "name".to_string(),
@@ -178,6 +285,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("User's full name".to_string()),
                             sample_values: vec!["John Doe".to_string(), "Jane Smith".to_string(), "Alice Johnson".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "email".to_string(),
@@ -188,6 +296,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("User's email address".to_string()),
                             sample_values: vec!["john@example.com".to_string(), "jane@example.com".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "age".to_string(),
@@ -198,6 +307,7 @@ impl JsonSchemaManager {
                             default_value: Some(serde_json::Value::Number(serde_json::Number::from(18))),
                             description: Some("User's age".to_string()),
                             sample_values: vec!["25".to_string(), "30".to_string(), "28".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "active".to_string(),
@@ -208,6 +318,7 @@ impl JsonSchemaManager {
                             default_value: Some(serde_json::Value::Bool(true)),
                             description: Some("Account status".to_string()),
                             sample_values: vec!["true".to_string(), "false".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "created_at".to_string(),
@@ -218,6 +329,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Account creation timestamp".to_string()),
                             sample_values: vec!["2024-01-01T12:00:00Z".to_string(), "2024-01-15T09:30:00Z".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "tags".to_string(),
@@ -228,6 +340,7 @@ impl JsonSchemaManager {
                             default_value: Some(serde_json::Value::Array(vec![])),
                             description: Some("User tags".to_string()),
                             sample_values: vec!["[\"admin\", \"user\"]".to_string(), "[\"guest\"]".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "profile".to_string(),
@@ -238,6 +351,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("User profile data".to_string()),
                             sample_values: vec!["{\"bio\": \"Developer\"}".to_string(), "{\"location\": \"NYC\"}".to_string()],
+                            frequency: None,
                         },
                     ],
                     indexes: vec![
@@ -311,6 +425,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Post title".to_string()),
                             sample_values: vec!["My First Post".to_string(), "Hello World".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "content".to_string(),
@@ -321,6 +436,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Post content".to_string()),
                             sample_values: vec!["This is my first blog post...".to_string(), "Welcome to my blog!".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "author_id".to_string(),
@@ -331,6 +447,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Reference to user ID".to_string()),
                             sample_values: vec!["user_123".to_string(), "user_456".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "published".to_string(),
@@ -341,6 +458,7 @@ impl JsonSchemaManager {
                             default_value: Some(serde_json::Value::Bool(false)),
                             description: Some("Publication status".to_string()),
                             sample_values: vec!["true".to_string(), "false".to_string()],
+                            frequency: None,
                         },
                         JsonField {
                             name: "created_at".to_string(),
@@ -351,6 +469,7 @@ impl JsonSchemaManager {
                             default_value: None,
                             description: Some("Creation timestamp".to_string()),
                             sample_values: vec!["2024-01-01T12:00:00Z".to_string(), "2024-01-15T14:30:00Z".to_string()],
+                            frequency: None,
                         },
                     ],
                     indexes: vec![
@@ -412,8 +531,16 @@ impl JsonSchemaManager {
     pub async fn export_collection_data<T>(&self, collection_name: &str, output_file: &str) -> Result<usize>
     where
         T: FromFirestore + Serialize,
+        S: 'static,
     {
-        let data: Vec<T> = self.client.list(collection_name, None).await?;
+        let documents = read_collection(&self.client, collection_name, self.read_partitions, &self.read_options()).await?;
+        let mut data: Vec<T> = Vec::new();
+        for document in documents {
+            match T::from_firestore(&document.fields) {
+                Ok(item) => data.push(item),
+                Err(e) => tracing::warn!(document = %document.name, error = %e, "Failed to parse document"),
+            }
+        }
         
         let json_data: Vec<serde_json::Value> = data.iter()
             .map(|item| serde_json::to_value(item).unwrap_or(serde_json::Value::Null))
@@ -424,6 +551,8 @@ impl JsonSchemaManager {
             exported_at: chrono::Utc::now().to_rfc3339(),
             count: json_data.len(),
             data: json_data,
+            document_ids: Vec::new(),
+            read_time: self.read_time.clone(),
         };
 
         let json_content = serde_json::to_string_pretty(&export)?;
@@ -433,47 +562,33 @@ impl JsonSchemaManager {
         Ok(export.count)
     }
 
-    pub async fn export_collection_raw(&self, collection_name: &str, output_file: &str) -> Result<usize> {
-        // Get raw documents from Firebase
-        let url = format!("{}/{}?key={}", 
-            self.client.base_url,
-            collection_name.trim_start_matches('/'),
-            self.client.api_key
-        );
-        
-        let response = self.client.client
-            .get(&url)
-            .send()
-            .await?;
+    pub async fn export_collection_raw(&self, collection_name: &str, output_file: &str) -> Result<usize>
+    where
+        S: 'static,
+    {
+        self.export_raw_at(collection_name, output_file, &self.read_options()).await
+    }
+
+    async fn export_raw_at(&self, collection_name: &str, output_file: &str, options: &ReadOptions) -> Result<usize>
+    where
+        S: 'static,
+    {
+        let documents = read_collection(&self.client, collection_name, self.read_partitions, options).await?;
         
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to list documents: {}", error_text)));
+        let mut json_data = Vec::new();
+        let mut document_ids = Vec::new();
+        for doc in &documents {
+            json_data.push(serde_json::to_value(&doc.fields)?);
+            document_ids.push(doc.id().to_string());
         }
-        
-        let response_text = response.text().await?;
-        let list_response: serde_json::Value = serde_json::from_str(&response_text)?;
-        
-        let empty_vec = vec![];
-        let documents = list_response.get("documents")
-            .and_then(|d| d.as_array())
-            .unwrap_or(&empty_vec);
-        
-        let json_data: Vec<serde_json::Value> = documents.iter()
-            .filter_map(|doc| {
-                if let Some(fields) = doc.get("fields") {
-                    Some(fields.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
 
         let export = DataExport {
             collection: collection_name.to_string(),
             exported_at: chrono::Utc::now().to_rfc3339(),
             count: json_data.len(),
             data: json_data,
+            document_ids,
+            read_time: options.read_time.clone(),
         };
 
         let json_content = serde_json::to_string_pretty(&export)?;
@@ -483,65 +598,138 @@ impl JsonSchemaManager {
         Ok(export.count)
     }
 
-    pub async fn import_collection_data<T>(&self, input_file: &str, collection_name: Option<&str>) -> Result<usize>
+    /// Imports a data export through a [`BulkWriter`]. Each document is first
+    /// validated against the target collection's schema, the one defined on
+    /// the schema manager or else the one stored in Firestore, with custom
+    /// rules resolved through the schema manager's validator registry.
+    /// Documents that fail are reported with operation `validate` and not
+    /// written. Failure indexes in the report are positions in the file's
+    /// `data` array.
+    pub async fn import_collection_data<T>(&self, input_file: &str, collection_name: Option<&str>) -> Result<BulkWriteReport>
     where
         T: for<'de> Deserialize<'de> + ToFirestore,
+        S: 'static,
     {
-        let file_content = fs::read_to_string(input_file)
-            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read import file: {}", e)))?;
-
-        let export: DataExport = if input_file.ends_with(".yaml") || input_file.ends_with(".yml") {
-            serde_yaml::from_str(&file_content)
-                .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse YAML import file: {}", e)))?
-        } else {
-            serde_json::from_str(&file_content)?
+        let export = read_data_export(input_file)?;
+        let target_collection = collection_name.unwrap_or(&export.collection).to_string();
+        let schema = match self.schema_manager.get_collection(&target_collection) {
+            Some(collection) => Some(collection.clone()),
+            None => self.stored_collection_schema(&target_collection).await?,
         };
-        let target_collection = collection_name.unwrap_or(&export.collection);
-
-        let mut imported_count = 0;
-        for item_value in export.data {
-            match serde_json::from_value::<T>(item_value) {
-                Ok(item) => {
-                    if let Err(e) = self.client.create(target_collection, &item).await {
-                        eprintln!("Failed to import item: {}", e);
-                    } else {
-                        imported_count += 1;
-                    }
-                },
+
+        let mut writer = BulkWriter::new(self.client.clone());
+        let mut positions = Vec::new();
+        let mut rejected = Vec::new();
+        for (position, item_value) in export.data.into_iter().enumerate() {
+            let reject = |operation: &str, error: String| BulkWriteFailure {
+                index: position,
+                path: target_collection.clone(),
+                operation: operation.to_string(),
+                attempts: 0,
+                error,
+            };
+            let fields = match serde_json::from_value::<T>(item_value) {
+                Ok(item) => item.to_firestore(),
                 Err(e) => {
-                    eprintln!("Failed to deserialize item: {}", e);
+                    rejected.push(reject("parse", e.to_string()));
+                    continue;
+                }
+            };
+            if let Some(schema) = &schema {
+                let report = self.schema_manager.validate_rules_async(&schema.fields, &schema.validation_rules, &fields).await?;
+                if !report.is_valid() {
+                    rejected.push(reject("validate", report.to_string()));
+                    continue;
                 }
             }
+            positions.push(position);
+            writer.create(&target_collection, None, fields).await;
         }
 
-        Ok(imported_count)
+        let mut report = writer.close().await;
+        for failure in &mut report.failures {
+            failure.index = positions[failure.index];
+        }
+        report.failures.extend(rejected);
+        report.failures.sort_by_key(|failure| failure.index);
+        Ok(report)
+    }
+
+    /// Writes every `*_backup.json` file in `backup_dir` back to its collection.
+    /// Documents keep their IDs and are overwritten if they exist; backups made
+    /// before IDs were recorded get new ones.
+    pub async fn restore_backup(&self, backup_dir: &str) -> Result<BulkWriteReport>
+    where
+        S: 'static,
+    {
+        let mut files: Vec<_> = fs::read_dir(backup_dir)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read backup directory {}: {}", backup_dir, e)))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.to_string_lossy().ends_with("_backup.json"))
+            .collect();
+        files.sort();
+
+        let mut writer = BulkWriter::new(self.client.clone());
+        for file in files {
+            let export = read_data_export(&file.to_string_lossy())?;
+            tracing::info!(collection = %export.collection, count = export.count, "Restoring collection");
+            for (position, value) in export.data.into_iter().enumerate() {
+                let fields: HashMap<String, FirestoreValue> = serde_json::from_value(value)?;
+                match export.document_ids.get(position) {
+                    Some(id) => writer.set(&document_path(&export.collection, id), fields).await,
+                    None => {
+                        writer.create(&export.collection, None, fields).await;
+                    }
+                }
+            }
+        }
+        Ok(writer.close().await)
     }
 
-    pub async fn backup_all_data(&self, backup_dir: &str) -> Result<HashMap<String, usize>> {
+    /// Backs up every collection as of one point in time: the configured read
+    /// time, or the moment the backup starts. Writes a manifest recording it.
+    pub async fn backup_all_data(&self, backup_dir: &str) -> Result<BackupManifest>
+    where
+        S: 'static,
+    {
+        // Pin a second in the past so the read time is never ahead of the server's clock
+        let now = chrono::Utc::now();
+        let read_time = self.read_time.clone().unwrap_or_else(|| {
+            (now - chrono::Duration::seconds(1)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        });
+        let options = ReadOptions::at(read_time.clone());
+        options.read_time()?;
+        tracing::info!(read_time = %read_time, "Starting backup");
+
         fs::create_dir_all(backup_dir)
             .map_err(|e| FirebaseError::ConfigError(format!("Failed to create backup directory: {}", e)))?;
 
-        let mut results = HashMap::new();
+        let mut results = BTreeMap::new();
 
-        // For now, we'll backup known collections
-        // In a real implementation, you might want to discover collections
-        let collections = vec!["users"];
-
-        for collection in collections {
+        for collection in BACKUP_COLLECTIONS {
             let output_file = format!("{}/{}_backup.json", backup_dir, collection);
-            match self.export_collection_raw(collection, &output_file).await {
+            match self.export_raw_at(collection, &output_file, &options).await {
                 Ok(count) => {
                     results.insert(collection.to_string(), count);
-                    println!("Backed up {} items from {}", count, collection);
+                    tracing::info!(collection, count, "Backed up collection");
                 },
+                Err(e @ (FirebaseError::BudgetExceeded(_) | FirebaseError::ValidationError(_))) => return Err(e),
                 Err(e) => {
-                    eprintln!("Failed to backup {}: {}", collection, e);
+                    tracing::error!(collection, error = %e, "Failed to back up collection");
                     results.insert(collection.to_string(), 0);
                 }
             }
         }
 
-        Ok(results)
+        let manifest = BackupManifest {
+            created_at: now.to_rfc3339(),
+            read_time,
+            collections: results,
+        };
+        fs::write(format!("{}/{}", backup_dir, BACKUP_MANIFEST), serde_json::to_string_pretty(&manifest)?)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to write backup manifest: {}", e)))?;
+
+        Ok(manifest)
     }
 
     // Enhanced schema export that includes discovered schemas
@@ -560,15 +748,16 @@ impl JsonSchemaManager {
         
         // Convert discovered collections to JSON schema format
         for collection_info in collections {
-            println!("📊 Analyzing schema for collection '{}'...", collection_info.name);
+            tracing::info!(collection = %collection_info.name, "Analyzing collection schema");
             
             match collection_manager.describe_collection(&collection_info.name, 50).await {
                 Ok(schema) => {
                     let json_collection = self.convert_collection_schema_to_json(schema);
                     json_schema.collections.insert(collection_info.name.clone(), json_collection);
                 }
+                Err(e @ FirebaseError::BudgetExceeded(_)) => return Err(e),
                 Err(e) => {
-                    eprintln!("⚠️ Failed to analyze collection '{}': {}", collection_info.name, e);
+                    tracing::warn!(collection = %collection_info.name, error = %e, "Failed to analyze collection");
                 }
             }
         }
@@ -587,6 +776,31 @@ impl JsonSchemaManager {
         Ok(())
     }
 
+    /// Estimates the reads `backup_all_data` would make, from aggregation counts.
+    pub async fn estimate_backup(&self) -> Result<CostEstimate> {
+        let mut estimate = CostEstimate::default();
+        for collection in BACKUP_COLLECTIONS {
+            let documents = self.client.count_documents(collection).await?;
+            estimate.estimate_reads += BillableOps::aggregation(documents).reads;
+            estimate.add(collection, documents, BillableOps::query(documents).reads);
+        }
+        Ok(estimate)
+    }
+
+    /// Estimates the reads `export_discovered_schemas` would make: listing the
+    /// collections, then sampling up to 50 documents from each non-empty one.
+    pub async fn estimate_discovered_schemas(&self) -> Result<CostEstimate> {
+        use crate::collections::CollectionManager;
+        
+        let mut estimate = CollectionManager::new(self.client.clone()).estimate_list_collections().await?;
+        for collection in &mut estimate.collections {
+            if collection.documents > 0 {
+                collection.reads += BillableOps::query(collection.documents.min(50)).reads;
+            }
+        }
+        Ok(estimate)
+    }
+
     fn convert_collection_schema_to_json(&self, schema: crate::collections::CollectionSchema) -> JsonCollection {
         let fields: Vec<JsonField> = schema.fields.into_iter().map(|field| {
             // Convert field type
@@ -653,6 +867,7 @@ impl JsonSchemaManager {
                     if is_auto_timestamp { " (auto-generated)" } else { "" }
                 )),
                 sample_values: field.sample_values.clone(),
+                frequency: Some(field.frequency),
             }
         }).collect();
         
@@ -719,6 +934,11 @@ impl JsonSchemaManager {
         };
 
         let default_value = json_field.default_value.map(|v| self.json_value_to_firestore_value(v)).transpose()?;
+        let auto_field = if json_field.auto_generated {
+            detect_auto_field(&json_field.name, &json_field.field_type, &json_field.sample_values)
+        } else {
+            None
+        };
 
         Ok(Field {
             name: json_field.name,
@@ -726,6 +946,7 @@ impl JsonSchemaManager {
             required: json_field.required,
             default_value,
             description: json_field.description,
+            auto_field,
         })
     }
 
@@ -817,15 +1038,15 @@ impl JsonSchemaManager {
         }
     }
 
-    pub fn get_schema_manager(&self) -> &SchemaManager {
+    pub fn get_schema_manager(&self) -> &SchemaManager<S> {
         &self.schema_manager
     }
 
-    pub fn get_schema_manager_mut(&mut self) -> &mut SchemaManager {
+    pub fn get_schema_manager_mut(&mut self) -> &mut SchemaManager<S> {
         &mut self.schema_manager
     }
 
-    pub fn get_client(&self) -> &FirebaseClient {
+    pub fn get_client(&self) -> &S {
         &self.client
     }
     
@@ -833,59 +1054,98 @@ impl JsonSchemaManager {
         self.convert_to_json_schema().collections.into_values().collect()
     }
     
-    // Save a schema to the 'collection_schemas' collection in Firestore
-    pub async fn save_schema_to_firestore(&self, schema: &FirestoreSchema) -> Result<String> {
-        let data = serde_json::to_value(schema)?;
-        let doc_id = self.client.create_document("collection_schemas", Some(schema.collection_name.clone()), data).await?;
-        Ok(doc_id)
+    /// Saves a schema as a new version of its collection's schema and makes it
+    /// the current one. The first version keeps `schema.version`; later ones
+    /// bump the current version by [`VersionBump::between`]. Versions are
+    /// kept in `collection_schemas/{collection}/versions` and never overwritten.
+    pub async fn save_schema_to_firestore(&self, schema: &FirestoreSchema) -> Result<FirestoreSchema> {
+        let mut transaction = Transaction::begin(&self.client).await?;
+        let current = match transaction.get(&schema_path(&schema.collection_name)).await? {
+            Some(document) => Some(serde_json::from_value::<FirestoreSchema>(document.to_json())?),
+            None => None,
+        };
+
+        let mut saved = schema.clone();
+        saved.last_updated = chrono::Utc::now().to_rfc3339();
+        if saved.author.is_none() {
+            saved.author = self.author.clone();
+        }
+        match &current {
+            Some(current) => {
+                let bump = VersionBump::between(current, schema);
+                saved.version = bump.apply(&current.version)?;
+                saved.previous_version = Some(current.version.clone());
+                saved.bump = Some(bump);
+
+                // Schemas saved before versions were kept only exist as the current document
+                let current_path = version_path(&current.collection_name, &current.version);
+                if transaction.get(&current_path).await?.is_none() {
+                    transaction.write(Write::Create { path: current_path, fields: json_to_firestore_fields(serde_json::to_value(current)?)? });
+                }
+            }
+            None => {
+                parse_version(&saved.version)?;
+                saved.previous_version = None;
+                saved.bump = None;
+            }
+        }
+
+        let fields = json_to_firestore_fields(serde_json::to_value(&saved)?)?;
+        transaction.write(Write::Create { path: version_path(&saved.collection_name, &saved.version), fields: fields.clone() });
+        transaction.set(&schema_path(&saved.collection_name), fields);
+        transaction.commit().await?;
+        Ok(saved)
     }
     
     // Load a schema from the 'collection_schemas' collection in Firestore
     pub async fn load_schema_from_firestore(&self, collection_name: &str) -> Result<Option<FirestoreSchema>> {
-        match self.client.get_document("collection_schemas", collection_name).await {
-            Ok(data) => {
-                let schema: FirestoreSchema = serde_json::from_value(data)?;
-                Ok(Some(schema))
-            },
-            Err(FirebaseError::DatabaseError(msg)) if msg.contains("not found") => Ok(None),
-            Err(e) => Err(e),
+        match self.client.fetch(&schema_path(collection_name), &ReadOptions::default()).await? {
+            Some(document) => Ok(Some(serde_json::from_value(document.to_json())?)),
+            None => Ok(None),
         }
     }
     
+    /// A saved version of a collection's schema.
+    pub async fn load_schema_version(&self, collection_name: &str, version: &str) -> Result<Option<FirestoreSchema>> {
+        match self.client.fetch(&version_path(collection_name, version), &ReadOptions::default()).await? {
+            Some(document) => Ok(Some(serde_json::from_value(document.to_json())?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Every saved version of a collection's schema, oldest first.
+    pub async fn schema_history(&self, collection_name: &str) -> Result<Vec<FirestoreSchema>> {
+        let documents = self.client.list_all(&versions_path(collection_name), &ReadOptions::default()).await?;
+        let mut versions = Vec::new();
+        for document in documents {
+            let schema: FirestoreSchema = serde_json::from_value(document.to_json())?;
+            versions.push((parse_version(&schema.version)?, schema));
+        }
+        versions.sort_by_key(|(version, _)| *version);
+        Ok(versions.into_iter().map(|(_, schema)| schema).collect())
+    }
+    
+    /// Makes an earlier version's content current again. This saves it as a
+    /// new version, so the history only moves forward.
+    pub async fn rollback_schema(&self, collection_name: &str, version: &str) -> Result<FirestoreSchema> {
+        let target = self.load_schema_version(collection_name, version).await?
+            .ok_or_else(|| FirebaseError::NotFound(format!("No version {} of the schema for collection '{}'", version, collection_name)))?;
+        let mut schema = target.clone();
+        schema.discovery_source = "rollback".to_string();
+        schema.author = None;
+        schema.restored_from = Some(target.version);
+        self.save_schema_to_firestore(&schema).await
+    }
+    
     // List all schemas from the 'collection_schemas' collection
     pub async fn list_firestore_schemas(&self) -> Result<Vec<FirestoreSchema>> {
         // Get all documents from the collection_schemas collection
-        let url = format!("{}/collection_schemas?key={}", 
-            self.client.base_url,
-            self.client.api_key
-        );
-        
-        let response = self.client.client
-            .get(&url)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(FirebaseError::DatabaseError(format!("Failed to list schemas: {}", error_text)));
-        }
-        
-        let response_text = response.text().await?;
-        let list_response: serde_json::Value = serde_json::from_str(&response_text)?;
-        
-        let empty_vec = vec![];
-        let documents = list_response.get("documents")
-            .and_then(|d| d.as_array())
-            .unwrap_or(&empty_vec);
+        let documents = self.client.list_all(SCHEMAS_COLLECTION, &ReadOptions::default()).await?;
         
         let mut schemas = Vec::new();
         for doc in documents {
-            if let Some(fields) = doc.get("fields") {
-                if let Ok(data) = self.convert_firestore_document_to_json(fields) {
-                    if let Ok(schema) = serde_json::from_value::<FirestoreSchema>(data) {
-                        schemas.push(schema);
-                    }
-                }
+            if let Ok(schema) = serde_json::from_value::<FirestoreSchema>(doc.to_json()) {
+                schemas.push(schema);
             }
         }
         
@@ -894,18 +1154,30 @@ impl JsonSchemaManager {
     
     // Discover and save schema for a collection
     pub async fn discover_and_save_schema(&self, collection_name: &str, sample_limit: usize) -> Result<FirestoreSchema> {
+        let firestore_schema = self.discover_schema(collection_name, sample_limit).await?;
+        
+        // Save to Firestore as a new version
+        self.save_schema_to_firestore(&firestore_schema).await
+    }
+    
+    /// The schema of a collection as found in a sample of its documents, without saving it.
+    pub async fn discover_schema(&self, collection_name: &str, sample_limit: usize) -> Result<FirestoreSchema> {
         use crate::collections::CollectionManager;
         
         let collection_manager = CollectionManager::new(self.client.clone());
         let discovered_schema = collection_manager.describe_collection(collection_name, sample_limit).await?;
+        Ok(self.convert_discovered_to_firestore_schema(discovered_schema))
+    }
+    
+    /// Samples a collection's documents and compares them with its stored schema.
+    pub async fn check_drift(&self, collection_name: &str, sample_limit: usize, frequency_threshold: f64) -> Result<DriftReport> {
+        use crate::collections::CollectionManager;
         
-        // Convert discovered schema to FirestoreSchema
-        let firestore_schema = self.convert_discovered_to_firestore_schema(discovered_schema);
-        
-        // Save to Firestore
-        self.save_schema_to_firestore(&firestore_schema).await?;
-        
-        Ok(firestore_schema)
+        let stored = self.load_schema_from_firestore(collection_name).await?
+            .ok_or_else(|| FirebaseError::NotFound(format!("No stored schema for collection '{}'", collection_name)))?;
+        let collection_manager = CollectionManager::new(self.client.clone());
+        let live = collection_manager.describe_collection(collection_name, sample_limit).await?;
+        Ok(detect_drift(&stored, &live, frequency_threshold))
     }
     
     // Validate data against stored schema
@@ -913,8 +1185,8 @@ impl JsonSchemaManager {
         let mut errors = Vec::new();
         
         // Load schema from Firestore
-        if let Some(schema) = self.load_schema_from_firestore(collection_name).await? {
-            if let serde_json::Value::Object(data_map) = data {
+        if let Some(schema) = self.load_schema_from_firestore(collection_name).await?
+            && let serde_json::Value::Object(data_map) = data {
                 // Check user-required fields (not auto-generated)
                 for field in &schema.fields {
                     if field.user_required && !data_map.contains_key(&field.name) {
@@ -943,13 +1215,59 @@ impl JsonSchemaManager {
                     }
                 }
                 
-                // TODO: Add validation rule checking here
+                // Rules, including custom ones resolved through the validator registry
+                let rules = schema.validation_rules.into_iter()
+                    .map(|rule| self.convert_from_json_validation_rule(rule))
+                    .collect::<Result<Vec<_>>>()?;
+                let fields = json_to_firestore_fields(data.clone())?;
+                let report = self.schema_manager.validate_rules_async(&[], &rules, &fields).await?;
+                errors.extend(report.violations.into_iter().map(|violation| violation.message));
             }
-        }
         
         Ok(errors)
     }
     
+    /// The schema stored in Firestore for a collection, ready for
+    /// [`SchemaManager::define_collection`]. Fields whose type varies between
+    /// documents (`mixed`) are left out, as are the sample defaults of
+    /// discovered schemas.
+    pub async fn stored_collection_schema(&self, collection_name: &str) -> Result<Option<Collection>> {
+        let Some(schema) = self.load_schema_from_firestore(collection_name).await? else {
+            return Ok(None);
+        };
+        // Discovery records sample values as defaults; they aren't real defaults
+        let discovered = schema.discovery_source == "discovered";
+        let fields = schema.fields.into_iter()
+            .map(|field| JsonField { default_value: if discovered { None } else { field.default_value }, ..field })
+            .filter_map(|field| match self.convert_from_json_field(field) {
+                Ok(field) => Some(field),
+                Err(e) => {
+                    tracing::debug!(collection = collection_name, error = %e, "Skipping untyped field");
+                    None
+                }
+            })
+            .collect();
+        let validation_rules = schema.validation_rules.into_iter()
+            .map(|rule| self.convert_from_json_validation_rule(rule))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Collection {
+            name: schema.collection_name,
+            fields,
+            indexes: Vec::new(),
+            validation_rules,
+        }))
+    }
+    
+    /// The validation rules of the schema stored in Firestore for a collection.
+    pub async fn stored_validation_rules(&self, collection_name: &str) -> Result<Vec<ValidationRule>> {
+        match self.load_schema_from_firestore(collection_name).await? {
+            Some(schema) => schema.validation_rules.into_iter()
+                .map(|rule| self.convert_from_json_validation_rule(rule))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+    
     // Helper method to convert discovered schema to FirestoreSchema
     fn convert_discovered_to_firestore_schema(&self, schema: crate::collections::CollectionSchema) -> FirestoreSchema {
         let fields: Vec<JsonField> = schema.fields.into_iter().map(|field| {
@@ -1017,6 +1335,7 @@ impl JsonSchemaManager {
                     if is_auto_timestamp { " (auto-generated)" } else { "" }
                 )),
                 sample_values: field.sample_values.clone(),
+                frequency: Some(field.frequency),
             }
         }).collect();
         
@@ -1030,63 +1349,11 @@ impl JsonSchemaManager {
             indexes: vec![], // Would need additional analysis to discover indexes
             validation_rules: vec![], // Could infer some rules from data patterns
             description: Some(format!("Auto-discovered schema with {} documents", schema.total_documents)),
+            author: None,
+            previous_version: None,
+            bump: None,
+            restored_from: None,
         }
     }
-    
-    // Helper method to convert Firestore document fields to JSON
-    fn convert_firestore_document_to_json(&self, fields: &serde_json::Value) -> Result<serde_json::Value> {
-        fn convert_firestore_value(value: &serde_json::Value) -> serde_json::Value {
-            if let Some(obj) = value.as_object() {
-                // Handle Firestore value types
-                if let Some(string_value) = obj.get("stringValue") {
-                    return string_value.clone();
-                }
-                if let Some(integer_value) = obj.get("integerValue") {
-                    if let Some(s) = integer_value.as_str() {
-                        if let Ok(n) = s.parse::<i64>() {
-                            return serde_json::Value::Number(serde_json::Number::from(n));
-                        }
-                    }
-                    return integer_value.clone();
-                }
-                if let Some(double_value) = obj.get("doubleValue") {
-                    return double_value.clone();
-                }
-                if let Some(boolean_value) = obj.get("booleanValue") {
-                    return boolean_value.clone();
-                }
-                if let Some(array_value) = obj.get("arrayValue") {
-                    if let Some(values) = array_value.get("values").and_then(|v| v.as_array()) {
-                        let converted: Vec<serde_json::Value> = values.iter()
-                            .map(convert_firestore_value)
-                            .collect();
-                        return serde_json::Value::Array(converted);
-                    }
-                }
-                if let Some(map_value) = obj.get("mapValue") {
-                    if let Some(fields) = map_value.get("fields").and_then(|v| v.as_object()) {
-                        let mut converted = serde_json::Map::new();
-                        for (k, v) in fields {
-                            converted.insert(k.clone(), convert_firestore_value(v));
-                        }
-                        return serde_json::Value::Object(converted);
-                    }
-                }
-                if obj.get("nullValue").is_some() {
-                    return serde_json::Value::Null;
-                }
-            }
-            value.clone()
-        }
-        
-        if let Some(fields_obj) = fields.as_object() {
-            let mut result = serde_json::Map::new();
-            for (key, value) in fields_obj {
-                result.insert(key.clone(), convert_firestore_value(value));
-            }
-            Ok(serde_json::Value::Object(result))
-        } else {
-            Ok(serde_json::Value::Null)
-        }
-    }
+
 }
\ No newline at end of file
@@ -7,13 +7,53 @@ pub mod security_rules;
 pub mod json_manager;
 pub mod collections;
 pub mod tui_form;
+pub mod store;
+pub mod memory;
+#[cfg(feature = "mock-server")]
+pub mod mock_server;
+pub mod recording;
+pub mod metrics;
+pub mod billing;
+pub mod throttle;
+pub mod bulk_writer;
+pub mod partition;
+pub mod listen;
+pub mod explain;
+pub mod indexes;
+pub mod index_admin;
+pub mod validation;
+pub mod validated;
+pub mod migration_steps;
+pub mod schema_diff;
+pub mod schema_drift;
+pub mod schema_history;
 
-pub use firebase::FirebaseClient;
+pub use firebase::{FirebaseClient, FirebaseClientBuilder, ACCESS_TOKEN_ENV, EMULATOR_HOST_ENV};
 pub use models::*;
-pub use error::FirebaseError;
-pub use query::{QueryBuilder, FieldOperator, create_filter};
-pub use schema::{SchemaManager, Collection, Field, FieldType, Index, IndexField, IndexOrder, ValidationRule, ValidationRuleType, MigrationManager, Migration};
+pub use error::{FirebaseError, ApiStatus, RpcCode};
+pub use query::{QueryBuilder, FieldOperator, DistanceMeasure, QueryError, create_filter};
+pub use schema::{SchemaManager, Collection, Field, FieldType, Index, IndexField, IndexOrder, ValidationRule, ValidationRuleType, MigrationManager, Migration, MigrationContext, MigrationDirection, MigrationStatus, MigrationRun, MigrationCheckpoint, AffectedDocument, MigrationFn, MigrationFuture, MIGRATIONS_COLLECTION};
 pub use security_rules::{SecurityRules, RuleBuilder, Expression, Permission};
-pub use json_manager::{JsonSchemaManager, JsonSchema, JsonCollection, JsonField, DataExport};
-pub use collections::{CollectionManager, CollectionInfo, CollectionSchema, FieldInfo};
-pub use tui_form::{TuiForm, FormField};
\ No newline at end of file
+pub use json_manager::{JsonSchemaManager, JsonSchema, JsonCollection, JsonField, FirestoreSchema, DataExport, BackupManifest};
+pub use collections::{CollectionManager, CollectionInfo, CollectionSchema, FieldInfo, AutoFieldType};
+pub use tui_form::{TuiForm, FormField};
+pub use store::{DocumentStore, StoredDocument, DocumentPage, ListOptions, ReadOptions, Write, WriteResult, CommitResponse, Transaction};
+pub use memory::MemoryStore;
+#[cfg(feature = "mock-server")]
+pub use mock_server::{MockServer, FaultConfig};
+pub use recording::{Fixture, Interaction, ReplayMatcher};
+pub use metrics::{ClientMetrics, MetricsSnapshot};
+pub use partition::{partitioned_query, read_collection};
+pub use listen::{ChangeKind, DocumentChange, ListenEvent, ListenOptions, ListenTarget, Listener, listen};
+pub use explain::{ExplainOptions, ExplainMetrics, ExplainedQuery, ExplainedCount, PlanSummary, IndexUsed, ExecutionStats, DebugStats, BillingDetails};
+pub use indexes::{IndexDefinitions, CompositeIndex, IndexedField, QueryScope, ArrayConfig, VectorConfig, FieldOverride, FieldIndex, SavedQuery, load_saved_queries};
+pub use index_admin::{IndexAdmin, IndexReconciler, IndexPlan, AdminIndex, IndexState, Operation, OperationError};
+pub use validation::{ValidationReport, Violation, RegexCache, CustomValidator, Validator, ValidatorRegistry, CheckResult};
+pub use validated::ValidatedClient;
+pub use migration_steps::{MigrationFile, MigrationStep, ConversionPolicy, load_migrations};
+pub use schema_diff::{SchemaDiff, SchemaChange, SchemaChangeKind, Severity, read_schema_file};
+pub use schema_drift::{DriftReport, DriftFinding, DriftKind, detect_drift, DEFAULT_FREQUENCY_THRESHOLD};
+pub use schema_history::{VersionBump, parse_version, SCHEMAS_COLLECTION, VERSIONS_SUBCOLLECTION};
+pub use bulk_writer::{BulkWriter, BulkWriteReport, BulkWriteFailure};
+pub use throttle::{RateLimit, Throttle, ThrottlePermit};
+pub use billing::{BillableOps, CallSiteCost, CollectionEstimate, CostEstimate, CostLedger, RateTable, with_call_site};
@@ -0,0 +1,236 @@
+use crate::billing::inherit_call_site;
+use crate::bulk_writer::is_retryable;
+use crate::error::{FirebaseError, Result};
+use crate::query::StructuredQuery;
+use crate::store::{DocumentStore, ReadOptions, StoredDocument};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use futures_core::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What a [`Listener`] watches.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenTarget {
+    /// A single document path, e.g. `users/alice`.
+    Document(String),
+    /// The results of a query under `parent` (`""` for the root).
+    Query { parent: String, query: Box<StructuredQuery> },
+}
+
+impl ListenTarget {
+    pub fn document(path: impl Into<String>) -> Self {
+        ListenTarget::Document(path.into())
+    }
+
+    pub fn query(parent: impl Into<String>, query: StructuredQuery) -> Self {
+        ListenTarget::Query { parent: parent.into(), query: Box::new(query) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+/// One document entering, changing within or leaving the target.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentChange {
+    pub kind: ChangeKind,
+    /// Full resource name of the document.
+    pub name: String,
+    /// The new version; `None` for removals.
+    pub document: Option<StoredDocument>,
+}
+
+impl DocumentChange {
+    pub fn id(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+}
+
+/// The changes found by one poll. The first event holds every matching
+/// document as `Added` (or only the changes since the resume token);
+/// later events are sent only when something changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListenEvent {
+    pub changes: Vec<DocumentChange>,
+    /// When the poll that found these changes started.
+    pub read_time: String,
+    /// Pass to [`ListenOptions::resume_token`] to continue from this point
+    /// without replaying the documents already seen.
+    pub resume_token: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListenOptions {
+    /// Delay between polls.
+    pub poll_interval: Duration,
+    /// Token from an earlier [`ListenEvent`] to resume from.
+    pub resume_token: Option<String>,
+}
+
+impl Default for ListenOptions {
+    fn default() -> Self {
+        Self { poll_interval: DEFAULT_POLL_INTERVAL, resume_token: None }
+    }
+}
+
+impl ListenOptions {
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn resume_token(mut self, token: impl Into<String>) -> Self {
+        self.resume_token = Some(token.into());
+        self
+    }
+}
+
+/// What the listener had seen: document names and their update times.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ResumeState {
+    read_time: String,
+    documents: BTreeMap<String, String>,
+}
+
+impl ResumeState {
+    fn decode(token: &str) -> Result<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(token)
+            .map_err(|e| FirebaseError::ValidationError(format!("Invalid resume token: {}", e)))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| FirebaseError::ValidationError(format!("Invalid resume token: {}", e)))
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+}
+
+/// A stream of [`ListenEvent`]s for a document or query, produced by polling
+/// and diffing on each document's `updateTime`. Transient failures are
+/// retried with backoff; any other error is yielded once and ends the stream.
+/// Dropping the listener stops polling.
+///
+/// The resume token records every document seen, so it grows with the size
+/// of the result set.
+pub struct Listener {
+    events: mpsc::Receiver<Result<ListenEvent>>,
+    task: JoinHandle<()>,
+}
+
+/// Starts listening to `target`. Each poll is a normal get or query and is
+/// billed as one.
+pub fn listen<S: DocumentStore + Clone + 'static>(store: S, target: ListenTarget, options: ListenOptions) -> Listener {
+    let (sender, events) = mpsc::channel(16);
+    let task = tokio::spawn(inherit_call_site(poll_changes(store, target, options, sender)));
+    Listener { events, task }
+}
+
+impl Listener {
+    /// Waits for the next event; `None` once the listener has stopped.
+    pub async fn next(&mut self) -> Option<Result<ListenEvent>> {
+        self.events.recv().await
+    }
+}
+
+impl Stream for Listener {
+    type Item = Result<ListenEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn poll_changes<S: DocumentStore>(
+    store: S,
+    target: ListenTarget,
+    options: ListenOptions,
+    sender: mpsc::Sender<Result<ListenEvent>>,
+) {
+    let (mut state, mut first) = match options.resume_token.as_deref().map(ResumeState::decode) {
+        Some(Ok(state)) => (state, false),
+        Some(Err(e)) => {
+            let _ = sender.send(Err(e)).await;
+            return;
+        }
+        None => (ResumeState::default(), true),
+    };
+    let mut backoff = options.poll_interval.max(Duration::from_millis(100));
+
+    loop {
+        let read_time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+        match read_target(&store, &target).await {
+            Ok(documents) => {
+                backoff = options.poll_interval.max(Duration::from_millis(100));
+                let changes = diff(&mut state.documents, documents);
+                state.read_time = read_time.clone();
+                if first || !changes.is_empty() {
+                    first = false;
+                    let event = ListenEvent { changes, read_time, resume_token: state.encode() };
+                    if sender.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                tokio::time::sleep(options.poll_interval).await;
+            }
+            Err(e) if is_retryable(&e) => {
+                tracing::warn!(error = %e, retry_ms = backoff.as_millis() as u64, "Listener poll failed, reconnecting");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e)).await;
+                return;
+            }
+        }
+    }
+}
+
+async fn read_target<S: DocumentStore>(store: &S, target: &ListenTarget) -> Result<Vec<StoredDocument>> {
+    match target {
+        ListenTarget::Document(path) => Ok(store.fetch(path, &ReadOptions::default()).await?.into_iter().collect()),
+        ListenTarget::Query { parent, query } => store.run_query(parent, query, &ReadOptions::default()).await,
+    }
+}
+
+/// Compares a poll's results with the update times seen so far, updating them.
+/// Changes come in result order, followed by removals in name order.
+fn diff(known: &mut BTreeMap<String, String>, documents: Vec<StoredDocument>) -> Vec<DocumentChange> {
+    let mut previous = std::mem::take(known);
+    let mut changes = Vec::new();
+
+    for document in documents {
+        let update_time = document.update_time.clone().unwrap_or_default();
+        let kind = match previous.remove(&document.name) {
+            None => Some(ChangeKind::Added),
+            Some(seen) if seen != update_time => Some(ChangeKind::Modified),
+            Some(_) => None,
+        };
+        known.insert(document.name.clone(), update_time);
+        if let Some(kind) = kind {
+            changes.push(DocumentChange { kind, name: document.name.clone(), document: Some(document) });
+        }
+    }
+
+    changes.extend(previous.into_keys().map(|name| DocumentChange { kind: ChangeKind::Removed, name, document: None }));
+    changes
+}
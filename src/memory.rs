@@ -0,0 +1,833 @@
+use crate::billing::BillableOps;
+use crate::error::{ApiStatus, FirebaseError, Result, RpcCode};
+use crate::explain::{
+    BillingDetails, DebugStats, ExecutionStats, ExplainMetrics, ExplainOptions, ExplainedCount, ExplainedQuery,
+    format_duration, plan_summary,
+};
+use crate::models::{FirestoreValue, get_field_path};
+use crate::query::{
+    CompositeOperator, Cursor, Direction, DistanceMeasure, FieldOperator, Filter, FindNearest, Order, StructuredQuery,
+    UnaryOperator, effective_orders,
+};
+use crate::store::{
+    CommitResponse, DocumentPage, DocumentStore, ListOptions, ReadOptions, StoredDocument, Write,
+    PITR_WINDOW, WriteResult, document_path, normalize_path,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand::Rng;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Instant;
+
+const NAME_FIELD: &str = "__name__";
+
+/// An in-process [`DocumentStore`] that mirrors Firestore semantics closely
+/// enough for unit tests: queries are evaluated locally (filters, ordering with
+/// Firestore's cross-type value order, cursors, offset and limit) and commits
+/// are atomic. Clones share the same data.
+///
+/// Earlier versions of each document are kept for 7 days, so reads can use
+/// a `read_time` like they can with point-in-time recovery enabled.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    root: String,
+    state: Arc<RwLock<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    documents: BTreeMap<String, StoredDocument>,
+    /// Every version of each document, oldest first.
+    history: BTreeMap<String, Vec<Version>>,
+    transactions: HashSet<String>,
+    last_time: Option<DateTime<Utc>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::with_project("local")
+    }
+
+    /// Uses `project_id` in document resource names, so they match what the
+    /// real API (or an emulator for that project) would return.
+    pub fn with_project(project_id: &str) -> Self {
+        Self {
+            root: format!("projects/{}/databases/(default)/documents", project_id),
+            state: Arc::new(RwLock::new(MemoryState::default())),
+        }
+    }
+
+    pub fn document_name(&self, path: &str) -> String {
+        format!("{}/{}", self.root, normalize_path(path))
+    }
+
+    /// Returns a copy of every stored document, ordered by path.
+    pub fn snapshot(&self) -> Vec<StoredDocument> {
+        self.read().documents.values().cloned().collect()
+    }
+
+    /// Replaces the contents of the store, e.g. with a previous [`snapshot`](Self::snapshot).
+    pub fn load(&self, documents: Vec<StoredDocument>) {
+        let mut state = self.write();
+        state.documents.clear();
+        state.history.clear();
+        for mut document in documents {
+            let path = normalize_path(document_relative_path(&document.name)).to_string();
+            document.name = self.document_name(&path);
+            let time = document.update_time.as_deref()
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map_or_else(Utc::now, |time| time.with_timezone(&Utc));
+            state.record(&path, time, Some(document.clone()));
+            state.documents.insert(path, document);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.write();
+        state.documents.clear();
+        state.history.clear();
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryState> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryState> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Checks the transaction and read time of `options`, returning the read time.
+    fn check_read(&self, options: &ReadOptions) -> Result<Option<DateTime<Utc>>> {
+        let read_time = options.read_time()?;
+        match &options.transaction {
+            Some(id) if !self.read().transactions.contains(id) => {
+                Err(inactive_transaction(id))
+            }
+            _ => Ok(read_time),
+        }
+    }
+
+    fn query_documents(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<Vec<StoredDocument>> {
+        let read_time = self.check_read(options)?;
+        let state = self.read();
+        evaluate_query(state.documents_at(read_time).values(), parent, query)
+    }
+}
+
+/// A document as of `time`; `None` marks a delete.
+type Version = (DateTime<Utc>, Option<StoredDocument>);
+
+impl MemoryState {
+    /// Returns a strictly increasing timestamp so update times order writes.
+    fn next_time(&mut self) -> DateTime<Utc> {
+        let mut now = Utc::now();
+        if let Some(last) = self.last_time
+            && now <= last
+        {
+            now = last + chrono::Duration::microseconds(1);
+        }
+        self.last_time = Some(now);
+        now
+    }
+
+    /// The documents as they were at `read_time`, or as they are now.
+    fn documents_at(&self, read_time: Option<DateTime<Utc>>) -> Cow<'_, BTreeMap<String, StoredDocument>> {
+        let Some(read_time) = read_time else {
+            return Cow::Borrowed(&self.documents);
+        };
+        Cow::Owned(self.history.iter()
+            .filter_map(|(path, versions)| {
+                let (_, document) = versions.iter().rev().find(|(time, _)| *time <= read_time)?;
+                document.clone().map(|document| (path.clone(), document))
+            })
+            .collect())
+    }
+
+    /// Adds a version of the document at `path`, dropping versions that fell
+    /// out of the recovery window.
+    fn record(&mut self, path: &str, time: DateTime<Utc>, document: Option<StoredDocument>) {
+        let versions = self.history.entry(path.to_string()).or_default();
+        versions.push((time, document));
+        // Keep the newest version from before the window; it is still current at its start
+        let cutoff = time - PITR_WINDOW;
+        let expired = versions.iter().take_while(|(time, _)| *time < cutoff).count();
+        versions.drain(..expired.saturating_sub(1));
+    }
+
+    fn apply(&mut self, root: &str, write: &Write, time: DateTime<Utc>) -> Result<()> {
+        match write {
+            Write::Create { path, fields } => {
+                let path = normalize_path(path);
+                if self.documents.contains_key(path) {
+                    return Err(already_exists(path));
+                }
+                self.put(root, path, fields.clone(), time);
+            }
+            Write::Set { path, fields } => {
+                self.put(root, normalize_path(path), fields.clone(), time);
+            }
+            Write::Update { path, fields, mask } => {
+                let path = normalize_path(path);
+                let mut merged = match self.documents.get(path) {
+                    Some(existing) => existing.fields.clone(),
+                    None => return Err(FirebaseError::NotFound(format!("No document to update: {}", path))),
+                };
+                apply_mask(&mut merged, fields, mask);
+                self.put(root, path, merged, time);
+            }
+            Write::Delete { path } => {
+                let path = normalize_path(path);
+                if self.documents.remove(path).is_some() {
+                    self.record(path, time, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, root: &str, path: &str, fields: HashMap<String, FirestoreValue>, time: DateTime<Utc>) {
+        let update_time = format_time(time);
+        let create_time = self.documents.get(path)
+            .and_then(|d| d.create_time.clone())
+            .unwrap_or_else(|| update_time.clone());
+        let document = StoredDocument {
+            name: format!("{}/{}", root, path),
+            fields,
+            create_time: Some(create_time),
+            update_time: Some(update_time),
+        };
+        self.record(path, time, Some(document.clone()));
+        self.documents.insert(path.to_string(), document);
+    }
+
+    /// Checks preconditions for a batch before anything is applied.
+    fn validate(&self, writes: &[Write]) -> Result<()> {
+        let mut exists: HashMap<&str, bool> = HashMap::new();
+        for write in writes {
+            let path = normalize_path(write.path());
+            let present = *exists.entry(path).or_insert_with(|| self.documents.contains_key(path));
+            match write {
+                Write::Create { .. } if present => {
+                    return Err(already_exists(path));
+                }
+                Write::Update { .. } if !present => {
+                    return Err(FirebaseError::NotFound(format!("No document to update: {}", path)));
+                }
+                _ => {}
+            }
+            exists.insert(path, !matches!(write, Write::Delete { .. }));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DocumentStore for MemoryStore {
+    async fn fetch(&self, path: &str, options: &ReadOptions) -> Result<Option<StoredDocument>> {
+        let read_time = self.check_read(options)?;
+        Ok(self.read().documents_at(read_time).get(normalize_path(path)).cloned())
+    }
+
+    async fn insert(
+        &self,
+        collection: &str,
+        doc_id: Option<&str>,
+        fields: HashMap<String, FirestoreValue>,
+    ) -> Result<StoredDocument> {
+        let doc_id = doc_id.map(|id| id.to_string()).unwrap_or_else(generate_document_id);
+        let path = document_path(collection, &doc_id);
+
+        let mut state = self.write();
+        let time = state.next_time();
+        state.apply(&self.root, &Write::Create { path: path.clone(), fields }, time)?;
+        Ok(state.documents[&path].clone())
+    }
+
+    async fn patch(
+        &self,
+        path: &str,
+        fields: HashMap<String, FirestoreValue>,
+        mask: Option<&[String]>,
+    ) -> Result<StoredDocument> {
+        let path = normalize_path(path);
+        let mut state = self.write();
+        let time = state.next_time();
+
+        let merged = match mask {
+            Some(mask) => {
+                let mut merged = state.documents.get(path).map(|d| d.fields.clone()).unwrap_or_default();
+                apply_mask(&mut merged, &fields, mask);
+                merged
+            }
+            None => fields,
+        };
+        state.put(&self.root, path, merged, time);
+        Ok(state.documents[path].clone())
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        let mut state = self.write();
+        let time = state.next_time();
+        state.apply(&self.root, &Write::Delete { path: path.to_string() }, time)
+    }
+
+    async fn list_page(&self, collection: &str, list: &ListOptions, options: &ReadOptions) -> Result<DocumentPage> {
+        let read_time = self.check_read(options)?;
+        let collection = normalize_path(collection);
+        let state = self.read();
+        let view = state.documents_at(read_time);
+
+        let mut documents = view.iter()
+            .filter(|(path, _)| parent_of(path) == collection)
+            .filter(|(path, _)| list.page_token.as_deref().is_none_or(|token| path.as_str() > token))
+            .map(|(_, document)| document.clone());
+
+        let page_size = list.page_size.unwrap_or(usize::MAX);
+        let page: Vec<StoredDocument> = documents.by_ref().take(page_size).collect();
+        let next_page_token = match (documents.next(), page.last()) {
+            (Some(_), Some(last)) => Some(normalize_path(document_relative_path(&last.name)).to_string()),
+            _ => None,
+        };
+
+        Ok(DocumentPage { documents: page, next_page_token })
+    }
+
+    async fn run_query(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<Vec<StoredDocument>> {
+        self.query_documents(parent, query, options)
+    }
+
+    async fn partition_query(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        partition_count: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<Cursor>> {
+        let documents = self.query_documents(parent, query, options)?;
+        let size = documents.len().div_ceil(partition_count.max(1)).max(1);
+        Ok(documents.iter()
+            .step_by(size)
+            .skip(1)
+            .map(|document| Cursor {
+                values: vec![FirestoreValue::ReferenceValue(document.name.clone())],
+                before: Some(true),
+            })
+            .collect())
+    }
+
+    async fn count(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<u64> {
+        Ok(self.query_documents(parent, query, options)?.len() as u64)
+    }
+
+    async fn explain_query(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        options: &ReadOptions,
+        explain: &ExplainOptions,
+    ) -> Result<ExplainedQuery> {
+        let started = Instant::now();
+        query.validate()?;
+        let read_time = self.check_read(options)?;
+        let mut metrics = ExplainMetrics { plan_summary: plan_summary(query), execution_stats: None };
+        if !explain.analyze {
+            return Ok(ExplainedQuery { documents: Vec::new(), metrics });
+        }
+
+        let state = self.read();
+        let documents = state.documents_at(read_time);
+        // There are no indexes, so every document in scope counts as an index entry scanned
+        let scanned = documents.values().filter(|document| in_scope(document, normalize_path(parent), query)).count();
+        let results = evaluate_query(documents.values(), parent, query)?;
+        let fetched = results.len() as u64 + query.offset.unwrap_or(0).max(0) as u64;
+        metrics.execution_stats = Some(ExecutionStats {
+            results_returned: results.len() as u64,
+            execution_duration: format_duration(started.elapsed()),
+            read_operations: BillableOps::query(fetched).reads,
+            debug_stats: DebugStats {
+                documents_scanned: fetched,
+                index_entries_scanned: scanned as u64,
+                billing_details: BillingDetails {
+                    documents_billable: fetched,
+                    min_query_cost: u64::from(fetched == 0),
+                    ..BillingDetails::default()
+                },
+            },
+        });
+        Ok(ExplainedQuery { documents: results, metrics })
+    }
+
+    async fn explain_count(
+        &self,
+        parent: &str,
+        query: &StructuredQuery,
+        options: &ReadOptions,
+        explain: &ExplainOptions,
+    ) -> Result<ExplainedCount> {
+        let explained = self.explain_query(parent, query, options, explain).await?;
+        let count = explain.analyze.then_some(explained.documents.len() as u64);
+        let mut metrics = explained.metrics;
+        if let (Some(stats), Some(count)) = (&mut metrics.execution_stats, count) {
+            // Aggregations read index entries rather than documents
+            stats.results_returned = 1;
+            stats.read_operations = BillableOps::aggregation(count).reads;
+            stats.debug_stats.documents_scanned = 0;
+            stats.debug_stats.billing_details = BillingDetails {
+                index_entries_billable: count,
+                min_query_cost: u64::from(count == 0),
+                ..BillingDetails::default()
+            };
+        }
+        Ok(ExplainedCount { count, metrics })
+    }
+
+    async fn batch_get(&self, paths: &[String], options: &ReadOptions) -> Result<Vec<Option<StoredDocument>>> {
+        let read_time = self.check_read(options)?;
+        let state = self.read();
+        let view = state.documents_at(read_time);
+        Ok(paths.iter()
+            .map(|path| view.get(normalize_path(document_relative_path(path))).cloned())
+            .collect())
+    }
+
+    async fn commit(&self, writes: Vec<Write>, transaction: Option<&str>) -> Result<CommitResponse> {
+        let mut state = self.write();
+        if let Some(id) = transaction
+            && !state.transactions.remove(id)
+        {
+            return Err(inactive_transaction(id));
+        }
+
+        state.validate(&writes)?;
+        let time = state.next_time();
+        let commit_time = format_time(time);
+        let mut write_results = Vec::with_capacity(writes.len());
+        for write in &writes {
+            state.apply(&self.root, write, time)?;
+            write_results.push(WriteResult { update_time: Some(commit_time.clone()) });
+        }
+
+        Ok(CommitResponse { write_results, commit_time: Some(commit_time) })
+    }
+
+    async fn begin_transaction(&self, _read_only: bool) -> Result<String> {
+        let id = generate_document_id();
+        self.write().transactions.insert(id.clone());
+        Ok(id)
+    }
+
+    async fn rollback(&self, transaction: &str) -> Result<()> {
+        self.write().transactions.remove(transaction);
+        Ok(())
+    }
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>> {
+        let parent = normalize_path(parent);
+        let prefix = if parent.is_empty() { String::new() } else { format!("{}/", parent) };
+        let state = self.read();
+
+        let ids: BTreeSet<String> = state.documents.keys()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .filter_map(|rest| rest.split_once('/').map(|(collection, _)| collection.to_string()))
+            .collect();
+        Ok(ids.into_iter().collect())
+    }
+}
+
+/// Generates a 20-character alphanumeric ID like Firestore's auto IDs.
+pub(crate) fn already_exists(path: &str) -> FirebaseError {
+    FirebaseError::ApiError(ApiStatus::new(RpcCode::AlreadyExists, format!("Document already exists: {}", path)))
+}
+
+fn inactive_transaction(id: &str) -> FirebaseError {
+    FirebaseError::ApiError(ApiStatus::new(RpcCode::InvalidArgument, format!("Transaction '{}' is not active", id)))
+}
+
+pub(crate) fn generate_document_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect()
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn document_relative_path(name: &str) -> &str {
+    match name.find("/documents/") {
+        Some(pos) => &name[pos + "/documents/".len()..],
+        None => name,
+    }
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+}
+
+fn apply_mask(target: &mut HashMap<String, FirestoreValue>, source: &HashMap<String, FirestoreValue>, mask: &[String]) {
+    for field_path in mask {
+        match get_field_path(source, field_path) {
+            Some(value) => set_field_path(target, field_path, value.clone()),
+            None => {
+                remove_field_path(target, field_path);
+            }
+        }
+    }
+}
+
+pub(crate) fn set_field_path(fields: &mut HashMap<String, FirestoreValue>, path: &str, value: FirestoreValue) {
+    match path.split_once('.') {
+        None => {
+            fields.insert(path.to_string(), value);
+        }
+        Some((head, rest)) => {
+            let entry = fields.entry(head.to_string())
+                .or_insert_with(|| FirestoreValue::MapValue { fields: HashMap::new() });
+            if !matches!(entry, FirestoreValue::MapValue { .. }) {
+                *entry = FirestoreValue::MapValue { fields: HashMap::new() };
+            }
+            if let FirestoreValue::MapValue { fields } = entry {
+                set_field_path(fields, rest, value);
+            }
+        }
+    }
+}
+
+pub(crate) fn remove_field_path(fields: &mut HashMap<String, FirestoreValue>, path: &str) -> Option<FirestoreValue> {
+    match path.split_once('.') {
+        None => fields.remove(path),
+        Some((head, rest)) => match fields.get_mut(head) {
+            Some(FirestoreValue::MapValue { fields }) => remove_field_path(fields, rest),
+            _ => None,
+        },
+    }
+}
+
+// Query evaluation
+
+/// Runs a structured query over `documents` the way Firestore would.
+pub(crate) fn evaluate_query<'a>(
+    documents: impl Iterator<Item = &'a StoredDocument>,
+    parent: &str,
+    query: &StructuredQuery,
+) -> Result<Vec<StoredDocument>> {
+    query.validate()?;
+    let parent = normalize_path(parent);
+    if let Some(nearest) = &query.find_nearest {
+        let candidates = documents
+            .filter(|document| in_scope(document, parent, query))
+            .filter(|document| query.r#where.as_ref().is_none_or(|filter| matches_filter(document, filter)));
+        return find_nearest(candidates, query, nearest);
+    }
+    let orders = effective_orders(query);
+
+    let mut matched: Vec<(&StoredDocument, Vec<FirestoreValue>)> = documents
+        .filter(|document| in_scope(document, parent, query))
+        .filter(|document| query.r#where.as_ref().is_none_or(|filter| matches_filter(document, filter)))
+        .filter_map(|document| {
+            // Documents without a value for every order-by field are excluded
+            let keys: Option<Vec<FirestoreValue>> = orders.iter()
+                .map(|order| field_value(document, &order.field.field_path))
+                .collect();
+            keys.map(|keys| (document, keys))
+        })
+        .collect();
+
+    matched.sort_by(|(_, a), (_, b)| compare_keys(a, b, &orders));
+
+    if let Some(cursor) = &query.start_at {
+        matched.retain(|(_, keys)| {
+            let ordering = compare_to_cursor(keys, cursor, &orders);
+            if cursor.before.unwrap_or(false) { ordering != Ordering::Less } else { ordering == Ordering::Greater }
+        });
+    }
+    if let Some(cursor) = &query.end_at {
+        matched.retain(|(_, keys)| {
+            let ordering = compare_to_cursor(keys, cursor, &orders);
+            if cursor.before.unwrap_or(false) { ordering == Ordering::Less } else { ordering != Ordering::Greater }
+        });
+    }
+
+    let offset = query.offset.unwrap_or(0).max(0) as usize;
+    let limit = query.limit.map(|l| l.max(0) as usize).unwrap_or(usize::MAX);
+    Ok(matched.into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(document, _)| document.clone())
+        .collect())
+}
+
+/// Brute-force vector search: measures the distance to every candidate with
+/// a vector of the right dimension and keeps the nearest.
+fn find_nearest<'a>(
+    candidates: impl Iterator<Item = &'a StoredDocument>,
+    query: &StructuredQuery,
+    nearest: &FindNearest,
+) -> Result<Vec<StoredDocument>> {
+    // The query has been validated, so this is a non-empty vector
+    let FirestoreValue::VectorValue(target) = &nearest.query_vector else {
+        return Ok(Vec::new());
+    };
+
+    let mut scored: Vec<(f64, &StoredDocument)> = candidates
+        .filter_map(|document| match get_field_path(&document.fields, &nearest.vector_field.field_path) {
+            Some(FirestoreValue::VectorValue(vector)) if vector.len() == target.len() => {
+                vector_distance(nearest.distance_measure, vector, target).map(|distance| (distance, document))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let descending = nearest.distance_measure == DistanceMeasure::DotProduct;
+    scored.sort_by(|(a, doc_a), (b, doc_b)| {
+        let ordering = compare_numbers(*a, *b);
+        if descending { ordering.reverse() } else { ordering }.then_with(|| doc_a.name.cmp(&doc_b.name))
+    });
+
+    let limit = query.limit.map_or(usize::MAX, |l| l.max(0) as usize).min(nearest.limit as usize);
+    Ok(scored.into_iter()
+        .take(limit)
+        .map(|(distance, document)| {
+            let mut document = document.clone();
+            if let Some(field) = &nearest.distance_result_field {
+                set_field_path(&mut document.fields, field, FirestoreValue::DoubleValue(distance));
+            }
+            document
+        })
+        .collect())
+}
+
+/// `None` when the distance is undefined (cosine with a zero vector).
+fn vector_distance(measure: DistanceMeasure, a: &[f64], b: &[f64]) -> Option<f64> {
+    let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    match measure {
+        DistanceMeasure::DotProduct => Some(dot),
+        DistanceMeasure::Euclidean => Some(a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f64>().sqrt()),
+        DistanceMeasure::Cosine => {
+            let norms = a.iter().map(|a| a * a).sum::<f64>().sqrt() * b.iter().map(|b| b * b).sum::<f64>().sqrt();
+            (norms > 0.0).then(|| 1.0 - dot / norms)
+        }
+    }
+}
+
+fn in_scope(document: &StoredDocument, parent: &str, query: &StructuredQuery) -> bool {
+    let path = normalize_path(document_relative_path(&document.name));
+    let collection_path = parent_of(path);
+    let (collection_parent, collection_id) = match collection_path.rsplit_once('/') {
+        Some((collection_parent, collection_id)) => (collection_parent, collection_id),
+        None => ("", collection_path),
+    };
+
+    query.from.iter().any(|selector| {
+        if selector.collection_id != collection_id {
+            return false;
+        }
+        if selector.all_descendants.unwrap_or(false) {
+            parent.is_empty() || collection_parent == parent || collection_parent.starts_with(&format!("{}/", parent))
+        } else {
+            collection_parent == parent
+        }
+    })
+}
+
+fn field_value(document: &StoredDocument, field_path: &str) -> Option<FirestoreValue> {
+    if field_path == NAME_FIELD {
+        return Some(FirestoreValue::ReferenceValue(document.name.clone()));
+    }
+    get_field_path(&document.fields, field_path).cloned()
+}
+
+fn compare_keys(a: &[FirestoreValue], b: &[FirestoreValue], orders: &[Order]) -> Ordering {
+    for ((a, b), order) in a.iter().zip(b).zip(orders) {
+        let mut ordering = compare_values(a, b);
+        if order.direction == Some(Direction::Descending) {
+            ordering = ordering.reverse();
+        }
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Compares a document's order-by values against a (possibly shorter) cursor.
+fn compare_to_cursor(keys: &[FirestoreValue], cursor: &Cursor, orders: &[Order]) -> Ordering {
+    let len = cursor.values.len().min(keys.len());
+    compare_keys(&keys[..len], &cursor.values[..len], &orders[..len])
+}
+
+pub(crate) fn matches_filter(document: &StoredDocument, filter: &Filter) -> bool {
+    match filter {
+        Filter::CompositeFilter(composite) => match composite.op {
+            CompositeOperator::And => composite.filters.iter().all(|f| matches_filter(document, f)),
+            CompositeOperator::Or => composite.filters.iter().any(|f| matches_filter(document, f)),
+        },
+        Filter::FieldFilter(field_filter) => {
+            let value = field_value(document, &field_filter.field.field_path);
+            matches_field_filter(value.as_ref(), field_filter.op, &field_filter.value)
+        }
+        Filter::UnaryFilter(unary) => {
+            let value = field_value(document, &unary.field.field_path);
+            match (unary.op, value) {
+                (UnaryOperator::IsNull, Some(FirestoreValue::NullValue(_))) => true,
+                (UnaryOperator::IsNan, Some(FirestoreValue::DoubleValue(d))) => d.is_nan(),
+                (UnaryOperator::IsNotNull, Some(value)) => !matches!(value, FirestoreValue::NullValue(_)),
+                (UnaryOperator::IsNotNan, Some(FirestoreValue::DoubleValue(d))) => !d.is_nan(),
+                (UnaryOperator::IsNotNan, Some(value)) => !matches!(value, FirestoreValue::NullValue(_)),
+                _ => false,
+            }
+        }
+    }
+}
+
+fn matches_field_filter(value: Option<&FirestoreValue>, op: FieldOperator, operand: &FirestoreValue) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    let is_null = matches!(value, FirestoreValue::NullValue(_));
+    let list = match operand {
+        FirestoreValue::ArrayValue { values } => values.as_slice(),
+        _ => &[],
+    };
+
+    match op {
+        FieldOperator::Equal => values_equal(value, operand),
+        FieldOperator::NotEqual => !is_null && !values_equal(value, operand),
+        FieldOperator::LessThan => comparable(value, operand) && compare_values(value, operand) == Ordering::Less,
+        FieldOperator::LessThanOrEqual => comparable(value, operand) && compare_values(value, operand) != Ordering::Greater,
+        FieldOperator::GreaterThan => comparable(value, operand) && compare_values(value, operand) == Ordering::Greater,
+        FieldOperator::GreaterThanOrEqual => comparable(value, operand) && compare_values(value, operand) != Ordering::Less,
+        FieldOperator::In => list.iter().any(|candidate| values_equal(value, candidate)),
+        FieldOperator::NotIn => !is_null && !list.iter().any(|candidate| values_equal(value, candidate)),
+        FieldOperator::ArrayContains => match value {
+            FirestoreValue::ArrayValue { values } => values.iter().any(|element| values_equal(element, operand)),
+            _ => false,
+        },
+        FieldOperator::ArrayContainsAny => match value {
+            FirestoreValue::ArrayValue { values } => values.iter()
+                .any(|element| list.iter().any(|candidate| values_equal(element, candidate))),
+            _ => false,
+        },
+    }
+}
+
+/// Range filters only match values of the same type class as the operand.
+fn comparable(a: &FirestoreValue, b: &FirestoreValue) -> bool {
+    type_order(a) == type_order(b)
+}
+
+pub(crate) fn values_equal(a: &FirestoreValue, b: &FirestoreValue) -> bool {
+    comparable(a, b) && compare_values(a, b) == Ordering::Equal
+}
+
+/// Rank of a value's type in Firestore's cross-type ordering.
+pub(crate) fn type_order(value: &FirestoreValue) -> u8 {
+    match value {
+        FirestoreValue::NullValue(_) => 0,
+        FirestoreValue::BooleanValue(_) => 1,
+        FirestoreValue::IntegerValue(_) | FirestoreValue::DoubleValue(_) => 2,
+        FirestoreValue::TimestampValue(_) => 3,
+        FirestoreValue::StringValue(_) => 4,
+        FirestoreValue::BytesValue(_) => 5,
+        FirestoreValue::ReferenceValue(_) => 6,
+        FirestoreValue::GeoPointValue { .. } => 7,
+        FirestoreValue::ArrayValue { .. } => 8,
+        FirestoreValue::VectorValue(_) => 9,
+        FirestoreValue::MapValue { .. } => 10,
+        FirestoreValue::Unknown => 11,
+    }
+}
+
+/// Total order over Firestore values: null < booleans < numbers (NaN first,
+/// integers and doubles compared numerically) < timestamps < strings < bytes
+/// < references < geo points < arrays < vectors < maps.
+pub fn compare_values(a: &FirestoreValue, b: &FirestoreValue) -> Ordering {
+    let by_type = type_order(a).cmp(&type_order(b));
+    if by_type != Ordering::Equal {
+        return by_type;
+    }
+
+    match (a, b) {
+        (FirestoreValue::BooleanValue(a), FirestoreValue::BooleanValue(b)) => a.cmp(b),
+        (FirestoreValue::IntegerValue(a), FirestoreValue::IntegerValue(b)) => {
+            match (a.parse::<i64>(), b.parse::<i64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            }
+        }
+        (a @ (FirestoreValue::IntegerValue(_) | FirestoreValue::DoubleValue(_)), b) => {
+            compare_numbers(a.as_f64().unwrap_or(f64::NAN), b.as_f64().unwrap_or(f64::NAN))
+        }
+        (FirestoreValue::TimestampValue(a), FirestoreValue::TimestampValue(b)) => {
+            match (DateTime::parse_from_rfc3339(a), DateTime::parse_from_rfc3339(b)) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            }
+        }
+        (FirestoreValue::StringValue(a), FirestoreValue::StringValue(b)) => a.as_bytes().cmp(b.as_bytes()),
+        (FirestoreValue::BytesValue(a), FirestoreValue::BytesValue(b)) => a.cmp(b),
+        (FirestoreValue::ReferenceValue(a), FirestoreValue::ReferenceValue(b)) => {
+            let a = document_relative_path(a).split('/');
+            let b = document_relative_path(b).split('/');
+            a.cmp(b)
+        }
+        (
+            FirestoreValue::GeoPointValue { latitude: lat_a, longitude: lng_a },
+            FirestoreValue::GeoPointValue { latitude: lat_b, longitude: lng_b },
+        ) => compare_numbers(*lat_a, *lat_b).then_with(|| compare_numbers(*lng_a, *lng_b)),
+        (FirestoreValue::ArrayValue { values: a }, FirestoreValue::ArrayValue { values: b }) => {
+            for (a, b) in a.iter().zip(b) {
+                let ordering = compare_values(a, b);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.len().cmp(&b.len())
+        }
+        // Vectors order by dimension first, then component by component
+        (FirestoreValue::VectorValue(a), FirestoreValue::VectorValue(b)) => a.len().cmp(&b.len())
+            .then_with(|| a.iter().zip(b).map(|(a, b)| compare_numbers(*a, *b)).find(|o| o.is_ne()).unwrap_or(Ordering::Equal)),
+        (FirestoreValue::MapValue { fields: a }, FirestoreValue::MapValue { fields: b }) => {
+            let a: BTreeMap<&String, &FirestoreValue> = a.iter().collect();
+            let b: BTreeMap<&String, &FirestoreValue> = b.iter().collect();
+            for ((key_a, value_a), (key_b, value_b)) in a.iter().zip(b.iter()) {
+                let ordering = key_a.as_bytes().cmp(key_b.as_bytes())
+                    .then_with(|| compare_values(value_a, value_b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.len().cmp(&b.len())
+        }
+        _ => Ordering::Equal,
+    }
+}
+
+/// Numeric comparison where NaN sorts before every other number.
+fn compare_numbers(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
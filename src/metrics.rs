@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Running totals for a [`crate::FirebaseClient`], shared by all its clones.
+#[derive(Debug, Default)]
+pub struct ClientMetrics {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+/// A point-in-time copy of [`ClientMetrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// HTTP requests sent (or replayed).
+    pub requests: u64,
+    /// Requests that failed or returned a non-2xx status.
+    pub errors: u64,
+    /// Documents returned by gets, lists, queries and batch gets.
+    pub reads: u64,
+    /// Documents created, updated or set.
+    pub writes: u64,
+    pub deletes: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl ClientMetrics {
+    pub(crate) fn record_request(&self, bytes_sent: usize, bytes_received: usize, success: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes_sent as u64, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes_received as u64, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_error(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_reads(&self, count: usize) {
+        self.reads.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_writes(&self, count: usize) {
+        self.writes.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_deletes(&self, count: usize) {
+        self.deletes.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.requests, &self.errors, &self.reads, &self.writes,
+            &self.deletes, &self.bytes_sent, &self.bytes_received,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Renders the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let counters = [
+            ("firestore_requests_total", "HTTP requests sent to Firestore", self.requests),
+            ("firestore_errors_total", "Firestore requests that failed", self.errors),
+            ("firestore_document_reads_total", "Documents read", self.reads),
+            ("firestore_document_writes_total", "Documents written", self.writes),
+            ("firestore_document_deletes_total", "Documents deleted", self.deletes),
+            ("firestore_bytes_sent_total", "Request body bytes sent", self.bytes_sent),
+            ("firestore_bytes_received_total", "Response body bytes received", self.bytes_received),
+        ];
+
+        let mut output = String::new();
+        for (name, help, value) in counters {
+            output.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value));
+        }
+        output
+    }
+}
@@ -0,0 +1,506 @@
+use crate::error::{FirebaseError, Result};
+use crate::memory::{remove_field_path, set_field_path};
+use crate::models::{FirestoreValue, firestore_value_to_json, get_field_path, json_to_firestore_fields, json_value_to_firestore};
+use crate::query::QueryBuilder;
+use crate::schema::{FieldType, Migration, MigrationCheckpoint, MigrationContext};
+use crate::store::{DocumentStore, ReadOptions, StoredDocument, Write, normalize_path};
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const DEFAULT_PAGE_SIZE: usize = 300;
+
+/// A migration defined in a YAML or JSON file, for runs from the CLI:
+///
+/// ```yaml
+/// version: "002"
+/// description: Split names
+/// up:
+///   - split_field: { collection: users, field: name, into: [first_name, last_name] }
+///   - set_default_where_missing: { collection: users, field: role, value: member }
+/// down:
+///   - merge_fields: { collection: users, from: [first_name, last_name], into: name }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationFile {
+    /// Defaults to the file name without its extension, e.g. `002_split_names`.
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Documents read per page by field steps; a checkpoint is saved after each page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<usize>,
+    #[serde(default)]
+    pub up: Vec<MigrationStep>,
+    #[serde(default)]
+    pub down: Vec<MigrationStep>,
+}
+
+/// One step of a [`MigrationFile`]. Document steps write a single document;
+/// the others scan every document in `collection` and rewrite the ones they
+/// apply to. Field paths may be dotted to reach into maps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStep {
+    /// Creates or replaces a document.
+    Set { path: String, data: serde_json::Value },
+    /// Overwrites the fields in `data`, leaving the rest of the document alone.
+    Update { path: String, data: serde_json::Value },
+    Delete { path: String },
+    RenameField { collection: String, from: String, to: String },
+    /// Converts values to `to`, one of `String`, `Integer`, `Double`,
+    /// `Boolean` or `Timestamp`. Nulls are left alone.
+    ChangeType {
+        collection: String,
+        field: String,
+        to: FieldType,
+        #[serde(default)]
+        on_error: ConversionPolicy,
+    },
+    SetDefaultWhereMissing { collection: String, field: String, value: serde_json::Value },
+    RemoveField { collection: String, field: String },
+    /// Splits a string at `separator` into the `into` fields; the last one
+    /// gets the remainder.
+    SplitField {
+        collection: String,
+        field: String,
+        into: Vec<String>,
+        #[serde(default = "default_separator")]
+        separator: String,
+        #[serde(default)]
+        keep_source: bool,
+    },
+    /// Joins the `from` fields that are present into one string.
+    MergeFields {
+        collection: String,
+        from: Vec<String>,
+        into: String,
+        #[serde(default = "default_separator")]
+        separator: String,
+        #[serde(default)]
+        keep_sources: bool,
+    },
+    /// Moves an array or map of entries out of each document into a
+    /// subcollection of it. Array entries are keyed by `id_field`, or by their
+    /// position without one; map entries by their key. Entries that are not
+    /// maps are stored under `value`.
+    MoveToSubcollection {
+        collection: String,
+        field: String,
+        subcollection: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id_field: Option<String>,
+    },
+}
+
+/// What `change_type` does with a value it cannot convert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionPolicy {
+    /// Stop the migration; it resumes from the last checkpoint once the data is fixed.
+    #[default]
+    Fail,
+    /// Leave the value as it is.
+    Skip,
+    /// Replace the value with null.
+    Null,
+    /// Delete the field.
+    Remove,
+}
+
+fn default_separator() -> String {
+    " ".to_string()
+}
+
+impl MigrationStep {
+    /// The collection a field step scans, or `None` for a document step.
+    pub fn collection(&self) -> Option<&str> {
+        match self {
+            MigrationStep::Set { .. } | MigrationStep::Update { .. } | MigrationStep::Delete { .. } => None,
+            MigrationStep::RenameField { collection, .. }
+            | MigrationStep::ChangeType { collection, .. }
+            | MigrationStep::SetDefaultWhereMissing { collection, .. }
+            | MigrationStep::RemoveField { collection, .. }
+            | MigrationStep::SplitField { collection, .. }
+            | MigrationStep::MergeFields { collection, .. }
+            | MigrationStep::MoveToSubcollection { collection, .. } => Some(collection),
+        }
+    }
+
+    /// The writes a field step makes to one document; none if it does not apply.
+    pub fn transform(&self, document: &StoredDocument) -> Result<Vec<Write>> {
+        let fields = &document.fields;
+        let mut update = FieldUpdate::default();
+        let mut writes = Vec::new();
+        match self {
+            MigrationStep::Set { .. } | MigrationStep::Update { .. } | MigrationStep::Delete { .. } => {}
+            MigrationStep::RenameField { from, to, .. } => {
+                if let Some(value) = get_field_path(fields, from) {
+                    update.remove(from);
+                    update.set(to, value.clone());
+                }
+            }
+            MigrationStep::ChangeType { field, to, on_error, .. } => match get_field_path(fields, field) {
+                None | Some(FirestoreValue::NullValue(_)) => {}
+                Some(value) => match convert(value, to) {
+                    Some(converted) if &converted == value => {}
+                    Some(converted) => update.set(field, converted),
+                    None => match on_error {
+                        ConversionPolicy::Fail => return Err(FirebaseError::ValidationError(format!(
+                            "Cannot convert '{}' of {} to {:?}: {}",
+                            field, document.path(), to, firestore_value_to_json(value)
+                        ))),
+                        ConversionPolicy::Skip => {}
+                        ConversionPolicy::Null => update.set(field, FirestoreValue::NullValue(None)),
+                        ConversionPolicy::Remove => update.remove(field),
+                    },
+                },
+            },
+            MigrationStep::SetDefaultWhereMissing { field, value, .. } => {
+                if get_field_path(fields, field).is_none() {
+                    update.set(field, json_value_to_firestore(value.clone()));
+                }
+            }
+            MigrationStep::RemoveField { field, .. } => {
+                if get_field_path(fields, field).is_some() {
+                    update.remove(field);
+                }
+            }
+            MigrationStep::SplitField { field, into, separator, keep_source, .. } => match get_field_path(fields, field) {
+                Some(FirestoreValue::StringValue(value)) => {
+                    if !keep_source {
+                        update.remove(field);
+                    }
+                    for (target, part) in into.iter().zip(value.splitn(into.len(), separator.as_str())) {
+                        update.set(target, FirestoreValue::StringValue(part.trim().to_string()));
+                    }
+                }
+                Some(value) => tracing::warn!(document = %document.path(), field, kind = crate::validation::type_name(value), "Not splitting a non-string field"),
+                None => {}
+            },
+            MigrationStep::MergeFields { from, into, separator, keep_sources, .. } => {
+                let parts: Vec<String> = from.iter()
+                    .filter_map(|source| get_field_path(fields, source).and_then(display_value))
+                    .collect();
+                if !parts.is_empty() {
+                    if !keep_sources {
+                        for source in from {
+                            if get_field_path(fields, source).is_some() {
+                                update.remove(source);
+                            }
+                        }
+                    }
+                    update.set(into, FirestoreValue::StringValue(parts.join(separator)));
+                }
+            }
+            MigrationStep::MoveToSubcollection { field, subcollection, id_field, .. } => {
+                let entries: Option<Vec<(String, &FirestoreValue)>> = match get_field_path(fields, field) {
+                    Some(FirestoreValue::ArrayValue { values }) => Some(values.iter()
+                        .enumerate()
+                        .map(|(i, value)| (entry_id(value, id_field.as_deref()).unwrap_or_else(|| i.to_string()), value))
+                        .collect()),
+                    Some(FirestoreValue::MapValue { fields }) => Some(fields.iter().map(|(key, value)| (key.clone(), value)).collect()),
+                    Some(value) => {
+                        tracing::warn!(document = %document.path(), field, kind = crate::validation::type_name(value), "Not moving a field that is neither an array nor a map");
+                        None
+                    }
+                    None => None,
+                };
+                if let Some(entries) = entries {
+                    for (id, value) in entries {
+                        let fields = match value {
+                            FirestoreValue::MapValue { fields } => fields.clone(),
+                            value => HashMap::from([("value".to_string(), value.clone())]),
+                        };
+                        let path = format!("{}/{}/{}", document.path(), subcollection, id);
+                        writes.push(Write::Set { path, fields });
+                    }
+                    update.remove(field);
+                }
+            }
+        }
+        if let Some(write) = update.into_write(document.path()) {
+            writes.push(write);
+        }
+        Ok(writes)
+    }
+
+    /// Applies the step. Field steps scan their collection a page at a time,
+    /// starting after `resume_after`, and save a checkpoint after each page.
+    pub async fn apply<S: DocumentStore + Clone + 'static>(
+        &self,
+        context: &mut MigrationContext<S>,
+        checkpoint: MigrationCheckpoint,
+        page_size: usize,
+    ) -> Result<MigrationCheckpoint> {
+        let step = checkpoint.step;
+        let Some(collection) = self.collection() else {
+            self.apply_to_document(context).await?;
+            return Ok(MigrationCheckpoint { step: step + 1, last_document: None, processed: checkpoint.processed });
+        };
+
+        let path = normalize_path(collection);
+        let (parent, collection_id) = path.rsplit_once('/').unwrap_or(("", path));
+        let mut checkpoint = checkpoint;
+        loop {
+            let mut query = QueryBuilder::new(collection_id)
+                .order_by("__name__", false)
+                .limit(page_size as i32);
+            if let Some(last_document) = &checkpoint.last_document {
+                query = query.start_after(vec![FirestoreValue::ReferenceValue(last_document.clone())]);
+            }
+            let page = context.client().run_query(parent, &query.build(), &ReadOptions::default()).await?;
+            for document in &page {
+                for write in self.transform(document)? {
+                    context.write(write).await;
+                }
+            }
+
+            let Some(last) = page.last() else {
+                break;
+            };
+            checkpoint.last_document = Some(last.name.clone());
+            checkpoint.processed += page.len() as u64;
+            context.save_checkpoint(&checkpoint).await?;
+            tracing::debug!(step, collection, processed = checkpoint.processed, "Migration page done");
+            if page.len() < page_size {
+                break;
+            }
+        }
+        Ok(MigrationCheckpoint { step: step + 1, last_document: None, processed: checkpoint.processed })
+    }
+
+    async fn apply_to_document<S: DocumentStore + Clone + 'static>(&self, context: &mut MigrationContext<S>) -> Result<()> {
+        match self {
+            MigrationStep::Set { path, data } => {
+                context.set(path, json_to_firestore_fields(data.clone())?).await;
+            }
+            MigrationStep::Update { path, data } => {
+                let fields = json_to_firestore_fields(data.clone())?;
+                let mask = fields.keys().cloned().collect();
+                context.update(path, fields, mask).await;
+            }
+            MigrationStep::Delete { path } => context.delete(path).await,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> std::result::Result<(), String> {
+        if self.collection().is_some_and(str::is_empty) {
+            return Err("'collection' must not be empty".to_string());
+        }
+        match self {
+            MigrationStep::Set { data, .. } | MigrationStep::Update { data, .. } if !data.is_object() => {
+                Err("'data' must be an object".to_string())
+            }
+            MigrationStep::ChangeType { to, .. }
+                if !matches!(to, FieldType::String | FieldType::Integer | FieldType::Double | FieldType::Boolean | FieldType::Timestamp) =>
+            {
+                Err(format!("cannot convert to {:?}", to))
+            }
+            MigrationStep::SplitField { into, .. } if into.is_empty() => Err("'into' must list at least one field".to_string()),
+            MigrationStep::SplitField { separator, .. } | MigrationStep::MergeFields { separator, .. } if separator.is_empty() => {
+                Err("'separator' must not be empty".to_string())
+            }
+            MigrationStep::MergeFields { from, .. } if from.is_empty() => Err("'from' must list at least one field".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MigrationFile {
+    /// Reads a `.yaml`, `.yml` or `.json` migration file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read migration {}: {}", path.display(), e)))?;
+        // Steps are externally tagged enums, which serde_yaml only reads as
+        // YAML tags, so YAML goes through a JSON value first
+        let value: serde_json::Value = if matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml")) {
+            serde_yaml::from_str(&content)
+                .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse migration {}: {}", path.display(), e)))?
+        } else {
+            serde_json::from_str(&content)?
+        };
+        let mut file: MigrationFile = serde_json::from_value(value)
+            .map_err(|e| FirebaseError::ConfigError(format!("Invalid migration {}: {}", path.display(), e)))?;
+
+        if file.version.is_empty() {
+            file.version = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+        }
+        if file.page_size == Some(0) {
+            return Err(FirebaseError::ConfigError(format!("Invalid migration {}: page_size must be at least 1", path.display())));
+        }
+        for (i, step) in file.up.iter().chain(&file.down).enumerate() {
+            step.check().map_err(|e| FirebaseError::ConfigError(format!(
+                "Invalid step {} in migration {}: {}", i + 1, path.display(), e
+            )))?;
+        }
+        Ok(file)
+    }
+
+    pub fn into_migration<S: DocumentStore + Clone + 'static>(self) -> Migration<S> {
+        let page_size = self.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let up = Arc::new(self.up);
+        let down = Arc::new(self.down);
+        Migration::new(self.version, self.description)
+            .up(move |context| {
+                let steps = up.clone();
+                Box::pin(async move { run_steps(&steps, page_size, context).await })
+            })
+            .down(move |context| {
+                let steps = down.clone();
+                Box::pin(async move { run_steps(&steps, page_size, context).await })
+            })
+    }
+}
+
+/// Every migration file in `dir`, in version order. A missing directory has none.
+pub fn load_migrations(dir: &str) -> Result<Vec<MigrationFile>> {
+    let dir = Path::new(dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml" | "json")) {
+            files.push(MigrationFile::load(&path)?);
+        }
+    }
+    files.sort_by(|a, b| a.version.cmp(&b.version));
+    if let Some(pair) = files.windows(2).find(|pair| pair[0].version == pair[1].version) {
+        return Err(FirebaseError::ConfigError(format!(
+            "Two migrations in {} have version '{}'", dir.display(), pair[0].version
+        )));
+    }
+    Ok(files)
+}
+
+/// Runs `steps` in order, resuming from the checkpoint of a run that died.
+/// Every step is safe to repeat, so the page that was in flight is simply
+/// processed again.
+///
+/// A dry run ignores checkpoints and reports every step against the data as
+/// it is now, since nothing is written: a step that depends on an earlier
+/// one (`rename_field` then `change_type` on the new name) reports nothing.
+async fn run_steps<S: DocumentStore + Clone + 'static>(
+    steps: &[MigrationStep],
+    page_size: usize,
+    context: &mut MigrationContext<S>,
+) -> Result<()> {
+    let mut checkpoint = if context.is_dry_run() {
+        MigrationCheckpoint::default()
+    } else {
+        context.checkpoint().await?.unwrap_or_default()
+    };
+    if checkpoint != MigrationCheckpoint::default() {
+        tracing::info!(
+            version = context.version(),
+            step = checkpoint.step + 1,
+            processed = checkpoint.processed,
+            "Resuming migration from checkpoint"
+        );
+    }
+    while let Some(step) = steps.get(checkpoint.step) {
+        checkpoint = step.apply(context, checkpoint, page_size).await?;
+        if checkpoint.step < steps.len() {
+            context.save_checkpoint(&checkpoint).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Changes to one document's fields, sent as a single masked update.
+#[derive(Default)]
+struct FieldUpdate {
+    fields: HashMap<String, FirestoreValue>,
+    mask: Vec<String>,
+}
+
+impl FieldUpdate {
+    fn set(&mut self, path: &str, value: FirestoreValue) {
+        set_field_path(&mut self.fields, path, value);
+        if !self.mask.iter().any(|masked| masked == path) {
+            self.mask.push(path.to_string());
+        }
+    }
+
+    // A masked path with no value is deleted
+    fn remove(&mut self, path: &str) {
+        remove_field_path(&mut self.fields, path);
+        if !self.mask.iter().any(|masked| masked == path) {
+            self.mask.push(path.to_string());
+        }
+    }
+
+    fn into_write(self, path: &str) -> Option<Write> {
+        (!self.mask.is_empty()).then(|| Write::Update { path: path.to_string(), fields: self.fields, mask: self.mask })
+    }
+}
+
+/// `value` converted to `to`, or `None` when it has no sensible equivalent.
+/// Integers become timestamps as seconds since the Unix epoch.
+fn convert(value: &FirestoreValue, to: &FieldType) -> Option<FirestoreValue> {
+    match (to, value) {
+        (FieldType::String, value) => display_value(value).map(FirestoreValue::StringValue),
+        (FieldType::Integer, FirestoreValue::IntegerValue(_)) => Some(value.clone()),
+        (FieldType::Integer, FirestoreValue::StringValue(s)) => s.trim().parse::<i64>().ok()
+            .map(|i| FirestoreValue::IntegerValue(i.to_string())),
+        (FieldType::Integer, FirestoreValue::DoubleValue(d)) if d.fract() == 0.0 && d.abs() < i64::MAX as f64 => {
+            Some(FirestoreValue::IntegerValue((*d as i64).to_string()))
+        }
+        (FieldType::Integer, FirestoreValue::BooleanValue(b)) => Some(FirestoreValue::IntegerValue(u8::from(*b).to_string())),
+        (FieldType::Double, FirestoreValue::DoubleValue(_)) => Some(value.clone()),
+        (FieldType::Double, FirestoreValue::IntegerValue(i)) => i.parse::<f64>().ok().map(FirestoreValue::DoubleValue),
+        (FieldType::Double, FirestoreValue::StringValue(s)) => s.trim().parse::<f64>().ok()
+            .filter(|d| d.is_finite())
+            .map(FirestoreValue::DoubleValue),
+        (FieldType::Boolean, FirestoreValue::BooleanValue(_)) => Some(value.clone()),
+        (FieldType::Boolean, FirestoreValue::StringValue(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(FirestoreValue::BooleanValue(true)),
+            "false" | "no" | "0" => Some(FirestoreValue::BooleanValue(false)),
+            _ => None,
+        },
+        (FieldType::Boolean, FirestoreValue::IntegerValue(i)) => match i.as_str() {
+            "1" => Some(FirestoreValue::BooleanValue(true)),
+            "0" => Some(FirestoreValue::BooleanValue(false)),
+            _ => None,
+        },
+        (FieldType::Timestamp, FirestoreValue::TimestampValue(_)) => Some(value.clone()),
+        (FieldType::Timestamp, FirestoreValue::StringValue(s)) => {
+            let s = s.trim();
+            DateTime::parse_from_rfc3339(s).ok()
+                .map(|timestamp| timestamp.to_utc())
+                .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|midnight| midnight.and_utc()))
+                .map(|timestamp| FirestoreValue::TimestampValue(timestamp.to_rfc3339()))
+        }
+        (FieldType::Timestamp, FirestoreValue::IntegerValue(i)) => i.parse::<i64>().ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|timestamp| FirestoreValue::TimestampValue(timestamp.to_rfc3339())),
+        _ => None,
+    }
+}
+
+/// A scalar as text, as used by `merge_fields` and conversions to strings.
+fn display_value(value: &FirestoreValue) -> Option<String> {
+    match value {
+        FirestoreValue::StringValue(s) | FirestoreValue::IntegerValue(s) | FirestoreValue::TimestampValue(s) => Some(s.clone()),
+        FirestoreValue::DoubleValue(d) => Some(d.to_string()),
+        FirestoreValue::BooleanValue(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn entry_id(value: &FirestoreValue, id_field: Option<&str>) -> Option<String> {
+    match value {
+        FirestoreValue::MapValue { fields } => fields.get(id_field?).and_then(display_value).filter(|id| !id.is_empty() && !id.contains('/')),
+        _ => None,
+    }
+}
@@ -0,0 +1,711 @@
+use crate::error::{FirebaseError, Result, RpcCode};
+use crate::explain::ExplainOptions;
+use crate::firebase::REST_OPERATIONS;
+use crate::index_admin::{AdminIndex, IndexState, Operation};
+use crate::indexes::{IndexedField, QueryScope};
+use crate::memory::{MemoryStore, already_exists};
+use crate::models::FirestoreValue;
+use crate::query::StructuredQuery;
+use crate::store::{DocumentStore, ListOptions, ReadOptions, StoredDocument, Write};
+use axum::{Json, Router};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Faults injected into responses so clients can be tested against a slow or
+/// unreliable backend.
+#[derive(Debug, Clone)]
+pub struct FaultConfig {
+    /// Fixed delay added before every response.
+    pub latency: Duration,
+    /// Extra random delay of up to this much on top of `latency`.
+    pub jitter: Duration,
+    /// Fraction (0.0-1.0) of requests answered with `error_status`.
+    pub error_rate: f64,
+    /// Fail this many requests before serving normally, for deterministic retry tests.
+    pub fail_first: usize,
+    pub error_status: u16,
+    /// Only inject faults into requests whose path contains this string.
+    pub path_filter: Option<String>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            error_rate: 0.0,
+            fail_first: 0,
+            error_status: 503,
+            path_filter: None,
+        }
+    }
+}
+
+/// A local stand-in for the Firestore v1 REST API, backed by a [`MemoryStore`].
+///
+/// Serves document CRUD, `:runQuery`, `:runAggregationQuery`, `:commit`,
+/// `:batchWrite`, `:batchGet`, `:partitionQuery`, `:listCollectionIds` and
+/// transactions under `/v1/projects/{project}/databases/(default)/documents`,
+/// plus the emulator's `DELETE /emulator/v1/projects/{project}/databases/(default)/documents`
+/// reset and the Admin API's composite index endpoints
+/// (`collectionGroups/{group}/indexes` and `operations/{id}`). Index builds
+/// finish the first time their operation is polled.
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    store: MemoryStore,
+    data_file: Option<PathBuf>,
+    faults: FaultConfig,
+    failures: Arc<AtomicUsize>,
+    indexes: Arc<Mutex<IndexRegistry>>,
+}
+
+/// Composite indexes created through the Admin API, and the operations that
+/// built them (operation name -> index name).
+#[derive(Debug, Default)]
+struct IndexRegistry {
+    indexes: Vec<AdminIndex>,
+    operations: HashMap<String, String>,
+    next_id: usize,
+}
+
+impl MockServer {
+    pub fn new(project_id: &str) -> Self {
+        Self::with_store(MemoryStore::with_project(project_id))
+    }
+
+    pub fn with_store(store: MemoryStore) -> Self {
+        Self {
+            store,
+            data_file: None,
+            faults: FaultConfig::default(),
+            failures: Arc::new(AtomicUsize::new(0)),
+            indexes: Arc::new(Mutex::new(IndexRegistry::default())),
+        }
+    }
+
+    /// Loads documents from `path` if it exists and writes them back after
+    /// every change.
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            let content = std::fs::read_to_string(&path)?;
+            let documents: Vec<StoredDocument> = serde_json::from_str(&content)?;
+            self.store.load(documents);
+        }
+        self.data_file = Some(path);
+        Ok(self)
+    }
+
+    pub fn faults(mut self, faults: FaultConfig) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    pub fn store(&self) -> &MemoryStore {
+        &self.store
+    }
+
+    pub fn router(self) -> Router {
+        Router::new().fallback(handle).with_state(Arc::new(self))
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    /// Binds `addr` (use port 0 for any free port) and serves in the background,
+    /// returning the bound address for `FIRESTORE_EMULATOR_HOST`.
+    pub async fn spawn(self, addr: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        tokio::spawn(self.serve(listener));
+        Ok(local_addr)
+    }
+
+    async fn inject_fault(&self, path: &str) -> Option<Response> {
+        let faults = &self.faults;
+        if faults.path_filter.as_deref().is_some_and(|filter| !path.contains(filter)) {
+            return None;
+        }
+
+        let mut delay = faults.latency;
+        if !faults.jitter.is_zero() {
+            delay += faults.jitter.mul_f64(rand::thread_rng().r#gen::<f64>());
+        }
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        let fail = self.failures.fetch_add(1, Ordering::SeqCst) < faults.fail_first
+            || (faults.error_rate > 0.0 && rand::thread_rng().r#gen::<f64>() < faults.error_rate);
+        fail.then(|| {
+            let status = StatusCode::from_u16(faults.error_status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            error_response(status, "Injected fault")
+        })
+    }
+
+    async fn persist(&self) -> Result<()> {
+        if let Some(path) = &self.data_file {
+            let content = serde_json::to_string_pretty(&self.store.snapshot())?;
+            tokio::fs::write(path, content).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a request is aimed, parsed from a path like
+/// `/v1/projects/p/databases/(default)/documents/users/abc:runQuery`.
+struct Target {
+    path: String,
+    operation: Option<String>,
+}
+
+fn parse_target(uri_path: &str) -> Option<Target> {
+    let decoded = percent_decode(uri_path);
+    let rest = decoded.strip_prefix("/v1/projects/")?;
+    let (_, rest) = rest.split_once("/databases/")?;
+    let (_, rest) = rest.split_once("/documents")?;
+
+    let (path, operation) = match rest.rsplit_once(':') {
+        Some((path, op)) if REST_OPERATIONS.contains(&op) => (path, Some(op.to_string())),
+        _ => (rest, None),
+    };
+    Some(Target { path: path.trim_matches('/').to_string(), operation })
+}
+
+async fn handle(State(server): State<Arc<MockServer>>, method: Method, uri: Uri, body: Bytes) -> Response {
+    if let Some(response) = server.inject_fault(uri.path()).await {
+        return response;
+    }
+
+    if method == Method::DELETE && uri.path().starts_with("/emulator/v1/") {
+        server.store.clear();
+        return match server.persist().await {
+            Ok(()) => Json(json!({})).into_response(),
+            Err(e) => error_from(&e),
+        };
+    }
+
+    if let Some(response) = handle_admin(&server, &method, uri.path(), &body) {
+        return response;
+    }
+
+    let Some(target) = parse_target(uri.path()) else {
+        return error_response(StatusCode::NOT_FOUND, &format!("Unknown path: {}", uri.path()));
+    };
+    let query = parse_query(uri.query().unwrap_or(""));
+    let body: Value = if body.is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(value) => value,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("Invalid JSON body: {}", e)),
+        }
+    };
+
+    let mutating = method != Method::GET
+        && !matches!(
+            target.operation.as_deref(),
+            Some(
+                "runQuery" | "runAggregationQuery" | "partitionQuery" | "batchGet" | "listCollectionIds"
+                    | "beginTransaction" | "rollback"
+            )
+        );
+
+    let result = dispatch(&server, &method, &target, &query, body).await;
+    if mutating && result.is_ok()
+        && let Err(e) = server.persist().await
+    {
+        return error_from(&e);
+    }
+
+    match result {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => error_from(&e),
+    }
+}
+
+async fn dispatch(
+    server: &MockServer,
+    method: &Method,
+    target: &Target,
+    query: &HashMap<String, Vec<String>>,
+    body: Value,
+) -> Result<Value> {
+    let store = &server.store;
+    let read_option = |name: &str| query.get(name).and_then(|v| v.first().cloned())
+        .or_else(|| body.get(name).and_then(|t| t.as_str()).map(|t| t.to_string()));
+    let read_options = ReadOptions {
+        transaction: read_option("transaction"),
+        read_time: read_option("readTime"),
+    };
+    // Results report the time they were read at
+    let read_time = read_options.read_time.clone().unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+
+    match (method, target.operation.as_deref()) {
+        (&Method::POST, Some("runQuery")) => {
+            let structured: StructuredQuery = from_body(&body, "structuredQuery")?;
+            if let Some(explain) = explain_options(&body)? {
+                // The metrics ride on the last item of the stream
+                let explained = store.explain_query(&target.path, &structured, &read_options, &explain).await?;
+                let mut items: Vec<Value> = explained.documents.into_iter()
+                    .map(|document| json!({ "document": document, "readTime": read_time }))
+                    .collect();
+                match items.last_mut() {
+                    Some(last) => last["explainMetrics"] = json!(explained.metrics),
+                    None => items.push(json!({ "readTime": read_time, "explainMetrics": explained.metrics })),
+                }
+                return Ok(Value::Array(items));
+            }
+            let documents = store.run_query(&target.path, &structured, &read_options).await?;
+            if documents.is_empty() {
+                return Ok(json!([{ "readTime": read_time }]));
+            }
+            Ok(Value::Array(documents.into_iter()
+                .map(|document| json!({ "document": document, "readTime": read_time }))
+                .collect()))
+        }
+        (&Method::POST, Some("runAggregationQuery")) => {
+            let request: AggregationQuery = from_body(&body, "structuredAggregationQuery")?;
+            let explained = match explain_options(&body)? {
+                Some(explain) => {
+                    let explained = store.explain_count(&target.path, &request.structured_query, &read_options, &explain).await?;
+                    if !explain.analyze {
+                        return Ok(json!([{ "readTime": read_time, "explainMetrics": explained.metrics }]));
+                    }
+                    Some(explained.metrics)
+                }
+                None => None,
+            };
+            let documents = store.run_query(&target.path, &request.structured_query, &read_options).await?;
+            let mut aggregate_fields = HashMap::new();
+            for (i, aggregation) in request.aggregations.iter().enumerate() {
+                let alias = aggregation.alias.clone().unwrap_or_else(|| format!("field_{}", i + 1));
+                aggregate_fields.insert(alias, aggregate(aggregation, &documents)?);
+            }
+            let mut item = json!({
+                "result": { "aggregateFields": aggregate_fields },
+                "readTime": read_time,
+            });
+            if let Some(metrics) = explained {
+                item["explainMetrics"] = json!(metrics);
+            }
+            Ok(json!([item]))
+        }
+        (&Method::POST, Some("partitionQuery")) => {
+            let query: StructuredQuery = from_body(&body, "structuredQuery")?;
+            // partitionCount is an int64, so it arrives as a string
+            let partition_count = match body.get("partitionCount") {
+                Some(Value::String(count)) => count.parse().ok(),
+                Some(count) => count.as_u64().map(|count| count as usize),
+                None => None,
+            }.ok_or_else(|| FirebaseError::ValidationError("Request body is missing 'partitionCount'".to_string()))?;
+            let partitions = store.partition_query(&target.path, &query, partition_count, &read_options).await?;
+            Ok(json!({ "partitions": partitions }))
+        }
+        (&Method::POST, Some("batchGet")) => {
+            let names: Vec<String> = from_body(&body, "documents")?;
+            let paths: Vec<String> = names.iter().map(|name| relative_path(name)).collect();
+            let documents = store.batch_get(&paths, &read_options).await?;
+            Ok(Value::Array(names.into_iter().zip(documents)
+                .map(|(name, document)| match document {
+                    Some(document) => json!({ "found": document, "readTime": read_time }),
+                    None => json!({ "missing": name, "readTime": read_time }),
+                })
+                .collect()))
+        }
+        (&Method::POST, Some("commit")) => {
+            let writes: Vec<Value> = body.get("writes").cloned()
+                .map(serde_json::from_value).transpose()?
+                .unwrap_or_default();
+            let writes = writes.iter().map(parse_write).collect::<Result<Vec<Write>>>()?;
+            let transaction = body.get("transaction").and_then(|t| t.as_str());
+            let response = store.commit(writes, transaction).await?;
+            Ok(json!({
+                "writeResults": response.write_results.iter()
+                    .map(|result| json!({ "updateTime": result.update_time }))
+                    .collect::<Vec<_>>(),
+                "commitTime": response.commit_time,
+            }))
+        }
+        (&Method::POST, Some("batchWrite")) => {
+            let writes: Vec<Value> = from_body(&body, "writes")?;
+            let writes = writes.iter().map(parse_write).collect::<Result<Vec<Write>>>()?;
+            let mut write_results = Vec::new();
+            let mut statuses = Vec::new();
+            for result in store.batch_write(writes).await? {
+                match result {
+                    Ok(result) => {
+                        write_results.push(json!({ "updateTime": result.update_time }));
+                        statuses.push(json!({}));
+                    }
+                    Err(e) => {
+                        write_results.push(json!({}));
+                        statuses.push(json!({ "code": rpc_code(&e) as i32, "message": e.to_string() }));
+                    }
+                }
+            }
+            Ok(json!({ "writeResults": write_results, "status": statuses }))
+        }
+        (&Method::POST, Some("beginTransaction")) => {
+            let read_only = body.pointer("/options/readOnly").is_some();
+            Ok(json!({ "transaction": store.begin_transaction(read_only).await? }))
+        }
+        (&Method::POST, Some("rollback")) => {
+            let transaction: String = from_body(&body, "transaction")?;
+            store.rollback(&transaction).await?;
+            Ok(json!({}))
+        }
+        (&Method::POST, Some("listCollectionIds")) => {
+            let collection_ids = store.list_collection_ids(&target.path).await?;
+            Ok(json!({ "collectionIds": collection_ids }))
+        }
+        (_, Some(operation)) => Err(FirebaseError::ValidationError(format!("Unsupported method for {}: {}", operation, method))),
+
+        (&Method::GET, None) if is_collection_path(&target.path) => {
+            let list = ListOptions {
+                page_size: query.get("pageSize").and_then(|v| v.first()).and_then(|v| v.parse().ok()),
+                page_token: query.get("pageToken").and_then(|v| v.first().cloned()),
+            };
+            let page = store.list_page(&target.path, &list, &read_options).await?;
+            let mut response = json!({});
+            if !page.documents.is_empty() {
+                response["documents"] = json!(page.documents);
+            }
+            if let Some(token) = page.next_page_token {
+                response["nextPageToken"] = json!(token);
+            }
+            Ok(response)
+        }
+        (&Method::GET, None) => {
+            match store.fetch(&target.path, &read_options).await? {
+                Some(document) => Ok(json!(document)),
+                None => Err(FirebaseError::NotFound(format!("Document not found: {}", target.path))),
+            }
+        }
+        (&Method::POST, None) => {
+            let fields = document_fields(&body)?;
+            let doc_id = query.get("documentId").and_then(|v| v.first()).map(|s| s.as_str());
+            Ok(json!(store.insert(&target.path, doc_id, fields).await?))
+        }
+        (&Method::PATCH, None) => {
+            let fields = document_fields(&body)?;
+            let must_exist = query.get("currentDocument.exists").and_then(|v| v.first()).map(|v| v == "true");
+            let exists = store.fetch(&target.path, &ReadOptions::default()).await?.is_some();
+            match must_exist {
+                Some(true) if !exists => return Err(FirebaseError::NotFound(format!("No document to update: {}", target.path))),
+                Some(false) if exists => return Err(already_exists(&target.path)),
+                _ => {}
+            }
+            let mask = query.get("updateMask.fieldPaths");
+            Ok(json!(store.patch(&target.path, fields, mask.map(|m| m.as_slice())).await?))
+        }
+        (&Method::DELETE, None) => {
+            // Deleting a missing document succeeds, as in Firestore
+            store.remove(&target.path).await?;
+            Ok(json!({}))
+        }
+        _ => Err(FirebaseError::ValidationError(format!("Unsupported method: {}", method))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AggregationQuery {
+    structured_query: StructuredQuery,
+    #[serde(default)]
+    aggregations: Vec<Aggregation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Aggregation {
+    alias: Option<String>,
+    count: Option<CountAggregation>,
+    sum: Option<FieldAggregation>,
+    avg: Option<FieldAggregation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountAggregation {
+    up_to: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldAggregation {
+    field: crate::query::FieldReference,
+}
+
+fn aggregate(aggregation: &Aggregation, documents: &[StoredDocument]) -> Result<FirestoreValue> {
+    if let Some(count) = &aggregation.count {
+        let up_to = count.up_to.as_ref()
+            .and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+            .unwrap_or(u64::MAX);
+        let total = (documents.len() as u64).min(up_to);
+        return Ok(FirestoreValue::IntegerValue(total.to_string()));
+    }
+
+    let (field, average) = match (&aggregation.sum, &aggregation.avg) {
+        (Some(sum), _) => (&sum.field.field_path, false),
+        (_, Some(avg)) => (&avg.field.field_path, true),
+        _ => return Err(FirebaseError::ValidationError("Aggregation must be count, sum or avg".to_string())),
+    };
+
+    let values: Vec<&FirestoreValue> = documents.iter()
+        .filter_map(|doc| crate::models::get_field_path(&doc.fields, field))
+        .filter(|value| matches!(value, FirestoreValue::IntegerValue(_) | FirestoreValue::DoubleValue(_)))
+        .collect();
+
+    if average {
+        if values.is_empty() {
+            return Ok(FirestoreValue::NullValue(None));
+        }
+        let total: f64 = values.iter().filter_map(|v| v.as_f64()).sum();
+        return Ok(FirestoreValue::DoubleValue(total / values.len() as f64));
+    }
+
+    if values.iter().all(|v| matches!(v, FirestoreValue::IntegerValue(_))) {
+        let total: i64 = values.iter()
+            .filter_map(|v| match v {
+                FirestoreValue::IntegerValue(i) => i.parse::<i64>().ok(),
+                _ => None,
+            })
+            .sum();
+        Ok(FirestoreValue::IntegerValue(total.to_string()))
+    } else {
+        Ok(FirestoreValue::DoubleValue(values.iter().filter_map(|v| v.as_f64()).sum()))
+    }
+}
+
+fn parse_write(write: &Value) -> Result<Write> {
+    if let Some(name) = write.get("delete").and_then(|d| d.as_str()) {
+        return Ok(Write::Delete { path: relative_path(name) });
+    }
+
+    let update = write.get("update")
+        .ok_or_else(|| FirebaseError::ValidationError("Write must contain update or delete".to_string()))?;
+    let path = update.get("name").and_then(|n| n.as_str())
+        .map(relative_path)
+        .ok_or_else(|| FirebaseError::ValidationError("Write is missing the document name".to_string()))?;
+    let fields = document_fields(update)?;
+    let mask: Option<Vec<String>> = write.pointer("/updateMask/fieldPaths").cloned()
+        .map(serde_json::from_value).transpose()?;
+
+    Ok(match (write.pointer("/currentDocument/exists").and_then(|e| e.as_bool()), mask) {
+        (Some(false), _) => Write::Create { path, fields },
+        (_, Some(mask)) => Write::Update { path, fields, mask },
+        _ => Write::Set { path, fields },
+    })
+}
+
+fn document_fields(body: &Value) -> Result<HashMap<String, FirestoreValue>> {
+    match body.get("fields") {
+        Some(fields) => Ok(serde_json::from_value(fields.clone())?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn from_body<T: serde::de::DeserializeOwned>(body: &Value, key: &str) -> Result<T> {
+    let value = body.get(key)
+        .ok_or_else(|| FirebaseError::ValidationError(format!("Request body is missing '{}'", key)))?;
+    Ok(serde_json::from_value(value.clone())?)
+}
+
+fn explain_options(body: &Value) -> Result<Option<ExplainOptions>> {
+    Ok(body.get("explainOptions").cloned().map(serde_json::from_value).transpose()?)
+}
+
+fn relative_path(name: &str) -> String {
+    match name.find("/documents/") {
+        Some(pos) => name[pos + "/documents/".len()..].to_string(),
+        None => name.trim_matches('/').to_string(),
+    }
+}
+
+/// Collection paths have an odd number of segments (`users`, `users/abc/posts`).
+fn is_collection_path(path: &str) -> bool {
+    !path.is_empty() && path.split('/').count() % 2 == 1
+}
+
+fn parse_query(query: &str) -> HashMap<String, Vec<String>> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params.entry(percent_decode(&key.replace('+', " ")))
+            .or_default()
+            .push(percent_decode(&value.replace('+', " ")));
+    }
+    params
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Serves the Admin API index endpoints, or returns `None` for any other path.
+fn handle_admin(server: &MockServer, method: &Method, uri_path: &str, body: &Bytes) -> Option<Response> {
+    let decoded = percent_decode(uri_path);
+    let rest = decoded.strip_prefix("/v1/")?;
+    let (database, resource) = match rest.split_once("/collectionGroups/") {
+        Some((database, _)) => (database, &rest[database.len() + 1..]),
+        None => rest.split_once("/operations/").map(|(database, _)| (database, &rest[database.len() + 1..]))?,
+    };
+    let segments: Vec<&str> = resource.split('/').collect();
+    let mut registry = server.indexes.lock().unwrap_or_else(|e| e.into_inner());
+
+    let response = match (method, segments.as_slice()) {
+        (&Method::GET, ["collectionGroups", group, "indexes"]) => {
+            let indexes: Vec<&AdminIndex> = registry.indexes.iter()
+                .filter(|index| *group == "-" || index.collection_group() == *group)
+                .collect();
+            Json(json!({ "indexes": indexes })).into_response()
+        }
+        (&Method::POST, ["collectionGroups", group, "indexes"]) => {
+            #[derive(Deserialize)]
+            #[serde(rename_all = "camelCase")]
+            struct CreateIndex {
+                query_scope: QueryScope,
+                fields: Vec<IndexedField>,
+            }
+            let request: CreateIndex = match serde_json::from_slice(body) {
+                Ok(request) => request,
+                Err(e) => return Some(error_response(StatusCode::BAD_REQUEST, &format!("Invalid index: {}", e))),
+            };
+            let mut fields = request.fields;
+            if fields.last().is_none_or(|field| field.field_path != "__name__") {
+                let direction = fields.last().and_then(|field| field.order).unwrap_or(crate::query::Direction::Ascending);
+                fields.push(IndexedField::ordered("__name__", direction));
+            }
+            if registry.indexes.iter().any(|index| index.collection_group() == *group
+                && index.query_scope == request.query_scope && index.fields == fields)
+            {
+                return Some(error_response(StatusCode::CONFLICT, "Index already exists"));
+            }
+
+            registry.next_id += 1;
+            let id = registry.next_id;
+            let name = format!("{}/collectionGroups/{}/indexes/mock-index-{}", database, group, id);
+            let operation = format!("{}/operations/mock-operation-{}", database, id);
+            registry.indexes.push(AdminIndex {
+                name: name.clone(),
+                query_scope: request.query_scope,
+                fields,
+                state: Some(IndexState::Creating),
+            });
+            registry.operations.insert(operation.clone(), name.clone());
+            Json(Operation {
+                name: operation,
+                done: false,
+                error: None,
+                metadata: Some(json!({ "index": name, "state": "INITIALIZING" })),
+            }).into_response()
+        }
+        (&Method::DELETE, ["collectionGroups", _, "indexes", _]) => {
+            let name = format!("{}/{}", database, resource);
+            let before = registry.indexes.len();
+            registry.indexes.retain(|index| index.name != name);
+            if registry.indexes.len() == before {
+                error_response(StatusCode::NOT_FOUND, &format!("Index not found: {}", name))
+            } else {
+                Json(json!({})).into_response()
+            }
+        }
+        (&Method::GET, ["operations", _]) => {
+            let name = format!("{}/{}", database, resource);
+            match registry.operations.get(&name).cloned() {
+                Some(index_name) => {
+                    if let Some(index) = registry.indexes.iter_mut().find(|index| index.name == index_name) {
+                        index.state = Some(IndexState::Ready);
+                    }
+                    Json(Operation {
+                        name,
+                        done: true,
+                        error: None,
+                        metadata: Some(json!({ "index": index_name, "state": "SUCCESSFUL" })),
+                    }).into_response()
+                }
+                None => error_response(StatusCode::NOT_FOUND, &format!("Operation not found: {}", name)),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, &format!("Unknown path: {}", uri_path)),
+    };
+    Some(response)
+}
+
+fn error_from(error: &FirebaseError) -> Response {
+    let code = rpc_code(error);
+    let (status, message) = match error {
+        FirebaseError::ApiError(api) => (api.http_status.unwrap_or_else(|| code.http_status()), api.message.clone()),
+        other => (code.http_status(), other.to_string()),
+    };
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let body = json!({
+        "error": { "code": status.as_u16(), "message": message, "status": code.name() }
+    });
+    (status, Json(body)).into_response()
+}
+
+/// The `google.rpc.Code` for an error from the store, also reported for a
+/// failed write in `:batchWrite`.
+fn rpc_code(error: &FirebaseError) -> RpcCode {
+    match error {
+        FirebaseError::NotFound(_) => RpcCode::NotFound,
+        FirebaseError::ValidationError(_) | FirebaseError::SchemaViolation(_) | FirebaseError::InvalidQuery(_)
+            | FirebaseError::SerdeError(_) => RpcCode::InvalidArgument,
+        FirebaseError::ApiError(status) => status.code,
+        _ => RpcCode::Internal,
+    }
+}
+
+/// Builds an error body shaped like Google API errors.
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let code_name = match status.as_u16() {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        409 => "ALREADY_EXISTS",
+        412 => "FAILED_PRECONDITION",
+        429 => "RESOURCE_EXHAUSTED",
+        499 => "CANCELLED",
+        501 => "UNIMPLEMENTED",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "INTERNAL",
+    };
+    let body = json!({
+        "error": { "code": status.as_u16(), "message": message, "status": code_name }
+    });
+    (status, Json(body)).into_response()
+}
@@ -34,9 +34,42 @@ pub struct FirestoreDocument<T> {
     pub _phantom: std::marker::PhantomData<T>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "WireValue", into = "WireValue")]
 pub enum FirestoreValue {
+    StringValue(String),
+    IntegerValue(String),
+    DoubleValue(f64),
+    BooleanValue(bool),
+    TimestampValue(String),
+    MapValue { 
+        fields: HashMap<String, FirestoreValue> 
+    },
+    ArrayValue { 
+        values: Vec<FirestoreValue> 
+    },
+    NullValue(Option<()>),
+    ReferenceValue(String),
+    /// Base64-encoded bytes, as returned by the REST API.
+    BytesValue(String),
+    GeoPointValue {
+        latitude: f64,
+        longitude: f64,
+    },
+    /// An embedding for vector search. On the wire this is a map value with
+    /// `__type__: "__vector__"` and the components in `value`.
+    VectorValue(Vec<f64>),
+    Unknown,
+}
+
+const VECTOR_TYPE_FIELD: &str = "__type__";
+const VECTOR_TYPE: &str = "__vector__";
+const VECTOR_VALUE_FIELD: &str = "value";
+
+/// The REST representation of [`FirestoreValue`], where vectors are maps.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum WireValue {
     StringValue(String),
     IntegerValue(String),
     DoubleValue(f64),
@@ -52,35 +85,202 @@ pub enum FirestoreValue {
     },
     #[serde(rename = "nullValue")]
     NullValue(Option<()>),
+    ReferenceValue(String),
+    BytesValue(String),
+    GeoPointValue {
+        #[serde(default)]
+        latitude: f64,
+        #[serde(default)]
+        longitude: f64,
+    },
     // Handle cases where Firestore returns different field names
     #[serde(other)]
     Unknown,
 }
 
-mod array_value_serde {
-    use super::*;
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-    
-    #[derive(Serialize, Deserialize)]
-    struct ArrayValueWrapper {
-        values: Vec<FirestoreValue>,
+impl From<WireValue> for FirestoreValue {
+    fn from(value: WireValue) -> Self {
+        match value {
+            WireValue::StringValue(s) => FirestoreValue::StringValue(s),
+            WireValue::IntegerValue(i) => FirestoreValue::IntegerValue(i),
+            WireValue::DoubleValue(d) => FirestoreValue::DoubleValue(d),
+            WireValue::BooleanValue(b) => FirestoreValue::BooleanValue(b),
+            WireValue::TimestampValue(ts) => FirestoreValue::TimestampValue(ts),
+            WireValue::MapValue { fields } => match vector_components(&fields) {
+                Some(components) => FirestoreValue::VectorValue(components),
+                None => FirestoreValue::MapValue { fields },
+            },
+            WireValue::ArrayValue { values } => FirestoreValue::ArrayValue { values },
+            WireValue::NullValue(n) => FirestoreValue::NullValue(n),
+            WireValue::ReferenceValue(r) => FirestoreValue::ReferenceValue(r),
+            WireValue::BytesValue(b) => FirestoreValue::BytesValue(b),
+            WireValue::GeoPointValue { latitude, longitude } => FirestoreValue::GeoPointValue { latitude, longitude },
+            WireValue::Unknown => FirestoreValue::Unknown,
+        }
+    }
+}
+
+impl From<FirestoreValue> for WireValue {
+    fn from(value: FirestoreValue) -> Self {
+        match value {
+            FirestoreValue::StringValue(s) => WireValue::StringValue(s),
+            FirestoreValue::IntegerValue(i) => WireValue::IntegerValue(i),
+            FirestoreValue::DoubleValue(d) => WireValue::DoubleValue(d),
+            FirestoreValue::BooleanValue(b) => WireValue::BooleanValue(b),
+            FirestoreValue::TimestampValue(ts) => WireValue::TimestampValue(ts),
+            FirestoreValue::MapValue { fields } => WireValue::MapValue { fields },
+            FirestoreValue::ArrayValue { values } => WireValue::ArrayValue { values },
+            FirestoreValue::NullValue(n) => WireValue::NullValue(n),
+            FirestoreValue::ReferenceValue(r) => WireValue::ReferenceValue(r),
+            FirestoreValue::BytesValue(b) => WireValue::BytesValue(b),
+            FirestoreValue::GeoPointValue { latitude, longitude } => WireValue::GeoPointValue { latitude, longitude },
+            FirestoreValue::VectorValue(components) => WireValue::MapValue { fields: vector_fields(&components) },
+            FirestoreValue::Unknown => WireValue::Unknown,
+        }
+    }
+}
+
+/// The map fields Firestore uses to encode a vector.
+fn vector_fields(components: &[f64]) -> HashMap<String, FirestoreValue> {
+    HashMap::from([
+        (VECTOR_TYPE_FIELD.to_string(), FirestoreValue::StringValue(VECTOR_TYPE.to_string())),
+        (VECTOR_VALUE_FIELD.to_string(), FirestoreValue::ArrayValue {
+            values: components.iter().map(|c| FirestoreValue::DoubleValue(*c)).collect(),
+        }),
+    ])
+}
+
+/// The components of a map that encodes a vector. Firestore always writes
+/// the components as doubles, so a map whose `value` is empty or holds
+/// anything else (e.g. integers) is left as an ordinary map.
+fn vector_components(fields: &HashMap<String, FirestoreValue>) -> Option<Vec<f64>> {
+    if fields.len() != 2 || fields.get(VECTOR_TYPE_FIELD) != Some(&FirestoreValue::StringValue(VECTOR_TYPE.to_string())) {
+        return None;
+    }
+    match fields.get(VECTOR_VALUE_FIELD)? {
+        FirestoreValue::ArrayValue { values } if !values.is_empty() => values.iter()
+            .map(|value| match value {
+                FirestoreValue::DoubleValue(component) => Some(*component),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+impl FirestoreValue {
+    /// Looks up a dotted field path (e.g. `address.city`) inside a map value.
+    pub fn get_path(&self, path: &str) -> Option<&FirestoreValue> {
+        let mut current = self;
+        for segment in path.split('.') {
+            match current {
+                FirestoreValue::MapValue { fields } => current = fields.get(segment)?,
+                _ => return None,
+            }
+        }
+        Some(current)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FirestoreValue::IntegerValue(i) => i.parse::<i64>().ok().map(|i| i as f64),
+            FirestoreValue::DoubleValue(d) => Some(*d),
+            _ => None,
+        }
+    }
+}
+
+/// Looks up a dotted field path in a document's top-level fields.
+pub fn get_field_path<'a>(fields: &'a HashMap<String, FirestoreValue>, path: &str) -> Option<&'a FirestoreValue> {
+    let (head, rest) = match path.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (path, None),
+    };
+    let value = fields.get(head)?;
+    match rest {
+        Some(rest) => value.get_path(rest),
+        None => Some(value),
     }
+}
+
+// Helper functions for JSON <-> Firestore conversion
+pub fn json_to_firestore_fields(value: serde_json::Value) -> crate::error::Result<HashMap<String, FirestoreValue>> {
+    let mut fields = HashMap::new();
     
-    pub fn serialize<S>(values: &Vec<FirestoreValue>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        ArrayValueWrapper { 
-            values: values.clone() 
-        }.serialize(serializer)
+    if let serde_json::Value::Object(map) = value {
+        for (key, val) in map {
+            fields.insert(key, json_value_to_firestore(val));
+        }
+    } else {
+        return Err(crate::error::FirebaseError::ValidationError("Root value must be an object".to_string()));
     }
     
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<FirestoreValue>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let wrapper = ArrayValueWrapper::deserialize(deserializer)?;
-        Ok(wrapper.values)
+    Ok(fields)
+}
+
+pub fn json_value_to_firestore(value: serde_json::Value) -> FirestoreValue {
+    match value {
+        serde_json::Value::String(s) => FirestoreValue::StringValue(s),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                FirestoreValue::IntegerValue(i.to_string())
+            } else if let Some(f) = n.as_f64() {
+                FirestoreValue::DoubleValue(f)
+            } else {
+                FirestoreValue::StringValue(n.to_string())
+            }
+        }
+        serde_json::Value::Bool(b) => FirestoreValue::BooleanValue(b),
+        serde_json::Value::Null => FirestoreValue::NullValue(None),
+        serde_json::Value::Array(arr) => FirestoreValue::ArrayValue {
+            values: arr.into_iter().map(json_value_to_firestore).collect(),
+        },
+        serde_json::Value::Object(map) => {
+            let fields = map.into_iter().map(|(k, v)| (k, json_value_to_firestore(v))).collect();
+            match vector_components(&fields) {
+                Some(components) => FirestoreValue::VectorValue(components),
+                None => FirestoreValue::MapValue { fields },
+            }
+        }
+    }
+}
+
+pub fn firestore_fields_to_json(fields: &HashMap<String, FirestoreValue>) -> serde_json::Value {
+    let map = fields.iter()
+        .map(|(key, value)| (key.clone(), firestore_value_to_json(value)))
+        .collect();
+    serde_json::Value::Object(map)
+}
+
+pub fn firestore_value_to_json(value: &FirestoreValue) -> serde_json::Value {
+    match value {
+        FirestoreValue::StringValue(s) => serde_json::Value::String(s.clone()),
+        FirestoreValue::IntegerValue(i) => match i.parse::<i64>() {
+            Ok(num) => serde_json::Value::Number(num.into()),
+            Err(_) => serde_json::Value::String(i.clone()),
+        },
+        FirestoreValue::DoubleValue(f) => {
+            serde_json::Value::Number(serde_json::Number::from_f64(*f).unwrap_or(serde_json::Number::from(0)))
+        }
+        FirestoreValue::BooleanValue(b) => serde_json::Value::Bool(*b),
+        FirestoreValue::NullValue(_) => serde_json::Value::Null,
+        FirestoreValue::TimestampValue(ts) => serde_json::Value::String(ts.clone()),
+        FirestoreValue::ReferenceValue(r) => serde_json::Value::String(r.clone()),
+        FirestoreValue::BytesValue(b) => serde_json::Value::String(b.clone()),
+        FirestoreValue::GeoPointValue { latitude, longitude } => serde_json::json!({
+            "latitude": latitude,
+            "longitude": longitude,
+        }),
+        FirestoreValue::ArrayValue { values } => {
+            serde_json::Value::Array(values.iter().map(firestore_value_to_json).collect())
+        }
+        FirestoreValue::MapValue { fields } => firestore_fields_to_json(fields),
+        // Keeps the type so the JSON converts back to a vector
+        FirestoreValue::VectorValue(components) => serde_json::json!({
+            VECTOR_TYPE_FIELD: VECTOR_TYPE,
+            VECTOR_VALUE_FIELD: components,
+        }),
+        FirestoreValue::Unknown => serde_json::Value::Null,
     }
 }
 
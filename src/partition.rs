@@ -0,0 +1,122 @@
+use crate::billing::inherit_call_site;
+use crate::error::{FirebaseError, Result};
+use crate::models::FirestoreValue;
+use crate::query::{Cursor, Direction, FieldReference, Order, QueryBuilder, StructuredQuery};
+use crate::store::{DocumentStore, ReadOptions, StoredDocument, normalize_path};
+use tokio::task::JoinSet;
+
+const NAME_FIELD: &str = "__name__";
+
+/// Reads every document matching `query` by splitting it into up to
+/// `partitions` ranges with [`DocumentStore::partition_query`] and running
+/// the ranges concurrently. Results are merged in document name order.
+///
+/// The query may filter, but must not limit, offset or set cursors, since the
+/// ranges are defined on document names. For the same reason its `order_by`
+/// is replaced with `__name__` ascending; any other order is an error.
+pub async fn partitioned_query<S: DocumentStore + Clone + 'static>(
+    store: &S,
+    parent: &str,
+    query: &StructuredQuery,
+    partitions: usize,
+    options: &ReadOptions,
+) -> Result<Vec<StoredDocument>> {
+    if query.limit.is_some() || query.offset.is_some() || query.start_at.is_some() || query.end_at.is_some()
+        || query.order_by.as_ref().is_some_and(|orders| !orders.iter().all(is_name_ascending))
+    {
+        return Err(FirebaseError::ValidationError(
+            "Partitioned queries cannot use order_by, limit, offset or cursors".to_string(),
+        ));
+    }
+
+    let mut query = query.clone();
+    query.order_by = Some(vec![name_ascending()]);
+    if partitions <= 1 {
+        return store.run_query(parent, &query, options).await;
+    }
+
+    // Firestore only partitions collection group queries without filters. The
+    // split points are document names, so they bound the original query too,
+    // as long as they are in the queried collection
+    let collection_group = query.from.iter().all(|from| from.all_descendants == Some(true));
+    let mut splitter = query.clone();
+    splitter.r#where = None;
+    for from in &mut splitter.from {
+        from.all_descendants = Some(true);
+    }
+    let mut split_points = store.partition_query(parent, &splitter, partitions, options).await?;
+    if !collection_group {
+        let collection = match (normalize_path(parent), query.from.first()) {
+            ("", Some(from)) => from.collection_id.clone(),
+            (parent, Some(from)) => format!("{}/{}", parent, from.collection_id),
+            (_, None) => return Err(FirebaseError::ValidationError("Partitioned queries need a collection".to_string())),
+        };
+        split_points.retain(|cursor| is_in_collection(cursor, &collection));
+    }
+    tracing::debug!(partitions = split_points.len() + 1, "Running partitioned query");
+
+    let mut bounds: Vec<Option<Cursor>> = vec![None];
+    bounds.extend(split_points.into_iter().map(|cursor| Some(Cursor { values: cursor.values, before: Some(true) })));
+    bounds.push(None);
+
+    let mut reads = JoinSet::new();
+    for (index, range) in bounds.windows(2).enumerate() {
+        let mut range_query = query.clone();
+        range_query.start_at = range[0].clone();
+        range_query.end_at = range[1].clone();
+        let store = store.clone();
+        let parent = parent.to_string();
+        let options = options.clone();
+        reads.spawn(inherit_call_site(async move {
+            (index, store.run_query(&parent, &range_query, &options).await)
+        }));
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = reads.join_next().await {
+        let (index, documents) = result
+            .map_err(|e| FirebaseError::DatabaseError(format!("Partition read failed: {}", e)))?;
+        results.push((index, documents?));
+    }
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().flat_map(|(_, documents)| documents).collect())
+}
+
+/// Reads a whole collection: page by page, or with `partitions` concurrent
+/// partitioned queries when that is more than one.
+pub async fn read_collection<S: DocumentStore + Clone + 'static>(
+    store: &S,
+    collection: &str,
+    partitions: usize,
+    options: &ReadOptions,
+) -> Result<Vec<StoredDocument>> {
+    if partitions <= 1 {
+        return store.list_all(collection, options).await;
+    }
+    let path = normalize_path(collection);
+    let (parent, collection_id) = path.rsplit_once('/').unwrap_or(("", path));
+    partitioned_query(store, parent, &QueryBuilder::new(collection_id).build(), partitions, options).await
+}
+
+/// Whether a split point names a document directly in `collection`, not in a
+/// namesake collection under another parent or in a subcollection.
+fn is_in_collection(cursor: &Cursor, collection: &str) -> bool {
+    let Some(FirestoreValue::ReferenceValue(name)) = cursor.values.first() else {
+        return false;
+    };
+    let path = name.split_once("/documents/").map_or(name.as_str(), |(_, path)| path);
+    path.strip_prefix(collection)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .is_some_and(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn name_ascending() -> Order {
+    Order {
+        field: FieldReference { field_path: NAME_FIELD.to_string() },
+        direction: Some(Direction::Ascending),
+    }
+}
+
+fn is_name_ascending(order: &Order) -> bool {
+    order.field.field_path == NAME_FIELD && order.direction != Some(Direction::Descending)
+}
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
+use crate::indexes::disjuncts;
 use crate::models::FirestoreValue;
+use std::collections::BTreeSet;
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+const NAME_FIELD: &str = "__name__";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StructuredQuery {
     pub from: Vec<CollectionSelector>,
@@ -13,9 +18,52 @@ pub struct StructuredQuery {
     pub limit: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub offset: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_at: Option<Cursor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_at: Option<Cursor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub find_nearest: Option<FindNearest>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A vector search: the `limit` documents whose `vector_field` is nearest to
+/// `query_vector`, nearest first. Applied after the query's filters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindNearest {
+    pub vector_field: FieldReference,
+    /// A [`FirestoreValue::VectorValue`].
+    pub query_vector: FirestoreValue,
+    pub distance_measure: DistanceMeasure,
+    /// At most 1000.
+    pub limit: i32,
+    /// Field to store each result's distance in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_result_field: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DistanceMeasure {
+    Euclidean,
+    /// One minus the cosine similarity.
+    Cosine,
+    /// Larger is nearer, so results come in descending order.
+    DotProduct,
+}
+
+/// A position in a query's result order, given as values for each `order_by`
+/// field. `before` selects whether the position sits just before or just
+/// after documents with exactly those values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cursor {
+    pub values: Vec<FirestoreValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectionSelector {
     pub collection_id: String,
@@ -23,7 +71,7 @@ pub struct CollectionSelector {
     pub all_descendants: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Filter {
     CompositeFilter(CompositeFilter),
@@ -31,21 +79,21 @@ pub enum Filter {
     UnaryFilter(UnaryFilter),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompositeFilter {
     pub op: CompositeOperator,
     pub filters: Vec<Filter>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum CompositeOperator {
     And,
     Or,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FieldFilter {
     pub field: FieldReference,
@@ -53,13 +101,13 @@ pub struct FieldFilter {
     pub value: FirestoreValue,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FieldReference {
     pub field_path: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum FieldOperator {
     LessThan,
@@ -74,14 +122,14 @@ pub enum FieldOperator {
     NotIn,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UnaryFilter {
     pub op: UnaryOperator,
     pub field: FieldReference,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum UnaryOperator {
     IsNan,
@@ -90,7 +138,7 @@ pub enum UnaryOperator {
     IsNotNull,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
     pub field: FieldReference,
@@ -98,7 +146,7 @@ pub struct Order {
     pub direction: Option<Direction>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Direction {
     Ascending,
@@ -121,6 +169,9 @@ impl QueryBuilder {
                 order_by: None,
                 limit: None,
                 offset: None,
+                start_at: None,
+                end_at: None,
+                find_nearest: None,
             },
         }
     }
@@ -265,6 +316,55 @@ impl QueryBuilder {
         self
     }
     
+    /// Queries every collection with this ID, at any depth, instead of a single collection.
+    pub fn collection_group(mut self) -> Self {
+        for selector in &mut self.query.from {
+            selector.all_descendants = Some(true);
+        }
+        self
+    }
+    
+    pub fn start_at(mut self, values: Vec<FirestoreValue>) -> Self {
+        self.query.start_at = Some(Cursor { values, before: Some(true) });
+        self
+    }
+    
+    pub fn start_after(mut self, values: Vec<FirestoreValue>) -> Self {
+        self.query.start_at = Some(Cursor { values, before: Some(false) });
+        self
+    }
+    
+    pub fn end_at(mut self, values: Vec<FirestoreValue>) -> Self {
+        self.query.end_at = Some(Cursor { values, before: Some(false) });
+        self
+    }
+    
+    pub fn end_before(mut self, values: Vec<FirestoreValue>) -> Self {
+        self.query.end_at = Some(Cursor { values, before: Some(true) });
+        self
+    }
+    
+    /// Turns the query into a vector search for the `limit` documents whose
+    /// `field` is nearest to `vector`.
+    pub fn find_nearest(mut self, field: &str, vector: Vec<f64>, limit: i32, distance_measure: DistanceMeasure) -> Self {
+        self.query.find_nearest = Some(FindNearest {
+            vector_field: FieldReference { field_path: field.to_string() },
+            query_vector: FirestoreValue::VectorValue(vector),
+            distance_measure,
+            limit,
+            distance_result_field: None,
+        });
+        self
+    }
+    
+    /// Returns each vector search result's distance in `field`.
+    pub fn distance_result_field(mut self, field: &str) -> Self {
+        if let Some(find_nearest) = &mut self.query.find_nearest {
+            find_nearest.distance_result_field = Some(field.to_string());
+        }
+        self
+    }
+    
     pub fn build(self) -> StructuredQuery {
         self.query
     }
@@ -276,4 +376,307 @@ pub fn create_filter(field: &str, op: FieldOperator, value: FirestoreValue) -> F
         op,
         value,
     })
-}
\ No newline at end of file
+}
+
+/// Most values an `in`, `not-in` or `array-contains-any` filter may list.
+pub const MAX_LIST_VALUES: usize = 30;
+/// Most disjunctions a filter may expand to in disjunctive normal form.
+pub const MAX_DISJUNCTIONS: usize = 30;
+/// Most fields that may have range or inequality filters.
+pub const MAX_INEQUALITY_FIELDS: usize = 10;
+/// Most results a vector search may return.
+pub const MAX_NEAREST_LIMIT: i32 = 1000;
+
+/// A query Firestore would reject, found before sending it.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum QueryError {
+    #[error("the query must select exactly one collection, but selects {0}; start from QueryBuilder::new(collection)")]
+    CollectionCount(usize),
+
+    #[error("inequality filters on {fields:?} need the first order_by to be one of those fields, but it is '{first_order}'; add .order_by() on {fields:?} before other orders")]
+    InequalityOrder { fields: Vec<String>, first_order: String },
+
+    #[error("inequality filters on {0} fields exceed the limit of {MAX_INEQUALITY_FIELDS}; filter some fields in code after the query")]
+    TooManyInequalityFields(usize),
+
+    #[error("{} filter on '{field}' needs an array value; build it with where_in or an ArrayValue", operator_name(*op))]
+    NotAList { field: String, op: FieldOperator },
+
+    #[error("{} filter on '{field}' has an empty list; skip the query, since it cannot match anything", operator_name(*op))]
+    EmptyList { field: String, op: FieldOperator },
+
+    #[error("{} filter on '{field}' lists {len} values, more than {MAX_LIST_VALUES}; split it into several queries and merge the results", operator_name(*op))]
+    ListTooLong { field: String, op: FieldOperator, len: usize },
+
+    #[error("the filter expands to {0} disjunctions, more than {MAX_DISJUNCTIONS}; shorten the in/array-contains-any lists or OR branches, or split the query")]
+    TooManyDisjunctions(usize),
+
+    #[error("not-in on '{not_in}' cannot be combined with != on '{not_equal}'; add the != value to a not-in list instead")]
+    NotInWithNotEqual { not_in: String, not_equal: String },
+
+    #[error("a query can use only one not-in filter; merge them or filter the rest in code")]
+    MultipleNotIn,
+
+    #[error("not-in on '{0}' cannot be combined with in, array-contains-any or OR; run separate queries")]
+    NotInWithDisjunction(String),
+
+    #[error("a query can use at most one array-contains or array-contains-any filter; filter the other array in code")]
+    MultipleArrayContains,
+
+    #[error("{cursor} has {values} values but the query orders by only {orders} fields ({fields}); add an order_by for each cursor value or drop values")]
+    CursorTooLong { cursor: &'static str, values: usize, orders: usize, fields: String },
+
+    #[error("{cursor} value for __name__ must be a document reference; use FirestoreValue::ReferenceValue with the full document name")]
+    NameCursorNotReference { cursor: &'static str },
+
+    #[error("{0} must not be negative")]
+    Negative(&'static str),
+
+    #[error("find_nearest {0}")]
+    InvalidVectorSearch(&'static str),
+}
+
+impl StructuredQuery {
+    /// Checks the query against Firestore's query constraints, returning an
+    /// error that explains how to fix it. The client runs this before every
+    /// query, so invalid queries fail without a round trip.
+    pub fn validate(&self) -> std::result::Result<(), QueryError> {
+        if self.from.len() != 1 {
+            return Err(QueryError::CollectionCount(self.from.len()));
+        }
+        if self.limit.is_some_and(|limit| limit < 0) {
+            return Err(QueryError::Negative("limit"));
+        }
+        if self.offset.is_some_and(|offset| offset < 0) {
+            return Err(QueryError::Negative("offset"));
+        }
+
+        if let Some(filter) = &self.r#where {
+            validate_filters(filter)?;
+        }
+
+        let mut inequality_fields = BTreeSet::new();
+        if let Some(filter) = &self.r#where {
+            collect_inequality_fields(filter, &mut inequality_fields);
+        }
+        if inequality_fields.len() > MAX_INEQUALITY_FIELDS {
+            return Err(QueryError::TooManyInequalityFields(inequality_fields.len()));
+        }
+        if let Some(first) = self.order_by.as_ref().and_then(|orders| orders.first())
+            && !inequality_fields.is_empty()
+            && !inequality_fields.contains(&first.field.field_path)
+        {
+            return Err(QueryError::InequalityOrder {
+                fields: inequality_fields.into_iter().collect(),
+                first_order: first.field.field_path.clone(),
+            });
+        }
+
+        if let Some(nearest) = &self.find_nearest {
+            return validate_find_nearest(self, nearest);
+        }
+
+        let orders = effective_orders(self);
+        for (cursor, name) in [(&self.start_at, "start_at"), (&self.end_at, "end_at")] {
+            let Some(cursor) = cursor else { continue };
+            if cursor.values.len() > orders.len() {
+                return Err(QueryError::CursorTooLong {
+                    cursor: name,
+                    values: cursor.values.len(),
+                    orders: orders.len(),
+                    fields: orders.iter().map(|order| order.field.field_path.as_str()).collect::<Vec<_>>().join(", "),
+                });
+            }
+            for (value, order) in cursor.values.iter().zip(&orders) {
+                if order.field.field_path == "__name__" && !matches!(value, FirestoreValue::ReferenceValue(_)) {
+                    return Err(QueryError::NameCursorNotReference { cursor: name });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn operator_name(op: FieldOperator) -> &'static str {
+    match op {
+        FieldOperator::LessThan => "<",
+        FieldOperator::LessThanOrEqual => "<=",
+        FieldOperator::GreaterThan => ">",
+        FieldOperator::GreaterThanOrEqual => ">=",
+        FieldOperator::Equal => "==",
+        FieldOperator::NotEqual => "!=",
+        FieldOperator::ArrayContains => "array-contains",
+        FieldOperator::In => "in",
+        FieldOperator::ArrayContainsAny => "array-contains-any",
+        FieldOperator::NotIn => "not-in",
+    }
+}
+
+fn validate_filters(filter: &Filter) -> std::result::Result<(), QueryError> {
+    let mut field_filters = Vec::new();
+    let mut has_or = false;
+    collect_field_filters(filter, &mut field_filters, &mut has_or);
+
+    for field_filter in &field_filters {
+        if matches!(field_filter.op, FieldOperator::In | FieldOperator::NotIn | FieldOperator::ArrayContainsAny) {
+            let field = || field_filter.field.field_path.clone();
+            let FirestoreValue::ArrayValue { values } = &field_filter.value else {
+                return Err(QueryError::NotAList { field: field(), op: field_filter.op });
+            };
+            if values.is_empty() {
+                return Err(QueryError::EmptyList { field: field(), op: field_filter.op });
+            }
+            if values.len() > MAX_LIST_VALUES {
+                return Err(QueryError::ListTooLong { field: field(), op: field_filter.op, len: values.len() });
+            }
+        }
+    }
+
+    let with_op = |op: FieldOperator| field_filters.iter().copied().filter(move |f| f.op == op);
+    let not_in: Vec<&FieldFilter> = with_op(FieldOperator::NotIn).collect();
+    if not_in.len() > 1 {
+        return Err(QueryError::MultipleNotIn);
+    }
+    if let Some(not_in) = not_in.first() {
+        if let Some(not_equal) = with_op(FieldOperator::NotEqual).next() {
+            return Err(QueryError::NotInWithNotEqual {
+                not_in: not_in.field.field_path.clone(),
+                not_equal: not_equal.field.field_path.clone(),
+            });
+        }
+        if has_or || with_op(FieldOperator::In).next().is_some() || with_op(FieldOperator::ArrayContainsAny).next().is_some() {
+            return Err(QueryError::NotInWithDisjunction(not_in.field.field_path.clone()));
+        }
+    }
+
+    let disjunctions = count_disjunctions(filter);
+    if disjunctions > MAX_DISJUNCTIONS {
+        return Err(QueryError::TooManyDisjunctions(disjunctions));
+    }
+    // The limit applies to each OR branch, not to the whole filter
+    for branch in disjuncts(filter) {
+        let array_contains = branch.iter()
+            .filter(|filter| matches!(
+                filter,
+                Filter::FieldFilter(FieldFilter { op: FieldOperator::ArrayContains | FieldOperator::ArrayContainsAny, .. })
+            ))
+            .count();
+        if array_contains > 1 {
+            return Err(QueryError::MultipleArrayContains);
+        }
+    }
+    Ok(())
+}
+
+fn validate_find_nearest(query: &StructuredQuery, nearest: &FindNearest) -> std::result::Result<(), QueryError> {
+    match &nearest.query_vector {
+        FirestoreValue::VectorValue(vector) if vector.is_empty() => {
+            return Err(QueryError::InvalidVectorSearch("query_vector must not be empty"));
+        }
+        FirestoreValue::VectorValue(_) => {}
+        _ => return Err(QueryError::InvalidVectorSearch("query_vector must be a FirestoreValue::VectorValue")),
+    }
+    if !(1..=MAX_NEAREST_LIMIT).contains(&nearest.limit) {
+        return Err(QueryError::InvalidVectorSearch("limit must be between 1 and 1000"));
+    }
+    if query.order_by.is_some() || query.start_at.is_some() || query.end_at.is_some() || query.offset.is_some() {
+        return Err(QueryError::InvalidVectorSearch(
+            "cannot be combined with order_by, cursors or offset; results are ordered by distance",
+        ));
+    }
+    Ok(())
+}
+
+fn collect_field_filters<'a>(filter: &'a Filter, filters: &mut Vec<&'a FieldFilter>, has_or: &mut bool) {
+    match filter {
+        Filter::CompositeFilter(composite) => {
+            *has_or |= composite.op == CompositeOperator::Or && composite.filters.len() > 1;
+            for filter in &composite.filters {
+                collect_field_filters(filter, filters, has_or);
+            }
+        }
+        Filter::FieldFilter(field_filter) => filters.push(field_filter),
+        Filter::UnaryFilter(_) => {}
+    }
+}
+
+/// Explicit orders, then implicit orders on inequality fields, then the
+/// document name as the final tie-breaker.
+pub(crate) fn effective_orders(query: &StructuredQuery) -> Vec<Order> {
+    let mut orders: Vec<Order> = query.order_by.clone().unwrap_or_default();
+
+    let mut inequality_fields = BTreeSet::new();
+    if let Some(filter) = &query.r#where {
+        collect_inequality_fields(filter, &mut inequality_fields);
+    }
+    for field_path in inequality_fields {
+        if !orders.iter().any(|o| o.field.field_path == field_path) {
+            orders.push(Order {
+                field: FieldReference { field_path },
+                direction: Some(Direction::Ascending),
+            });
+        }
+    }
+
+    if !orders.iter().any(|o| o.field.field_path == NAME_FIELD) {
+        let direction = orders.last().and_then(|o| o.direction).unwrap_or(Direction::Ascending);
+        orders.push(Order {
+            field: FieldReference { field_path: NAME_FIELD.to_string() },
+            direction: Some(direction),
+        });
+    }
+    orders
+}
+
+pub(crate) fn collect_inequality_fields(filter: &Filter, fields: &mut BTreeSet<String>) {
+    match filter {
+        Filter::CompositeFilter(composite) => {
+            for filter in &composite.filters {
+                collect_inequality_fields(filter, fields);
+            }
+        }
+        Filter::FieldFilter(field_filter) => {
+            if is_inequality(field_filter.op) {
+                fields.insert(field_filter.field.field_path.clone());
+            }
+        }
+        Filter::UnaryFilter(unary) => {
+            if matches!(unary.op, UnaryOperator::IsNotNan | UnaryOperator::IsNotNull) {
+                fields.insert(unary.field.field_path.clone());
+            }
+        }
+    }
+}
+
+pub(crate) fn is_inequality(op: FieldOperator) -> bool {
+    matches!(
+        op,
+        FieldOperator::LessThan
+            | FieldOperator::LessThanOrEqual
+            | FieldOperator::GreaterThan
+            | FieldOperator::GreaterThanOrEqual
+            | FieldOperator::NotEqual
+            | FieldOperator::NotIn
+    )
+}
+
+/// How many AND-only queries the filter becomes once ORs (and `in` or
+/// `array-contains-any` lists, which are ORs of equalities) are distributed.
+fn count_disjunctions(filter: &Filter) -> usize {
+    match filter {
+        Filter::CompositeFilter(composite) => {
+            let counts = composite.filters.iter().map(count_disjunctions);
+            match composite.op {
+                CompositeOperator::Or => counts.fold(0, usize::saturating_add).max(1),
+                CompositeOperator::And => counts.fold(1, usize::saturating_mul),
+            }
+        }
+        Filter::FieldFilter(FieldFilter { op: FieldOperator::In | FieldOperator::ArrayContainsAny, value, .. }) => {
+            match value {
+                FirestoreValue::ArrayValue { values } => values.len().max(1),
+                _ => 1,
+            }
+        }
+        _ => 1,
+    }
+}
@@ -0,0 +1,269 @@
+use crate::error::{FirebaseError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Placeholder written instead of the API key in recorded URLs.
+pub const REDACTED: &str = "REDACTED";
+
+/// One recorded request/response pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// Request path and query, with the API key replaced by [`REDACTED`]. The
+    /// host is left out so sessions recorded against an emulator replay too.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<Value>,
+    pub status: u16,
+    /// Response body, kept as JSON when it parses so fixtures stay readable.
+    pub response_body: Value,
+}
+
+/// A fixture file: the interactions of one recorded session, in order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Fixture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to read fixture {}: {}", path.display(), e)))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// How replay decides whether a request matches a recorded interaction.
+/// The default is an exact match on method, redacted URL and body.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayMatcher {
+    ignore_body: bool,
+    ignored_fields: Vec<String>,
+    normalize: bool,
+}
+
+impl ReplayMatcher {
+    /// Matches on method and URL alone.
+    pub fn ignore_body(mut self) -> Self {
+        self.ignore_body = true;
+        self
+    }
+
+    /// Ignores the values of these keys wherever they appear in a request
+    /// body, e.g. `transaction` or `readTime`.
+    pub fn ignore_fields<I, F>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        self.ignored_fields.extend(fields.into_iter().map(Into::into));
+        self
+    }
+
+    /// Treats any two RFC 3339 timestamps as equal, and likewise generated
+    /// IDs (UUIDs and 20-character Firestore auto IDs), in URLs and bodies.
+    /// This lets sessions that stamp documents with the current time or
+    /// random IDs replay.
+    pub fn normalize(mut self) -> Self {
+        self.normalize = true;
+        self
+    }
+
+    fn matches(&self, interaction: &Interaction, method: &str, url: &str, request_body: Option<&Value>) -> bool {
+        if interaction.method != method || self.prepare_str(&interaction.url) != self.prepare_str(url) {
+            return false;
+        }
+        self.ignore_body
+            || interaction.request_body.as_ref().map(|body| self.prepare(body)) == request_body.map(|body| self.prepare(body))
+    }
+
+    /// The body as compared: ignored fields nulled and, when normalizing,
+    /// volatile strings replaced by placeholders.
+    fn prepare(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(map.iter()
+                .map(|(key, value)| {
+                    let value = if self.ignored_fields.contains(key) { Value::Null } else { self.prepare(value) };
+                    (key.clone(), value)
+                })
+                .collect()),
+            Value::Array(values) => Value::Array(values.iter().map(|value| self.prepare(value)).collect()),
+            Value::String(s) => Value::String(self.prepare_str(s)),
+            other => other.clone(),
+        }
+    }
+
+    fn prepare_str(&self, s: &str) -> String {
+        if !self.normalize {
+            return s.to_string();
+        }
+        // Paths and query strings are normalized a segment at a time
+        let mut normalized = String::with_capacity(s.len());
+        let mut rest = s;
+        loop {
+            let end = rest.find(['/', '?', '&', '=']).unwrap_or(rest.len());
+            let (segment, tail) = rest.split_at(end);
+            normalized.push_str(normalize_segment(segment));
+            let Some(separator) = tail.chars().next() else {
+                break;
+            };
+            normalized.push(separator);
+            rest = &tail[1..];
+        }
+        normalized
+    }
+}
+
+fn normalize_segment(segment: &str) -> &str {
+    let decoded = segment.replace("%3A", ":").replace("%3a", ":");
+    if chrono::DateTime::parse_from_rfc3339(&decoded).is_ok() {
+        "{timestamp}"
+    } else if is_generated_id(segment) {
+        "{id}"
+    } else {
+        segment
+    }
+}
+
+/// A UUID, or an ID shaped like Firestore's auto IDs: 20 alphanumerics that
+/// aren't all lowercase letters.
+fn is_generated_id(segment: &str) -> bool {
+    uuid::Uuid::parse_str(segment).is_ok()
+        || (segment.len() == 20
+            && segment.chars().all(|c| c.is_ascii_alphanumeric())
+            && !segment.chars().all(|c| c.is_ascii_lowercase()))
+}
+
+#[derive(Debug)]
+enum Mode {
+    /// `unsaved` is set while interactions are captured but not yet written.
+    Record { path: PathBuf, fixture: Fixture, unsaved: bool },
+    Replay { path: PathBuf, interactions: Vec<(Interaction, bool)>, matcher: ReplayMatcher },
+}
+
+/// Captures or serves HTTP traffic for [`crate::FirebaseClient`].
+///
+/// In replay mode each request must match an unused recorded interaction
+/// according to the [`ReplayMatcher`]; anything else is an error, so
+/// changes in what the client sends show up as test failures.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    mode: Mutex<Mode>,
+}
+
+impl Recorder {
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self { mode: Mutex::new(Mode::Record { path: path.into(), fixture: Fixture::default(), unsaved: false }) }
+    }
+
+    pub fn replay(path: impl Into<PathBuf>, matcher: ReplayMatcher) -> Result<Self> {
+        let path = path.into();
+        let fixture = Fixture::load(&path)?;
+        let interactions = fixture.interactions.into_iter().map(|i| (i, false)).collect();
+        Ok(Self { mode: Mutex::new(Mode::Replay { path, interactions, matcher }) })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(*self.lock(), Mode::Replay { .. })
+    }
+
+    /// Appends an interaction in memory; [`save`](Self::save) writes them out.
+    pub fn capture(&self, method: &str, url: &str, request_body: Option<&Value>, status: u16, response_body: &str) {
+        let mut mode = self.lock();
+        if let Mode::Record { fixture, unsaved, .. } = &mut *mode {
+            fixture.interactions.push(Interaction {
+                method: method.to_string(),
+                url: url.to_string(),
+                request_body: request_body.cloned(),
+                status,
+                response_body: serde_json::from_str(response_body)
+                    .unwrap_or_else(|_| Value::String(response_body.to_string())),
+            });
+            *unsaved = true;
+        }
+    }
+
+    /// Writes the fixture if anything was captured since the last save.
+    pub fn save(&self) -> Result<()> {
+        let mut mode = self.lock();
+        if let Mode::Record { path, fixture, unsaved: unsaved @ true } = &mut *mode {
+            fixture.save(path)?;
+            *unsaved = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the recorded status and body for a request.
+    pub fn lookup(&self, method: &str, url: &str, request_body: Option<&Value>) -> Result<(u16, String)> {
+        let mut mode = self.lock();
+        let Mode::Replay { path, interactions, matcher } = &mut *mode else {
+            return Err(FirebaseError::ConfigError("Client is not in replay mode".to_string()));
+        };
+
+        let found = interactions.iter_mut()
+            .find(|(interaction, used)| !*used && matcher.matches(interaction, method, url, request_body));
+
+        match found {
+            Some((interaction, used)) => {
+                *used = true;
+                let body = match &interaction.response_body {
+                    Value::String(body) => body.clone(),
+                    other => other.to_string(),
+                };
+                Ok((interaction.status, body))
+            }
+            None => Err(FirebaseError::ConfigError(format!(
+                "No recorded interaction in {} matches {} {}{}",
+                path.display(),
+                method,
+                url,
+                request_body.map(|b| format!(" with body {}", b)).unwrap_or_default(),
+            ))),
+        }
+    }
+
+    /// Number of recorded interactions that replay has not served yet.
+    pub fn remaining(&self) -> usize {
+        match &*self.lock() {
+            Mode::Replay { interactions, .. } => interactions.iter().filter(|(_, used)| !used).count(),
+            Mode::Record { .. } => 0,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Mode> {
+        self.mode.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Saves whatever wasn't saved explicitly once the last client clone is gone.
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            tracing::warn!(error = %e, "Could not save the recorded fixture");
+        }
+    }
+}
+
+/// Formats `url` for a fixture: path and query, with the `key` parameter replaced.
+pub(crate) fn redact_url(url: &reqwest::Url) -> String {
+    let mut redacted = url.clone();
+    let pairs: Vec<(String, String)> = url.query_pairs()
+        .map(|(name, value)| {
+            let value = if name == "key" { REDACTED.to_string() } else { value.into_owned() };
+            (name.into_owned(), value)
+        })
+        .collect();
+    redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    match redacted.query() {
+        Some(query) => format!("{}?{}", redacted.path(), query),
+        None => redacted.path().to_string(),
+    }
+}
@@ -1,9 +1,21 @@
+use crate::bulk_writer::{BulkWriteReport, BulkWriter};
+use crate::collections::AutoFieldType;
 use crate::error::{FirebaseError, Result};
 use crate::firebase::FirebaseClient;
+use crate::index_admin::{IndexAdmin, IndexPlan, IndexReconciler};
+use crate::indexes::IndexDefinitions;
 use crate::models::{FirestoreValue, ToFirestore};
+use crate::query::StructuredQuery;
+use crate::memory::generate_document_id;
+use crate::store::{DocumentStore, ReadOptions, StoredDocument, Transaction, Write, document_path};
+use crate::validation::{RegexCache, ValidationReport, Validator, ValidatorRegistry, missing_paths, resolve_path, type_name};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Collection {
@@ -20,9 +32,12 @@ pub struct Field {
     pub required: bool,
     pub default_value: Option<FirestoreValue>,
     pub description: Option<String>,
+    /// Filled in by [`ValidatedClient`](crate::validated::ValidatedClient) when writing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_field: Option<AutoFieldType>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FieldType {
     String,
     Integer,
@@ -70,17 +85,46 @@ pub enum ValidationRuleType {
     Custom(String),
 }
 
+/// The rule as it appears in a [`Violation`](crate::validation::Violation), e.g. `min_length(3)`.
+fn rule_name(rule: &ValidationRuleType) -> String {
+    match rule {
+        ValidationRuleType::MinLength(n) => format!("min_length({})", n),
+        ValidationRuleType::MaxLength(n) => format!("max_length({})", n),
+        ValidationRuleType::Min(n) => format!("min({})", n),
+        ValidationRuleType::Max(n) => format!("max({})", n),
+        ValidationRuleType::Regex(pattern) => format!("regex({})", pattern),
+        ValidationRuleType::Email => "email".to_string(),
+        ValidationRuleType::Url => "url".to_string(),
+        ValidationRuleType::Custom(name) => format!("custom({})", name),
+    }
+}
+
+/// Deliberately loose: something@domain.tld without whitespace.
+const EMAIL_PATTERN: &str = r"^[^@\s]+@[^@\s]+\.[^@\s.]+$";
+
 #[derive(Debug, Clone)]
-pub struct SchemaManager {
-    client: FirebaseClient,
+pub struct SchemaManager<S: DocumentStore = FirebaseClient> {
+    client: S,
     collections: HashMap<String, Collection>,
+    regexes: RegexCache,
+    validators: ValidatorRegistry,
+}
+
+/// A custom rule whose validator is async, to be awaited after the sync checks.
+struct DeferredCheck<'a> {
+    path: String,
+    rule: String,
+    validator: &'a Validator,
+    value: &'a FirestoreValue,
 }
 
-impl SchemaManager {
-    pub fn new(client: FirebaseClient) -> Self {
+impl<S: DocumentStore> SchemaManager<S> {
+    pub fn new(client: S) -> Self {
         Self {
             client,
             collections: HashMap::new(),
+            regexes: RegexCache::new(),
+            validators: ValidatorRegistry::with_builtins(),
         }
     }
     
@@ -88,133 +132,234 @@ impl SchemaManager {
         self.collections.insert(collection.name.clone(), collection);
     }
     
+    pub fn get_collection(&self, collection_name: &str) -> Option<&Collection> {
+        self.collections.get(collection_name)
+    }
+    
+    pub fn client(&self) -> &S {
+        &self.client
+    }
+    
+    /// Checks `item` against every field definition and validation rule of the
+    /// collection, failing with a [`FirebaseError::SchemaViolation`] holding
+    /// every violation (this used to be a `ValidationError` message).
     pub fn validate<T: ToFirestore>(&self, collection_name: &str, item: &T) -> Result<()> {
-        let collection = self.collections.get(collection_name)
-            .ok_or_else(|| FirebaseError::ConfigError(format!("Collection {} not defined", collection_name)))?;
-        
-        let fields = item.to_firestore();
-        
-        for field_def in &collection.fields {
-            if field_def.required && !fields.contains_key(&field_def.name) {
-                return Err(FirebaseError::DatabaseError(
-                    format!("Required field '{}' is missing", field_def.name)
-                ));
+        self.validate_report(collection_name, item)?.into_result()
+    }
+    
+    /// Like [`validate`](Self::validate) but returns the full report. Errors
+    /// are reserved for schema problems such as an unknown collection or an
+    /// invalid regex.
+    pub fn validate_report<T: ToFirestore>(&self, collection_name: &str, item: &T) -> Result<ValidationReport> {
+        self.validate_fields(collection_name, &item.to_firestore())
+    }
+    
+    /// Rule paths may point into maps (`address.zip`) and at every element of
+    /// an array (`tags[]`, `items[].price`). Custom rules backed by an async
+    /// validator need [`validate_fields_async`](Self::validate_fields_async).
+    pub fn validate_fields(&self, collection_name: &str, fields: &HashMap<String, FirestoreValue>) -> Result<ValidationReport> {
+        let collection = self.collection(collection_name)?;
+        let (report, deferred) = self.check(&collection.fields, &collection.validation_rules, fields)?;
+        if let Some(check) = deferred.first() {
+            return Err(FirebaseError::ConfigError(format!(
+                "Custom rule '{}' on field '{}' is async; use validate_async", check.rule, check.path
+            )));
+        }
+        Ok(report)
+    }
+    
+    /// Like [`validate`](Self::validate), also running async custom validators.
+    pub async fn validate_async<T: ToFirestore>(&self, collection_name: &str, item: &T) -> Result<()> {
+        self.validate_fields_async(collection_name, &item.to_firestore()).await?.into_result()
+    }
+    
+    pub async fn validate_fields_async(&self, collection_name: &str, fields: &HashMap<String, FirestoreValue>) -> Result<ValidationReport> {
+        let collection = self.collection(collection_name)?;
+        self.validate_rules_async(&collection.fields, &collection.validation_rules, fields).await
+    }
+    
+    /// Checks `fields` against definitions and rules that are not part of a
+    /// defined collection, e.g. a schema stored in Firestore.
+    pub async fn validate_rules_async(
+        &self,
+        field_defs: &[Field],
+        rules: &[ValidationRule],
+        fields: &HashMap<String, FirestoreValue>,
+    ) -> Result<ValidationReport> {
+        let (mut report, deferred) = self.check(field_defs, rules, fields)?;
+        for check in deferred {
+            if let Err(message) = check.validator.validate(check.value).await {
+                report.push(&check.path, check.rule, Some(check.value), format!("Field '{}' {}", check.path, message));
             }
-            
-            if let Some(value) = fields.get(&field_def.name) {
-                self.validate_field_type(&field_def.name, &field_def.field_type, value)?;
+        }
+        Ok(report)
+    }
+    
+    /// The validators `Custom` rules resolve through; starts with the built-ins.
+    pub fn validators(&self) -> &ValidatorRegistry {
+        &self.validators
+    }
+    
+    pub fn validators_mut(&mut self) -> &mut ValidatorRegistry {
+        &mut self.validators
+    }
+    
+    pub fn with_validators(mut self, validators: ValidatorRegistry) -> Self {
+        self.validators = validators;
+        self
+    }
+    
+    /// Custom rules, by collection, whose validator is not registered.
+    pub fn unresolved_custom_rules(&self) -> Vec<(&str, &ValidationRule)> {
+        let mut unresolved: Vec<(&str, &ValidationRule)> = self.collections.values()
+            .flat_map(|collection| collection.validation_rules.iter().map(move |rule| (collection.name.as_str(), rule)))
+            .filter(|(_, rule)| matches!(&rule.rule, ValidationRuleType::Custom(name) if !self.validators.contains(name)))
+            .collect();
+        unresolved.sort_by(|a, b| (a.0, &a.1.field).cmp(&(b.0, &b.1.field)));
+        unresolved
+    }
+    
+    fn collection(&self, collection_name: &str) -> Result<&Collection> {
+        self.collections.get(collection_name)
+            .ok_or_else(|| FirebaseError::ConfigError(format!("Collection {} not defined", collection_name)))
+    }
+    
+    /// Runs every synchronous check, returning the async custom checks still to run.
+    fn check<'a>(
+        &'a self,
+        field_defs: &[Field],
+        rules: &'a [ValidationRule],
+        fields: &'a HashMap<String, FirestoreValue>,
+    ) -> Result<(ValidationReport, Vec<DeferredCheck<'a>>)> {
+        let mut report = ValidationReport::default();
+        for field_def in field_defs {
+            if field_def.required {
+                for path in missing_paths(fields, &field_def.name) {
+                    report.push(&path, "required", None, format!("Required field '{}' is missing", path));
+                }
+            }
+            for (path, value) in resolve_path(fields, &field_def.name) {
+                self.validate_field_type(&path, field_def, value, &mut report);
             }
         }
         
-        for rule in &collection.validation_rules {
-            if let Some(value) = fields.get(&rule.field) {
-                self.validate_rule(&rule.field, &rule.rule, value)?;
+        let mut deferred = Vec::new();
+        for rule in rules {
+            for (path, value) in resolve_path(fields, &rule.field) {
+                if matches!(value, FirestoreValue::NullValue(_)) {
+                    continue;
+                }
+                if let ValidationRuleType::Custom(name) = &rule.rule {
+                    let validator = self.validators.get(name).ok_or_else(|| FirebaseError::ConfigError(format!(
+                        "No validator registered for custom rule '{}' on field '{}'", name, path
+                    )))?;
+                    match validator {
+                        Validator::Sync(check) => {
+                            if let Err(message) = check(value) {
+                                report.push(&path, rule_name(&rule.rule), Some(value), format!("Field '{}' {}", path, message));
+                            }
+                        }
+                        Validator::Async(_) => deferred.push(DeferredCheck { path, rule: rule_name(&rule.rule), validator, value }),
+                    }
+                } else {
+                    self.validate_rule(&path, &rule.rule, value, &mut report)?;
+                }
             }
         }
         
-        Ok(())
+        Ok((report, deferred))
     }
     
-    fn validate_field_type(&self, field_name: &str, expected_type: &FieldType, value: &FirestoreValue) -> Result<()> {
-        let valid = match (expected_type, value) {
-            (FieldType::String, FirestoreValue::StringValue(_)) => true,
-            (FieldType::Integer, FirestoreValue::IntegerValue(_)) => true,
-            (FieldType::Double, FirestoreValue::DoubleValue(_)) => true,
-            (FieldType::Boolean, FirestoreValue::BooleanValue(_)) => true,
-            (FieldType::Timestamp, FirestoreValue::TimestampValue(_)) => true,
-            (FieldType::Map, FirestoreValue::MapValue { .. }) => true,
-            (FieldType::Array, FirestoreValue::ArrayValue { .. }) => true,
-            _ => false,
-        };
+    fn validate_field_type(&self, path: &str, field_def: &Field, value: &FirestoreValue, report: &mut ValidationReport) {
+        let valid = matches!(
+            (&field_def.field_type, value),
+            (FieldType::String, FirestoreValue::StringValue(_))
+                | (FieldType::Integer, FirestoreValue::IntegerValue(_))
+                | (FieldType::Double, FirestoreValue::DoubleValue(_))
+                | (FieldType::Boolean, FirestoreValue::BooleanValue(_))
+                | (FieldType::Timestamp, FirestoreValue::TimestampValue(_))
+                | (FieldType::Map, FirestoreValue::MapValue { .. })
+                | (FieldType::Array, FirestoreValue::ArrayValue { .. })
+                | (FieldType::Reference, FirestoreValue::ReferenceValue(_))
+        ) || (!field_def.required && matches!(value, FirestoreValue::NullValue(_)));
         
         if !valid {
-            return Err(FirebaseError::DatabaseError(
-                format!("Field '{}' has incorrect type. Expected {:?}", field_name, expected_type)
-            ));
+            report.push(
+                path,
+                format!("type({:?})", field_def.field_type),
+                Some(value),
+                format!("Field '{}' has incorrect type. Expected {:?}, got {}", path, field_def.field_type, type_name(value)),
+            );
         }
-        
-        Ok(())
     }
     
-    fn validate_rule(&self, field_name: &str, rule: &ValidationRuleType, value: &FirestoreValue) -> Result<()> {
+    fn validate_rule(&self, path: &str, rule: &ValidationRuleType, value: &FirestoreValue, report: &mut ValidationReport) -> Result<()> {
+        let name = rule_name(rule);
+        let mut fail = |message: String| report.push(path, name.clone(), Some(value), message);
         match rule {
-            ValidationRuleType::MinLength(min) => {
-                if let FirestoreValue::StringValue(s) = value {
-                    if s.len() < *min {
-                        return Err(FirebaseError::DatabaseError(
-                            format!("Field '{}' must be at least {} characters", field_name, min)
-                        ));
+            ValidationRuleType::MinLength(_) | ValidationRuleType::MaxLength(_) => {
+                let (length, unit) = match value {
+                    FirestoreValue::StringValue(s) => (s.chars().count(), "characters"),
+                    FirestoreValue::ArrayValue { values } => (values.len(), "elements"),
+                    _ => {
+                        fail(format!("Field '{}' must be a string or array to check its length, got {}", path, type_name(value)));
+                        return Ok(());
                     }
-                }
-            }
-            ValidationRuleType::MaxLength(max) => {
-                if let FirestoreValue::StringValue(s) = value {
-                    if s.len() > *max {
-                        return Err(FirebaseError::DatabaseError(
-                            format!("Field '{}' must be at most {} characters", field_name, max)
-                        ));
+                };
+                match rule {
+                    ValidationRuleType::MinLength(min) if length < *min => {
+                        fail(format!("Field '{}' must be at least {} {}", path, min, unit));
                     }
-                }
-            }
-            ValidationRuleType::Min(min) => {
-                match value {
-                    FirestoreValue::IntegerValue(i) => {
-                        if i.parse::<f64>().unwrap_or(0.0) < *min {
-                            return Err(FirebaseError::DatabaseError(
-                                format!("Field '{}' must be at least {}", field_name, min)
-                            ));
-                        }
-                    }
-                    FirestoreValue::DoubleValue(d) => {
-                        if d < min {
-                            return Err(FirebaseError::DatabaseError(
-                                format!("Field '{}' must be at least {}", field_name, min)
-                            ));
-                        }
+                    ValidationRuleType::MaxLength(max) if length > *max => {
+                        fail(format!("Field '{}' must be at most {} {}", path, max, unit));
                     }
                     _ => {}
                 }
             }
-            ValidationRuleType::Max(max) => {
-                match value {
-                    FirestoreValue::IntegerValue(i) => {
-                        if i.parse::<f64>().unwrap_or(0.0) > *max {
-                            return Err(FirebaseError::DatabaseError(
-                                format!("Field '{}' must be at most {}", field_name, max)
-                            ));
-                        }
+            ValidationRuleType::Min(_) | ValidationRuleType::Max(_) => {
+                let number = match value {
+                    FirestoreValue::IntegerValue(i) => i.parse::<f64>().ok(),
+                    FirestoreValue::DoubleValue(d) => Some(*d),
+                    _ => None,
+                };
+                match (rule, number) {
+                    (_, None) => fail(format!("Field '{}' must be a number, got {}", path, type_name(value))),
+                    (ValidationRuleType::Min(min), Some(n)) if n < *min => {
+                        fail(format!("Field '{}' must be at least {}", path, min));
                     }
-                    FirestoreValue::DoubleValue(d) => {
-                        if d > max {
-                            return Err(FirebaseError::DatabaseError(
-                                format!("Field '{}' must be at most {}", field_name, max)
-                            ));
-                        }
+                    (ValidationRuleType::Max(max), Some(n)) if n > *max => {
+                        fail(format!("Field '{}' must be at most {}", path, max));
                     }
                     _ => {}
                 }
             }
-            ValidationRuleType::Email => {
-                if let FirestoreValue::StringValue(s) = value {
-                    if !s.contains('@') || !s.contains('.') {
-                        return Err(FirebaseError::DatabaseError(
-                            format!("Field '{}' must be a valid email", field_name)
-                        ));
+            ValidationRuleType::Regex(_) | ValidationRuleType::Email => {
+                let FirestoreValue::StringValue(s) = value else {
+                    fail(format!("Field '{}' must be a string, got {}", path, type_name(value)));
+                    return Ok(());
+                };
+                match rule {
+                    ValidationRuleType::Regex(pattern) if !self.regexes.get(pattern)?.is_match(s) => {
+                        fail(format!("Field '{}' does not match pattern {}", path, pattern));
                     }
+                    ValidationRuleType::Email if !self.regexes.get(EMAIL_PATTERN)?.is_match(s) => {
+                        fail(format!("Field '{}' must be a valid email", path));
+                    }
+                    _ => {}
                 }
             }
-            ValidationRuleType::Regex(pattern) => {
-                if let FirestoreValue::StringValue(s) = value {
-                    let re = regex::Regex::new(pattern)
-                        .map_err(|e| FirebaseError::ConfigError(format!("Invalid regex: {}", e)))?;
-                    if !re.is_match(s) {
-                        return Err(FirebaseError::DatabaseError(
-                            format!("Field '{}' does not match pattern {}", field_name, pattern)
-                        ));
-                    }
+            ValidationRuleType::Url => {
+                let valid = match value {
+                    FirestoreValue::StringValue(s) => reqwest::Url::parse(s)
+                        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host()),
+                    _ => false,
+                };
+                if !valid {
+                    fail(format!("Field '{}' must be a valid http(s) URL", path));
                 }
             }
-            _ => {}
+            // Resolved through the registry in `check`
+            ValidationRuleType::Custom(_) => {}
         }
         
         Ok(())
@@ -222,7 +367,7 @@ impl SchemaManager {
     
     pub async fn initialize_collections(&self) -> Result<()> {
         for (name, collection) in &self.collections {
-            println!("Initializing collection: {}", name);
+            tracing::info!(collection = %name, "Initializing collection");
             
             let metadata_doc = CollectionMetadata {
                 name: collection.name.clone(),
@@ -232,30 +377,22 @@ impl SchemaManager {
                 validation_rules: collection.validation_rules.clone(),
             };
             
-            self.client.create("_metadata_collections", &metadata_doc).await?;
+            self.client.insert("_metadata_collections", None, metadata_doc.to_firestore()).await?;
         }
         
         Ok(())
     }
     
-    pub async fn create_indexes(&self, collection_name: &str) -> Result<()> {
-        let collection = self.collections.get(collection_name)
-            .ok_or_else(|| FirebaseError::ConfigError(format!("Collection {} not defined", collection_name)))?;
-        
-        println!("Note: Firestore indexes must be created in the Firebase Console or via Firebase CLI");
-        println!("Required indexes for collection '{}':", collection_name);
-        
-        for (i, index) in collection.indexes.iter().enumerate() {
-            println!("\nIndex {}:", i + 1);
-            for field in &index.fields {
-                println!("  - Field: {}, Order: {:?}", field.field_path, field.order);
-            }
-            if index.unique {
-                println!("  - Unique: true");
-            }
+    /// The composite indexes declared on every collection, merged with those
+    /// `queries` need.
+    pub fn index_definitions<'a>(&self, queries: impl IntoIterator<Item = &'a StructuredQuery>) -> IndexDefinitions {
+        let mut definitions = IndexDefinitions::default();
+        for collection in self.collections.values() {
+            definitions.add_schema(collection);
         }
-        
-        Ok(())
+        definitions.merge(IndexDefinitions::from_queries(queries));
+        definitions.sort();
+        definitions
     }
     
     pub fn export_schema(&self) -> String {
@@ -278,6 +415,35 @@ struct CollectionMetadata {
     validation_rules: Vec<ValidationRule>,
 }
 
+impl<S: DocumentStore + IndexAdmin> SchemaManager<S> {
+    /// Reconciles the composite indexes of `collection_name` with the schema:
+    /// indexes the schema declares are created, others in that collection
+    /// group are deleted. With `dry_run` nothing changes and the plan is only
+    /// returned; otherwise this waits for the new indexes to finish building.
+    pub async fn create_indexes(&self, collection_name: &str, dry_run: bool) -> Result<IndexPlan> {
+        let collection = self.collections.get(collection_name)
+            .ok_or_else(|| FirebaseError::ConfigError(format!("Collection {} not defined", collection_name)))?;
+
+        let mut desired = IndexDefinitions::default();
+        desired.add_schema(collection);
+        let groups = BTreeSet::from([collection_name.to_string()]);
+        let reconciler = IndexReconciler::new(&self.client);
+        let plan = reconciler.plan(&desired, &groups).await?;
+        tracing::info!(
+            collection = collection_name,
+            create = plan.create.len(),
+            delete = plan.delete.len(),
+            unchanged = plan.unchanged.len(),
+            dry_run,
+            "Index plan"
+        );
+        if !dry_run {
+            reconciler.apply(&plan).await?;
+        }
+        Ok(plan)
+    }
+}
+
 impl ToFirestore for CollectionMetadata {
     fn to_firestore(&self) -> HashMap<String, FirestoreValue> {
         let mut map = HashMap::new();
@@ -290,40 +456,591 @@ impl ToFirestore for CollectionMetadata {
     }
 }
 
-pub struct Migration {
+
+/// Collection recording applied migrations, one document per version.
+pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+// Document in the migrations collection held while a runner applies migrations
+const LOCK_ID: &str = "_lock";
+const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub type MigrationFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// One direction of a migration: an async closure given a [`MigrationContext`],
+/// e.g. `|ctx| Box::pin(async move { ctx.delete("users/old").await; Ok(()) })`.
+pub type MigrationFn<S = FirebaseClient> =
+    Box<dyn for<'a> Fn(&'a mut MigrationContext<S>) -> MigrationFuture<'a> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationDirection {
+    Up,
+    Down,
+}
+
+impl std::fmt::Display for MigrationDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationDirection::Up => write!(f, "up"),
+            MigrationDirection::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A document a migration wrote, or would have written in a dry run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AffectedDocument {
+    pub path: String,
+    /// `create`, `set`, `update` or `delete`.
+    pub operation: String,
+}
+
+/// Progress of a long-running migration, saved so that a run which dies can
+/// pick up where it left off.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MigrationCheckpoint {
+    /// Index of the step in progress.
+    pub step: usize,
+    /// Full name of the last document that step processed.
+    pub last_document: Option<String>,
+    /// Documents processed so far, across resumed runs.
+    pub processed: u64,
+}
+
+impl MigrationCheckpoint {
+    fn from_document(document: &StoredDocument) -> Self {
+        Self {
+            step: string_field(&document.fields, "step").and_then(|step| step.parse().ok()).unwrap_or(0),
+            last_document: string_field(&document.fields, "last_document"),
+            processed: string_field(&document.fields, "processed").and_then(|count| count.parse().ok()).unwrap_or(0),
+        }
+    }
+}
+
+impl ToFirestore for MigrationCheckpoint {
+    fn to_firestore(&self) -> HashMap<String, FirestoreValue> {
+        let mut map = HashMap::new();
+        map.insert("step".to_string(), FirestoreValue::IntegerValue(self.step.to_string()));
+        if let Some(last_document) = &self.last_document {
+            map.insert("last_document".to_string(), FirestoreValue::StringValue(last_document.clone()));
+        }
+        map.insert("processed".to_string(), FirestoreValue::IntegerValue(self.processed.to_string()));
+        map.insert("saved_at".to_string(), FirestoreValue::TimestampValue(Utc::now().to_rfc3339()));
+        map
+    }
+}
+
+/// What a migration closure works with. Reads go through
+/// [`client`](Self::client); writes are queued on a [`BulkWriter`] and flushed
+/// when the closure returns. In a dry run writes are only recorded.
+pub struct MigrationContext<S: DocumentStore + Clone + 'static = FirebaseClient> {
+    client: S,
+    version: String,
+    direction: MigrationDirection,
+    writer: Option<BulkWriter<S>>,
+    affected: Vec<AffectedDocument>,
+    checkpoint_path: String,
+    checkpointed: bool,
+    lock: Option<Arc<MigrationLock<S>>>,
+}
+
+impl<S: DocumentStore + Clone + 'static> MigrationContext<S> {
+    fn new(client: S, version: &str, direction: MigrationDirection, lock: Option<Arc<MigrationLock<S>>>, checkpoint_path: String) -> Self {
+        // Only dry runs go without the lock
+        let writer = lock.is_some().then(|| BulkWriter::new(client.clone()));
+        Self {
+            client,
+            version: version.to_string(),
+            direction,
+            writer,
+            affected: Vec::new(),
+            checkpoint_path,
+            checkpointed: false,
+            lock,
+        }
+    }
+
+    pub fn client(&self) -> &S {
+        &self.client
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn direction(&self) -> MigrationDirection {
+        self.direction
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.writer.is_none()
+    }
+
+    /// Documents written so far.
+    pub fn affected(&self) -> &[AffectedDocument] {
+        &self.affected
+    }
+
+    pub async fn write(&mut self, write: Write) {
+        self.affected.push(AffectedDocument {
+            path: write.path().to_string(),
+            operation: write.operation().to_string(),
+        });
+        if let Some(writer) = &mut self.writer {
+            writer.queue(write).await;
+        }
+    }
+
+    /// Queues a create, generating an ID when none is given. Returns the document path.
+    pub async fn create(&mut self, collection: &str, doc_id: Option<&str>, fields: HashMap<String, FirestoreValue>) -> String {
+        let id = doc_id.map(str::to_string).unwrap_or_else(generate_document_id);
+        let path = document_path(collection, &id);
+        self.write(Write::Create { path: path.clone(), fields }).await;
+        path
+    }
+
+    pub async fn set(&mut self, path: &str, fields: HashMap<String, FirestoreValue>) {
+        self.write(Write::Set { path: path.to_string(), fields }).await;
+    }
+
+    pub async fn update(&mut self, path: &str, fields: HashMap<String, FirestoreValue>, mask: Vec<String>) {
+        self.write(Write::Update { path: path.to_string(), fields, mask }).await;
+    }
+
+    pub async fn delete(&mut self, path: &str) {
+        self.write(Write::Delete { path: path.to_string() }).await;
+    }
+
+    /// The checkpoint saved by an earlier run of this migration and
+    /// direction that did not finish, if any.
+    pub async fn checkpoint(&self) -> Result<Option<MigrationCheckpoint>> {
+        let document = self.client.fetch(&self.checkpoint_path, &ReadOptions::default()).await?;
+        Ok(document.as_ref().map(MigrationCheckpoint::from_document))
+    }
+
+    /// Waits for every queued write, then saves `checkpoint`. It is cleared
+    /// once the migration finishes. Also renews the migration lock, failing
+    /// if another runner has taken it over. Does nothing in a dry run.
+    pub async fn save_checkpoint(&mut self, checkpoint: &MigrationCheckpoint) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        if let Some(lock) = &self.lock {
+            lock.renew().await?;
+        }
+        writer.flush().await;
+        if !writer.report().is_success() {
+            return Err(failed_writes(&self.version, self.direction, writer.report()));
+        }
+        self.client.patch(&self.checkpoint_path, checkpoint.to_firestore(), None).await?;
+        self.checkpointed = true;
+        Ok(())
+    }
+
+    async fn finish(self) -> (Vec<AffectedDocument>, BulkWriteReport, bool) {
+        let report = match self.writer {
+            Some(writer) => writer.close().await,
+            None => BulkWriteReport::default(),
+        };
+        (self.affected, report, self.checkpointed)
+    }
+}
+
+fn failed_writes(version: &str, direction: MigrationDirection, report: &BulkWriteReport) -> FirebaseError {
+    FirebaseError::DatabaseError(format!(
+        "Migration {} ({}) failed: {} of {} writes failed, first: {}",
+        version, direction, report.failures.len(), report.total(),
+        report.failures.first().map(|failure| failure.error.as_str()).unwrap_or_default()
+    ))
+}
+
+pub struct Migration<S: DocumentStore + Clone + 'static = FirebaseClient> {
+    pub version: String,
+    pub description: String,
+    pub up: MigrationFn<S>,
+    pub down: MigrationFn<S>,
+}
+
+impl<S: DocumentStore + Clone + 'static> Migration<S> {
+    /// A migration whose `up` and `down` do nothing until set. Versions are
+    /// applied in string order, so zero-pad them (`001`, `002`, ...).
+    pub fn new(version: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            description: description.into(),
+            up: Box::new(|_| Box::pin(async { Ok(()) })),
+            down: Box::new(|_| Box::pin(async { Ok(()) })),
+        }
+    }
+
+    pub fn up<F>(mut self, up: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut MigrationContext<S>) -> MigrationFuture<'a> + Send + Sync + 'static,
+    {
+        self.up = Box::new(up);
+        self
+    }
+
+    pub fn down<F>(mut self, down: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut MigrationContext<S>) -> MigrationFuture<'a> + Send + Sync + 'static,
+    {
+        self.down = Box::new(down);
+        self
+    }
+}
+
+/// A registered or recorded migration and whether it has been applied.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationStatus {
+    pub version: String,
+    pub description: String,
+    /// When it was applied, or `None` while pending.
+    pub applied_at: Option<String>,
+    /// Number of documents it wrote when applied.
+    pub documents: Option<u64>,
+    /// False for a recorded version with no registered migration.
+    pub known: bool,
+}
+
+/// One migration applied or rolled back.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationRun {
     pub version: String,
     pub description: String,
-    pub up: Box<dyn Fn(&FirebaseClient) -> Result<()>>,
-    pub down: Box<dyn Fn(&FirebaseClient) -> Result<()>>,
+    pub direction: MigrationDirection,
+    pub dry_run: bool,
+    pub affected: Vec<AffectedDocument>,
+    pub report: BulkWriteReport,
 }
 
-pub struct MigrationManager {
-    client: FirebaseClient,
-    migrations: Vec<Migration>,
+/// Applies migrations in version order and records each applied version in
+/// the `_migrations` collection. Runs that write take a lock document in that
+/// collection, so two runners cannot apply migrations at the same time; a
+/// lock left by a crashed runner expires after `lock_ttl`. The lock is renewed
+/// at every checkpoint and before each migration is recorded, and a run fails
+/// if it finds the lock taken over.
+pub struct MigrationManager<S: DocumentStore + Clone + 'static = FirebaseClient> {
+    client: S,
+    migrations: Vec<Migration<S>>,
+    collection: String,
+    lock_ttl: Duration,
 }
 
-impl MigrationManager {
-    pub fn new(client: FirebaseClient) -> Self {
+impl<S: DocumentStore + Clone + 'static> MigrationManager<S> {
+    pub fn new(client: S) -> Self {
         Self {
             client,
             migrations: Vec::new(),
+            collection: MIGRATIONS_COLLECTION.to_string(),
+            lock_ttl: DEFAULT_LOCK_TTL,
         }
     }
-    
-    pub fn add_migration(&mut self, migration: Migration) {
+
+    /// Records applied migrations in `collection` instead of `_migrations`.
+    pub fn collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = collection.into();
+        self
+    }
+
+    /// How long a lock stays valid without being renewed; default 10 minutes.
+    pub fn lock_ttl(mut self, ttl: Duration) -> Self {
+        self.lock_ttl = ttl;
+        self
+    }
+
+    pub fn client(&self) -> &S {
+        &self.client
+    }
+
+    /// Registers a migration, replacing any with the same version.
+    pub fn add_migration(&mut self, migration: Migration<S>) {
+        self.migrations.retain(|existing| existing.version != migration.version);
         self.migrations.push(migration);
+        self.migrations.sort_by(|a, b| a.version.cmp(&b.version));
     }
-    
-    pub async fn run_migrations(&self) -> Result<()> {
-        println!("Running migrations...");
-        
-        for migration in &self.migrations {
-            println!("Running migration: {} - {}", migration.version, migration.description);
-            
-            // Note: In a real implementation, you'd track which migrations have been run
-            // by storing them in a _migrations collection
+
+    /// Registered migrations in version order.
+    pub fn migrations(&self) -> &[Migration<S>] {
+        &self.migrations
+    }
+
+    /// Every registered or recorded migration, in version order.
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>> {
+        let mut applied = self.applied().await?;
+        let mut statuses: Vec<MigrationStatus> = self.migrations.iter()
+            .map(|migration| {
+                let record = applied.remove(&migration.version);
+                MigrationStatus {
+                    version: migration.version.clone(),
+                    description: migration.description.clone(),
+                    applied_at: record.as_ref().map(|record| record.applied_at.clone()),
+                    documents: record.map(|record| record.documents),
+                    known: true,
+                }
+            })
+            .collect();
+        statuses.extend(applied.into_iter().map(|(version, record)| MigrationStatus {
+            version,
+            description: record.description,
+            applied_at: Some(record.applied_at),
+            documents: Some(record.documents),
+            known: false,
+        }));
+        statuses.sort_by(|a, b| a.version.cmp(&b.version));
+        Ok(statuses)
+    }
+
+    /// Applies every pending migration.
+    pub async fn run_migrations(&self) -> Result<Vec<MigrationRun>> {
+        self.up(None, false).await
+    }
+
+    /// Applies pending migrations in order, up to and including `target`.
+    pub async fn up(&self, target: Option<&str>, dry_run: bool) -> Result<Vec<MigrationRun>> {
+        if let Some(target) = target && self.find(target).is_none() {
+            return Err(FirebaseError::ConfigError(format!("Unknown migration version '{}'", target)));
         }
-        
+        self.locked(dry_run, |lock| async move {
+            let applied = self.applied().await?;
+            let mut runs = Vec::new();
+            for migration in &self.migrations {
+                if applied.contains_key(&migration.version)
+                    || target.is_some_and(|target| migration.version.as_str() > target)
+                {
+                    continue;
+                }
+                runs.push(self.run(migration, MigrationDirection::Up, lock.clone()).await?);
+            }
+            Ok(runs)
+        }).await
+    }
+
+    /// Rolls back the last `steps` applied migrations, newest first.
+    pub async fn down(&self, steps: usize, dry_run: bool) -> Result<Vec<MigrationRun>> {
+        self.locked(dry_run, |lock| async move {
+            let applied = self.applied().await?;
+            let mut runs = Vec::new();
+            for version in applied.keys().rev().take(steps) {
+                let migration = self.registered(version)?;
+                runs.push(self.run(migration, MigrationDirection::Down, lock.clone()).await?);
+            }
+            Ok(runs)
+        }).await
+    }
+
+    /// Rolls back the last applied migration and applies it again.
+    pub async fn redo(&self, dry_run: bool) -> Result<Vec<MigrationRun>> {
+        self.locked(dry_run, |lock| async move {
+            let applied = self.applied().await?;
+            let version = applied.keys().next_back()
+                .ok_or_else(|| FirebaseError::ConfigError("No migrations have been applied".to_string()))?;
+            let migration = self.registered(version)?;
+            Ok(vec![
+                self.run(migration, MigrationDirection::Down, lock.clone()).await?,
+                self.run(migration, MigrationDirection::Up, lock).await?,
+            ])
+        }).await
+    }
+
+    /// Removes the lock regardless of who holds it. Returns whether there was one.
+    pub async fn force_unlock(&self) -> Result<bool> {
+        let path = document_path(&self.collection, LOCK_ID);
+        if self.client.fetch(&path, &ReadOptions::default()).await?.is_none() {
+            return Ok(false);
+        }
+        self.client.remove(&path).await?;
+        Ok(true)
+    }
+
+    fn find(&self, version: &str) -> Option<&Migration<S>> {
+        self.migrations.iter().find(|migration| migration.version == version)
+    }
+
+    fn registered(&self, version: &str) -> Result<&Migration<S>> {
+        self.find(version).ok_or_else(|| FirebaseError::ConfigError(format!(
+            "Migration '{}' is applied but not registered, so it cannot be rolled back", version
+        )))
+    }
+
+    async fn applied(&self) -> Result<BTreeMap<String, MigrationRecord>> {
+        let documents = self.client.list_all(&self.collection, &ReadOptions::default()).await?;
+        Ok(documents.iter()
+            .filter(|document| document.id() != LOCK_ID)
+            .map(|document| (document.id().to_string(), MigrationRecord::from_document(document)))
+            .collect())
+    }
+
+    // Runs without a lock are dry runs
+    async fn run(&self, migration: &Migration<S>, direction: MigrationDirection, lock: Option<Arc<MigrationLock<S>>>) -> Result<MigrationRun> {
+        let dry_run = lock.is_none();
+        tracing::info!(version = %migration.version, description = %migration.description, %direction, dry_run, "Running migration");
+        let checkpoint_path = format!("{}/{}/checkpoints/{}", self.collection, migration.version, direction);
+        let mut context = MigrationContext::new(self.client.clone(), &migration.version, direction, lock.clone(), checkpoint_path.clone());
+        let step = match direction {
+            MigrationDirection::Up => &migration.up,
+            MigrationDirection::Down => &migration.down,
+        };
+        // Writes queued before a failure are still flushed, so the report is accurate
+        let outcome = step(&mut context).await;
+        let (affected, report, checkpointed) = context.finish().await;
+        outcome?;
+        if !report.is_success() {
+            return Err(failed_writes(&migration.version, direction, &report));
+        }
+
+        if let Some(lock) = &lock {
+            // Another runner may have taken over an expired lock meanwhile
+            lock.renew().await?;
+            if checkpointed {
+                self.client.remove(&checkpoint_path).await?;
+            }
+            let path = document_path(&self.collection, &migration.version);
+            match direction {
+                MigrationDirection::Up => {
+                    let record = MigrationRecord {
+                        description: migration.description.clone(),
+                        applied_at: Utc::now().to_rfc3339(),
+                        documents: affected.len() as u64,
+                    };
+                    self.client.patch(&path, record.to_firestore(), None).await?;
+                }
+                MigrationDirection::Down => self.client.remove(&path).await?,
+            }
+        }
+
+        Ok(MigrationRun {
+            version: migration.version.clone(),
+            description: migration.description.clone(),
+            direction,
+            dry_run,
+            affected,
+            report,
+        })
+    }
+
+    // Dry runs write nothing, so they run without the lock
+    async fn locked<T, F, Fut>(&self, dry_run: bool, work: F) -> Result<T>
+    where
+        F: FnOnce(Option<Arc<MigrationLock<S>>>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if dry_run {
+            return work(None).await;
+        }
+        let lock = Arc::new(MigrationLock::acquire(&self.client, &self.collection, self.lock_ttl).await?);
+        let result = work(Some(lock.clone())).await;
+        lock.release().await?;
+        result
+    }
+}
+
+/// The lock document held by a runner while it applies migrations.
+struct MigrationLock<S: DocumentStore + Clone + 'static> {
+    client: S,
+    path: String,
+    owner: String,
+    ttl: Duration,
+}
+
+impl<S: DocumentStore + Clone + 'static> MigrationLock<S> {
+    async fn acquire(client: &S, collection: &str, ttl: Duration) -> Result<Self> {
+        let path = document_path(collection, LOCK_ID);
+        let now = Utc::now();
+        let lock = Self { client: client.clone(), path, owner: uuid::Uuid::new_v4().to_string(), ttl };
+        let mut fields = lock.fields(now)?;
+        let mut transaction = Transaction::begin(client).await?;
+        if let Some(held) = transaction.get(&lock.path).await? {
+            let held_by = string_field(&held.fields, "owner").unwrap_or_default();
+            let expires_at = string_field(&held.fields, "expires_at").unwrap_or_default();
+            let expired = DateTime::parse_from_rfc3339(&expires_at).is_ok_and(|expires_at| expires_at < now);
+            if !expired {
+                transaction.rollback().await?;
+                return Err(FirebaseError::DatabaseError(format!(
+                    "Migrations are locked by {} until {}", held_by, expires_at
+                )));
+            }
+            tracing::warn!(owner = %held_by, %expires_at, "Taking over expired migration lock");
+        }
+
+        fields.insert("acquired_at".to_string(), FirestoreValue::TimestampValue(now.to_rfc3339()));
+        transaction.set(&lock.path, fields);
+        transaction.commit().await?;
+        Ok(lock)
+    }
+
+    /// Extends the lock by its TTL, failing if another runner holds it now.
+    async fn renew(&self) -> Result<()> {
+        let mut fields = self.fields(Utc::now())?;
+        fields.retain(|name, _| name == "expires_at");
+        let mut transaction = Transaction::begin(&self.client).await?;
+        let held_by = transaction.get(&self.path).await?
+            .and_then(|lock| string_field(&lock.fields, "owner"));
+        if held_by.as_deref() != Some(self.owner.as_str()) {
+            transaction.rollback().await?;
+            return Err(FirebaseError::DatabaseError(format!(
+                "Migration lock was lost to {}; another runner may be applying migrations",
+                held_by.as_deref().unwrap_or("nobody")
+            )));
+        }
+        transaction.write(Write::Update { path: self.path.clone(), fields, mask: vec!["expires_at".to_string()] });
+        transaction.commit().await?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn release(&self) -> Result<()> {
+        match self.client.fetch(&self.path, &ReadOptions::default()).await? {
+            Some(lock) if string_field(&lock.fields, "owner").as_deref() == Some(self.owner.as_str()) => self.client.remove(&self.path).await,
+            _ => {
+                tracing::warn!(owner = %self.owner, "Migration lock was taken over before it was released");
+                Ok(())
+            }
+        }
+    }
+
+    fn fields(&self, now: DateTime<Utc>) -> Result<HashMap<String, FirestoreValue>> {
+        let expires_at = chrono::Duration::from_std(self.ttl).ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .ok_or_else(|| FirebaseError::ConfigError(format!("Migration lock TTL of {:?} is too large", self.ttl)))?;
+        let mut fields = HashMap::new();
+        fields.insert("owner".to_string(), FirestoreValue::StringValue(self.owner.clone()));
+        fields.insert("expires_at".to_string(), FirestoreValue::TimestampValue(expires_at.to_rfc3339()));
+        Ok(fields)
+    }
+}
+
+/// The `_migrations` document written when a migration is applied.
+struct MigrationRecord {
+    description: String,
+    applied_at: String,
+    documents: u64,
+}
+
+impl MigrationRecord {
+    fn from_document(document: &StoredDocument) -> Self {
+        Self {
+            description: string_field(&document.fields, "description").unwrap_or_default(),
+            applied_at: string_field(&document.fields, "applied_at").unwrap_or_default(),
+            documents: string_field(&document.fields, "documents").and_then(|count| count.parse().ok()).unwrap_or(0),
+        }
+    }
+}
+
+impl ToFirestore for MigrationRecord {
+    fn to_firestore(&self) -> HashMap<String, FirestoreValue> {
+        let mut map = HashMap::new();
+        map.insert("description".to_string(), FirestoreValue::StringValue(self.description.clone()));
+        map.insert("applied_at".to_string(), FirestoreValue::TimestampValue(self.applied_at.clone()));
+        map.insert("documents".to_string(), FirestoreValue::IntegerValue(self.documents.to_string()));
+        map
+    }
+}
+
+fn string_field(fields: &HashMap<String, FirestoreValue>, name: &str) -> Option<String> {
+    match fields.get(name)? {
+        FirestoreValue::StringValue(value)
+        | FirestoreValue::TimestampValue(value)
+        | FirestoreValue::IntegerValue(value) => Some(value.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,302 @@
+use crate::error::{FirebaseError, Result};
+use crate::json_manager::{FirestoreSchema, JsonCollection, JsonField, JsonIndex, JsonSchema, JsonValidationRule};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+/// Whether a change can break existing documents or the code reading them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    NonBreaking,
+    Breaking,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::NonBreaking => write!(f, "non-breaking"),
+            Severity::Breaking => write!(f, "breaking"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaChangeKind {
+    CollectionAdded,
+    CollectionRemoved,
+    FieldAdded,
+    FieldRemoved,
+    TypeChanged,
+    RequiredChanged,
+    IndexAdded,
+    IndexRemoved,
+    RuleAdded,
+    RuleRemoved,
+    RuleChanged,
+}
+
+/// One difference between two schemas, from the old side to the new one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaChange {
+    pub collection: String,
+    /// The field, index or rule that changed, e.g. `email`, `status asc, created_at desc`
+    /// or `name min_length`.
+    pub target: String,
+    pub kind: SchemaChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+    pub severity: Severity,
+}
+
+impl SchemaChange {
+    pub fn is_breaking(&self) -> bool {
+        self.severity == Severity::Breaking
+    }
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self.old.as_deref().unwrap_or("-");
+        let new = self.new.as_deref().unwrap_or("-");
+        match self.kind {
+            SchemaChangeKind::CollectionAdded => write!(f, "collection '{}' added", self.collection),
+            SchemaChangeKind::CollectionRemoved => write!(f, "collection '{}' removed", self.collection),
+            SchemaChangeKind::FieldAdded => write!(f, "field '{}' added ({})", self.target, new),
+            SchemaChangeKind::FieldRemoved => write!(f, "field '{}' removed (was {})", self.target, old),
+            SchemaChangeKind::TypeChanged => write!(f, "field '{}' type changed from {} to {}", self.target, old, new),
+            SchemaChangeKind::RequiredChanged => write!(f, "field '{}' required changed from {} to {}", self.target, old, new),
+            SchemaChangeKind::IndexAdded => write!(f, "index ({}) added", self.target),
+            SchemaChangeKind::IndexRemoved => write!(f, "index ({}) removed", self.target),
+            SchemaChangeKind::RuleAdded => write!(f, "rule {} added", new),
+            SchemaChangeKind::RuleRemoved => write!(f, "rule {} removed", old),
+            SchemaChangeKind::RuleChanged => write!(f, "rule {} changed to {}", old, new),
+        }
+    }
+}
+
+/// Every difference between two sets of collection schemas.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl SchemaDiff {
+    /// Compares collections by name; ones on only one side are added or removed.
+    pub fn between(old: &[JsonCollection], new: &[JsonCollection]) -> Self {
+        let old: BTreeMap<&str, &JsonCollection> = old.iter().map(|collection| (collection.name.as_str(), collection)).collect();
+        let new: BTreeMap<&str, &JsonCollection> = new.iter().map(|collection| (collection.name.as_str(), collection)).collect();
+        let mut diff = SchemaDiff::default();
+        let mut names: Vec<&str> = old.keys().chain(new.keys()).copied().collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            match (old.get(name), new.get(name)) {
+                (Some(old), Some(new)) => diff.changes.extend(Self::collections(old, new).changes),
+                (Some(_), None) => diff.push(name, name, SchemaChangeKind::CollectionRemoved, None, None, Severity::Breaking),
+                (None, Some(_)) => diff.push(name, name, SchemaChangeKind::CollectionAdded, None, None, Severity::NonBreaking),
+                (None, None) => {}
+            }
+        }
+        diff
+    }
+
+    /// Compares two versions of one collection, whatever their names.
+    pub fn collections(old: &JsonCollection, new: &JsonCollection) -> Self {
+        let mut diff = SchemaDiff::default();
+        diff.diff_fields(&new.name, &old.fields, &new.fields);
+        diff.diff_indexes(&new.name, &old.indexes, &new.indexes);
+        diff.diff_rules(&new.name, &old.validation_rules, &new.validation_rules);
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn breaking(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes.iter().filter(|change| change.is_breaking())
+    }
+
+    pub fn has_breaking(&self) -> bool {
+        self.breaking().next().is_some()
+    }
+
+    /// The most severe change, or `None` when there are none.
+    pub fn severity(&self) -> Option<Severity> {
+        self.changes.iter().map(|change| change.severity).max()
+    }
+
+    fn push(&mut self, collection: &str, target: &str, kind: SchemaChangeKind, old: Option<String>, new: Option<String>, severity: Severity) {
+        self.changes.push(SchemaChange {
+            collection: collection.to_string(),
+            target: target.to_string(),
+            kind,
+            old,
+            new,
+            severity,
+        });
+    }
+
+    fn diff_fields(&mut self, collection: &str, old: &[JsonField], new: &[JsonField]) {
+        for field in old {
+            if !new.iter().any(|candidate| candidate.name == field.name) {
+                self.push(collection, &field.name, SchemaChangeKind::FieldRemoved, Some(describe_field(field)), None, Severity::Breaking);
+            }
+        }
+        for field in new {
+            let Some(previous) = old.iter().find(|candidate| candidate.name == field.name) else {
+                // Existing documents won't have a new required field unless something fills it
+                let severity = if field.required && !has_fallback(field) { Severity::Breaking } else { Severity::NonBreaking };
+                self.push(collection, &field.name, SchemaChangeKind::FieldAdded, None, Some(describe_field(field)), severity);
+                continue;
+            };
+
+            let (old_type, new_type) = (previous.field_type.to_lowercase(), field.field_type.to_lowercase());
+            if old_type != new_type {
+                let widened = new_type == "mixed" || (old_type == "integer" && new_type == "double");
+                let severity = if widened { Severity::NonBreaking } else { Severity::Breaking };
+                self.push(collection, &field.name, SchemaChangeKind::TypeChanged, Some(old_type), Some(new_type), severity);
+            }
+            if previous.required != field.required {
+                let severity = if field.required && !has_fallback(field) { Severity::Breaking } else { Severity::NonBreaking };
+                self.push(collection, &field.name, SchemaChangeKind::RequiredChanged,
+                    Some(previous.required.to_string()), Some(field.required.to_string()), severity);
+            }
+        }
+    }
+
+    fn diff_indexes(&mut self, collection: &str, old: &[JsonIndex], new: &[JsonIndex]) {
+        let old: Vec<String> = old.iter().map(describe_index).collect();
+        let new: Vec<String> = new.iter().map(describe_index).collect();
+        // Queries relying on a removed index stop working
+        for index in old.iter().filter(|index| !new.contains(index)) {
+            self.push(collection, index, SchemaChangeKind::IndexRemoved, Some(index.clone()), None, Severity::Breaking);
+        }
+        for index in new.iter().filter(|index| !old.contains(index)) {
+            self.push(collection, index, SchemaChangeKind::IndexAdded, None, Some(index.clone()), Severity::NonBreaking);
+        }
+    }
+
+    fn diff_rules(&mut self, collection: &str, old: &[JsonValidationRule], new: &[JsonValidationRule]) {
+        let (old, new) = (group_rules(old), group_rules(new));
+        let mut keys: Vec<&(String, String)> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        for key in keys {
+            let target = format!("{} {}", key.0, key.1);
+            let before = old.get(key).map(Vec::as_slice).unwrap_or_default();
+            let after = new.get(key).map(Vec::as_slice).unwrap_or_default();
+            if let ([before], [after]) = (before, after) {
+                if before.value != after.value {
+                    let severity = rule_change_severity(&key.1, before, after);
+                    self.push(collection, &target, SchemaChangeKind::RuleChanged, Some(describe_rule(before)), Some(describe_rule(after)), severity);
+                }
+                continue;
+            }
+            // New rules may reject documents that are already stored
+            for rule in before.iter().filter(|rule| !after.iter().any(|other| other.value == rule.value)) {
+                self.push(collection, &target, SchemaChangeKind::RuleRemoved, Some(describe_rule(rule)), None, Severity::NonBreaking);
+            }
+            for rule in after.iter().filter(|rule| !before.iter().any(|other| other.value == rule.value)) {
+                self.push(collection, &target, SchemaChangeKind::RuleAdded, None, Some(describe_rule(rule)), Severity::Breaking);
+            }
+        }
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "no changes");
+        }
+        let breaking = self.breaking().count();
+        write!(f, "{} change(s), {} breaking", self.changes.len(), breaking)
+    }
+}
+
+/// Reads a schema file as collections. Accepts a full schema file (as written
+/// by `schema export`), a stored `FirestoreSchema` or a single collection, in
+/// JSON or YAML.
+pub fn read_schema_file(path: &str) -> Result<Vec<JsonCollection>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| FirebaseError::ConfigError(format!("Failed to read schema file {}: {}", path, e)))?;
+    let value: serde_json::Value = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&content)
+            .map_err(|e| FirebaseError::ConfigError(format!("Failed to parse schema file {}: {}", path, e)))?
+    } else {
+        serde_json::from_str(&content)?
+    };
+
+    if let Ok(schema) = serde_json::from_value::<JsonSchema>(value.clone()) {
+        let mut collections: Vec<JsonCollection> = schema.collections.into_iter()
+            .map(|(name, collection)| JsonCollection { name, ..collection })
+            .collect();
+        collections.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(collections);
+    }
+    if let Ok(schema) = serde_json::from_value::<FirestoreSchema>(value.clone()) {
+        return Ok(vec![schema.to_json_collection()]);
+    }
+    serde_json::from_value::<JsonCollection>(value)
+        .map(|collection| vec![collection])
+        .map_err(|e| FirebaseError::ConfigError(format!("{} is not a schema file: {}", path, e)))
+}
+
+/// Rules by field and rule type.
+fn group_rules(rules: &[JsonValidationRule]) -> BTreeMap<(String, String), Vec<&JsonValidationRule>> {
+    let mut grouped: BTreeMap<(String, String), Vec<&JsonValidationRule>> = BTreeMap::new();
+    for rule in rules {
+        grouped.entry((rule.field.clone(), rule.rule_type.to_lowercase())).or_default().push(rule);
+    }
+    grouped
+}
+
+// A default or an automatic value fills the field in on existing documents
+fn has_fallback(field: &JsonField) -> bool {
+    field.default_value.is_some() || field.auto_generated
+}
+
+fn describe_field(field: &JsonField) -> String {
+    if field.required {
+        format!("{}, required", field.field_type.to_lowercase())
+    } else {
+        field.field_type.to_lowercase()
+    }
+}
+
+fn describe_index(index: &JsonIndex) -> String {
+    let fields: Vec<String> = index.fields.iter()
+        .map(|field| format!("{} {}", field.field_path, field.order.to_lowercase()))
+        .collect();
+    if index.unique {
+        format!("{} unique", fields.join(", "))
+    } else {
+        fields.join(", ")
+    }
+}
+
+fn describe_rule(rule: &JsonValidationRule) -> String {
+    match &rule.value {
+        Some(serde_json::Value::String(value)) => format!("{}.{}({})", rule.field, rule.rule_type, value),
+        Some(value) => format!("{}.{}({})", rule.field, rule.rule_type, value),
+        None => format!("{}.{}", rule.field, rule.rule_type),
+    }
+}
+
+/// Loosening a bound is safe; tightening one, or changing a pattern or a
+/// custom validator, may reject stored documents.
+fn rule_change_severity(rule_type: &str, before: &JsonValidationRule, after: &JsonValidationRule) -> Severity {
+    let bounds = (before.value.as_ref().and_then(|v| v.as_f64()), after.value.as_ref().and_then(|v| v.as_f64()));
+    let loosened = match (rule_type, bounds) {
+        ("min" | "min_length", (Some(before), Some(after))) => after < before,
+        ("max" | "max_length", (Some(before), Some(after))) => after > before,
+        _ => false,
+    };
+    if loosened { Severity::NonBreaking } else { Severity::Breaking }
+}
@@ -0,0 +1,183 @@
+use crate::collections::{CollectionSchema, FieldInfo};
+use crate::json_manager::{FirestoreSchema, JsonField};
+use crate::schema_diff::Severity;
+use serde::Serialize;
+use std::fmt;
+
+/// Change in the share of documents having a field, as a fraction, above
+/// which [`detect_drift`] reports it by default.
+pub const DEFAULT_FREQUENCY_THRESHOLD: f64 = 0.2;
+
+const MIXED_PREFIX: &str = "Mixed(";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// A field the stored schema doesn't have.
+    NewField,
+    /// Documents hold more than one type in a field.
+    TypeMixing,
+    /// Documents consistently hold another type than the stored one.
+    TypeChanged,
+    /// A required field is absent from some documents.
+    MissingRequired,
+    /// The share of documents having a field moved by more than the threshold.
+    FrequencyChanged,
+}
+
+/// One way the documents differ from their stored schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftFinding {
+    pub field: String,
+    pub kind: DriftKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<String>,
+    pub severity: Severity,
+}
+
+impl fmt::Display for DriftFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = self.expected.as_deref().unwrap_or("-");
+        let found = self.found.as_deref().unwrap_or("-");
+        match self.kind {
+            DriftKind::NewField => write!(f, "new field '{}' ({})", self.field, found),
+            DriftKind::TypeMixing => write!(f, "field '{}' mixes types {}, expected {}", self.field, found, expected),
+            DriftKind::TypeChanged => write!(f, "field '{}' is {}, expected {}", self.field, found, expected),
+            DriftKind::MissingRequired => write!(f, "required field '{}' is missing from {}", self.field, found),
+            DriftKind::FrequencyChanged => write!(f, "field '{}' is in {} of documents, was {}", self.field, found, expected),
+        }
+    }
+}
+
+/// How a sample of a collection's documents compares with its stored schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftReport {
+    pub collection: String,
+    /// Version of the stored schema compared against.
+    pub schema_version: String,
+    /// Documents sampled.
+    pub sampled: usize,
+    pub findings: Vec<DriftFinding>,
+}
+
+impl DriftReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// The most severe finding, or `None` when there are none.
+    pub fn severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+
+    /// Whether any finding is at least as severe as `threshold`.
+    pub fn exceeds(&self, threshold: Severity) -> bool {
+        self.severity().is_some_and(|severity| severity >= threshold)
+    }
+}
+
+/// Compares sampled documents, as analyzed by
+/// [`CollectionManager::describe_collection`](crate::CollectionManager::describe_collection),
+/// with the stored schema of their collection. Frequency changes are only
+/// found for fields whose stored schema recorded a frequency, i.e. ones saved
+/// by discovery.
+pub fn detect_drift(stored: &FirestoreSchema, live: &CollectionSchema, frequency_threshold: f64) -> DriftReport {
+    let mut findings = Vec::new();
+    let sampled = live.total_documents;
+
+    for field in &live.fields {
+        let Some(expected) = stored.fields.iter().find(|candidate| candidate.name == field.name) else {
+            findings.push(DriftFinding {
+                field: field.name.clone(),
+                kind: DriftKind::NewField,
+                expected: None,
+                found: Some(format!("{}, in {}", field.field_type, share(field.frequency, sampled))),
+                severity: Severity::NonBreaking,
+            });
+            continue;
+        };
+        if let Some(finding) = type_drift(expected, field) {
+            findings.push(finding);
+        }
+    }
+
+    for expected in &stored.fields {
+        let frequency = live.fields.iter()
+            .find(|field| field.name == expected.name)
+            .map(|field| field.frequency)
+            .unwrap_or(0);
+        if expected.required && frequency < sampled {
+            findings.push(DriftFinding {
+                field: expected.name.clone(),
+                kind: DriftKind::MissingRequired,
+                expected: Some("every document".to_string()),
+                found: Some(format!("{} of {} documents", sampled - frequency, sampled)),
+                severity: Severity::Breaking,
+            });
+            continue;
+        }
+        if let Some(stored_frequency) = expected.frequency
+            && stored.total_documents > 0
+            && sampled > 0
+        {
+            let before = stored_frequency as f64 / stored.total_documents as f64;
+            let after = frequency as f64 / sampled as f64;
+            if (after - before).abs() > frequency_threshold {
+                findings.push(DriftFinding {
+                    field: expected.name.clone(),
+                    kind: DriftKind::FrequencyChanged,
+                    expected: Some(share(stored_frequency, stored.total_documents)),
+                    found: Some(share(frequency, sampled)),
+                    severity: Severity::NonBreaking,
+                });
+            }
+        }
+    }
+
+    DriftReport {
+        collection: stored.collection_name.clone(),
+        schema_version: stored.version.clone(),
+        sampled,
+        findings,
+    }
+}
+
+fn type_drift(expected: &JsonField, field: &FieldInfo) -> Option<DriftFinding> {
+    let expected_type = expected.field_type.to_lowercase();
+    // Nothing to hold the documents to
+    if expected_type == "mixed" || expected_type == "unknown" {
+        return None;
+    }
+
+    // Nulls in an optional field aren't a second type
+    let mut types: Vec<&str> = match field.field_type.strip_prefix(MIXED_PREFIX).and_then(|rest| rest.strip_suffix(')')) {
+        Some(types) => types.split(", ").collect(),
+        None => vec![field.field_type.as_str()],
+    };
+    if !expected.required {
+        types.retain(|kind| *kind != "null");
+    }
+
+    let kind = match types.as_slice() {
+        [] => return None,
+        [only] if *only == expected_type => return None,
+        [_] => DriftKind::TypeChanged,
+        _ => DriftKind::TypeMixing,
+    };
+    Some(DriftFinding {
+        field: field.name.clone(),
+        kind,
+        expected: Some(expected_type),
+        found: Some(types.join(", ")),
+        severity: Severity::Breaking,
+    })
+}
+
+fn share(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "0%".to_string();
+    }
+    format!("{:.0}%", count as f64 * 100.0 / total as f64)
+}
@@ -0,0 +1,79 @@
+use crate::error::{FirebaseError, Result};
+use crate::json_manager::FirestoreSchema;
+use crate::schema_diff::{SchemaDiff, Severity};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Holds the current schema of each collection, as `collection_schemas/{collection}`.
+pub const SCHEMAS_COLLECTION: &str = "collection_schemas";
+
+/// Subcollection of each schema document keeping every version saved, by version number.
+pub const VERSIONS_SUBCOLLECTION: &str = "versions";
+
+/// Which part of a schema's semantic version a new version increments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionBump {
+    /// Nothing `SchemaDiff` tracks changed, e.g. only descriptions or document counts.
+    Patch,
+    /// Only non-breaking changes.
+    Minor,
+    /// At least one breaking change.
+    Major,
+}
+
+impl VersionBump {
+    /// The bump a change from `old` to `new` calls for.
+    pub fn between(old: &FirestoreSchema, new: &FirestoreSchema) -> Self {
+        let diff = SchemaDiff::collections(&old.to_json_collection(), &new.to_json_collection());
+        match diff.severity() {
+            Some(Severity::Breaking) => VersionBump::Major,
+            Some(Severity::NonBreaking) => VersionBump::Minor,
+            None => VersionBump::Patch,
+        }
+    }
+
+    /// Applies the bump to a `major.minor.patch` version.
+    pub fn apply(self, version: &str) -> Result<String> {
+        let (major, minor, patch) = parse_version(version)?;
+        Ok(match self {
+            VersionBump::Major => format!("{}.0.0", major + 1),
+            VersionBump::Minor => format!("{}.{}.0", major, minor + 1),
+            VersionBump::Patch => format!("{}.{}.{}", major, minor, patch + 1),
+        })
+    }
+}
+
+impl fmt::Display for VersionBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionBump::Patch => write!(f, "patch"),
+            VersionBump::Minor => write!(f, "minor"),
+            VersionBump::Major => write!(f, "major"),
+        }
+    }
+}
+
+/// Splits a `major.minor.patch` version into its numbers.
+pub fn parse_version(version: &str) -> Result<(u64, u64, u64)> {
+    let invalid = || FirebaseError::ValidationError(format!("Invalid schema version '{}', expected major.minor.patch", version));
+    let mut parts = version.trim().trim_start_matches('v').split('.');
+    let mut next = || parts.next().and_then(|part| part.parse::<u64>().ok()).ok_or_else(invalid);
+    let parsed = (next()?, next()?, next()?);
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(parsed)
+}
+
+pub(crate) fn schema_path(collection_name: &str) -> String {
+    format!("{}/{}", SCHEMAS_COLLECTION, collection_name)
+}
+
+pub(crate) fn versions_path(collection_name: &str) -> String {
+    format!("{}/{}", schema_path(collection_name), VERSIONS_SUBCOLLECTION)
+}
+
+pub(crate) fn version_path(collection_name: &str, version: &str) -> String {
+    format!("{}/{}", versions_path(collection_name), version)
+}
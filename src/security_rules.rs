@@ -43,6 +43,12 @@ pub enum Expression {
     Custom(String),
 }
 
+impl Default for SecurityRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SecurityRules {
     pub fn new() -> Self {
         Self {
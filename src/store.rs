@@ -0,0 +1,356 @@
+use crate::error::{FirebaseError, Result};
+use crate::explain::{ExplainOptions, ExplainedCount, ExplainedQuery};
+use crate::models::{FirestoreValue, firestore_fields_to_json};
+use crate::query::{Cursor, QueryBuilder, StructuredQuery};
+use async_trait::async_trait;
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A document as returned by a [`DocumentStore`]. The shape matches the
+/// Firestore REST `Document` resource so it can be (de)serialized directly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredDocument {
+    /// Full resource name, e.g. `projects/p/databases/(default)/documents/users/abc`.
+    pub name: String,
+    #[serde(default)]
+    pub fields: HashMap<String, FirestoreValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+}
+
+impl StoredDocument {
+    pub fn id(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    /// Path relative to the database root, e.g. `users/abc`.
+    pub fn path(&self) -> &str {
+        match self.name.find("/documents/") {
+            Some(pos) => &self.name[pos + "/documents/".len()..],
+            None => &self.name,
+        }
+    }
+
+    /// Path of the collection containing this document, e.g. `users`.
+    pub fn collection_path(&self) -> &str {
+        let path = self.path();
+        path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        firestore_fields_to_json(&self.fields)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DocumentPage {
+    pub documents: Vec<StoredDocument>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub page_size: Option<usize>,
+    pub page_token: Option<String>,
+}
+
+impl ListOptions {
+    pub fn page_size(page_size: usize) -> Self {
+        Self { page_size: Some(page_size), page_token: None }
+    }
+}
+
+/// How far back point-in-time recovery lets reads go.
+pub const PITR_WINDOW: chrono::Duration = chrono::Duration::days(7);
+/// Read times older than this must fall on a whole minute.
+pub const VERSION_RETENTION: chrono::Duration = chrono::Duration::hours(1);
+
+/// Consistency options shared by all read operations.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Read within this transaction (from [`DocumentStore::begin_transaction`]).
+    pub transaction: Option<String>,
+    /// Read the documents as they were at this RFC 3339 timestamp.
+    pub read_time: Option<String>,
+}
+
+impl ReadOptions {
+    pub fn in_transaction(transaction: impl Into<String>) -> Self {
+        Self { transaction: Some(transaction.into()), ..Self::default() }
+    }
+
+    /// Reads as of `read_time`, e.g. `2024-05-01T12:00:00Z`.
+    pub fn at(read_time: impl Into<String>) -> Self {
+        Self { read_time: Some(read_time.into()), ..Self::default() }
+    }
+
+    /// The parsed read time, checked against Firestore's rules: not in the
+    /// future, within the last 7 days, and on a whole minute if older than an hour.
+    pub fn read_time(&self) -> Result<Option<DateTime<Utc>>> {
+        let Some(read_time) = &self.read_time else {
+            return Ok(None);
+        };
+        if self.transaction.is_some() {
+            return Err(FirebaseError::ValidationError(
+                "A read cannot use both a transaction and a read time".to_string(),
+            ));
+        }
+        let time = DateTime::parse_from_rfc3339(read_time)
+            .map_err(|e| FirebaseError::ValidationError(format!("Invalid read time '{}': {}", read_time, e)))?
+            .with_timezone(&Utc);
+
+        let age = Utc::now() - time;
+        if age < chrono::Duration::zero() {
+            return Err(FirebaseError::ValidationError(format!("Read time {} is in the future", read_time)));
+        }
+        if age > PITR_WINDOW {
+            return Err(FirebaseError::ValidationError(format!(
+                "Read time {} is older than the 7 day point-in-time recovery window", read_time
+            )));
+        }
+        if age > VERSION_RETENTION && (time.second() != 0 || time.nanosecond() != 0) {
+            return Err(FirebaseError::ValidationError(format!(
+                "Read time {} is more than an hour old, so it must be a whole minute", read_time
+            )));
+        }
+        Ok(Some(time))
+    }
+}
+
+/// A single mutation applied by [`DocumentStore::commit`]. Paths are relative
+/// to the database root, e.g. `users/abc`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Write {
+    /// Creates the document, failing if it already exists.
+    Create { path: String, fields: HashMap<String, FirestoreValue> },
+    /// Creates the document or replaces all of its fields.
+    Set { path: String, fields: HashMap<String, FirestoreValue> },
+    /// Overwrites only the fields listed in `mask` on an existing document.
+    /// Paths in the mask that are absent from `fields` are deleted.
+    Update { path: String, fields: HashMap<String, FirestoreValue>, mask: Vec<String> },
+    Delete { path: String },
+}
+
+impl Write {
+    pub fn path(&self) -> &str {
+        match self {
+            Write::Create { path, .. }
+            | Write::Set { path, .. }
+            | Write::Update { path, .. }
+            | Write::Delete { path } => path,
+        }
+    }
+
+    /// `create`, `set`, `update` or `delete`.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Write::Create { .. } => "create",
+            Write::Set { .. } => "set",
+            Write::Update { .. } => "update",
+            Write::Delete { .. } => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteResult {
+    pub update_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommitResponse {
+    pub write_results: Vec<WriteResult>,
+    pub commit_time: Option<String>,
+}
+
+/// Storage operations used by the managers and the CLI. [`crate::FirebaseClient`]
+/// talks to Firestore over REST; [`crate::MemoryStore`] keeps everything in
+/// process for tests and offline use.
+///
+/// Collection and document paths are relative to the database root and may
+/// point into subcollections (`users/abc/posts`). Query and list-collection
+/// `parent` arguments are document paths, with `""` meaning the root.
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    /// Fetches a document, returning `None` if it does not exist.
+    async fn fetch(&self, path: &str, options: &ReadOptions) -> Result<Option<StoredDocument>>;
+
+    /// Creates a document, generating an ID when `doc_id` is `None`.
+    async fn insert(
+        &self,
+        collection: &str,
+        doc_id: Option<&str>,
+        fields: HashMap<String, FirestoreValue>,
+    ) -> Result<StoredDocument>;
+
+    /// Writes a document. With a `mask` only the listed fields are replaced,
+    /// otherwise the whole document is. Missing documents are created.
+    async fn patch(
+        &self,
+        path: &str,
+        fields: HashMap<String, FirestoreValue>,
+        mask: Option<&[String]>,
+    ) -> Result<StoredDocument>;
+
+    async fn remove(&self, path: &str) -> Result<()>;
+
+    async fn list_page(&self, collection: &str, list: &ListOptions, options: &ReadOptions) -> Result<DocumentPage>;
+
+    async fn run_query(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<Vec<StoredDocument>>;
+
+    /// Counts the documents matching `query` (honouring its `limit`).
+    async fn count(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<u64>;
+
+    /// Runs `query` with Query Explain, returning its plan and, when
+    /// analyzing, the documents and execution statistics.
+    async fn explain_query(
+        &self,
+        _parent: &str,
+        _query: &StructuredQuery,
+        _options: &ReadOptions,
+        _explain: &ExplainOptions,
+    ) -> Result<ExplainedQuery> {
+        Err(FirebaseError::DatabaseError("This store does not support query explain".to_string()))
+    }
+
+    /// Like [`DocumentStore::explain_query`] for a count aggregation.
+    async fn explain_count(
+        &self,
+        _parent: &str,
+        _query: &StructuredQuery,
+        _options: &ReadOptions,
+        _explain: &ExplainOptions,
+    ) -> Result<ExplainedCount> {
+        Err(FirebaseError::DatabaseError("This store does not support query explain".to_string()))
+    }
+
+    /// Split points dividing `query` (ordered by `__name__` only) into up to
+    /// `partition_count` ranges of similar size. Returns at most
+    /// `partition_count - 1` cursors, in order; none means one partition.
+    async fn partition_query(
+        &self,
+        _parent: &str,
+        _query: &StructuredQuery,
+        _partition_count: usize,
+        _options: &ReadOptions,
+    ) -> Result<Vec<Cursor>> {
+        Ok(Vec::new())
+    }
+
+    /// Fetches several documents at once; the result is in request order.
+    async fn batch_get(&self, paths: &[String], options: &ReadOptions) -> Result<Vec<Option<StoredDocument>>>;
+
+    /// Applies all writes atomically, inside `transaction` if given.
+    async fn commit(&self, writes: Vec<Write>, transaction: Option<&str>) -> Result<CommitResponse>;
+
+    /// Applies writes independently rather than atomically: each succeeds or
+    /// fails on its own, and the results come back in request order.
+    async fn batch_write(&self, writes: Vec<Write>) -> Result<Vec<Result<WriteResult>>> {
+        let mut results = Vec::with_capacity(writes.len());
+        for write in writes {
+            results.push(self.commit(vec![write], None).await
+                .map(|response| response.write_results.into_iter().next().unwrap_or_default()));
+        }
+        Ok(results)
+    }
+
+    async fn begin_transaction(&self, read_only: bool) -> Result<String>;
+
+    async fn rollback(&self, transaction: &str) -> Result<()>;
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>>;
+
+    /// How many requests bulk operations should keep in flight at once.
+    fn max_in_flight(&self) -> usize {
+        1
+    }
+
+    /// Counts the documents in a collection with an aggregation, which is billed
+    /// at one read per 1,000 documents rather than one per document.
+    async fn count_documents(&self, collection: &str) -> Result<u64> {
+        let path = normalize_path(collection);
+        let (parent, collection_id) = path.rsplit_once('/').unwrap_or(("", path));
+        self.count(parent, &QueryBuilder::new(collection_id).build(), &ReadOptions::default()).await
+    }
+
+    /// Reads every page of a collection.
+    async fn list_all(&self, collection: &str, options: &ReadOptions) -> Result<Vec<StoredDocument>> {
+        let mut documents = Vec::new();
+        let mut list = ListOptions::default();
+        loop {
+            let page = self.list_page(collection, &list, options).await?;
+            documents.extend(page.documents);
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => list.page_token = Some(token),
+                _ => break,
+            }
+        }
+        Ok(documents)
+    }
+}
+
+/// Buffers writes made inside a Firestore transaction and commits them
+/// together. Dropping it without calling [`Transaction::commit`] leaves the
+/// transaction to expire on the server; call [`Transaction::rollback`] to
+/// release it eagerly.
+pub struct Transaction<'a, S: DocumentStore + ?Sized> {
+    store: &'a S,
+    id: String,
+    writes: Vec<Write>,
+}
+
+impl<'a, S: DocumentStore + ?Sized> Transaction<'a, S> {
+    pub async fn begin(store: &'a S) -> Result<Self> {
+        let id = store.begin_transaction(false).await?;
+        Ok(Self { store, id, writes: Vec::new() })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub async fn get(&self, path: &str) -> Result<Option<StoredDocument>> {
+        self.store.fetch(path, &ReadOptions::in_transaction(self.id.clone())).await
+    }
+
+    pub async fn query(&self, parent: &str, query: &StructuredQuery) -> Result<Vec<StoredDocument>> {
+        self.store.run_query(parent, query, &ReadOptions::in_transaction(self.id.clone())).await
+    }
+
+    pub fn write(&mut self, write: Write) -> &mut Self {
+        self.writes.push(write);
+        self
+    }
+
+    pub fn set(&mut self, path: &str, fields: HashMap<String, FirestoreValue>) -> &mut Self {
+        self.write(Write::Set { path: path.to_string(), fields })
+    }
+
+    pub fn delete(&mut self, path: &str) -> &mut Self {
+        self.write(Write::Delete { path: path.to_string() })
+    }
+
+    pub async fn commit(self) -> Result<CommitResponse> {
+        self.store.commit(self.writes, Some(&self.id)).await
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        self.store.rollback(&self.id).await
+    }
+}
+
+pub(crate) fn document_path(collection: &str, doc_id: &str) -> String {
+    format!("{}/{}", normalize_path(collection), doc_id)
+}
+
+pub(crate) fn normalize_path(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+pub(crate) fn not_found(path: &str) -> FirebaseError {
+    FirebaseError::NotFound(format!("Document '{}' not found", path))
+}
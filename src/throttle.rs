@@ -0,0 +1,176 @@
+use crate::error::{FirebaseError, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// How fast a [`Throttle`] lets operations through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimit {
+    /// At most this many operations per second, with bursts of up to one second's worth.
+    PerSecond(f64),
+    /// Starts at `initial` operations per second and grows by `growth` (0.5 = 50%)
+    /// every `every`, up to `max` if given.
+    RampUp {
+        initial: f64,
+        growth: f64,
+        every: Duration,
+        max: Option<f64>,
+    },
+}
+
+impl RateLimit {
+    /// Firestore's "500/50/5" rule for new traffic: start at 500 operations per
+    /// second and increase by 50% every 5 minutes.
+    pub fn ramp_up() -> Self {
+        RateLimit::RampUp {
+            initial: 500.0,
+            growth: 0.5,
+            every: Duration::from_secs(5 * 60),
+            max: None,
+        }
+    }
+
+    /// Never allows more than `max` operations per second.
+    pub fn capped_at(self, max: f64) -> Self {
+        match self {
+            RateLimit::PerSecond(rate) => RateLimit::PerSecond(rate.min(max)),
+            RateLimit::RampUp { initial, growth, every, max: current } => RateLimit::RampUp {
+                initial: initial.min(max),
+                growth,
+                every,
+                max: Some(current.map_or(max, |current| current.min(max))),
+            },
+        }
+    }
+
+    /// Checks that every rate is finite and positive and that a ramp-up grows.
+    pub fn validate(&self) -> Result<()> {
+        let rates = match *self {
+            RateLimit::PerSecond(rate) => vec![("rate", rate)],
+            RateLimit::RampUp { initial, growth, every, max } => {
+                if !growth.is_finite() || growth < 0.0 {
+                    return Err(FirebaseError::ConfigError(format!("Ramp-up growth must be 0 or more, got {}", growth)));
+                }
+                if every.is_zero() {
+                    return Err(FirebaseError::ConfigError("Ramp-up interval must be greater than 0".to_string()));
+                }
+                std::iter::once(("initial rate", initial)).chain(max.map(|max| ("maximum rate", max))).collect()
+            }
+        };
+        match rates.into_iter().find(|(_, rate)| !rate.is_finite() || *rate <= 0.0) {
+            Some((name, rate)) => Err(FirebaseError::ConfigError(format!("The {} must be a positive number, got {}", name, rate))),
+            None => Ok(()),
+        }
+    }
+
+    /// Operations per second allowed `elapsed` after the limiter started.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        match *self {
+            RateLimit::PerSecond(rate) => rate,
+            RateLimit::RampUp { initial, growth, every, max } => {
+                let steps = (elapsed.as_secs_f64() / every.as_secs_f64().max(f64::EPSILON)).floor();
+                let rate = initial * (1.0 + growth).powf(steps);
+                max.map_or(rate, |max| rate.min(max))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Client-side flow control: a token bucket limiting operations per second and
+/// a semaphore limiting requests in flight. Either part is optional.
+///
+/// A commit costs one token per write, so batches are limited by the documents
+/// they touch rather than by request count.
+#[derive(Debug)]
+pub struct Throttle {
+    rate_limit: Option<RateLimit>,
+    bucket: Mutex<Bucket>,
+    started: Instant,
+    in_flight: Option<Arc<Semaphore>>,
+    max_in_flight: Option<usize>,
+}
+
+/// Held while a request is in flight; dropping it frees the concurrency slot.
+#[derive(Debug)]
+pub struct ThrottlePermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Throttle {
+    /// Fails with a `ConfigError` if the rate limit doesn't [validate](RateLimit::validate).
+    pub fn new(rate_limit: Option<RateLimit>, max_in_flight: Option<usize>) -> Result<Self> {
+        if let Some(limit) = &rate_limit {
+            limit.validate()?;
+        }
+        let now = Instant::now();
+        let max_in_flight = max_in_flight.map(|n| n.max(1));
+        Ok(Self {
+            rate_limit,
+            bucket: Mutex::new(Bucket {
+                tokens: rate_limit.map_or(0.0, |limit| limit.rate_at(Duration::ZERO)),
+                last_refill: now,
+            }),
+            started: now,
+            in_flight: max_in_flight.map(|n| Arc::new(Semaphore::new(n))),
+            max_in_flight,
+        })
+    }
+
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    pub fn max_in_flight(&self) -> Option<usize> {
+        self.max_in_flight
+    }
+
+    /// Operations per second allowed right now.
+    pub fn current_rate(&self) -> Option<f64> {
+        self.rate_limit.map(|limit| limit.rate_at(self.started.elapsed()))
+    }
+
+    /// Waits for a concurrency slot, then for `cost` tokens.
+    pub async fn acquire(&self, cost: u32) -> ThrottlePermit {
+        // Take the slot first so queued requests don't hold tokens they can't use
+        let permit = match &self.in_flight {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        if let Some(limit) = self.rate_limit {
+            let cost = f64::from(cost.max(1));
+            loop {
+                let wait = {
+                    let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+                    let now = Instant::now();
+                    let rate = limit.rate_at(now - self.started).max(f64::MIN_POSITIVE);
+                    let refill = (now - bucket.last_refill).as_secs_f64() * rate;
+                    bucket.tokens = (bucket.tokens + refill).min(rate);
+                    bucket.last_refill = now;
+
+                    // Operations bigger than the bucket go through once it is full,
+                    // leaving it in debt
+                    if bucket.tokens >= cost.min(rate) {
+                        bucket.tokens -= cost;
+                        None
+                    } else {
+                        Some(Duration::from_secs_f64((cost.min(rate) - bucket.tokens) / rate))
+                    }
+                };
+                match wait {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => break,
+                }
+            }
+        }
+
+        ThrottlePermit { _permit: permit }
+    }
+}
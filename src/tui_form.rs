@@ -15,6 +15,9 @@ use ratatui::{
 use serde_json::{Map, Value};
 use crate::collections::{CollectionSchema, AutoFieldType};
 use crate::error::FirebaseError;
+use crate::models::json_value_to_firestore;
+use crate::schema::{ValidationRule, ValidationRuleType};
+use crate::validation::{Validator, ValidatorRegistry};
 
 #[derive(Debug, Clone)]
 pub struct FormField {
@@ -39,6 +42,8 @@ pub struct TuiForm {
     button_selected: bool,  // Track if a button is selected
     selected_button: usize,  // 0 = Add/Submit, 1 = Cancel
     schema: Option<CollectionSchema>,  // Store schema for automatic field generation
+    custom_rules: Vec<ValidationRule>,
+    validators: ValidatorRegistry,
 }
 
 impl TuiForm {
@@ -56,9 +61,21 @@ impl TuiForm {
             button_selected: false,
             selected_button: 0,
             schema: None,
+            custom_rules: Vec::new(),
+            validators: ValidatorRegistry::new(),
         }
     }
 
+    /// Checks `Custom` rules as fields are entered. Only synchronous validators
+    /// run here; async ones are left to the write.
+    pub fn with_custom_rules(mut self, rules: Vec<ValidationRule>, validators: ValidatorRegistry) -> Self {
+        self.custom_rules = rules.into_iter()
+            .filter(|rule| matches!(rule.rule, ValidationRuleType::Custom(_)))
+            .collect();
+        self.validators = validators;
+        self
+    }
+
     pub fn from_schema(collection_name: &str, schema: &CollectionSchema) -> Self {
         let mut form = Self::new(format!("Create Document in '{}'", collection_name));
         form.schema = Some(schema.clone());
@@ -175,6 +192,7 @@ impl TuiForm {
         Ok(Value::Object(map))
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
     fn validate_and_set_field(&mut self, index: usize, value: &str) -> Result<(), String> {
         if index >= self.fields.len() {
             return Ok(());
@@ -196,41 +214,55 @@ impl TuiForm {
         // Validate based on field type
         match field.field_type.as_str() {
             "integer" => {
-                if let Err(_) = value.parse::<i64>() {
-                    return Err(format!("Invalid integer. Example: 42, -10, 0"));
+                if value.parse::<i64>().is_err() {
+                    return Err("Invalid integer. Example: 42, -10, 0".to_string());
                 }
             }
             "number" => {
-                if let Err(_) = value.parse::<f64>() {
-                    return Err(format!("Invalid number. Example: 3.14, -0.5, 42"));
+                if value.parse::<f64>().is_err() {
+                    return Err("Invalid number. Example: 3.14, -0.5, 42".to_string());
                 }
             }
             "boolean" => {
                 let lower = value.to_lowercase();
                 if !["true", "false", "yes", "no", "1", "0"].contains(&lower.as_str()) {
-                    return Err(format!("Invalid boolean. Use: true, false, yes, no, 1, or 0"));
+                    return Err("Invalid boolean. Use: true, false, yes, no, 1, or 0".to_string());
                 }
             }
             "array" => {
                 if !value.trim().is_empty() && serde_json::from_str::<Value>(value).is_err() {
-                    return Err(format!("Invalid JSON array. Example: [1, 2, 3] or [\"a\", \"b\"]"));
+                    return Err("Invalid JSON array. Example: [1, 2, 3] or [\"a\", \"b\"]".to_string());
                 }
             }
             "object" => {
                 if !value.trim().is_empty() && serde_json::from_str::<Value>(value).is_err() {
-                    return Err(format!("Invalid JSON object. Example: {{\"key\": \"value\"}}"));
+                    return Err("Invalid JSON object. Example: {\"key\": \"value\"}".to_string());
                 }
             }
             "timestamp" => {
                 if !value.trim().is_empty() && value.to_lowercase() != "now" {
                     if chrono::DateTime::parse_from_rfc3339(value).is_err() {
-                        return Err(format!("Invalid timestamp. Use ISO format (2024-01-01T12:00:00Z) or 'now'"));
+                        return Err("Invalid timestamp. Use ISO format (2024-01-01T12:00:00Z) or 'now'".to_string());
                     }
                 }
             }
             _ => {} // String type, no validation needed
         }
         
+        for rule in self.custom_rules.iter().filter(|rule| rule.field == field.name) {
+            let ValidationRuleType::Custom(name) = &rule.rule else {
+                continue;
+            };
+            match self.validators.get(name) {
+                Some(Validator::Sync(check)) => {
+                    let parsed = parse_field_value(value, &field.field_type).map_err(|e| e.to_string())?;
+                    check(&json_value_to_firestore(parsed)).map_err(|message| format!("'{}' {}", field.name, message))?;
+                }
+                Some(Validator::Async(_)) => {}
+                None => return Err(format!("No validator registered for custom rule '{}'", name)),
+            }
+        }
+        
         self.fields[index].value = value.to_string();
         Ok(())
     }
@@ -269,6 +301,7 @@ impl TuiForm {
         result
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
     fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<Option<Value>, FirebaseError> {
         // Start in editing mode for the first field
         let mut editing_field = true;
@@ -0,0 +1,306 @@
+use crate::error::{FirebaseError, Result};
+use crate::explain::{ExplainOptions, ExplainedCount, ExplainedQuery};
+use crate::firebase::FirebaseClient;
+use crate::models::{FirestoreValue, ToFirestore};
+use crate::query::{Cursor, StructuredQuery};
+use crate::schema::{Collection, Field, FieldType, SchemaManager};
+use crate::store::{CommitResponse, DocumentPage, DocumentStore, ListOptions, ReadOptions, StoredDocument, Write, WriteResult, normalize_path};
+use crate::validation::{ValidationReport, resolve_path};
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// A [`DocumentStore`] that enforces the schemas of a [`SchemaManager`] on
+/// every write. Creates and full replacements get defaults and auto fields
+/// filled in and are validated in full; masked updates only have the fields
+/// they write checked (and `UpdatedAt` fields refreshed), and may not mask
+/// away a required field. Invalid writes fail
+/// with [`FirebaseError::SchemaViolation`] before reaching the store.
+/// Collections without a schema pass through unchanged.
+#[derive(Debug, Clone)]
+pub struct ValidatedClient<S: DocumentStore = FirebaseClient> {
+    schemas: SchemaManager<S>,
+    strict: bool,
+}
+
+impl<S: DocumentStore> ValidatedClient<S> {
+    pub fn new(schemas: SchemaManager<S>) -> Self {
+        Self { schemas, strict: false }
+    }
+
+    /// Rejects fields the schema does not declare.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn schema_manager(&self) -> &SchemaManager<S> {
+        &self.schemas
+    }
+
+    /// The wrapped store, for writes that should skip validation.
+    pub fn inner(&self) -> &S {
+        self.schemas.client()
+    }
+
+    /// Creates a document from a model, see [`DocumentStore::insert`].
+    pub async fn create_item<T: ToFirestore>(&self, collection: &str, doc_id: Option<&str>, item: &T) -> Result<StoredDocument> {
+        self.insert(collection, doc_id, item.to_firestore()).await
+    }
+
+    /// Applies defaults and auto fields for `collection` and validates the
+    /// result without writing it. `partial` checks only the fields present,
+    /// as for a masked update.
+    pub async fn prepare(&self, collection: &str, fields: HashMap<String, FirestoreValue>, partial: bool) -> Result<HashMap<String, FirestoreValue>> {
+        self.prepare_fields(collection, fields, partial.then_some(&[])).await
+    }
+
+    /// `mask` is set for masked updates, which only have the fields present
+    /// checked. A masked required field without a value would be deleted, so
+    /// that is a violation too.
+    async fn prepare_fields(
+        &self,
+        collection: &str,
+        mut fields: HashMap<String, FirestoreValue>,
+        mask: Option<&[String]>,
+    ) -> Result<HashMap<String, FirestoreValue>> {
+        let Some(schema) = self.schemas.get_collection(collection_id(collection)) else {
+            return Ok(fields);
+        };
+        let partial = mask.is_some();
+        fill_fields(schema, &mut fields, partial);
+        coerce_types(&schema.fields, &mut fields);
+        let mut report = self.check(schema, &fields, partial).await?;
+        for path in mask.unwrap_or_default() {
+            if !resolve_path(&fields, path).is_empty() {
+                continue;
+            }
+            for field in schema.fields.iter().filter(|field| field.required && clears(path, &field.name)) {
+                report.push(&field.name, "required", None, format!("Required field '{}' can't be removed by the update mask", field.name));
+            }
+        }
+        report.into_result()?;
+        Ok(fields)
+    }
+
+    /// Prepares a masked update and adds the fields it writes, such as
+    /// refreshed `UpdatedAt` fields, to its mask.
+    async fn prepare_update(
+        &self,
+        collection: &str,
+        fields: HashMap<String, FirestoreValue>,
+        mask: &[String],
+    ) -> Result<(HashMap<String, FirestoreValue>, Vec<String>)> {
+        let fields = self.prepare_fields(collection, fields, Some(mask)).await?;
+        let mut mask = mask.to_vec();
+        for name in fields.keys() {
+            if !mask.contains(name) {
+                mask.push(name.clone());
+            }
+        }
+        Ok((fields, mask))
+    }
+
+    async fn check(&self, schema: &Collection, fields: &HashMap<String, FirestoreValue>, partial: bool) -> Result<ValidationReport> {
+        let field_defs: Vec<Field> = if partial {
+            // Absent fields are left as they are, so they can't be missing
+            schema.fields.iter().map(|field| Field { required: false, ..field.clone() }).collect()
+        } else {
+            schema.fields.clone()
+        };
+        let mut report = self.schemas.validate_rules_async(&field_defs, &schema.validation_rules, fields).await?;
+
+        if self.strict {
+            let mut unknown: Vec<&String> = fields.keys()
+                .filter(|name| !schema.fields.iter().any(|field| top_level(&field.name) == name.as_str()))
+                .collect();
+            unknown.sort();
+            for name in unknown {
+                report.push(name, "strict", fields.get(name), format!("Field '{}' is not in the schema for {}", name, schema.name));
+            }
+        }
+        Ok(report)
+    }
+
+    async fn prepare_write(&self, write: Write) -> Result<Write> {
+        Ok(match write {
+            Write::Create { path, fields } => {
+                let fields = self.prepare(parent_collection(&path), fields, false).await?;
+                Write::Create { path, fields }
+            }
+            Write::Set { path, fields } => {
+                let fields = self.prepare(parent_collection(&path), fields, false).await?;
+                Write::Set { path, fields }
+            }
+            Write::Update { path, fields, mask } => {
+                let (fields, mask) = self.prepare_update(parent_collection(&path), fields, &mask).await?;
+                Write::Update { path, fields, mask }
+            }
+            delete @ Write::Delete { .. } => delete,
+        })
+    }
+}
+
+#[async_trait]
+impl<S: DocumentStore> DocumentStore for ValidatedClient<S> {
+    async fn fetch(&self, path: &str, options: &ReadOptions) -> Result<Option<StoredDocument>> {
+        self.inner().fetch(path, options).await
+    }
+
+    async fn insert(&self, collection: &str, doc_id: Option<&str>, fields: HashMap<String, FirestoreValue>) -> Result<StoredDocument> {
+        let fields = self.prepare(collection, fields, false).await?;
+        self.inner().insert(collection, doc_id, fields).await
+    }
+
+    async fn patch(&self, path: &str, fields: HashMap<String, FirestoreValue>, mask: Option<&[String]>) -> Result<StoredDocument> {
+        let collection = parent_collection(path);
+        match mask {
+            Some(mask) => {
+                let (fields, mask) = self.prepare_update(collection, fields, mask).await?;
+                self.inner().patch(path, fields, Some(&mask)).await
+            }
+            None => {
+                let fields = self.prepare(collection, fields, false).await?;
+                self.inner().patch(path, fields, None).await
+            }
+        }
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        self.inner().remove(path).await
+    }
+
+    async fn list_page(&self, collection: &str, list: &ListOptions, options: &ReadOptions) -> Result<DocumentPage> {
+        self.inner().list_page(collection, list, options).await
+    }
+
+    async fn run_query(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<Vec<StoredDocument>> {
+        self.inner().run_query(parent, query, options).await
+    }
+
+    async fn count(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions) -> Result<u64> {
+        self.inner().count(parent, query, options).await
+    }
+
+    async fn explain_query(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions, explain: &ExplainOptions) -> Result<ExplainedQuery> {
+        self.inner().explain_query(parent, query, options, explain).await
+    }
+
+    async fn explain_count(&self, parent: &str, query: &StructuredQuery, options: &ReadOptions, explain: &ExplainOptions) -> Result<ExplainedCount> {
+        self.inner().explain_count(parent, query, options, explain).await
+    }
+
+    async fn partition_query(&self, parent: &str, query: &StructuredQuery, partition_count: usize, options: &ReadOptions) -> Result<Vec<Cursor>> {
+        self.inner().partition_query(parent, query, partition_count, options).await
+    }
+
+    async fn batch_get(&self, paths: &[String], options: &ReadOptions) -> Result<Vec<Option<StoredDocument>>> {
+        self.inner().batch_get(paths, options).await
+    }
+
+    async fn commit(&self, writes: Vec<Write>, transaction: Option<&str>) -> Result<CommitResponse> {
+        let mut prepared = Vec::with_capacity(writes.len());
+        for write in writes {
+            prepared.push(self.prepare_write(write).await?);
+        }
+        self.inner().commit(prepared, transaction).await
+    }
+
+    /// Writes that fail validation fail on their own; the rest are sent.
+    async fn batch_write(&self, writes: Vec<Write>) -> Result<Vec<Result<WriteResult>>> {
+        let mut results: Vec<Option<Result<WriteResult>>> = Vec::with_capacity(writes.len());
+        let mut valid = Vec::new();
+        for write in writes {
+            match self.prepare_write(write).await {
+                Ok(write) => {
+                    valid.push(write);
+                    results.push(None);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+        let mut written = self.inner().batch_write(valid).await?.into_iter();
+        Ok(results.into_iter()
+            .map(|result| result.unwrap_or_else(|| written.next()
+                .unwrap_or_else(|| Err(FirebaseError::DatabaseError("Missing batch write result".to_string())))))
+            .collect())
+    }
+
+    async fn begin_transaction(&self, read_only: bool) -> Result<String> {
+        self.inner().begin_transaction(read_only).await
+    }
+
+    async fn rollback(&self, transaction: &str) -> Result<()> {
+        self.inner().rollback(transaction).await
+    }
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>> {
+        self.inner().list_collection_ids(parent).await
+    }
+
+    fn max_in_flight(&self) -> usize {
+        self.inner().max_in_flight()
+    }
+}
+
+/// Defaults and auto fields for missing top-level fields. Partial writes
+/// only get fields that change on every update.
+fn fill_fields(schema: &Collection, fields: &mut HashMap<String, FirestoreValue>, partial: bool) {
+    for field in &schema.fields {
+        if field.name.contains(['.', '[']) {
+            continue;
+        }
+        if let Some(auto) = &field.auto_field {
+            if auto.on_update() || (!partial && !fields.contains_key(&field.name)) {
+                fields.insert(field.name.clone(), auto.generate_firestore_value());
+            }
+        } else if let Some(default) = &field.default_value
+            && !partial
+            && !fields.contains_key(&field.name)
+        {
+            fields.insert(field.name.clone(), default.clone());
+        }
+    }
+}
+
+/// Converts values JSON can't express directly to the declared type: RFC 3339
+/// strings to timestamps and integers to doubles.
+fn coerce_types(field_defs: &[Field], fields: &mut HashMap<String, FirestoreValue>) {
+    for field in field_defs {
+        let Some(value) = fields.get_mut(&field.name) else {
+            continue;
+        };
+        match (&field.field_type, &*value) {
+            (FieldType::Timestamp, FirestoreValue::StringValue(s)) if chrono::DateTime::parse_from_rfc3339(s).is_ok() => {
+                *value = FirestoreValue::TimestampValue(s.clone());
+            }
+            (FieldType::Double, FirestoreValue::IntegerValue(i)) => {
+                if let Ok(n) = i.parse::<f64>() {
+                    *value = FirestoreValue::DoubleValue(n);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether an update masking `mask_path` without a value removes the field
+/// at `field_path`, i.e. the field is at or under that path.
+fn clears(mask_path: &str, field_path: &str) -> bool {
+    field_path.strip_prefix(mask_path).is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+}
+
+fn top_level(path: &str) -> &str {
+    path.split(['.', '[']).next().unwrap_or(path)
+}
+
+/// The collection ID schemas are keyed by, e.g. `posts` for `users/abc/posts`.
+fn collection_id(collection: &str) -> &str {
+    let collection = normalize_path(collection);
+    collection.rsplit('/').next().unwrap_or(collection)
+}
+
+/// The collection a document path is in, e.g. `users/abc/posts` for `users/abc/posts/xyz`.
+fn parent_collection(path: &str) -> &str {
+    let path = normalize_path(path);
+    path.rsplit_once('/').map_or(path, |(collection, _)| collection)
+}
@@ -0,0 +1,314 @@
+use crate::error::{FirebaseError, Result};
+use crate::models::FirestoreValue;
+use async_trait::async_trait;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Marks "every element" in a rule path, e.g. `tags[]` or `items[].price`.
+const EACH_ELEMENT: &str = "[]";
+
+/// One rule a document broke.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// Concrete path of the offending value, e.g. `address.zip` or `tags[2]`.
+    pub field: String,
+    /// The rule that failed, e.g. `required`, `type(String)` or `min_length(3)`.
+    pub rule: String,
+    /// The value found, or `None` when it was missing.
+    pub actual: Option<FirestoreValue>,
+    pub message: String,
+}
+
+/// Every violation found while validating a document against a schema.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn push(&mut self, field: impl Into<String>, rule: impl Into<String>, actual: Option<&FirestoreValue>, message: impl Into<String>) {
+        self.violations.push(Violation {
+            field: field.into(),
+            rule: rule.into(),
+            actual: actual.cloned(),
+            message: message.into(),
+        });
+    }
+
+    /// Violations of one field, by concrete path.
+    pub fn for_field<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a Violation> {
+        self.violations.iter().filter(move |violation| violation.field == field)
+    }
+
+    /// `Ok` when valid, otherwise a `SchemaViolation` holding the report.
+    pub fn into_result(self) -> Result<()> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(FirebaseError::SchemaViolation(self))
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<&str> = self.violations.iter().map(|violation| violation.message.as_str()).collect();
+        match messages.len() {
+            0 => write!(f, "valid"),
+            1 => write!(f, "{}", messages[0]),
+            n => write!(f, "{} validation errors: {}", n, messages.join("; ")),
+        }
+    }
+}
+
+/// Compiled `Regex` rules, shared by clones so each pattern is compiled once.
+#[derive(Debug, Clone, Default)]
+pub struct RegexCache {
+    compiled: Arc<Mutex<HashMap<String, Regex>>>,
+}
+
+impl RegexCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The compiled pattern; an invalid pattern is a schema problem, so it
+    /// fails with `ConfigError`.
+    pub fn get(&self, pattern: &str) -> Result<Regex> {
+        let mut compiled = self.compiled.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(regex) = compiled.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern)
+            .map_err(|e| FirebaseError::ConfigError(format!("Invalid regex '{}': {}", pattern, e)))?;
+        compiled.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    pub fn len(&self) -> usize {
+        self.compiled.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Outcome of a custom validator: `Err` carries the violation message.
+pub type CheckResult = std::result::Result<(), String>;
+
+/// A validator for [`ValidationRuleType::Custom`](crate::schema::ValidationRuleType::Custom)
+/// rules that needs to await something, e.g. a lookup of a referenced document.
+#[async_trait]
+pub trait CustomValidator: Send + Sync {
+    async fn validate(&self, value: &FirestoreValue) -> CheckResult;
+}
+
+type SyncCheck = dyn Fn(&FirestoreValue) -> CheckResult + Send + Sync;
+
+/// A registered validator, either a plain function or an async one.
+#[derive(Clone)]
+pub enum Validator {
+    Sync(Arc<SyncCheck>),
+    Async(Arc<dyn CustomValidator>),
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Validator::Sync(_) => write!(f, "Validator::Sync"),
+            Validator::Async(_) => write!(f, "Validator::Async"),
+        }
+    }
+}
+
+impl Validator {
+    pub async fn validate(&self, value: &FirestoreValue) -> CheckResult {
+        match self {
+            Validator::Sync(check) => check(value),
+            Validator::Async(validator) => validator.validate(value).await,
+        }
+    }
+}
+
+struct AsyncFn<F>(F);
+
+#[async_trait]
+impl<F, Fut> CustomValidator for AsyncFn<F>
+where
+    F: Fn(FirestoreValue) -> Fut + Send + Sync,
+    Fut: Future<Output = CheckResult> + Send,
+{
+    async fn validate(&self, value: &FirestoreValue) -> CheckResult {
+        (self.0)(value.clone()).await
+    }
+}
+
+/// Named validators that `Custom` rules refer to, e.g. `Custom("sku_format")`.
+/// Registering on a clone does not change the original.
+#[derive(Debug, Clone, Default)]
+pub struct ValidatorRegistry {
+    validators: BTreeMap<String, Validator>,
+}
+
+impl ValidatorRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the built-in validators: `non_blank`, `uuid`, `slug`
+    /// and `iso_date` (`YYYY-MM-DD`).
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register_fn("non_blank", |value| match value {
+            FirestoreValue::StringValue(s) if s.trim().is_empty() => Err("must not be blank".to_string()),
+            _ => Ok(()),
+        });
+        registry.register_fn("uuid", |value| match_pattern(value, r"^(?i)[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$", "must be a UUID"));
+        registry.register_fn("slug", |value| match_pattern(value, r"^[a-z0-9]+(?:-[a-z0-9]+)*$", "must be a lowercase slug such as 'my-post-1'"));
+        registry.register_fn("iso_date", |value| match value {
+            FirestoreValue::StringValue(s) if chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() => Ok(()),
+            _ => Err("must be a date in YYYY-MM-DD format".to_string()),
+        });
+        registry
+    }
+
+    /// Registers a synchronous check, replacing any validator of that name.
+    pub fn register_fn<F>(&mut self, name: impl Into<String>, check: F) -> &mut Self
+    where
+        F: Fn(&FirestoreValue) -> CheckResult + Send + Sync + 'static,
+    {
+        self.validators.insert(name.into(), Validator::Sync(Arc::new(check)));
+        self
+    }
+
+    /// Registers an async check from a closure returning a future.
+    pub fn register_async_fn<F, Fut>(&mut self, name: impl Into<String>, check: F) -> &mut Self
+    where
+        F: Fn(FirestoreValue) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CheckResult> + Send + 'static,
+    {
+        self.register(name, AsyncFn(check))
+    }
+
+    /// Registers a [`CustomValidator`] trait object.
+    pub fn register(&mut self, name: impl Into<String>, validator: impl CustomValidator + 'static) -> &mut Self {
+        self.validators.insert(name.into(), Validator::Async(Arc::new(validator)));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Validator> {
+        self.validators.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.validators.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.validators.keys().map(String::as_str)
+    }
+}
+
+fn match_pattern(value: &FirestoreValue, pattern: &str, message: &str) -> CheckResult {
+    static PATTERNS: OnceLock<RegexCache> = OnceLock::new();
+    let regex = PATTERNS.get_or_init(RegexCache::new).get(pattern).map_err(|e| e.to_string())?;
+    match value {
+        FirestoreValue::StringValue(s) if regex.is_match(s) => Ok(()),
+        _ => Err(message.to_string()),
+    }
+}
+
+/// The values at a rule path, with their concrete paths. Dots step into maps
+/// and a `[]` suffix fans out over array elements, so `items[].price` yields
+/// `items[0].price`, `items[1].price` and so on. Missing values are skipped.
+pub fn resolve_path<'a>(fields: &'a HashMap<String, FirestoreValue>, path: &str) -> Vec<(String, &'a FirestoreValue)> {
+    let mut segments = path.split('.');
+    let Some(first) = segments.next() else {
+        return Vec::new();
+    };
+    let mut current: Vec<(String, &FirestoreValue)> = Vec::new();
+    let (name, each) = split_segment(first);
+    if let Some(value) = fields.get(name) {
+        current.push((name.to_string(), value));
+    }
+    if each {
+        current = elements(current);
+    }
+
+    for segment in segments {
+        let (name, each) = split_segment(segment);
+        current = current.into_iter()
+            .filter_map(|(prefix, value)| match value {
+                FirestoreValue::MapValue { fields } => fields.get(name).map(|value| (format!("{}.{}", prefix, name), value)),
+                _ => None,
+            })
+            .collect();
+        if each {
+            current = elements(current);
+        }
+    }
+    current
+}
+
+/// Concrete paths where a required `path` is absent. For `items[].sku` that
+/// is every element of `items` without a `sku`.
+pub fn missing_paths(fields: &HashMap<String, FirestoreValue>, path: &str) -> Vec<String> {
+    let path = path.strip_suffix(EACH_ELEMENT).unwrap_or(path);
+    match path.rsplit_once('.') {
+        None => if fields.contains_key(path) { Vec::new() } else { vec![path.to_string()] },
+        Some((parent, name)) => resolve_path(fields, parent).into_iter()
+            .filter_map(|(prefix, value)| match value {
+                FirestoreValue::MapValue { fields } if !fields.contains_key(name) => Some(format!("{}.{}", prefix, name)),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+fn split_segment(segment: &str) -> (&str, bool) {
+    match segment.strip_suffix(EACH_ELEMENT) {
+        Some(name) => (name, true),
+        None => (segment, false),
+    }
+}
+
+fn elements(values: Vec<(String, &FirestoreValue)>) -> Vec<(String, &FirestoreValue)> {
+    values.into_iter()
+        .flat_map(|(prefix, value)| match value {
+            FirestoreValue::ArrayValue { values } => values.iter()
+                .enumerate()
+                .map(|(i, value)| (format!("{}[{}]", prefix, i), value))
+                .collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// The kind of a value, as used in violation messages.
+pub(crate) fn type_name(value: &FirestoreValue) -> &'static str {
+    match value {
+        FirestoreValue::StringValue(_) => "string",
+        FirestoreValue::IntegerValue(_) => "integer",
+        FirestoreValue::DoubleValue(_) => "double",
+        FirestoreValue::BooleanValue(_) => "boolean",
+        FirestoreValue::TimestampValue(_) => "timestamp",
+        FirestoreValue::MapValue { .. } => "map",
+        FirestoreValue::ArrayValue { .. } => "array",
+        FirestoreValue::NullValue(_) => "null",
+        FirestoreValue::ReferenceValue(_) => "reference",
+        FirestoreValue::BytesValue(_) => "bytes",
+        FirestoreValue::GeoPointValue { .. } => "geopoint",
+        FirestoreValue::VectorValue(_) => "vector",
+        FirestoreValue::Unknown => "unknown",
+    }
+}
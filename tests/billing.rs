@@ -0,0 +1,68 @@
+mod common;
+
+use firebase_db::{BillableOps, CallSiteCost, DocumentStore, ListOptions, QueryBuilder, ReadOptions, with_call_site};
+
+fn ops(reads: u64, index_entry_reads: u64) -> BillableOps {
+    BillableOps { reads, index_entry_reads, ..BillableOps::default() }
+}
+
+fn entry(call_site: &str, operation: &str, collection: &str, ops: BillableOps) -> CallSiteCost {
+    CallSiteCost { call_site: call_site.to_string(), operation: operation.to_string(), collection: collection.to_string(), ops }
+}
+
+#[tokio::test]
+async fn reads_are_attributed_to_the_enclosing_call_site() {
+    let (client, store) = common::mock_client().await;
+    common::seed(&store, "users", 5).await;
+    let query = QueryBuilder::new("users").build();
+
+    with_call_site("report", async {
+        client.list_page("users", &ListOptions::default(), &ReadOptions::default()).await.unwrap();
+        client.run_query("", &query, &ReadOptions::default()).await.unwrap();
+        assert_eq!(client.count("", &query, &ReadOptions::default()).await.unwrap(), 5);
+        // The innermost call site wins
+        with_call_site("report lookup", client.fetch("users/000", &ReadOptions::default())).await.unwrap();
+    }).await;
+    client.fetch("users/001", &ReadOptions::default()).await.unwrap();
+
+    assert_eq!(client.costs().by_call_site(), vec![
+        entry("get", "get", "users", ops(1, 0)),
+        entry("report", "aggregate", "users", ops(1, 5)),
+        entry("report", "list", "users", ops(5, 0)),
+        entry("report", "query", "users", ops(5, 0)),
+        entry("report lookup", "get", "users", ops(1, 0)),
+    ]);
+    assert_eq!(client.costs().totals(), ops(13, 5));
+
+    client.costs().reset();
+    assert!(client.costs().totals().is_empty());
+}
+
+#[tokio::test]
+async fn empty_results_still_cost_one_read() {
+    let (client, _store) = common::mock_client().await;
+    let query = QueryBuilder::new("nothing").build();
+
+    assert!(client.run_query("", &query, &ReadOptions::default()).await.unwrap().is_empty());
+    assert_eq!(client.count("", &query, &ReadOptions::default()).await.unwrap(), 0);
+    assert_eq!(client.costs().totals(), ops(2, 0));
+}
+
+#[tokio::test]
+async fn counts_bill_one_read_per_thousand_index_entries() {
+    let (client, store) = common::mock_client().await;
+    common::seed(&store, "users", 1001).await;
+    let query = QueryBuilder::new("users").build();
+
+    assert_eq!(client.count("", &query, &ReadOptions::default()).await.unwrap(), 1001);
+    assert_eq!(client.costs().totals(), ops(2, 1001));
+}
+
+#[test]
+fn aggregations_bill_one_read_per_thousand_index_entries() {
+    assert_eq!(BillableOps::aggregation(0), ops(1, 0));
+    assert_eq!(BillableOps::aggregation(1000), ops(1, 1000));
+    assert_eq!(BillableOps::aggregation(1001), ops(2, 1001));
+    assert_eq!(BillableOps::query(0), ops(1, 0));
+    assert_eq!(BillableOps::query(7), ops(7, 0));
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::PROJECT_ID;
+use firebase_db::{BulkWriter, FaultConfig, FirebaseClient, FirebaseError, MemoryStore, MockServer, RpcCode};
+use std::collections::HashMap;
+use std::time::Duration;
+
+async fn faulty_client(faults: FaultConfig) -> (FirebaseClient, MemoryStore) {
+    let store = MemoryStore::with_project(PROJECT_ID);
+    let addr = MockServer::with_store(store.clone()).faults(faults).spawn("127.0.0.1:0").await.unwrap();
+    let client = FirebaseClient::builder(PROJECT_ID, "test-key").emulator_host(addr.to_string()).build().unwrap();
+    (client, store)
+}
+
+fn writer(client: FirebaseClient) -> BulkWriter<FirebaseClient> {
+    BulkWriter::new(client).max_retries(3).initial_backoff(Duration::from_millis(1))
+}
+
+#[tokio::test]
+async fn http_errors_carry_their_status() {
+    let (client, _store) = faulty_client(FaultConfig { fail_first: 1, error_status: 503, ..FaultConfig::default() }).await;
+    match client.get_document("users", "a").await {
+        Err(FirebaseError::ApiError(status)) => {
+            assert_eq!(status.code, RpcCode::Unavailable);
+            assert_eq!(status.http_status, Some(503));
+        }
+        other => panic!("expected an API error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn unavailable_batches_are_retried() {
+    let (client, _store) = faulty_client(FaultConfig { fail_first: 2, error_status: 503, ..FaultConfig::default() }).await;
+    let mut writer = writer(client);
+    writer.create("users", Some("a"), HashMap::new()).await;
+    let report = writer.close().await;
+    assert!(report.is_success(), "{:?}", report.failures);
+    assert_eq!(report.retried, 1);
+}
+
+#[tokio::test]
+async fn invalid_requests_are_not_retried() {
+    let (client, _store) = faulty_client(FaultConfig { fail_first: 1, error_status: 400, ..FaultConfig::default() }).await;
+    let mut writer = writer(client);
+    writer.create("users", Some("a"), HashMap::new()).await;
+    let report = writer.close().await;
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].attempts, 1);
+    assert!(report.failures[0].error.contains("INVALID_ARGUMENT"));
+}
+
+#[tokio::test]
+async fn failed_writes_in_a_batch_are_not_retried() {
+    let (client, store) = faulty_client(FaultConfig::default()).await;
+    common::seed(&store, "users", 1).await;
+    let mut writer = writer(client);
+    writer.create("users", Some("000"), HashMap::new()).await;
+    writer.create("users", Some("001"), HashMap::new()).await;
+    let report = writer.close().await;
+    assert_eq!(report.succeeded, 1);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].attempts, 1);
+    assert!(report.failures[0].error.contains("ALREADY_EXISTS"));
+}
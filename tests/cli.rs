@@ -0,0 +1,186 @@
+//! Runs the `firebase-cli` binary against a Firestore mock: the one named by
+//! `FIRESTORE_EMULATOR_HOST` when set (CI starts `firestore-mock`), otherwise
+//! one spawned in process.
+
+mod common;
+
+use firebase_db::{BulkWriteReport, MockServer, EMULATOR_HOST_ENV};
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+static HOST: OnceLock<String> = OnceLock::new();
+
+fn emulator_host() -> &'static str {
+    HOST.get_or_init(|| match std::env::var(EMULATOR_HOST_ENV) {
+        Ok(host) if !host.is_empty() => host,
+        _ => {
+            // The mock needs a runtime that outlives every test
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Runtime::new().unwrap();
+                runtime.block_on(async {
+                    let addr = MockServer::new(common::PROJECT_ID).spawn("127.0.0.1:0").await.unwrap();
+                    sender.send(addr.to_string()).unwrap();
+                    std::future::pending::<()>().await;
+                });
+            });
+            receiver.recv().unwrap()
+        }
+    })
+}
+
+/// A collection name no other test (or earlier run against the same mock) uses.
+fn collection(name: &str) -> String {
+    format!("cli_{}_{}_{}", name, std::process::id(), chrono::Utc::now().timestamp_micros())
+}
+
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_firebase-cli"))
+        .args(args)
+        .current_dir(std::env::temp_dir())
+        .env(EMULATOR_HOST_ENV, emulator_host())
+        .env("FIREBASE_PROJECT_ID", common::PROJECT_ID)
+        .env_remove("FIREBASE_API_KEY")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// The JSON document `data read` prints after its status line.
+fn read_json(output: &Output) -> serde_json::Value {
+    let text = stdout(output);
+    let start = text.find('{').unwrap_or_else(|| panic!("no JSON in output: {}", text));
+    serde_json::from_str(&text[start..]).unwrap()
+}
+
+#[test]
+fn create_update_read_and_delete_a_document() {
+    let users = collection("crud");
+
+    let created = cli(&["data", "create", "-c", &users, "-i", "ann", "name=Ann", "age=30"]);
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+
+    let updated = cli(&["data", "update", "-c", &users, "-i", "ann", "-j", r#"{"age": 31}"#]);
+    assert!(updated.status.success(), "{}", String::from_utf8_lossy(&updated.stderr));
+
+    let read = cli(&["data", "read", "-c", &users, "-i", "ann"]);
+    assert!(read.status.success());
+    let document = read_json(&read);
+    assert_eq!(document["name"], "Ann");
+    assert_eq!(document["age"], 31);
+
+    assert!(cli(&["data", "delete", "-c", &users, "-i", "ann", "-y"]).status.success());
+    assert!(!cli(&["data", "read", "-c", &users, "-i", "ann"]).status.success());
+}
+
+#[test]
+fn writes_are_checked_against_the_synced_schema() {
+    let users = collection("schema");
+    for (id, age) in [("a", "30"), ("b", "40")] {
+        let name = format!("name={}", id);
+        let age = format!("age={}", age);
+        assert!(cli(&["data", "create", "-c", &users, "-i", id, &name, &age]).status.success());
+    }
+    let synced = cli(&["schema", "sync", "-c", &users]);
+    assert!(synced.status.success(), "{}", String::from_utf8_lossy(&synced.stderr));
+
+    let invalid = cli(&["data", "create", "-c", &users, "-i", "c", "name=c", "age=old"]);
+    assert!(!invalid.status.success());
+    assert!(stdout(&invalid).contains("does not match the schema"));
+
+    let undeclared = cli(&["data", "create", "-c", &users, "-i", "d", "--strict", "name=d", "age=5", "extra=1"]);
+    assert!(!undeclared.status.success());
+
+    assert!(cli(&["data", "create", "-c", &users, "-i", "e", "name=e", "age=5"]).status.success());
+    assert!(!cli(&["data", "read", "-c", &users, "-i", "c"]).status.success());
+}
+
+#[test]
+fn import_reports_documents_that_fail_to_parse() {
+    let users = collection("import");
+    let file = std::env::temp_dir().join(format!("{}.json", users));
+    let export = serde_json::json!({
+        "collection": users,
+        "exported_at": "2024-01-01T00:00:00Z",
+        "count": 2,
+        "data": [
+            { "id": null, "name": "Ann", "email": "ann@example.com", "age": 30,
+              "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z" },
+            { "name": "Broken" },
+        ],
+    });
+    std::fs::write(&file, export.to_string()).unwrap();
+    let report = std::env::temp_dir().join(format!("{}-failures.json", users));
+
+    let imported = cli(&["data", "import", "-i", file.to_str().unwrap(), "--report", report.to_str().unwrap()]);
+    assert!(!imported.status.success());
+    let written: BulkWriteReport = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+    std::fs::remove_file(&file).ok();
+    std::fs::remove_file(&report).ok();
+    assert_eq!(written.succeeded, 1);
+    assert_eq!(written.failures.len(), 1);
+    assert_eq!((written.failures[0].index, written.failures[0].operation.as_str()), (1, "parse"));
+
+    let listed = cli(&["data", "list", "-c", &users]);
+    assert!(stdout(&listed).contains("Total: 1 documents"));
+}
+
+#[test]
+fn describe_replays_a_recorded_fixture() {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/products-describe.json");
+    let output = cli(&["--replay", fixture, "collections", "describe", "-c", "products", "-f", "text"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    assert!(text.contains("Collection: products (3 documents)"), "{}", text);
+    assert!(text.contains("price (double) *required"), "{}", text);
+    assert!(text.contains("on_sale (boolean) - 2 occurrences"), "{}", text);
+}
+
+#[test]
+fn drift_fails_on_breaking_changes_when_asked() {
+    let users = collection("drift");
+    assert!(cli(&["data", "create", "-c", &users, "-i", "a", "name=a", "age=30"]).status.success());
+    assert!(cli(&["data", "create", "-c", &users, "-i", "b", "name=b", "age=40"]).status.success());
+    assert!(cli(&["data", "create", "-c", &users, "-i", "c", "name=c"]).status.success());
+    // Sampling a and b records `age` as required, which c breaks
+    let synced = cli(&["schema", "sync", "-c", &users, "-s", "2"]);
+    assert!(synced.status.success(), "{}", String::from_utf8_lossy(&synced.stderr));
+
+    let report = cli(&["schema", "drift", "-c", &users]);
+    assert!(report.status.success(), "{}", String::from_utf8_lossy(&report.stderr));
+    assert!(stdout(&report).contains("age"), "{}", stdout(&report));
+
+    let failed = cli(&["schema", "drift", "-c", &users, "--fail-on", "breaking"]);
+    assert!(!failed.status.success());
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("breaking schema drift"), "{}", String::from_utf8_lossy(&failed.stderr));
+}
+
+#[test]
+fn cost_report_lists_reads_by_command() {
+    let users = collection("costs");
+    for id in ["a", "b", "c"] {
+        assert!(cli(&["data", "create", "-c", &users, "-i", id, "name=x"]).status.success());
+    }
+    let rates = std::env::temp_dir().join(format!("{}-rates.json", users));
+    std::fs::write(&rates, r#"{"currency": "EUR", "read_per_100k": 100000.0, "write_per_100k": 0.0, "delete_per_100k": 0.0}"#).unwrap();
+
+    let listed = cli(&["--cost-report", "--rates", rates.to_str().unwrap(), "data", "list", "-c", &users]);
+    assert!(listed.status.success());
+    let report = String::from_utf8_lossy(&listed.stderr);
+    let rows: Vec<Vec<&str>> = report.lines()
+        .filter(|line| line.starts_with("| "))
+        .map(|line| line.trim_matches('|').split('|').map(str::trim).collect())
+        .collect();
+    assert_eq!(rows, vec![
+        vec!["Call site", "Operation", "Collection", "Reads", "Index entries", "Writes", "Deletes", "Cost (EUR)"],
+        vec!["data list", "list", users.as_str(), "3", "0", "0", "0", "3.00000000"],
+        vec!["Total", "", "", "3", "0", "0", "0", "3.00000000"],
+    ]);
+    std::fs::remove_file(&rates).unwrap();
+
+    let quiet = cli(&["data", "list", "-c", &users]);
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("Billable operations"));
+}
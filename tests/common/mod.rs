@@ -0,0 +1,29 @@
+#![allow(dead_code)]
+
+use firebase_db::{DocumentStore, FirebaseClient, FirebaseClientBuilder, FirestoreValue, MemoryStore, MockServer};
+use std::collections::HashMap;
+
+pub const PROJECT_ID: &str = "demo-project";
+
+/// Serves a fresh mock on a free port, returning a client builder pointed at
+/// it and the mock's store for seeding and inspecting documents.
+pub async fn mock_server() -> (FirebaseClientBuilder, MemoryStore) {
+    let store = MemoryStore::with_project(PROJECT_ID);
+    let addr = MockServer::with_store(store.clone()).spawn("127.0.0.1:0").await.unwrap();
+    let builder = FirebaseClient::builder(PROJECT_ID, "test-key").emulator_host(addr.to_string());
+    (builder, store)
+}
+
+pub async fn mock_client() -> (FirebaseClient, MemoryStore) {
+    let (builder, store) = mock_server().await;
+    (builder.build().unwrap(), store)
+}
+
+/// Creates `count` documents `000`, `001`, ... with an integer field `n`.
+pub async fn seed(store: &MemoryStore, collection: &str, count: usize) {
+    for i in 0..count {
+        let mut fields = HashMap::new();
+        fields.insert("n".to_string(), FirestoreValue::IntegerValue(i.to_string()));
+        store.insert(collection, Some(&format!("{:03}", i)), fields).await.unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+use firebase_db::{
+    Collection, DocumentStore, Field, FieldType, FirestoreValue, JsonSchemaManager, MemoryStore, ReadOptions, User,
+    ValidationRule, ValidationRuleType,
+};
+use serde_json::json;
+
+fn field(name: &str, field_type: FieldType) -> Field {
+    Field { name: name.to_string(), field_type, required: true, default_value: None, description: None, auto_field: None }
+}
+
+#[tokio::test]
+async fn import_rejects_documents_failing_schema_rules() {
+    let store = MemoryStore::new();
+    let mut manager = JsonSchemaManager::new(store.clone());
+    let schema_manager = manager.get_schema_manager_mut();
+    schema_manager.define_collection(Collection {
+        name: "users".to_string(),
+        fields: vec![field("name", FieldType::String), field("age", FieldType::Integer)],
+        indexes: Vec::new(),
+        validation_rules: vec![ValidationRule { field: "age".to_string(), rule: ValidationRuleType::Custom("adult".to_string()) }],
+    });
+    schema_manager.validators_mut().register_fn("adult", |value| match value {
+        FirestoreValue::IntegerValue(age) if age.parse::<i64>().unwrap_or(0) >= 18 => Ok(()),
+        _ => Err("must be at least 18".to_string()),
+    });
+
+    let user = |name: &str, age: u32| {
+        json!({ "id": null, "name": name, "email": "a@b.c", "age": age, "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z" })
+    };
+    let export = json!({
+        "collection": "users",
+        "exported_at": "2024-01-01T00:00:00Z",
+        "count": 3,
+        "data": [user("Ann", 30), user("Kid", 12), { "name": "Broken" }],
+    });
+    let path = std::env::temp_dir().join(format!("import-{}.json", std::process::id()));
+    std::fs::write(&path, export.to_string()).unwrap();
+
+    let report = manager.import_collection_data::<User>(path.to_str().unwrap(), None).await.unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(report.succeeded, 1);
+    let failures: Vec<(usize, &str)> = report.failures.iter().map(|f| (f.index, f.operation.as_str())).collect();
+    assert_eq!(failures, vec![(1, "validate"), (2, "parse")]);
+    assert!(report.failures[0].error.contains("must be at least 18"));
+
+    let documents = store.list_all("users", &ReadOptions::default()).await.unwrap();
+    assert_eq!(documents.len(), 1);
+}
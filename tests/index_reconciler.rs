@@ -0,0 +1,50 @@
+mod common;
+
+use firebase_db::query::Direction;
+use firebase_db::{CompositeIndex, IndexAdmin, IndexDefinitions, IndexReconciler, IndexState, IndexedField, QueryScope};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+fn index(collection_group: &str, fields: &[(&str, Direction)]) -> CompositeIndex {
+    CompositeIndex {
+        collection_group: collection_group.to_string(),
+        query_scope: QueryScope::Collection,
+        fields: fields.iter().map(|(path, direction)| IndexedField::ordered(*path, *direction)).collect(),
+    }
+}
+
+#[tokio::test]
+async fn reconciler_plans_and_applies_against_the_mock() {
+    let (client, _store) = common::mock_client().await;
+    let kept = index("users", &[("age", Direction::Ascending), ("name", Direction::Ascending)]);
+    let stale = index("users", &[("email", Direction::Ascending), ("age", Direction::Descending)]);
+    let other_group = index("posts", &[("author", Direction::Ascending), ("date", Direction::Descending)]);
+    for seeded in [&kept, &stale, &other_group] {
+        client.create_index(seeded).await.unwrap();
+    }
+
+    let wanted = index("users", &[("city", Direction::Ascending), ("age", Direction::Ascending)]);
+    let desired = IndexDefinitions {
+        indexes: vec![kept.clone(), wanted.clone(), index("comments", &[("a", Direction::Ascending), ("b", Direction::Ascending)])],
+        ..IndexDefinitions::default()
+    };
+    let groups = BTreeSet::from(["users".to_string()]);
+
+    let reconciler = IndexReconciler::new(&client).poll_interval(Duration::from_millis(1));
+    let plan = reconciler.plan(&desired, &groups).await.unwrap();
+    assert_eq!(plan.create, vec![wanted.clone()]);
+    assert_eq!(plan.delete.iter().map(|index| index.definition()).collect::<Vec<_>>(), vec![stale]);
+    assert_eq!(plan.unchanged.iter().map(|index| index.definition()).collect::<Vec<_>>(), vec![kept.clone()]);
+
+    reconciler.apply(&plan).await.unwrap();
+
+    let users = client.list_indexes("users").await.unwrap();
+    let definitions: Vec<CompositeIndex> = users.iter().map(|index| index.definition()).collect();
+    assert_eq!(definitions, vec![kept, wanted.clone()]);
+    // The mock only reports a build as ready once its operation is polled
+    let created = users.iter().find(|index| index.definition() == wanted).unwrap();
+    assert_eq!(created.state, Some(IndexState::Ready));
+
+    assert_eq!(client.list_indexes("posts").await.unwrap().len(), 1);
+    assert!(reconciler.plan(&desired, &groups).await.unwrap().is_empty());
+}
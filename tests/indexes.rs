@@ -0,0 +1,175 @@
+use firebase_db::{
+    CompositeIndex, FirestoreValue, IndexDefinitions, IndexedField, QueryBuilder, QueryScope, create_filter,
+    load_saved_queries,
+};
+use firebase_db::query::{CompositeFilter, CompositeOperator, FieldOperator, Filter};
+use serde_json::json;
+
+fn string(value: &str) -> FirestoreValue {
+    FirestoreValue::StringValue(value.to_string())
+}
+
+fn int(n: i64) -> FirestoreValue {
+    FirestoreValue::IntegerValue(n.to_string())
+}
+
+fn eq(field: &str, value: FirestoreValue) -> Filter {
+    create_filter(field, FieldOperator::Equal, value)
+}
+
+fn all(filters: Vec<Filter>) -> Filter {
+    Filter::CompositeFilter(CompositeFilter { op: CompositeOperator::And, filters })
+}
+
+fn described(definitions: &IndexDefinitions) -> Vec<String> {
+    definitions.indexes.iter()
+        .map(|index| format!("{} {}", index.collection_group, index.describe()))
+        .collect()
+}
+
+#[test]
+fn equality_range_and_order_share_one_index() {
+    let ordered = QueryBuilder::new("users")
+        .and(vec![eq("status", string("active")), create_filter("age", FieldOperator::GreaterThan, int(18))])
+        .order_by("age", true)
+        .build();
+    // Without an explicit order the range field is ordered ascending
+    let implicit = QueryBuilder::new("users")
+        .and(vec![
+            eq("country", string("NZ")),
+            eq("status", string("active")),
+            create_filter("age", FieldOperator::LessThan, int(65)),
+        ])
+        .build();
+    let definitions = IndexDefinitions::from_queries([&ordered, &implicit]);
+    assert_eq!(described(&definitions), vec![
+        "users (status ASC, age DESC)",
+        "users (country ASC, status ASC, age ASC)",
+    ]);
+    assert!(definitions.indexes.iter().all(|index| index.query_scope == QueryScope::Collection));
+}
+
+#[test]
+fn single_field_queries_need_no_composite_index() {
+    let queries = [
+        QueryBuilder::new("users").where_gt("age", int(18)).build(),
+        QueryBuilder::new("users").order_by("age", true).build(),
+        QueryBuilder::new("users").and(vec![eq("status", string("active")), eq("country", string("NZ"))]).build(),
+        QueryBuilder::new("users").where_array_contains("tags", string("admin")).build(),
+    ];
+    assert_eq!(IndexDefinitions::from_queries(&queries), IndexDefinitions::default());
+}
+
+#[test]
+fn array_contains_is_indexed_as_contains() {
+    let query = QueryBuilder::new("posts")
+        .and(vec![
+            create_filter("tags", FieldOperator::ArrayContains, string("rust")),
+            eq("published", FirestoreValue::BooleanValue(true)),
+        ])
+        .order_by("created_at", true)
+        .build();
+    let definitions = IndexDefinitions::from_queries([&query]);
+    assert_eq!(definitions.indexes, vec![CompositeIndex {
+        collection_group: "posts".to_string(),
+        query_scope: QueryScope::Collection,
+        fields: vec![
+            IndexedField::ascending("published"),
+            IndexedField::contains("tags"),
+            IndexedField::descending("created_at"),
+        ],
+    }]);
+}
+
+#[test]
+fn equivalent_queries_are_deduplicated() {
+    let first = QueryBuilder::new("users")
+        .and(vec![eq("status", string("active")), eq("country", string("NZ"))])
+        .order_by("age", false)
+        .build();
+    // Same filters in a different order, and an OR whose branches both need the same index
+    let reordered = QueryBuilder::new("users")
+        .and(vec![eq("country", string("AU")), eq("status", string("banned"))])
+        .order_by("age", false)
+        .build();
+    let or = QueryBuilder::new("users")
+        .or(vec![
+            all(vec![eq("country", string("NZ")), eq("status", string("active"))]),
+            all(vec![eq("status", string("banned")), eq("country", string("NZ"))]),
+        ])
+        .order_by("age", false)
+        .build();
+
+    let mut definitions = IndexDefinitions::from_queries([&first, &reordered, &or]);
+    assert_eq!(described(&definitions), vec!["users (country ASC, status ASC, age ASC)"]);
+
+    definitions.merge(IndexDefinitions::from_queries([&first]));
+    assert_eq!(definitions.indexes.len(), 1);
+}
+
+#[test]
+fn saved_queries_produce_a_firestore_indexes_file() {
+    let queries = json!([
+        { "name": "recent-posts", "query": QueryBuilder::new("posts")
+            .where_array_contains("tags", string("rust"))
+            .order_by("created_at", true)
+            .build() },
+        { "name": "adults", "query": QueryBuilder::new("users")
+            .and(vec![eq("status", string("active")), create_filter("age", FieldOperator::GreaterThanOrEqual, int(18))])
+            .build() },
+        { "name": "adults-again", "query": QueryBuilder::new("users")
+            .and(vec![eq("status", string("inactive")), create_filter("age", FieldOperator::GreaterThanOrEqual, int(21))])
+            .build() },
+        { "name": "all-comments", "query": QueryBuilder::new("comments")
+            .collection_group()
+            .where_eq("author", string("ann"))
+            .build() },
+    ]);
+    let dir = std::env::temp_dir().join(format!("indexes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let queries_path = dir.join("queries.json");
+    let output_path = dir.join("firestore.indexes.json");
+    std::fs::write(&queries_path, queries.to_string()).unwrap();
+
+    let saved = load_saved_queries(queries_path.to_str().unwrap()).unwrap();
+    assert_eq!(saved.len(), 4);
+    let mut definitions = IndexDefinitions::from_queries(saved.iter().map(|saved| &saved.query));
+    definitions.sort();
+    definitions.save(output_path.to_str().unwrap()).unwrap();
+
+    let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&output_path).unwrap()).unwrap();
+    assert_eq!(written, json!({
+        "indexes": [
+            {
+                "collectionGroup": "posts",
+                "queryScope": "COLLECTION",
+                "fields": [
+                    { "fieldPath": "tags", "arrayConfig": "CONTAINS" },
+                    { "fieldPath": "created_at", "order": "DESCENDING" },
+                ],
+            },
+            {
+                "collectionGroup": "users",
+                "queryScope": "COLLECTION",
+                "fields": [
+                    { "fieldPath": "status", "order": "ASCENDING" },
+                    { "fieldPath": "age", "order": "ASCENDING" },
+                ],
+            },
+        ],
+        "fieldOverrides": [
+            {
+                "collectionGroup": "comments",
+                "fieldPath": "author",
+                "indexes": [
+                    { "order": "ASCENDING", "queryScope": "COLLECTION" },
+                    { "order": "DESCENDING", "queryScope": "COLLECTION" },
+                    { "arrayConfig": "CONTAINS", "queryScope": "COLLECTION" },
+                    { "order": "ASCENDING", "queryScope": "COLLECTION_GROUP" },
+                ],
+            },
+        ],
+    }));
+    assert_eq!(IndexDefinitions::load(output_path.to_str().unwrap()).unwrap(), definitions);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use firebase_db::{
+    ChangeKind, DocumentChange, DocumentStore, FirebaseError, FirestoreValue, ListenEvent, ListenOptions, ListenTarget,
+    Listener, MemoryStore, QueryBuilder, Write, listen,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const POLL: Duration = Duration::from_millis(10);
+
+fn fields(n: i64) -> HashMap<String, FirestoreValue> {
+    HashMap::from([("n".to_string(), FirestoreValue::IntegerValue(n.to_string()))])
+}
+
+fn listen_to_items(store: &MemoryStore, options: ListenOptions) -> Listener {
+    let query = QueryBuilder::new("items").build();
+    listen(store.clone(), ListenTarget::query("", query), options.poll_interval(POLL))
+}
+
+async fn next_event(listener: &mut Listener) -> ListenEvent {
+    tokio::time::timeout(Duration::from_secs(5), listener.next())
+        .await
+        .expect("no event within 5s")
+        .expect("listener stopped")
+        .unwrap()
+}
+
+fn summary(changes: &[DocumentChange]) -> Vec<(ChangeKind, &str)> {
+    changes.iter().map(|change| (change.kind, change.id())).collect()
+}
+
+#[tokio::test]
+async fn polls_report_added_modified_and_removed_documents() {
+    let store = MemoryStore::new();
+    common::seed(&store, "items", 3).await;
+    let mut listener = listen_to_items(&store, ListenOptions::default());
+
+    let initial = next_event(&mut listener).await;
+    assert_eq!(summary(&initial.changes), vec![
+        (ChangeKind::Added, "000"),
+        (ChangeKind::Added, "001"),
+        (ChangeKind::Added, "002"),
+    ]);
+
+    // One commit, so a single poll sees all three changes
+    store.commit(vec![
+        Write::Set { path: "items/001".to_string(), fields: fields(10) },
+        Write::Delete { path: "items/002".to_string() },
+        Write::Create { path: "items/003".to_string(), fields: fields(3) },
+    ], None).await.unwrap();
+
+    let event = next_event(&mut listener).await;
+    assert_eq!(summary(&event.changes), vec![
+        (ChangeKind::Modified, "001"),
+        (ChangeKind::Added, "003"),
+        (ChangeKind::Removed, "002"),
+    ]);
+    let modified = event.changes[0].document.as_ref().unwrap();
+    assert_eq!(modified.fields["n"], FirestoreValue::IntegerValue("10".to_string()));
+    assert!(event.changes[2].document.is_none());
+    assert!(event.read_time > initial.read_time);
+}
+
+#[tokio::test]
+async fn unchanged_polls_send_nothing() {
+    let store = MemoryStore::new();
+    common::seed(&store, "items", 1).await;
+    let mut listener = listen_to_items(&store, ListenOptions::default());
+    next_event(&mut listener).await;
+
+    let quiet = tokio::time::timeout(POLL * 10, listener.next()).await;
+    assert!(quiet.is_err(), "expected no event, got {:?}", quiet);
+}
+
+#[tokio::test]
+async fn document_targets_report_deletion() {
+    let store = MemoryStore::new();
+    common::seed(&store, "items", 2).await;
+    let mut listener = listen(store.clone(), ListenTarget::document("items/001"), ListenOptions::default().poll_interval(POLL));
+
+    assert_eq!(summary(&next_event(&mut listener).await.changes), vec![(ChangeKind::Added, "001")]);
+    store.remove("items/000").await.unwrap();
+    store.remove("items/001").await.unwrap();
+    assert_eq!(summary(&next_event(&mut listener).await.changes), vec![(ChangeKind::Removed, "001")]);
+}
+
+#[tokio::test]
+async fn resuming_skips_documents_already_seen() {
+    let store = MemoryStore::new();
+    common::seed(&store, "items", 3).await;
+    let mut listener = listen_to_items(&store, ListenOptions::default());
+    let token = next_event(&mut listener).await.resume_token;
+    drop(listener);
+
+    store.patch("items/000", fields(100), None).await.unwrap();
+    store.remove("items/001").await.unwrap();
+    store.insert("items", Some("004"), fields(4)).await.unwrap();
+
+    let mut resumed = listen_to_items(&store, ListenOptions::default().resume_token(&token));
+    let event = next_event(&mut resumed).await;
+    assert_eq!(summary(&event.changes), vec![
+        (ChangeKind::Modified, "000"),
+        (ChangeKind::Added, "004"),
+        (ChangeKind::Removed, "001"),
+    ]);
+
+    // Resuming from the latest token with nothing new stays quiet
+    let mut caught_up = listen_to_items(&store, ListenOptions::default().resume_token(event.resume_token));
+    assert!(tokio::time::timeout(POLL * 10, caught_up.next()).await.is_err());
+}
+
+#[tokio::test]
+async fn invalid_resume_token_ends_the_stream() {
+    let store = MemoryStore::new();
+    let mut listener = listen_to_items(&store, ListenOptions::default().resume_token("not a token"));
+    assert!(matches!(listener.next().await, Some(Err(FirebaseError::ValidationError(_)))));
+    assert!(listener.next().await.is_none());
+}
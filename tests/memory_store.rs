@@ -0,0 +1,127 @@
+use firebase_db::{DocumentStore, FirebaseError, FirestoreValue, MemoryStore, QueryBuilder, ReadOptions, RpcCode, StoredDocument, Write};
+use std::collections::HashMap;
+
+fn int(n: i64) -> FirestoreValue {
+    FirestoreValue::IntegerValue(n.to_string())
+}
+
+fn string(value: &str) -> FirestoreValue {
+    FirestoreValue::StringValue(value.to_string())
+}
+
+async fn insert(store: &MemoryStore, id: &str, fields: Vec<(&str, FirestoreValue)>) {
+    let fields: HashMap<String, FirestoreValue> = fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+    store.insert("items", Some(id), fields).await.unwrap();
+}
+
+fn ids(documents: &[StoredDocument]) -> Vec<&str> {
+    documents.iter().map(|document| document.id()).collect()
+}
+
+/// One document per type, plus one without the field.
+async fn mixed_types() -> MemoryStore {
+    let store = MemoryStore::new();
+    insert(&store, "map", vec![("v", FirestoreValue::MapValue { fields: HashMap::new() })]).await;
+    insert(&store, "string", vec![("v", string("a"))]).await;
+    insert(&store, "double", vec![("v", FirestoreValue::DoubleValue(2.5))]).await;
+    insert(&store, "timestamp", vec![("v", FirestoreValue::TimestampValue("2024-01-01T00:00:00Z".to_string()))]).await;
+    insert(&store, "integer", vec![("v", int(1))]).await;
+    insert(&store, "array", vec![("v", FirestoreValue::ArrayValue { values: vec![int(1)] })]).await;
+    insert(&store, "boolean", vec![("v", FirestoreValue::BooleanValue(true))]).await;
+    insert(&store, "null", vec![("v", FirestoreValue::NullValue(None))]).await;
+    insert(&store, "missing", vec![("other", int(0))]).await;
+    store
+}
+
+#[tokio::test]
+async fn ordering_follows_firestore_cross_type_order() {
+    let store = mixed_types().await;
+    let query = QueryBuilder::new("items").order_by("v", false).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    // Documents without the field are left out of ordered queries
+    assert_eq!(ids(&documents), vec!["null", "boolean", "integer", "double", "timestamp", "string", "array", "map"]);
+}
+
+#[tokio::test]
+async fn range_filters_only_match_values_of_the_same_type() {
+    let store = mixed_types().await;
+    let query = QueryBuilder::new("items").where_gt("v", int(0)).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    // Inequalities order by their field first
+    assert_eq!(ids(&documents), vec!["integer", "double"]);
+
+    // Integers and doubles compare by value
+    let query = QueryBuilder::new("items").where_eq("v", FirestoreValue::DoubleValue(1.0)).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["integer"]);
+}
+
+#[tokio::test]
+async fn not_equal_skips_documents_without_the_field() {
+    let store = mixed_types().await;
+    let query = QueryBuilder::new("items").where_ne("v", string("a")).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert!(!ids(&documents).contains(&"missing"));
+    assert!(!ids(&documents).contains(&"string"));
+    assert!(ids(&documents).contains(&"integer"));
+}
+
+#[tokio::test]
+async fn array_contains_and_in_filters() {
+    let store = MemoryStore::new();
+    insert(&store, "a", vec![("tags", FirestoreValue::ArrayValue { values: vec![string("x"), string("y")] }), ("n", int(1))]).await;
+    insert(&store, "b", vec![("tags", FirestoreValue::ArrayValue { values: vec![string("y")] }), ("n", int(2))]).await;
+    insert(&store, "c", vec![("tags", string("x")), ("n", int(3))]).await;
+
+    let query = QueryBuilder::new("items").where_array_contains("tags", string("x")).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["a"]);
+
+    let query = QueryBuilder::new("items").where_in("n", vec![int(1), FirestoreValue::DoubleValue(3.0)]).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["a", "c"]);
+}
+
+#[tokio::test]
+async fn cursors_offset_and_limit_page_through_ordered_results() {
+    let store = MemoryStore::new();
+    for i in 0..10 {
+        insert(&store, &format!("{:02}", i), vec![("n", int(i % 5))]).await;
+    }
+
+    // Ordered by n, then by name as a tie-breaker
+    let query = QueryBuilder::new("items").order_by("n", true).limit(3).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["09", "04", "08"]);
+
+    let query = QueryBuilder::new("items").order_by("n", false).start_after(vec![int(1)]).end_at(vec![int(3)]).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["02", "07", "03", "08"]);
+
+    let query = QueryBuilder::new("items").order_by("n", false).start_at(vec![int(2)]).offset(1).limit(2).build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    assert_eq!(ids(&documents), vec!["07", "03"]);
+}
+
+fn rpc_code<T: std::fmt::Debug>(result: firebase_db::error::Result<T>) -> RpcCode {
+    match result {
+        Err(FirebaseError::ApiError(status)) => status.code,
+        other => panic!("expected an API error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn failed_preconditions_carry_rpc_codes() {
+    let store = MemoryStore::new();
+    insert(&store, "a", vec![]).await;
+    assert_eq!(rpc_code(store.insert("items", Some("a"), HashMap::new()).await), RpcCode::AlreadyExists);
+
+    let create = Write::Create { path: "items/a".to_string(), fields: HashMap::new() };
+    assert_eq!(rpc_code(store.commit(vec![create], None).await), RpcCode::AlreadyExists);
+
+    let transaction = store.begin_transaction(false).await.unwrap();
+    store.rollback(&transaction).await.unwrap();
+    assert_eq!(rpc_code(store.commit(Vec::new(), Some(&transaction)).await), RpcCode::InvalidArgument);
+    let options = ReadOptions { transaction: Some(transaction), ..ReadOptions::default() };
+    assert_eq!(rpc_code(store.fetch("items/a", &options).await), RpcCode::InvalidArgument);
+}
@@ -0,0 +1,141 @@
+use firebase_db::{
+    ConversionPolicy, DocumentStore, FieldType, FirestoreValue, MemoryStore, MigrationCheckpoint, MigrationFile,
+    MigrationManager, MigrationStep, ReadOptions, StoredDocument, ToFirestore, Write, json_to_firestore_fields,
+};
+use serde_json::json;
+
+async fn insert(store: &MemoryStore, path: &str, data: serde_json::Value) -> StoredDocument {
+    let (collection, id) = path.rsplit_once('/').unwrap();
+    store.insert(collection, Some(id), json_to_firestore_fields(data).unwrap()).await.unwrap()
+}
+
+async fn read(store: &MemoryStore, path: &str) -> serde_json::Value {
+    store.fetch(path, &ReadOptions::default()).await.unwrap().unwrap().to_json()
+}
+
+fn migration(up: Vec<MigrationStep>) -> MigrationFile {
+    MigrationFile { version: "001".to_string(), description: "test".to_string(), page_size: Some(2), up, down: Vec::new() }
+}
+
+#[tokio::test]
+async fn rename_field_writes_a_masked_update() {
+    let store = MemoryStore::new();
+    let document = insert(&store, "users/a", json!({ "name": "Ann", "age": 3 })).await;
+    let step = MigrationStep::RenameField { collection: "users".to_string(), from: "name".to_string(), to: "full_name".to_string() };
+
+    let writes = step.transform(&document).unwrap();
+    let [Write::Update { path, fields, mask }] = writes.as_slice() else {
+        panic!("expected one update, got {:?}", writes);
+    };
+    assert_eq!(path, "users/a");
+    assert_eq!(fields.get("full_name"), Some(&FirestoreValue::StringValue("Ann".to_string())));
+    assert!(!fields.contains_key("name"));
+    assert!(mask.contains(&"name".to_string()) && mask.contains(&"full_name".to_string()));
+
+    // Documents without the field are left alone
+    let other = insert(&store, "users/b", json!({ "age": 4 })).await;
+    assert!(step.transform(&other).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn field_steps_rewrite_every_document() {
+    let store = MemoryStore::new();
+    for i in 0..5 {
+        insert(&store, &format!("users/{}", i), json!({ "name": format!("First{} Last", i), "age": i.to_string(), "tmp": true })).await;
+    }
+    insert(&store, "users/bad", json!({ "name": "Solo", "age": "old", "tags": [{ "id": "x", "n": 1 }] })).await;
+
+    let file = migration(vec![
+        MigrationStep::SplitField {
+            collection: "users".to_string(),
+            field: "name".to_string(),
+            into: vec!["first".to_string(), "last".to_string()],
+            separator: " ".to_string(),
+            keep_source: false,
+        },
+        MigrationStep::ChangeType {
+            collection: "users".to_string(),
+            field: "age".to_string(),
+            to: FieldType::Integer,
+            on_error: ConversionPolicy::Null,
+        },
+        MigrationStep::SetDefaultWhereMissing { collection: "users".to_string(), field: "role".to_string(), value: json!("member") },
+        MigrationStep::RemoveField { collection: "users".to_string(), field: "tmp".to_string() },
+        MigrationStep::MoveToSubcollection {
+            collection: "users".to_string(),
+            field: "tags".to_string(),
+            subcollection: "tags".to_string(),
+            id_field: Some("id".to_string()),
+        },
+    ]);
+    let mut manager = MigrationManager::new(store.clone());
+    manager.add_migration(file.into_migration());
+    manager.run_migrations().await.unwrap();
+
+    let user = read(&store, "users/3").await;
+    assert_eq!(user["first"], "First3");
+    assert_eq!(user["last"], "Last");
+    assert_eq!(user["age"], 3);
+    assert_eq!(user["role"], "member");
+    assert!(user.get("name").is_none() && user.get("tmp").is_none());
+
+    let bad = read(&store, "users/bad").await;
+    assert_eq!(bad["first"], "Solo");
+    assert!(bad["age"].is_null());
+    assert!(bad.get("tags").is_none());
+    assert_eq!(read(&store, "users/bad/tags/x").await["n"], 1);
+
+    // The checkpoint is gone once the migration finishes
+    assert!(store.fetch("_migrations/001/checkpoints/up", &ReadOptions::default()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn failed_conversion_resumes_from_the_checkpoint() {
+    let store = MemoryStore::new();
+    for i in 0..4 {
+        insert(&store, &format!("items/{}", i), json!({ "count": i.to_string() })).await;
+    }
+    insert(&store, "items/9", json!({ "count": "many" })).await;
+    let file = migration(vec![
+        MigrationStep::SetDefaultWhereMissing { collection: "items".to_string(), field: "kind".to_string(), value: json!("plain") },
+        MigrationStep::ChangeType {
+            collection: "items".to_string(),
+            field: "count".to_string(),
+            to: FieldType::Integer,
+            on_error: ConversionPolicy::Fail,
+        },
+    ]);
+
+    let mut manager = MigrationManager::new(store.clone());
+    manager.add_migration(file.clone().into_migration());
+    assert!(manager.run_migrations().await.is_err());
+    let checkpoint = store.fetch("_migrations/001/checkpoints/up", &ReadOptions::default()).await.unwrap().unwrap();
+    assert_eq!(checkpoint.to_json()["step"], 1);
+
+    store.patch("items/9", json_to_firestore_fields(json!({ "count": "9", "kind": "plain" })).unwrap(), None).await.unwrap();
+    let runs = manager.run_migrations().await.unwrap();
+    // Only the change_type step ran again
+    assert!(runs[0].affected.iter().all(|document| document.operation == "update"));
+    assert_eq!(read(&store, "items/9").await["count"], 9);
+}
+
+#[tokio::test]
+async fn dry_run_ignores_checkpoints() {
+    let store = MemoryStore::new();
+    for i in 0..3 {
+        insert(&store, &format!("items/{}", i), json!({ "old": i })).await;
+    }
+    // A crashed run got past the first step
+    let checkpoint = MigrationCheckpoint { step: 1, ..MigrationCheckpoint::default() };
+    store.patch("_migrations/001/checkpoints/up", checkpoint.to_firestore(), None).await.unwrap();
+
+    let file = migration(vec![
+        MigrationStep::RenameField { collection: "items".to_string(), from: "old".to_string(), to: "new".to_string() },
+        MigrationStep::RemoveField { collection: "items".to_string(), field: "missing".to_string() },
+    ]);
+    let mut manager = MigrationManager::new(store.clone());
+    manager.add_migration(file.into_migration());
+    let runs = manager.up(None, true).await.unwrap();
+    assert_eq!(runs[0].affected.len(), 3);
+    assert_eq!(read(&store, "items/0").await["old"], 0);
+}
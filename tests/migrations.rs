@@ -0,0 +1,72 @@
+use firebase_db::{DocumentStore, FirebaseError, MemoryStore, Migration, MigrationCheckpoint, MigrationManager, ReadOptions};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn run_fails_when_its_lock_is_taken_over() {
+    let store = MemoryStore::new();
+    let mut manager = MigrationManager::new(store.clone()).lock_ttl(Duration::from_millis(50));
+    manager.add_migration(Migration::<MemoryStore>::new("001", "slow").up(|_ctx| Box::pin(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        Ok(())
+    })));
+    let manager = Arc::new(manager);
+
+    let first = tokio::spawn({
+        let manager = manager.clone();
+        async move { manager.up(None, false).await }
+    });
+    // The first runner's lock expires while its migration is still running
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let mut second = MigrationManager::new(store.clone()).lock_ttl(Duration::from_secs(60));
+    second.add_migration(Migration::<MemoryStore>::new("002", "holds the lock").up(|_ctx| Box::pin(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        Ok(())
+    })));
+    let holder = tokio::spawn(async move { second.up(Some("002"), false).await });
+
+    let result = first.await.unwrap();
+    assert!(result.is_err(), "a runner that lost its lock must not record the migration");
+    assert!(store.fetch("_migrations/001", &ReadOptions::default()).await.unwrap().is_none());
+    assert_eq!(holder.await.unwrap().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn checkpoints_renew_the_lock() {
+    let store = MemoryStore::new();
+    let mut manager = MigrationManager::new(store.clone()).lock_ttl(Duration::from_millis(200));
+    manager.add_migration(Migration::<MemoryStore>::new("001", "paged").up(|ctx| Box::pin(async move {
+        for page in 0..5 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            ctx.save_checkpoint(&MigrationCheckpoint { processed: page, ..MigrationCheckpoint::default() }).await?;
+        }
+        Ok(())
+    })));
+    let manager = Arc::new(manager);
+
+    let first = tokio::spawn({
+        let manager = manager.clone();
+        async move { manager.up(None, false).await }
+    });
+    // Past the TTL, but the lock has been renewed since
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    let second = MigrationManager::<MemoryStore>::new(store.clone());
+    let error = second.down(1, false).await.unwrap_err();
+    assert!(error.to_string().contains("locked"), "{}", error);
+
+    let runs = first.await.unwrap().unwrap();
+    assert_eq!(runs.len(), 1);
+    assert!(store.fetch("_migrations/001", &ReadOptions::default()).await.unwrap().is_some());
+    assert!(store.fetch("_migrations/_lock", &ReadOptions::default()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn oversized_lock_ttl_is_a_config_error() {
+    let store = MemoryStore::new();
+    let mut manager = MigrationManager::new(store.clone()).lock_ttl(Duration::MAX);
+    manager.add_migration(Migration::<MemoryStore>::new("001", "never runs").up(|_ctx| Box::pin(async move { Ok(()) })));
+
+    let error = manager.up(None, false).await.unwrap_err();
+    assert!(matches!(error, FirebaseError::ConfigError(_)), "{:?}", error);
+    assert!(store.fetch("_migrations/_lock", &ReadOptions::default()).await.unwrap().is_none());
+}
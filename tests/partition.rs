@@ -0,0 +1,37 @@
+mod common;
+
+use firebase_db::{MemoryStore, QueryBuilder, ReadOptions, StoredDocument, partitioned_query, read_collection};
+
+fn ids(documents: &[StoredDocument]) -> Vec<String> {
+    documents.iter().map(|document| document.path().to_string()).collect()
+}
+
+#[tokio::test]
+async fn partitioned_collection_read_ignores_namesake_collections() {
+    let store = MemoryStore::new();
+    common::seed(&store, "users", 10).await;
+    common::seed(&store, "archive/a/users", 10).await;
+    common::seed(&store, "users/005/users", 10).await;
+
+    let documents = read_collection(&store, "users", 4, &ReadOptions::default()).await.unwrap();
+    let expected: Vec<String> = (0..10).map(|i| format!("users/{:03}", i)).collect();
+    assert_eq!(ids(&documents), expected);
+}
+
+#[tokio::test]
+async fn partitioned_collection_group_query_reads_every_collection() {
+    let store = MemoryStore::new();
+    common::seed(&store, "users", 5).await;
+    common::seed(&store, "archive/a/users", 5).await;
+
+    let query = QueryBuilder::new("users").collection_group().build();
+    let documents = partitioned_query(&store, "", &query, 3, &ReadOptions::default()).await.unwrap();
+    assert_eq!(documents.len(), 10);
+}
+
+#[tokio::test]
+async fn partitioned_query_rejects_other_orders() {
+    let store = MemoryStore::new();
+    let query = QueryBuilder::new("users").order_by("n", false).build();
+    assert!(partitioned_query(&store, "", &query, 2, &ReadOptions::default()).await.is_err());
+}
@@ -0,0 +1,85 @@
+use firebase_db::query::{CompositeFilter, CompositeOperator, Filter};
+use firebase_db::{FieldOperator, FirestoreValue, QueryBuilder, QueryError, create_filter};
+
+fn string(value: &str) -> FirestoreValue {
+    FirestoreValue::StringValue(value.to_string())
+}
+
+#[test]
+fn array_contains_in_separate_or_branches_is_valid() {
+    let query = QueryBuilder::new("posts")
+        .or(vec![
+            create_filter("tags", FieldOperator::ArrayContains, string("a")),
+            create_filter("labels", FieldOperator::ArrayContains, string("b")),
+        ])
+        .build();
+    assert_eq!(query.validate(), Ok(()));
+}
+
+#[test]
+fn two_array_contains_in_one_branch_are_rejected() {
+    let query = QueryBuilder::new("posts")
+        .and(vec![
+            create_filter("tags", FieldOperator::ArrayContains, string("a")),
+            create_filter("labels", FieldOperator::ArrayContains, string("b")),
+        ])
+        .build();
+    assert_eq!(query.validate(), Err(QueryError::MultipleArrayContains));
+}
+
+#[test]
+fn array_contains_distributed_into_an_or_branch_is_rejected() {
+    // tags AND (labels OR x == 1) has the branch tags AND labels
+    let query = QueryBuilder::new("posts")
+        .and(vec![
+            create_filter("tags", FieldOperator::ArrayContains, string("a")),
+            Filter::CompositeFilter(CompositeFilter {
+                op: CompositeOperator::Or,
+                filters: vec![
+                    create_filter("labels", FieldOperator::ArrayContains, string("b")),
+                    create_filter("x", FieldOperator::Equal, FirestoreValue::IntegerValue("1".to_string())),
+                ],
+            }),
+        ])
+        .build();
+    assert_eq!(query.validate(), Err(QueryError::MultipleArrayContains));
+}
+
+#[test]
+fn inequality_needs_matching_first_order() {
+    let query = QueryBuilder::new("users")
+        .where_gt("age", FirestoreValue::IntegerValue("18".to_string()))
+        .order_by("name", false)
+        .build();
+    assert!(matches!(query.validate(), Err(QueryError::InequalityOrder { .. })));
+
+    let query = QueryBuilder::new("users")
+        .where_gt("age", FirestoreValue::IntegerValue("18".to_string()))
+        .order_by("age", false)
+        .order_by("name", false)
+        .build();
+    assert_eq!(query.validate(), Ok(()));
+}
+
+#[test]
+fn list_filters_are_checked() {
+    let query = QueryBuilder::new("users").where_in("role", vec![]).build();
+    assert!(matches!(query.validate(), Err(QueryError::EmptyList { .. })));
+
+    let values = (0..31).map(|i| FirestoreValue::IntegerValue(i.to_string())).collect();
+    let query = QueryBuilder::new("users").where_in("role", values).build();
+    assert!(matches!(query.validate(), Err(QueryError::ListTooLong { len: 31, .. })));
+}
+
+#[test]
+fn cursor_longer_than_orders_is_rejected() {
+    let query = QueryBuilder::new("users")
+        .order_by("age", false)
+        .start_at(vec![
+            FirestoreValue::IntegerValue("1".to_string()),
+            FirestoreValue::ReferenceValue("projects/p/databases/(default)/documents/users/a".to_string()),
+            string("extra"),
+        ])
+        .build();
+    assert!(matches!(query.validate(), Err(QueryError::CursorTooLong { values: 3, orders: 2, .. })));
+}
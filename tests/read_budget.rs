@@ -0,0 +1,75 @@
+mod common;
+
+use common::{mock_server, seed};
+use firebase_db::{DocumentStore, FirebaseError, ListOptions, QueryBuilder, ReadOptions};
+
+#[tokio::test]
+async fn query_that_exactly_fits_the_budget_succeeds() {
+    let (builder, store) = mock_server().await;
+    seed(&store, "users", 5).await;
+    let client = builder.max_reads(5).build().unwrap();
+
+    let documents = client.run_query("", &QueryBuilder::new("users").build(), &ReadOptions::default()).await.unwrap();
+    assert_eq!(documents.len(), 5);
+    assert_eq!(client.costs().totals().reads, 5);
+}
+
+#[tokio::test]
+async fn query_cut_short_by_the_budget_fails() {
+    let (builder, store) = mock_server().await;
+    seed(&store, "users", 6).await;
+    let client = builder.max_reads(5).build().unwrap();
+
+    let result = client.run_query("", &QueryBuilder::new("users").build(), &ReadOptions::default()).await;
+    assert!(matches!(result, Err(FirebaseError::BudgetExceeded(_))));
+}
+
+#[tokio::test]
+async fn list_that_exactly_fits_the_budget_succeeds() {
+    let (builder, store) = mock_server().await;
+    seed(&store, "users", 4).await;
+    let client = builder.max_reads(4).build().unwrap();
+
+    let page = client.list_page("users", &ListOptions::default(), &ReadOptions::default()).await.unwrap();
+    assert_eq!(page.documents.len(), 4);
+    assert!(page.next_page_token.is_none());
+
+    seed(&store, "more", 5).await;
+    let result = client.list_page("more", &ListOptions::default(), &ReadOptions::default()).await;
+    assert!(matches!(result, Err(FirebaseError::BudgetExceeded(_))));
+}
+
+#[tokio::test]
+async fn concurrent_reads_stay_within_the_budget() {
+    let (builder, store) = mock_server().await;
+    seed(&store, "users", 10).await;
+    let client = builder.max_reads(3).build().unwrap();
+
+    let mut reads = tokio::task::JoinSet::new();
+    for i in 0..10 {
+        let client = client.clone();
+        reads.spawn(async move { client.fetch(&format!("users/{:03}", i), &ReadOptions::default()).await });
+    }
+    let results = reads.join_all().await;
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+    assert_eq!(client.costs().totals().reads, 3);
+}
+
+#[tokio::test]
+async fn count_is_bounded_by_the_remaining_budget() {
+    let (builder, store) = mock_server().await;
+    seed(&store, "users", 1500).await;
+
+    // Two reads pay for up to 2,000 index entries
+    let client = builder.clone().max_reads(2).build().unwrap();
+    assert_eq!(client.count_documents("users").await.unwrap(), 1500);
+    assert_eq!(client.costs().totals().reads, 2);
+    assert_eq!(client.costs().totals().index_entry_reads, 1500);
+
+    // One read only covers 1,000, so the count stops there and fails
+    let client = builder.max_reads(1).build().unwrap();
+    let result = client.count_documents("users").await;
+    assert!(matches!(result, Err(FirebaseError::BudgetExceeded(_))), "{:?}", result);
+    assert_eq!(client.costs().totals().reads, 1);
+    assert!(client.count_documents("users").await.is_err());
+}
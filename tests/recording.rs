@@ -0,0 +1,134 @@
+mod common;
+
+use firebase_db::error::Result;
+use firebase_db::{
+    CollectionManager, CollectionSchema, DocumentStore, FirebaseClient, FirebaseClientBuilder, FirebaseError,
+    JsonSchema, JsonSchemaManager, MemoryStore, ReplayMatcher, User, json_to_firestore_fields,
+};
+use serde_json::json;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/users-session.json");
+const DESCRIBE_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/products-describe.json");
+const DISCOVER_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/discovered-schemas.json");
+
+/// Creates, reads, updates and lists a user. Every run stamps the user with
+/// the current time.
+async fn session(client: &FirebaseClient) -> Result<Vec<User>> {
+    let mut user = User::new("Ann".to_string(), "ann@example.com".to_string(), 30);
+    let id = client.create("users", &user).await?;
+    let stored: User = client.get("users", &id).await?;
+    assert_eq!(stored.name, "Ann");
+    user.age = 31;
+    client.update("users", &id, &user).await?;
+    client.list("users", Some(10)).await
+}
+
+/// Three products; only two have `on_sale`.
+async fn seed_products(store: &MemoryStore) {
+    let products = [
+        ("apple", json!({ "name": "Apple", "price": 0.5, "stock": 120, "on_sale": true })),
+        ("bread", json!({ "name": "Bread", "price": 2.25, "stock": 8 })),
+        ("cheese", json!({ "name": "Cheese", "price": 7.0, "stock": 0, "on_sale": false })),
+    ];
+    for (id, product) in products {
+        store.insert("products", Some(id), json_to_firestore_fields(product).unwrap()).await.unwrap();
+    }
+}
+
+async fn describe_products(client: &FirebaseClient) -> Result<CollectionSchema> {
+    CollectionManager::new(client.clone()).describe_collection("products", 50).await
+}
+
+async fn discover_schemas(client: &FirebaseClient) -> Result<JsonSchema> {
+    let path = std::env::temp_dir().join(format!("discovered-{}-{}.json", std::process::id(), uuid::Uuid::new_v4()));
+    JsonSchemaManager::new(client.clone()).export_discovered_schemas(path.to_str().unwrap()).await?;
+    let schema = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    std::fs::remove_file(&path).ok();
+    Ok(schema)
+}
+
+fn replay_client() -> FirebaseClientBuilder {
+    replay(FIXTURE)
+}
+
+fn replay(fixture: &str) -> FirebaseClientBuilder {
+    FirebaseClient::builder(common::PROJECT_ID, "test-key").replay_from(fixture)
+}
+
+/// Rewrites the fixtures from sessions against the mock server:
+/// `cargo test --test recording -- --ignored`
+#[tokio::test]
+#[ignore]
+async fn record_fixture() {
+    let (builder, _store) = common::mock_server().await;
+    let client = builder.record_to(FIXTURE).build().unwrap();
+    session(&client).await.unwrap();
+    client.save_recording().unwrap();
+
+    let (builder, store) = common::mock_server().await;
+    seed_products(&store).await;
+    let client = builder.record_to(DESCRIBE_FIXTURE).build().unwrap();
+    describe_products(&client).await.unwrap();
+    client.save_recording().unwrap();
+
+    let (builder, store) = common::mock_server().await;
+    seed_products(&store).await;
+    let client = builder.record_to(DISCOVER_FIXTURE).build().unwrap();
+    discover_schemas(&client).await.unwrap();
+    client.save_recording().unwrap();
+}
+
+#[tokio::test]
+async fn recorded_session_replays() {
+    let client = replay_client().replay_matcher(ReplayMatcher::default().normalize()).build().unwrap();
+    let users = session(&client).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].age, 31);
+    assert_eq!(client.replay_remaining(), 0);
+}
+
+#[tokio::test]
+async fn exact_replay_rejects_changed_timestamps() {
+    let client = replay_client().build().unwrap();
+    match session(&client).await {
+        Err(FirebaseError::ConfigError(message)) => assert!(message.contains("No recorded interaction")),
+        other => panic!("expected a replay mismatch, got {:?}", other.map(|users| users.len())),
+    }
+}
+
+#[tokio::test]
+async fn replay_can_ignore_bodies() {
+    let client = replay_client().replay_matcher(ReplayMatcher::default().ignore_body()).build().unwrap();
+    session(&client).await.unwrap();
+    assert_eq!(client.replay_remaining(), 0);
+}
+
+#[tokio::test]
+async fn describe_collection_replays() {
+    let client = replay(DESCRIBE_FIXTURE).build().unwrap();
+    let schema = describe_products(&client).await.unwrap();
+    assert_eq!(client.replay_remaining(), 0);
+
+    assert_eq!(schema.total_documents, 3);
+    let fields: Vec<(&str, &str, bool)> = schema.fields.iter()
+        .map(|field| (field.name.as_str(), field.field_type.as_str(), field.is_required))
+        .collect();
+    assert_eq!(fields, vec![
+        ("name", "string", true),
+        ("on_sale", "boolean", false),
+        ("price", "double", true),
+        ("stock", "integer", true),
+    ]);
+}
+
+#[tokio::test]
+async fn discovered_schemas_replay() {
+    let client = replay(DISCOVER_FIXTURE).build().unwrap();
+    let schema = discover_schemas(&client).await.unwrap();
+    assert_eq!(client.replay_remaining(), 0);
+
+    let products = &schema.collections["products"];
+    let mut fields: Vec<(&str, bool)> = products.fields.iter().map(|field| (field.name.as_str(), field.required)).collect();
+    fields.sort();
+    assert_eq!(fields, vec![("name", true), ("on_sale", false), ("price", true), ("stock", true)]);
+}
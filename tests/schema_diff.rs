@@ -0,0 +1,139 @@
+use firebase_db::{JsonCollection, SchemaChangeKind, SchemaDiff, Severity, read_schema_file};
+use serde_json::{Value, json};
+
+fn collection(fields: Value, indexes: Value, rules: Value) -> JsonCollection {
+    serde_json::from_value(json!({
+        "name": "users",
+        "description": null,
+        "fields": fields,
+        "indexes": indexes,
+        "validation_rules": rules,
+    }))
+    .unwrap()
+}
+
+fn fields(fields: Value) -> JsonCollection {
+    collection(fields, json!([]), json!([]))
+}
+
+fn rules(rules: Value) -> JsonCollection {
+    collection(json!([]), json!([]), rules)
+}
+
+fn field(name: &str, field_type: &str, required: bool) -> Value {
+    json!({ "name": name, "field_type": field_type, "required": required, "default_value": null, "description": null })
+}
+
+fn rule(rule_type: &str, value: Value) -> Value {
+    json!({ "field": "name", "rule_type": rule_type, "value": value, "description": null })
+}
+
+/// The kind and severity of each change, in order.
+fn changes(old: &JsonCollection, new: &JsonCollection) -> Vec<(SchemaChangeKind, String, Severity)> {
+    SchemaDiff::collections(old, new).changes.into_iter()
+        .map(|change| (change.kind, change.target, change.severity))
+        .collect()
+}
+
+#[test]
+fn removed_fields_are_breaking() {
+    let old = fields(json!([field("name", "string", true), field("age", "integer", false)]));
+    let new = fields(json!([field("name", "string", true)]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::FieldRemoved, "age".to_string(), Severity::Breaking)]);
+}
+
+#[test]
+fn new_required_fields_are_breaking_without_a_default() {
+    let old = fields(json!([field("name", "string", true)]));
+
+    let new = fields(json!([field("name", "string", true), field("plan", "string", true)]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::FieldAdded, "plan".to_string(), Severity::Breaking)]);
+
+    let mut with_default = field("plan", "string", true);
+    with_default["default_value"] = json!("free");
+    let new = fields(json!([field("name", "string", true), with_default]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::FieldAdded, "plan".to_string(), Severity::NonBreaking)]);
+
+    let new = fields(json!([field("name", "string", true), field("plan", "string", false)]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::FieldAdded, "plan".to_string(), Severity::NonBreaking)]);
+}
+
+#[test]
+fn widening_integer_to_double_is_safe() {
+    let integer = fields(json!([field("score", "integer", true)]));
+    let double = fields(json!([field("score", "double", true)]));
+    assert_eq!(changes(&integer, &double), vec![(SchemaChangeKind::TypeChanged, "score".to_string(), Severity::NonBreaking)]);
+    assert_eq!(changes(&double, &integer), vec![(SchemaChangeKind::TypeChanged, "score".to_string(), Severity::Breaking)]);
+}
+
+#[test]
+fn loosened_bounds_are_safe_and_tightened_ones_breaking() {
+    let cases = [
+        ("min_length", 5, 3, Severity::NonBreaking),
+        ("min_length", 3, 5, Severity::Breaking),
+        ("max", 100, 150, Severity::NonBreaking),
+        ("max", 150, 100, Severity::Breaking),
+    ];
+    for (rule_type, before, after, severity) in cases {
+        let old = rules(json!([rule(rule_type, json!(before))]));
+        let new = rules(json!([rule(rule_type, json!(after))]));
+        let expected = vec![(SchemaChangeKind::RuleChanged, format!("name {}", rule_type), severity)];
+        assert_eq!(changes(&old, &new), expected, "{} {} -> {}", rule_type, before, after);
+    }
+
+    // A changed pattern can't be compared, so it is assumed to reject something
+    let old = rules(json!([rule("pattern", json!("^[a-z]+$"))]));
+    let new = rules(json!([rule("pattern", json!("^[a-z0-9]+$"))]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::RuleChanged, "name pattern".to_string(), Severity::Breaking)]);
+}
+
+#[test]
+fn removed_indexes_are_breaking() {
+    let index = json!({ "fields": [{ "field_path": "status", "order": "ASC" }, { "field_path": "created_at", "order": "desc" }], "unique": false, "description": null });
+    let old = collection(json!([]), json!([index]), json!([]));
+    let new = collection(json!([]), json!([]), json!([]));
+    assert_eq!(changes(&old, &new), vec![(SchemaChangeKind::IndexRemoved, "status asc, created_at desc".to_string(), Severity::Breaking)]);
+    assert_eq!(changes(&new, &old), vec![(SchemaChangeKind::IndexAdded, "status asc, created_at desc".to_string(), Severity::NonBreaking)]);
+}
+
+#[test]
+fn schema_files_can_hold_a_schema_a_stored_version_or_one_collection() {
+    let dir = std::env::temp_dir().join(format!("schema-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let users = json!({ "name": "users", "description": null, "fields": [field("name", "string", true)], "indexes": [], "validation_rules": [] });
+    let write = |name: &str, content: String| {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+
+    let schema = write("schema.json", json!({
+        "version": "1.0.0",
+        "collections": { "users": users, "posts": { "name": "", "description": null, "fields": [], "indexes": [], "validation_rules": [] } },
+    }).to_string());
+    let stored = write("stored.json", json!({
+        "collection_name": "users",
+        "version": "2.0.0",
+        "last_updated": "2024-01-01T00:00:00Z",
+        "total_documents": 10,
+        "discovery_source": "manual",
+        "fields": [field("name", "string", true)],
+        "description": null,
+    }).to_string());
+    let single = write("users.yaml", serde_yaml::to_string(&users).unwrap());
+
+    // Collections of a full schema take their names from the map keys
+    let names: Vec<String> = read_schema_file(&schema).unwrap().into_iter().map(|collection| collection.name).collect();
+    assert_eq!(names, vec!["posts", "users"]);
+
+    for path in [stored, single] {
+        let collections = read_schema_file(&path).unwrap();
+        assert_eq!(collections.len(), 1, "{}", path);
+        assert_eq!(collections[0].name, "users");
+        assert_eq!(collections[0].fields[0].name, "name");
+    }
+
+    let bogus = write("bogus.json", json!({ "hello": "world" }).to_string());
+    assert!(read_schema_file(&bogus).is_err());
+    std::fs::remove_dir_all(&dir).ok();
+}
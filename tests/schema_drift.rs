@@ -0,0 +1,113 @@
+use firebase_db::{
+    CollectionSchema, DEFAULT_FREQUENCY_THRESHOLD, DriftKind, FirestoreSchema, Severity, detect_drift,
+};
+use serde_json::{Value, json};
+
+fn stored(total_documents: usize, fields: Value) -> FirestoreSchema {
+    serde_json::from_value(json!({
+        "collection_name": "users",
+        "version": "1.0.0",
+        "last_updated": "2024-01-01T00:00:00Z",
+        "total_documents": total_documents,
+        "discovery_source": "discovered",
+        "fields": fields,
+        "description": null,
+    }))
+    .unwrap()
+}
+
+fn stored_field(name: &str, field_type: &str, required: bool, frequency: Option<usize>) -> Value {
+    json!({
+        "name": name, "field_type": field_type, "required": required,
+        "default_value": null, "description": null, "frequency": frequency,
+    })
+}
+
+fn live(total_documents: usize, fields: &[(&str, &str, usize)]) -> CollectionSchema {
+    let fields: Vec<Value> = fields.iter()
+        .map(|(name, field_type, frequency)| json!({
+            "name": name, "field_type": field_type, "is_required": *frequency == total_documents,
+            "sample_values": [], "frequency": frequency, "unique_values": 1, "auto_field": null,
+        }))
+        .collect();
+    serde_json::from_value(json!({
+        "collection_name": "users",
+        "total_documents": total_documents,
+        "fields": fields,
+        "sample_document": null,
+    }))
+    .unwrap()
+}
+
+fn findings(stored: &FirestoreSchema, live: &CollectionSchema) -> Vec<(String, DriftKind, Severity)> {
+    detect_drift(stored, live, DEFAULT_FREQUENCY_THRESHOLD).findings.into_iter()
+        .map(|finding| (finding.field, finding.kind, finding.severity))
+        .collect()
+}
+
+#[test]
+fn mixed_types_are_split_into_their_parts() {
+    let schema = stored(10, json!([stored_field("age", "integer", false, None)]));
+
+    let report = detect_drift(&schema, &live(10, &[("age", "Mixed(integer, string)", 10)]), DEFAULT_FREQUENCY_THRESHOLD);
+    assert_eq!(report.findings.len(), 1);
+    assert_eq!(report.findings[0].kind, DriftKind::TypeMixing);
+    assert_eq!(report.findings[0].found.as_deref(), Some("integer, string"));
+
+    // One type that isn't the stored one is a change, not a mix
+    let report = detect_drift(&schema, &live(10, &[("age", "Mixed(string, null)", 10)]), DEFAULT_FREQUENCY_THRESHOLD);
+    assert_eq!(report.findings[0].kind, DriftKind::TypeChanged);
+    assert_eq!(report.findings[0].found.as_deref(), Some("string"));
+}
+
+#[test]
+fn nulls_are_only_ignored_in_optional_fields() {
+    let documents = live(10, &[("nickname", "Mixed(string, null)", 10)]);
+
+    let optional = stored(10, json!([stored_field("nickname", "string", false, None)]));
+    assert_eq!(findings(&optional, &documents), vec![]);
+
+    let required = stored(10, json!([stored_field("nickname", "string", true, None)]));
+    assert_eq!(findings(&required, &documents), vec![("nickname".to_string(), DriftKind::TypeMixing, Severity::Breaking)]);
+}
+
+#[test]
+fn missing_required_fields_are_reported_instead_of_a_frequency_change() {
+    // Every document had `email`; now only half do
+    let schema = stored(10, json!([stored_field("email", "string", true, Some(10))]));
+    let documents = live(10, &[("email", "string", 5)]);
+    assert_eq!(findings(&schema, &documents), vec![("email".to_string(), DriftKind::MissingRequired, Severity::Breaking)]);
+
+    let report = detect_drift(&schema, &live(10, &[]), DEFAULT_FREQUENCY_THRESHOLD);
+    assert_eq!(report.findings[0].kind, DriftKind::MissingRequired);
+    assert_eq!(report.findings[0].found.as_deref(), Some("10 of 10 documents"));
+}
+
+#[test]
+fn frequency_changes_past_the_threshold_are_reported() {
+    let schema = stored(10, json!([stored_field("phone", "string", false, Some(5))]));
+
+    // 50% -> 60% stays within the default 20%
+    assert_eq!(findings(&schema, &live(10, &[("phone", "string", 6)])), vec![]);
+
+    // 50% -> 80% doesn't
+    let documents = live(10, &[("phone", "string", 8)]);
+    assert_eq!(findings(&schema, &documents), vec![("phone".to_string(), DriftKind::FrequencyChanged, Severity::NonBreaking)]);
+
+    // A looser threshold lets it through
+    assert!(detect_drift(&schema, &documents, 0.5).is_clean());
+
+    // Without a recorded frequency there is nothing to compare with
+    let manual = stored(10, json!([stored_field("phone", "string", false, None)]));
+    assert_eq!(findings(&manual, &documents), vec![]);
+}
+
+#[test]
+fn new_fields_are_not_breaking() {
+    let schema = stored(10, json!([stored_field("name", "string", true, None)]));
+    let report = detect_drift(&schema, &live(10, &[("name", "string", 10), ("plan", "string", 3)]), DEFAULT_FREQUENCY_THRESHOLD);
+    assert_eq!(report.findings.len(), 1);
+    assert_eq!(report.findings[0].kind, DriftKind::NewField);
+    assert!(report.exceeds(Severity::NonBreaking));
+    assert!(!report.exceeds(Severity::Breaking));
+}
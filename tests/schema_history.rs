@@ -0,0 +1,126 @@
+use firebase_db::{
+    DocumentStore, FirebaseError, FirestoreSchema, JsonSchemaManager, MemoryStore, ReadOptions, RpcCode, VersionBump,
+    json_to_firestore_fields, parse_version,
+};
+use serde_json::{Value, json};
+
+fn field(name: &str, required: bool) -> Value {
+    json!({ "name": name, "field_type": "string", "required": required, "default_value": null, "description": null })
+}
+
+fn schema(version: &str, fields: Value) -> FirestoreSchema {
+    serde_json::from_value(json!({
+        "collection_name": "users",
+        "version": version,
+        "last_updated": "2024-01-01T00:00:00Z",
+        "total_documents": 0,
+        "discovery_source": "manual",
+        "fields": fields,
+        "description": null,
+    }))
+    .unwrap()
+}
+
+async fn version_exists(store: &MemoryStore, version: &str) -> bool {
+    store.fetch(&format!("collection_schemas/users/versions/{}", version), &ReadOptions::default()).await.unwrap().is_some()
+}
+
+#[test]
+fn versions_parse_and_bump() {
+    assert_eq!(parse_version("1.2.3").unwrap(), (1, 2, 3));
+    assert_eq!(parse_version("v2.0.10").unwrap(), (2, 0, 10));
+    for invalid in ["1.2", "1.2.3.4", "a.b.c", ""] {
+        assert!(parse_version(invalid).is_err(), "{}", invalid);
+    }
+
+    assert_eq!(VersionBump::Major.apply("1.2.3").unwrap(), "2.0.0");
+    assert_eq!(VersionBump::Minor.apply("1.2.3").unwrap(), "1.3.0");
+    assert_eq!(VersionBump::Patch.apply("1.2.3").unwrap(), "1.2.4");
+
+    let base = schema("1.0.0", json!([field("name", true)]));
+    assert_eq!(VersionBump::between(&base, &schema("1.0.0", json!([]))), VersionBump::Major);
+    assert_eq!(VersionBump::between(&base, &schema("1.0.0", json!([field("name", true), field("bio", false)]))), VersionBump::Minor);
+    let mut described = base.clone();
+    described.description = Some("People".to_string());
+    assert_eq!(VersionBump::between(&base, &described), VersionBump::Patch);
+}
+
+#[tokio::test]
+async fn first_save_keeps_its_version_and_later_ones_bump_it() {
+    let store = MemoryStore::new();
+    let manager = JsonSchemaManager::new(store.clone());
+
+    let first = manager.save_schema_to_firestore(&schema("1.2.0", json!([field("name", true), field("email", true)]))).await.unwrap();
+    assert_eq!(first.version, "1.2.0");
+    assert_eq!((first.previous_version, first.bump), (None, None));
+
+    // Dropping a field is breaking, whatever version the caller passed
+    let second = manager.save_schema_to_firestore(&schema("1.2.0", json!([field("name", true)]))).await.unwrap();
+    assert_eq!(second.version, "2.0.0");
+    assert_eq!(second.previous_version.as_deref(), Some("1.2.0"));
+    assert_eq!(second.bump, Some(VersionBump::Major));
+
+    let third = manager.save_schema_to_firestore(&schema("9.9.9", json!([field("name", true), field("bio", false)]))).await.unwrap();
+    assert_eq!(third.version, "2.1.0");
+
+    let history: Vec<String> = manager.schema_history("users").await.unwrap().into_iter().map(|schema| schema.version).collect();
+    assert_eq!(history, vec!["1.2.0", "2.0.0", "2.1.0"]);
+    assert_eq!(manager.load_schema_from_firestore("users").await.unwrap().unwrap().version, "2.1.0");
+}
+
+#[tokio::test]
+async fn legacy_current_schema_is_back_filled_into_versions() {
+    let store = MemoryStore::new();
+    let legacy = schema("1.0.0", json!([field("name", true)]));
+    let fields = json_to_firestore_fields(serde_json::to_value(&legacy).unwrap()).unwrap();
+    store.insert("collection_schemas", Some("users"), fields).await.unwrap();
+    assert!(!version_exists(&store, "1.0.0").await);
+
+    let manager = JsonSchemaManager::new(store.clone());
+    let saved = manager.save_schema_to_firestore(&schema("1.0.0", json!([field("name", true), field("bio", false)]))).await.unwrap();
+    assert_eq!(saved.version, "1.1.0");
+    assert!(version_exists(&store, "1.0.0").await);
+    assert!(version_exists(&store, "1.1.0").await);
+}
+
+#[tokio::test]
+async fn saved_versions_are_never_overwritten() {
+    let store = MemoryStore::new();
+    let manager = JsonSchemaManager::new(store.clone());
+    manager.save_schema_to_firestore(&schema("1.0.0", json!([field("name", true)]))).await.unwrap();
+
+    // Something else already wrote the version the next save would get
+    let squatter = schema("2.0.0", json!([field("other", false)]));
+    let fields = json_to_firestore_fields(serde_json::to_value(&squatter).unwrap()).unwrap();
+    store.insert("collection_schemas/users/versions", Some("2.0.0"), fields).await.unwrap();
+
+    match manager.save_schema_to_firestore(&schema("1.0.0", json!([]))).await {
+        Err(FirebaseError::ApiError(status)) => assert_eq!(status.code, RpcCode::AlreadyExists),
+        other => panic!("expected the save to be refused, got {:?}", other.map(|schema| schema.version)),
+    }
+    assert_eq!(manager.load_schema_from_firestore("users").await.unwrap().unwrap().version, "1.0.0");
+    let kept = manager.load_schema_version("users", "2.0.0").await.unwrap().unwrap();
+    assert_eq!(kept.fields[0].name, "other");
+}
+
+#[tokio::test]
+async fn rollback_saves_a_new_version_restoring_an_old_one() {
+    let store = MemoryStore::new();
+    let manager = JsonSchemaManager::new(store.clone());
+    manager.save_schema_to_firestore(&schema("1.0.0", json!([field("name", true), field("bio", false)]))).await.unwrap();
+    manager.save_schema_to_firestore(&schema("1.0.0", json!([field("name", true)]))).await.unwrap();
+
+    let restored = manager.rollback_schema("users", "1.0.0").await.unwrap();
+    // Bringing back an optional field is a minor change from 2.0.0
+    assert_eq!(restored.version, "2.1.0");
+    assert_eq!(restored.restored_from.as_deref(), Some("1.0.0"));
+    assert_eq!(restored.previous_version.as_deref(), Some("2.0.0"));
+    assert_eq!(restored.discovery_source, "rollback");
+
+    let current = manager.load_schema_from_firestore("users").await.unwrap().unwrap();
+    let names: Vec<&str> = current.fields.iter().map(|field| field.name.as_str()).collect();
+    assert_eq!(names, vec!["name", "bio"]);
+    assert_eq!(manager.schema_history("users").await.unwrap().len(), 3);
+
+    assert!(matches!(manager.rollback_schema("users", "7.0.0").await, Err(FirebaseError::NotFound(_))));
+}
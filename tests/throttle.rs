@@ -0,0 +1,86 @@
+use firebase_db::{FirebaseClient, FirebaseError, RateLimit, Throttle};
+use std::time::Duration;
+use tokio::time::Instant;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[test]
+fn ramp_up_grows_by_half_every_five_minutes() {
+    let limit = RateLimit::ramp_up();
+    assert_eq!(limit.rate_at(Duration::ZERO), 500.0);
+    assert_eq!(limit.rate_at(5 * MINUTE - Duration::from_secs(1)), 500.0);
+    assert_eq!(limit.rate_at(5 * MINUTE), 750.0);
+    assert_eq!(limit.rate_at(10 * MINUTE), 1125.0);
+}
+
+#[test]
+fn capped_limits_never_exceed_the_cap() {
+    assert_eq!(RateLimit::PerSecond(100.0).capped_at(50.0), RateLimit::PerSecond(50.0));
+    assert_eq!(RateLimit::PerSecond(10.0).capped_at(50.0), RateLimit::PerSecond(10.0));
+
+    let limit = RateLimit::ramp_up().capped_at(600.0);
+    assert_eq!(limit.rate_at(Duration::ZERO), 500.0);
+    assert_eq!(limit.rate_at(5 * MINUTE), 600.0);
+    assert_eq!(limit.rate_at(60 * MINUTE), 600.0);
+    assert_eq!(RateLimit::ramp_up().capped_at(100.0).rate_at(Duration::ZERO), 100.0);
+}
+
+#[test]
+fn rates_must_be_finite_and_positive() {
+    for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(RateLimit::PerSecond(rate).validate(), Err(FirebaseError::ConfigError(_))), "{}", rate);
+        assert!(RateLimit::ramp_up().capped_at(rate).validate().is_err(), "{}", rate);
+        assert!(Throttle::new(Some(RateLimit::PerSecond(rate)), None).is_err(), "{}", rate);
+    }
+    assert!(RateLimit::ramp_up().validate().is_ok());
+
+    let built = FirebaseClient::builder("demo-project", "test-key").rate_limit(RateLimit::PerSecond(0.0)).build();
+    assert!(matches!(built, Err(FirebaseError::ConfigError(_))));
+}
+
+#[tokio::test]
+async fn bucket_waits_once_a_seconds_worth_is_spent() {
+    tokio::time::pause();
+    let throttle = Throttle::new(Some(RateLimit::PerSecond(10.0)), None).unwrap();
+    let start = Instant::now();
+
+    for _ in 0..10 {
+        throttle.acquire(1).await;
+    }
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    // Five more tokens refill in half a second (timers round up to the millisecond)
+    throttle.acquire(5).await;
+    let waited = start.elapsed();
+    assert!(waited >= Duration::from_millis(500) && waited <= Duration::from_millis(501), "{:?}", waited);
+}
+
+#[tokio::test]
+async fn ramp_up_admits_more_as_time_passes() {
+    tokio::time::pause();
+    let throttle = Throttle::new(Some(RateLimit::ramp_up()), None).unwrap();
+    assert_eq!(throttle.current_rate(), Some(500.0));
+
+    tokio::time::advance(5 * MINUTE).await;
+    assert_eq!(throttle.current_rate(), Some(750.0));
+
+    // A full bucket at 750/s covers a 750-write burst without waiting
+    let start = Instant::now();
+    throttle.acquire(750).await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn max_in_flight_holds_back_extra_requests() {
+    tokio::time::pause();
+    let throttle = Throttle::new(None, Some(2)).unwrap();
+    let first = throttle.acquire(1).await;
+    let _second = throttle.acquire(1).await;
+
+    let third = tokio::time::timeout(Duration::from_secs(1), throttle.acquire(1)).await;
+    assert!(third.is_err(), "a third request must wait for a free slot");
+
+    drop(first);
+    let third = tokio::time::timeout(Duration::from_secs(1), throttle.acquire(1)).await;
+    assert!(third.is_ok());
+}
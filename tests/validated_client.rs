@@ -0,0 +1,68 @@
+use firebase_db::{
+    Collection, DocumentStore, Field, FieldType, FirebaseError, MemoryStore, ReadOptions, SchemaManager, ValidatedClient,
+    Write, json_to_firestore_fields,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn field(name: &str, field_type: FieldType, required: bool) -> Field {
+    Field { name: name.to_string(), field_type, required, default_value: None, description: None, auto_field: None }
+}
+
+async fn client() -> ValidatedClient<MemoryStore> {
+    let store = MemoryStore::new();
+    let mut schemas = SchemaManager::new(store.clone());
+    schemas.define_collection(Collection {
+        name: "users".to_string(),
+        fields: vec![
+            field("name", FieldType::String, true),
+            field("address", FieldType::Map, false),
+            field("address.zip", FieldType::String, true),
+            field("nickname", FieldType::String, false),
+        ],
+        indexes: Vec::new(),
+        validation_rules: Vec::new(),
+    });
+    let fields = json_to_firestore_fields(json!({ "name": "Ann", "address": { "zip": "12345" }, "nickname": "A" })).unwrap();
+    store.insert("users", Some("ann"), fields).await.unwrap();
+    ValidatedClient::new(schemas)
+}
+
+fn violated_fields(error: FirebaseError) -> Vec<String> {
+    match error {
+        FirebaseError::SchemaViolation(report) => report.violations.into_iter().map(|violation| violation.field).collect(),
+        other => panic!("expected a schema violation, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn masked_update_cannot_remove_required_fields() {
+    let client = client().await;
+
+    let error = client.patch("users/ann", HashMap::new(), Some(&["name".to_string()])).await.unwrap_err();
+    assert_eq!(violated_fields(error), vec!["name"]);
+
+    // Masking a map removes the required fields inside it
+    let error = client.patch("users/ann", HashMap::new(), Some(&["address".to_string()])).await.unwrap_err();
+    assert_eq!(violated_fields(error), vec!["address.zip"]);
+
+    let write = Write::Update { path: "users/ann".to_string(), fields: HashMap::new(), mask: vec!["name".to_string()] };
+    assert!(matches!(client.commit(vec![write], None).await, Err(FirebaseError::SchemaViolation(_))));
+
+    let document = client.fetch("users/ann", &ReadOptions::default()).await.unwrap().unwrap();
+    assert_eq!(document.to_json()["name"], "Ann");
+}
+
+#[tokio::test]
+async fn masked_update_may_remove_optional_fields() {
+    let client = client().await;
+
+    client.patch("users/ann", HashMap::new(), Some(&["nickname".to_string()])).await.unwrap();
+    let fields = json_to_firestore_fields(json!({ "name": "Anne" })).unwrap();
+    client.patch("users/ann", fields, Some(&["name".to_string()])).await.unwrap();
+
+    let document = client.fetch("users/ann", &ReadOptions::default()).await.unwrap().unwrap().to_json();
+    assert_eq!(document["name"], "Anne");
+    assert!(document.get("nickname").is_none());
+    assert_eq!(document["address"]["zip"], "12345");
+}
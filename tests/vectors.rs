@@ -0,0 +1,131 @@
+use firebase_db::{
+    DistanceMeasure, DocumentStore, FirestoreValue, MemoryStore, QueryBuilder, ReadOptions, firestore_value_to_json,
+    json_value_to_firestore,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn wire_map(fields: serde_json::Value) -> FirestoreValue {
+    serde_json::from_value(json!({ "mapValue": { "fields": fields } })).unwrap()
+}
+
+#[test]
+fn vectors_round_trip_as_typed_maps() {
+    let vector = FirestoreValue::VectorValue(vec![0.5, 1.0]);
+    let wire = serde_json::to_value(&vector).unwrap();
+    assert_eq!(wire, json!({ "mapValue": { "fields": {
+        "__type__": { "stringValue": "__vector__" },
+        "value": { "arrayValue": { "values": [{ "doubleValue": 0.5 }, { "doubleValue": 1.0 }] } },
+    } } }));
+    assert_eq!(serde_json::from_value::<FirestoreValue>(wire).unwrap(), vector);
+
+    let data = firestore_value_to_json(&vector);
+    assert_eq!(data, json!({ "__type__": "__vector__", "value": [0.5, 1.0] }));
+    let reparsed: serde_json::Value = serde_json::from_str(&data.to_string()).unwrap();
+    assert_eq!(json_value_to_firestore(reparsed), vector);
+}
+
+#[test]
+fn maps_that_only_look_like_vectors_stay_maps() {
+    let lookalikes = [
+        json!({ "__type__": { "stringValue": "__vector__" }, "value": { "arrayValue": { "values": [{ "integerValue": "1" }] } } }),
+        json!({ "__type__": { "stringValue": "__vector__" }, "value": { "arrayValue": {} } }),
+        json!({ "__type__": { "stringValue": "__vector__" }, "value": { "stringValue": "0.5" } }),
+        json!({ "__type__": { "stringValue": "vector" }, "value": { "arrayValue": { "values": [{ "doubleValue": 0.5 }] } } }),
+        json!({
+            "__type__": { "stringValue": "__vector__" },
+            "value": { "arrayValue": { "values": [{ "doubleValue": 0.5 }] } },
+            "label": { "stringValue": "extra" },
+        }),
+    ];
+    for fields in lookalikes {
+        let value = wire_map(fields.clone());
+        assert!(matches!(value, FirestoreValue::MapValue { .. }), "{} decoded as {:?}", fields, value);
+    }
+
+    // A user's own data with integer components is not an embedding either
+    let data = json_value_to_firestore(json!({ "__type__": "__vector__", "value": [1, 2] }));
+    assert!(matches!(data, FirestoreValue::MapValue { .. }), "{:?}", data);
+}
+
+async fn embeddings() -> MemoryStore {
+    let store = MemoryStore::new();
+    let vectors: [(&str, Vec<f64>); 6] = [
+        ("a", vec![2.0, 0.0]),
+        ("b", vec![0.0, 1.0]),
+        ("c", vec![10.0, 10.0]),
+        ("d", vec![-1.0, 0.0]),
+        ("wrong-size", vec![1.0, 2.0, 3.0]),
+        ("zero", vec![0.0, 0.0]),
+    ];
+    for (id, vector) in vectors {
+        let fields = HashMap::from([("embedding".to_string(), FirestoreValue::VectorValue(vector))]);
+        store.insert("items", Some(id), fields).await.unwrap();
+    }
+    store
+}
+
+async fn nearest(store: &MemoryStore, measure: DistanceMeasure) -> Vec<(String, f64)> {
+    let query = QueryBuilder::new("items")
+        .find_nearest("embedding", vec![1.0, 0.0], 10, measure)
+        .distance_result_field("distance")
+        .build();
+    store.run_query("", &query, &ReadOptions::default()).await.unwrap()
+        .into_iter()
+        .map(|document| {
+            let distance = document.fields["distance"].as_f64().unwrap();
+            (document.id().to_string(), (distance * 1000.0).round() / 1000.0)
+        })
+        .collect()
+}
+
+fn owned(results: &[(&str, f64)]) -> Vec<(String, f64)> {
+    results.iter().map(|(id, distance)| (id.to_string(), *distance)).collect()
+}
+
+#[tokio::test]
+async fn euclidean_is_nearest_first_with_ties_by_name() {
+    let store = embeddings().await;
+    assert_eq!(nearest(&store, DistanceMeasure::Euclidean).await, owned(&[
+        ("a", 1.0),
+        ("zero", 1.0),
+        ("b", 1.414),
+        ("d", 2.0),
+        ("c", 13.454),
+    ]));
+}
+
+#[tokio::test]
+async fn cosine_skips_zero_vectors() {
+    let store = embeddings().await;
+    assert_eq!(nearest(&store, DistanceMeasure::Cosine).await, owned(&[
+        ("a", 0.0),
+        ("c", 0.293),
+        ("b", 1.0),
+        ("d", 2.0),
+    ]));
+}
+
+#[tokio::test]
+async fn dot_product_is_largest_first() {
+    let store = embeddings().await;
+    assert_eq!(nearest(&store, DistanceMeasure::DotProduct).await, owned(&[
+        ("c", 10.0),
+        ("a", 2.0),
+        ("b", 0.0),
+        ("zero", 0.0),
+        ("d", -1.0),
+    ]));
+}
+
+#[tokio::test]
+async fn the_smaller_of_the_two_limits_applies() {
+    let store = embeddings().await;
+    let query = QueryBuilder::new("items")
+        .find_nearest("embedding", vec![1.0, 0.0], 3, DistanceMeasure::Euclidean)
+        .limit(2)
+        .build();
+    let documents = store.run_query("", &query, &ReadOptions::default()).await.unwrap();
+    let ids: Vec<&str> = documents.iter().map(|document| document.id()).collect();
+    assert_eq!(ids, vec!["a", "zero"]);
+}